@@ -17,6 +17,67 @@ use query_handler::QueryHandler;
 use sources_handler::SourcesHandler;
 use verify_source_packet_handler::VerifySourcePacketHandler;
 
+// ----------------------------------------------------------------------
+// - PackagingSettings:
+// ----------------------------------------------------------------------
+
+/// The format used for reports
+#[derive(Clone, Copy, Debug, Eq, PartialEq, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// A table for humans to read
+    Table,
+    /// JSON for tools to process
+    Json,
+}
+
+/// Settings used while packaging
+#[derive(Clone, Debug)]
+pub struct PackagingSettings {
+    /// The directory to write packets into
+    pub output_directory: std::path::PathBuf,
+    /// What to do about packet files that exist already
+    pub existing_packets: gng_package::ExistingPacketPolicy,
+    /// Report which packet claimed which file in the given format
+    pub explain: Option<ReportFormat>,
+    /// What to do about files that no packet claims
+    pub unclaimed_files: gng_package::ClaimPolicy,
+    /// What to do about files that several packets claim
+    pub ambiguous_files: gng_package::ClaimPolicy,
+    /// What to do about absolute links into `GNG_INST_DIR`
+    pub staging_links: gng_package::LinkPolicy,
+    /// Packets providing interpreters, by absolute path of the interpreter
+    pub interpreter_providers: std::collections::BTreeMap<std::path::PathBuf, gng_core::Name>,
+    /// What to do about scripts with interpreters not provided by any known packet
+    pub unresolved_interpreters: gng_package::InterpreterPolicy,
+    /// The `passwd` file used to look up users named in `attributes`
+    pub passwd_file: Option<std::path::PathBuf>,
+    /// The `group` file used to look up groups named in `attributes`
+    pub group_file: Option<std::path::PathBuf>,
+    /// The SBOM formats to embed into packets and write next to them
+    pub sbom_formats: Vec<gng_package::sbom::SbomFormat>,
+    /// The time recorded in SBOMs, in seconds since the epoch
+    pub sbom_timestamp: u64,
+}
+
+impl Default for PackagingSettings {
+    fn default() -> Self {
+        Self {
+            output_directory: std::path::PathBuf::from("."),
+            existing_packets: gng_package::ExistingPacketPolicy::Overwrite,
+            explain: None,
+            unclaimed_files: gng_package::ClaimPolicy::Warn,
+            ambiguous_files: gng_package::ClaimPolicy::Warn,
+            staging_links: gng_package::LinkPolicy::Fix,
+            interpreter_providers: std::collections::BTreeMap::new(),
+            unresolved_interpreters: gng_package::InterpreterPolicy::Fail,
+            passwd_file: None,
+            group_file: None,
+            sbom_formats: vec![gng_package::sbom::SbomFormat::Spdx],
+            sbom_timestamp: 0,
+        }
+    }
+}
+
 // ----------------------------------------------------------------------
 // - Handler:
 // ----------------------------------------------------------------------
@@ -101,7 +162,10 @@ fn clean_up(handlers: &HandlerList, mode: &crate::Mode) -> eyre::Result<()> {
 
 /// Constructor
 #[must_use]
-fn create_handlers(case_officer: &crate::case_officer::CaseOfficer) -> HandlerList {
+fn create_handlers(
+    case_officer: &crate::case_officer::CaseOfficer,
+    packaging_settings: &PackagingSettings,
+) -> HandlerList {
     let query_handler = Box::<QueryHandler>::default();
     let verify_source_packet_handler = Box::new(VerifySourcePacketHandler::new(
         query_handler.source_packet(),
@@ -116,7 +180,9 @@ fn create_handlers(case_officer: &crate::case_officer::CaseOfficer) -> HandlerLi
     ));
     let packaging_handler = Box::new(PackagingHandler::new(
         query_handler.source_packet(),
+        &case_officer.root_directory(),
         &case_officer.install_directory(),
+        packaging_settings,
     ));
 
     let handlers: Vec<Box<dyn Handler>> = vec![
@@ -134,8 +200,11 @@ fn create_handlers(case_officer: &crate::case_officer::CaseOfficer) -> HandlerLi
 ///
 /// # Errors
 /// Return some Error if something  goes wrong.
-pub fn run(case_officer: &mut crate::case_officer::CaseOfficer) -> eyre::Result<()> {
-    let prepare_handlers = create_handlers(case_officer);
+pub fn run(
+    case_officer: &mut crate::case_officer::CaseOfficer,
+    packaging_settings: &PackagingSettings,
+) -> eyre::Result<()> {
+    let prepare_handlers = create_handlers(case_officer, packaging_settings);
     let handle_handlers = prepare_handlers.clone();
     let clean_up_handlers = prepare_handlers.clone();
 
@@ -4,12 +4,12 @@
 //! A `Handler` for `query` Mode
 
 use super::query_handler::SourcePacketHandle;
-use crate::handler::Handler;
+use crate::handler::{Handler, PackagingSettings, ReportFormat};
 
 use gng_build_shared::SourcePacket;
 use gng_core::Names;
 
-use eyre::Result;
+use eyre::{eyre, Result, WrapErr};
 
 // ----------------------------------------------------------------------
 // - Helper:
@@ -46,10 +46,18 @@ fn generate_data(
         url: source.url.clone(),
         bug_url: source.bug_url.clone(),
         dependencies: packet.dependencies.clone(),
+        needed_libraries: Vec::new(),
         facet,
     }
 }
 
+fn generate_filter(
+    packet: &gng_build_shared::PacketDefinition,
+) -> gng_package::filter::BoxedFilter {
+    gng_package::filter::expression::parse_files(&packet.files)
+        .expect("This was validated to be OK!")
+}
+
 fn generate_packet_definitions(source_packet: &SourcePacket) -> Vec<gng_package::PacketDefinition> {
     source_packet
         .packets
@@ -61,9 +69,7 @@ fn generate_packet_definitions(source_packet: &SourcePacket) -> Vec<gng_package:
             gng_package::PacketDefinition::new(
                 data,
                 merged_facets,
-                std::rc::Rc::new(gng_package::filter::GlobFilter::new(
-                    gng_package::strings_to_globs(&p.files).expect("This was validated to be OK!"),
-                )),
+                std::sync::Arc::new(generate_filter(p)),
                 p.files.is_empty(),
             )
         })
@@ -76,10 +82,107 @@ fn generate_facet_definitions(_source_packet: &SourcePacket) -> Vec<gng_package:
     // by the installation step!
     vec![gng_package::FacetDefinition::new(
         None, // Catch-all main facet, Must be last!
-        std::rc::Rc::new(gng_package::filter::AlwaysTrue::default()),
+        std::sync::Arc::new(gng_package::filter::AlwaysTrue::default()),
     )]
 }
 
+fn id_file<'a>(
+    needed: bool,
+    file: Option<&'a std::path::Path>,
+    option: &str,
+) -> Result<Option<&'a std::path::Path>> {
+    match (needed, file) {
+        (false, _) => Ok(None),
+        (true, Some(f)) => Ok(Some(f)),
+        (true, None) => Err(eyre!(
+            "Recipe \"attributes\" name a {0}, but no {0} file was given (\"--{0}\").",
+            option
+        )),
+    }
+}
+
+fn generate_attribute_overrides(
+    source_packet: &SourcePacket,
+    settings: &PackagingSettings,
+) -> Result<Vec<gng_package::attributes::AttributeOverride>> {
+    // Names are looked up in files given explicitly: The users and groups
+    // of the build host are unrelated to the system the packets are for.
+    let is_name = |n: &Option<String>| n.as_ref().map_or(false, |n| n.parse::<u32>().is_err());
+    let users = gng_package::attributes::UserDatabase::from_files(
+        id_file(
+            source_packet.attributes.values().any(|a| is_name(&a.user)),
+            settings.passwd_file.as_deref(),
+            "passwd",
+        )?,
+        id_file(
+            source_packet.attributes.values().any(|a| is_name(&a.group)),
+            settings.group_file.as_deref(),
+            "group",
+        )?,
+    )?;
+
+    // The longest pattern is considered the most specific one, so apply it
+    // last (see `SourcePacket::attributes`):
+    let mut attributes = source_packet.attributes.iter().collect::<Vec<_>>();
+    attributes.sort_by_key(|(glob, _)| glob.len());
+
+    attributes
+        .into_iter()
+        .map(|(glob, a)| {
+            gng_package::attributes::AttributeOverride::new(
+                glob,
+                a.mode.as_deref(),
+                a.user.as_deref(),
+                a.group.as_deref(),
+                &users,
+            )
+            .wrap_err(eyre!("Invalid attributes for \"{}\".", glob))
+        })
+        .collect()
+}
+
+fn generate_build_information(
+    source_packet: &SourcePacket,
+    settings: &PackagingSettings,
+) -> gng_package::sbom::BuildInformation {
+    gng_package::sbom::BuildInformation {
+        name: source_packet.name.clone(),
+        version: source_packet.version.clone(),
+        license: source_packet.license.clone(),
+        url: source_packet.url.clone(),
+        sources: source_packet
+            .sources
+            .iter()
+            .map(|s| gng_package::sbom::SourceInformation {
+                url: s.source.clone(),
+                sha256: s.sha256.clone(),
+            })
+            .collect(),
+        created: settings.sbom_timestamp,
+    }
+}
+
+fn write_sbom(
+    source_packet: &SourcePacket,
+    documents: &[gng_package::sbom::SbomDocument],
+    output_directory: &std::path::Path,
+) -> Result<()> {
+    for d in documents {
+        let path = output_directory.join(format!(
+            "{}-{}.{}",
+            source_packet.name,
+            source_packet.version,
+            d.format.extension()
+        ));
+        std::fs::write(&path, &d.data).wrap_err(eyre!(
+            "Failed to write SBOM \"{}\".",
+            path.to_string_lossy()
+        ))?;
+        println!("{}", path.to_string_lossy());
+    }
+    Ok(())
+}
+
 // ----------------------------------------------------------------------
 // - PackagingHandler:
 // ----------------------------------------------------------------------
@@ -87,15 +190,24 @@ fn generate_facet_definitions(_source_packet: &SourcePacket) -> Vec<gng_package:
 /// Make sure the source as seen by the `gng-build-agent` stays constant
 pub struct PackagingHandler {
     source_packet: SourcePacketHandle,
+    root_directory: std::path::PathBuf,
     install_directory: std::path::PathBuf,
+    settings: PackagingSettings,
 }
 
 impl PackagingHandler {
     /// Create a new `PackagingHandler`
-    pub fn new(source_packet: SourcePacketHandle, install_directory: &std::path::Path) -> Self {
+    pub fn new(
+        source_packet: SourcePacketHandle,
+        root_directory: &std::path::Path,
+        install_directory: &std::path::Path,
+        settings: &PackagingSettings,
+    ) -> Self {
         Self {
             source_packet,
+            root_directory: root_directory.to_path_buf(),
             install_directory: install_directory.to_path_buf(),
+            settings: settings.clone(),
         }
     }
 }
@@ -117,13 +229,40 @@ impl Handler for PackagingHandler {
             .as_ref()
             .expect("SourcePacket should be defined here.");
 
-        for p in &gng_package::package(
+        let result = gng_package::package(
             &self.install_directory,
+            &self.root_directory.join("usr"),
             &generate_packet_definitions(source_packet),
             &generate_facet_definitions(source_packet),
-        )? {
+            &gng_package::PackagingOptions {
+                output_directory: self.settings.output_directory.clone(),
+                existing_packets: self.settings.existing_packets,
+                explain: self.settings.explain.is_some(),
+                unclaimed_files: self.settings.unclaimed_files,
+                ambiguous_files: self.settings.ambiguous_files,
+                install_prefix: Some(gng_build_shared::constants::container::GNG_INST_DIR.clone()),
+                work_prefix: Some(gng_build_shared::constants::container::GNG_WORK_DIR.clone()),
+                staging_links: self.settings.staging_links,
+                interpreter_providers: self.settings.interpreter_providers.clone(),
+                unresolved_interpreters: self.settings.unresolved_interpreters,
+                attribute_overrides: generate_attribute_overrides(source_packet, &self.settings)?,
+                build_information: (!self.settings.sbom_formats.is_empty())
+                    .then(|| generate_build_information(source_packet, &self.settings)),
+                sbom_formats: self.settings.sbom_formats.clone(),
+                ..gng_package::PackagingOptions::default()
+            },
+        )?;
+
+        for p in &result.packets {
             println!("{}", p.to_string_lossy());
         }
+        write_sbom(source_packet, &result.sbom, &self.settings.output_directory)?;
+
+        match (&self.settings.explain, &result.report) {
+            (Some(ReportFormat::Table), Some(report)) => print!("{report}"),
+            (Some(ReportFormat::Json), Some(report)) => println!("{}", report.to_json()?),
+            _ => {}
+        }
 
         Ok(())
     }
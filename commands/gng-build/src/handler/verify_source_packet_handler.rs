@@ -38,8 +38,8 @@ fn verify_packet(packet: &PacketDefinition) -> Result<()> {
             &packet.name,
         ));
     }
-    gng_package::strings_to_globs(&packet.files).wrap_err(eyre!(
-        "The `files` of packet \"{}\" contains an invalid glob pattern.",
+    gng_package::filter::expression::parse_files(&packet.files).wrap_err(eyre!(
+        "The `files` of packet \"{}\" contains an invalid entry.",
         &packet.name,
     ))?;
 
@@ -76,6 +76,15 @@ fn verify_source(source: &SourceDefinition) -> Result<()> {
             s,
         ))?;
     }
+    if let Some(sha256) = &source.sha256 {
+        if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(eyre!(
+                "\"{}\" has an invalid `sha256` \"{}\".",
+                &source.source,
+                sha256
+            ));
+        }
+    }
     if valid_file_path(&source.destination) {
         Ok(())
     } else {
@@ -94,6 +103,20 @@ fn verify_sources(sources: &[gng_build_shared::SourceDefinition]) -> Result<()>
     Ok(())
 }
 
+fn verify_attributes(
+    attributes: &std::collections::BTreeMap<String, gng_build_shared::AttributeDefinition>,
+) -> Result<()> {
+    for (glob, attributes) in attributes {
+        gng_package::strings_to_globs(std::slice::from_ref(glob))
+            .wrap_err(eyre!("\"{}\" is an invalid glob pattern.", glob))?;
+        if let Some(mode) = &attributes.mode {
+            gng_package::attributes::parse_mode(mode)
+                .wrap_err(eyre!("\"{}\" has an invalid `mode`.", glob))?;
+        }
+    }
+    Ok(())
+}
+
 fn verify_source_packet(source_packet: &SourcePacket) -> Result<()> {
     if source_packet.license.is_empty() {
         Err(eyre!("The Source definition must include a `license`."))
@@ -122,6 +145,9 @@ fn verify_source_packet(source_packet: &SourcePacket) -> Result<()> {
         verify_sources(&source_packet.sources).wrap_err(eyre!(
             "The source definition contains an invalid `sources` definition."
         ))?;
+        verify_attributes(&source_packet.attributes).wrap_err(eyre!(
+            "The source definition contains an invalid `attributes` definition."
+        ))?;
         Ok(())
     }
 }
@@ -23,6 +23,71 @@ use eyre::{Result, WrapErr};
 // - Helper:
 // ----------------------------------------------------------------------
 
+fn parse_interpreter_provider(input: &str) -> Result<(PathBuf, gng_core::Name)> {
+    let (path, packet) = input
+        .split_once('=')
+        .ok_or_else(|| eyre::eyre!("Expected INTERPRETER=PACKET, got \"{}\".", input))?;
+    let path = PathBuf::from(path);
+    if !path.is_absolute() {
+        return Err(eyre::eyre!(
+            "Interpreter \"{}\" must be an absolute path.",
+            path.to_string_lossy()
+        ));
+    }
+    Ok((path, gng_core::Name::try_from(packet)?))
+}
+
+/// Open the repository at `location`, a URL or a local directory
+fn open_repository(
+    location: &str,
+    trusted_keys: &[gng_packet_db::signature::PublicKey],
+) -> Result<gng_packet_db::Repository> {
+    // Builds must not depend on what this host has seen before, so there is
+    // no rollback protection:
+    let trust = gng_packet_db::Trust::without_rollback_protection(trusted_keys.to_vec());
+    match url::Url::parse(location) {
+        Ok(url) if url.scheme().len() > 1 => {
+            let cache = gng_core::user_directory("XDG_CACHE_HOME", ".cache")
+                .map(|c| c.join("gng"))
+                .ok_or_else(|| eyre::eyre!("No cache directory found."))?;
+            gng_packet_db::Repository::from_url(&url, &cache, false, &trust)
+        }
+        _ => {
+            let directory = std::fs::canonicalize(location).wrap_err(eyre::eyre!(
+                "Repository directory \"{}\" not found.",
+                location
+            ))?;
+            gng_packet_db::Repository::from_local_directory(&directory, false, &trust)
+        }
+    }
+    .wrap_err(eyre::eyre!("Failed to open repository \"{}\".", location))
+}
+
+/// The packets of `repository` providing interpreters
+///
+/// Only `/usr/bin` and `/usr/sbin` are considered. If several packets
+/// provide the same interpreter, the first one by name wins.
+fn repository_interpreter_providers(
+    repository: &gng_packet_db::Repository,
+) -> Result<std::collections::BTreeMap<PathBuf, gng_core::Name>> {
+    let mut providers = std::collections::BTreeMap::new();
+    for pattern in ["/usr/bin/*", "/usr/sbin/*"] {
+        for (packet, _, file) in repository.find_file(pattern)? {
+            providers.entry(file).or_insert(packet.name);
+        }
+    }
+    Ok(providers)
+}
+
+/// The SBOM creation time: `SOURCE_DATE_EPOCH` if set, so builds stay reproducible
+fn sbom_timestamp() -> Result<u64> {
+    std::env::var("SOURCE_DATE_EPOCH").map_or(Ok(0), |epoch| {
+        epoch
+            .parse::<u64>()
+            .wrap_err(eyre::eyre!("Invalid SOURCE_DATE_EPOCH \"{}\".", epoch))
+    })
+}
+
 #[derive(Debug, Parser)]
 #[clap(name = "gng-build", about = "A packet builder for GnG.")]
 struct Args {
@@ -30,10 +95,16 @@ struct Args {
     #[clap(long, value_parser(ValueParser::os_string()), value_name = "FILE")]
     config: Option<PathBuf>,
 
-    /// the repository to use
+    /// the repository to use, a URL or a local directory
+    ///
+    /// Interpreters of scripts are looked up in the packets of this repository.
     #[clap(long, value_name = "REPO")]
     repository: Option<String>,
 
+    /// verify the repository data with this key
+    #[clap(long = "trusted-key", value_name = "KEY", requires = "repository")]
+    trusted_keys: Vec<gng_packet_db::signature::PublicKey>,
+
     /// The build agent to use
     #[clap(
         long,
@@ -68,10 +139,70 @@ struct Args {
     #[clap(value_parser(ValueParser::os_string()), value_name = "DIR")]
     recipes_dir: PathBuf,
 
+    /// The directory to write packets into
+    #[clap(
+        long,
+        value_parser(ValueParser::os_string()),
+        value_name = "DIR",
+        default_value = "."
+    )]
+    output_dir: PathBuf,
+
+    /// What to do about packets that exist already ("overwrite", "skip" or "fail")
+    #[clap(long, value_name = "POLICY", default_value = "overwrite")]
+    existing_packets: gng_package::ExistingPacketPolicy,
+
+    /// The passwd file of the target system to look up users named in recipe
+    /// `attributes`, needed if any are named
+    #[clap(long, value_parser(ValueParser::os_string()), value_name = "FILE")]
+    passwd: Option<PathBuf>,
+
+    /// The group file of the target system to look up groups named in recipe
+    /// `attributes`, needed if any are named
+    #[clap(long, value_parser(ValueParser::os_string()), value_name = "FILE")]
+    group: Option<PathBuf>,
+
     /// Keep temporary directories after build
     #[clap(long)]
     keep_temporaries: bool,
 
+    /// Report which packet claimed which file and why
+    #[clap(long, value_enum, value_name = "FORMAT")]
+    explain_packaging: Option<gng_build::handler::ReportFormat>,
+
+    /// What to do about files no packet claims ("warn" or "fail")
+    #[clap(long, value_name = "POLICY", default_value = "warn")]
+    unclaimed_files: gng_package::ClaimPolicy,
+
+    /// What to do about files several packets claim ("warn" or "fail")
+    #[clap(long, value_name = "POLICY", default_value = "warn")]
+    ambiguous_files: gng_package::ClaimPolicy,
+
+    /// What to do about absolute links into the install directory ("fix" or "reject")
+    #[clap(long, value_name = "POLICY", default_value = "fix")]
+    staging_links: gng_package::LinkPolicy,
+
+    /// A packet providing an interpreter used by scripts, overriding the
+    /// providers found in the repository
+    #[clap(
+        long,
+        value_parser(parse_interpreter_provider),
+        value_name = "INTERPRETER=PACKET"
+    )]
+    interpreter_provider: Vec<(PathBuf, gng_core::Name)>,
+
+    /// What to do about interpreters no known packet provides ("warn" or "fail")
+    #[clap(long, value_name = "POLICY", default_value = "fail")]
+    unresolved_interpreters: gng_package::InterpreterPolicy,
+
+    /// The SBOM format to generate ("spdx" or "cyclonedx"), may be repeated
+    #[clap(long, value_name = "FORMAT", default_value = "spdx")]
+    sbom: Vec<gng_package::sbom::SbomFormat>,
+
+    /// Do not generate any SBOM
+    #[clap(long, conflicts_with = "sbom")]
+    no_sbom: bool,
+
     #[clap(flatten)]
     logging: gng_core::log::LogArgs,
 }
@@ -91,9 +222,8 @@ fn main() -> Result<()> {
 
     tracing::debug!("Command line arguments: {:#?}", args);
 
-    let recipes_dir = std::env::current_dir()
-        .wrap_err("Failed to get current work directory.")?
-        .join(args.recipes_dir);
+    let current_dir = std::env::current_dir().wrap_err("Failed to get current work directory.")?;
+    let recipes_dir = current_dir.join(args.recipes_dir);
 
     let mut case_officer = gng_build::CaseOfficerBuilder::default();
     if let Some(tmp) = &args.lua_dir {
@@ -110,5 +240,28 @@ fn main() -> Result<()> {
         .build(&recipes_dir)
         .wrap_err("Failed to initialize build container environment.")?;
 
-    gng_build::handler::run(&mut case_officer)
+    let mut interpreter_providers = match &args.repository {
+        Some(location) => {
+            repository_interpreter_providers(&open_repository(location, &args.trusted_keys)?)?
+        }
+        None => std::collections::BTreeMap::new(),
+    };
+    interpreter_providers.extend(args.interpreter_provider);
+
+    let packaging_settings = gng_build::handler::PackagingSettings {
+        output_directory: current_dir.join(args.output_dir),
+        existing_packets: args.existing_packets,
+        explain: args.explain_packaging,
+        unclaimed_files: args.unclaimed_files,
+        ambiguous_files: args.ambiguous_files,
+        staging_links: args.staging_links,
+        interpreter_providers,
+        unresolved_interpreters: args.unresolved_interpreters,
+        passwd_file: args.passwd.map(|p| current_dir.join(p)),
+        group_file: args.group.map(|g| current_dir.join(g)),
+        sbom_formats: if args.no_sbom { Vec::new() } else { args.sbom },
+        sbom_timestamp: sbom_timestamp()?,
+    };
+
+    gng_build::handler::run(&mut case_officer, &packaging_settings)
 }
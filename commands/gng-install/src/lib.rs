@@ -0,0 +1,691 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! `gng-install` functionality
+
+// Setup warnings/errors:
+#![forbid(unsafe_code)]
+#![deny(
+    bare_trait_objects,
+    unused_doc_comments,
+    unused_import_braces,
+    missing_docs
+)]
+// Clippy:
+#![warn(clippy::all, clippy::nursery, clippy::pedantic)]
+#![allow(clippy::module_name_repetitions, clippy::let_unit_value)]
+
+use std::path::{Path, PathBuf};
+
+use eyre::{eyre, Result, WrapErr};
+
+use gng_core::{Name, Version};
+use gng_packet_db::resolver::ResolvedPacket;
+use gng_packet_db::{BinaryPacketDefinition, RepositorySet};
+use gng_packet_io::{ContentType, PacketReader};
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// The directory holding the packet metadata, relative to `usr`
+pub const METADATA_DIRECTORY: &str = ".gng";
+
+fn full_name(packet: &BinaryPacketDefinition) -> String {
+    packet.facet_name().map_or_else(
+        || packet.name.to_string(),
+        |f| format!("{}:{}", packet.name, f),
+    )
+}
+
+/// The path of `path` from a packet in the installed system
+fn system_path(path: &Path) -> PathBuf {
+    Path::new("/usr").join(path)
+}
+
+// ----------------------------------------------------------------------
+// - Installed:
+// ----------------------------------------------------------------------
+
+/// The packets installed in a `usr` directory
+#[derive(Default)]
+pub struct Installed {
+    packets: std::collections::BTreeMap<(Name, Option<Name>), Version>,
+    files: std::collections::BTreeMap<PathBuf, String>,
+}
+
+impl Installed {
+    /// Read the metadata in `usr_directory`
+    ///
+    /// # Errors
+    /// Errors out if the metadata can not be read.
+    pub fn read(usr_directory: &Path) -> Result<Self> {
+        let mut installed = Self::default();
+
+        let metadata_directory = usr_directory.join(METADATA_DIRECTORY);
+        if !metadata_directory.exists() {
+            return Ok(installed);
+        }
+
+        for entry in std::fs::read_dir(&metadata_directory).wrap_err(eyre!(
+            "Failed to read metadata directory \"{}\".",
+            metadata_directory.to_string_lossy()
+        ))? {
+            let path = entry.wrap_err("Failed to read metadata directory.")?.path();
+            if path.extension() != Some(std::ffi::OsStr::new("meta")) {
+                continue;
+            }
+
+            let packet: BinaryPacketDefinition = serde_json::from_slice(
+                &std::fs::read(&path)
+                    .wrap_err(eyre!("Failed to read \"{}\".", path.to_string_lossy()))?,
+            )
+            .wrap_err(eyre!("Failed to parse \"{}\".", path.to_string_lossy()))?;
+
+            let files_path = path.with_extension("files");
+            if files_path.exists() {
+                let files = std::fs::read_to_string(&files_path).wrap_err(eyre!(
+                    "Failed to read \"{}\".",
+                    files_path.to_string_lossy()
+                ))?;
+                for f in files.lines().filter(|l| !l.is_empty()) {
+                    installed.files.insert(PathBuf::from(f), full_name(&packet));
+                }
+            }
+
+            installed
+                .packets
+                .insert((packet.name.clone(), packet.facet_name()), packet.version);
+        }
+        Ok(installed)
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Installation:
+// ----------------------------------------------------------------------
+
+/// A packet to install
+pub struct Installation {
+    packet: ResolvedPacket,
+    packet_file: PathBuf,
+    /// Files and links, relative to `usr`
+    files: Vec<PathBuf>,
+    /// Directories, relative to `usr`
+    directories: Vec<PathBuf>,
+}
+
+impl Installation {
+    /// Prepare to install `packet`, taken from `repositories`
+    ///
+    /// # Errors
+    /// Errors out if the repository of the packet is not in `repositories`
+    /// or if the packet file is not available or can not be read.
+    pub fn new(packet: ResolvedPacket, repositories: &RepositorySet) -> Result<Self> {
+        let packet_file = repositories
+            .get(&packet.repository_name)
+            .ok_or_else(|| eyre!("Unknown repository \"{}\".", packet.repository_name))?
+            .repository()
+            .packet_file(&packet.file)?;
+
+        let (_, contents) = PacketReader::new(&packet_file).contents().wrap_err(eyre!(
+            "Failed to read contents of \"{}\".",
+            packet_file.to_string_lossy()
+        ))?;
+
+        let mut files = Vec::new();
+        let mut directories = Vec::new();
+        for c in contents {
+            let path = c
+                .path
+                .components()
+                .filter(|c| matches!(c, std::path::Component::Normal(_)))
+                .collect::<PathBuf>();
+            if path.components().next().is_none() {
+                continue;
+            }
+            if matches!(c.content_type, ContentType::Directory {}) {
+                directories.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+
+        Ok(Self {
+            packet,
+            packet_file,
+            files,
+            directories,
+        })
+    }
+
+    /// The packet name including the facet name (if any)
+    #[must_use]
+    pub fn name(&self) -> String {
+        self.packet.full_name()
+    }
+
+    /// The packet to install
+    #[must_use]
+    pub const fn packet(&self) -> &ResolvedPacket {
+        &self.packet
+    }
+
+    /// The files installed into the system, without packet metadata
+    fn system_files(&self) -> impl Iterator<Item = &PathBuf> {
+        self.files
+            .iter()
+            .filter(|f| !f.starts_with(METADATA_DIRECTORY))
+    }
+
+    /// The directories needed in the installed system, without packet metadata
+    ///
+    /// These are the directories in the packet as well as all parent
+    /// directories of its files and directories.
+    fn system_directories(&self) -> std::collections::BTreeSet<PathBuf> {
+        let file_directories = self.files.iter().filter_map(|f| f.parent());
+        self.directories
+            .iter()
+            .map(PathBuf::as_path)
+            .chain(file_directories)
+            .flat_map(Path::ancestors)
+            .filter(|d| d.components().next().is_some() && !d.starts_with(METADATA_DIRECTORY))
+            .map(Path::to_path_buf)
+            .collect()
+    }
+
+    /// The directories `install` will create in `usr_directory`
+    fn missing_directories(&self, usr_directory: &Path) -> Vec<PathBuf> {
+        self.system_directories()
+            .into_iter()
+            .map(|d| usr_directory.join(d))
+            .filter(|d| d.symlink_metadata().is_err())
+            .collect()
+    }
+
+    /// The list of installed files in the metadata directory, relative to
+    /// `usr`
+    ///
+    /// This is named like the metadata file written by `PacketReader::extract`.
+    fn files_list(&self) -> PathBuf {
+        let definition = &self.packet.definition;
+        PathBuf::from(METADATA_DIRECTORY).join(format!(
+            "{}.files",
+            definition.name.combine(&definition.facet_name())
+        ))
+    }
+
+    fn install(&self, root_directory: &Path) -> Result<()> {
+        PacketReader::new(&self.packet_file)
+            .extract(root_directory)
+            .wrap_err(eyre!("Failed to extract \"{}\".", self.name()))?;
+
+        let files_path = root_directory.join("usr").join(self.files_list());
+        let files = self
+            .system_files()
+            .map(|f| system_path(f).to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        gng_packet_db::files::write_atomically(&files_path, files.as_bytes())
+    }
+
+    /// Remove all files `install` might have created
+    fn uninstall(&self, root_directory: &Path) {
+        let usr_directory = root_directory.join("usr");
+        for f in self.files.iter().chain(std::iter::once(&self.files_list())) {
+            let path = usr_directory.join(f);
+            if let Err(e) = std::fs::remove_file(&path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    tracing::warn!("Failed to remove \"{}\": {}", path.to_string_lossy(), e);
+                }
+            }
+        }
+    }
+}
+
+/// Find everything that prevents installing `installations`
+#[must_use]
+pub fn conflicts(
+    usr_directory: &Path,
+    installed: &Installed,
+    installations: &[Installation],
+) -> Vec<String> {
+    let mut conflicts = Vec::new();
+    // Path to the claiming packet and whether the path is a directory:
+    let mut claimed = std::collections::BTreeMap::<PathBuf, (String, bool)>::new();
+
+    for i in installations {
+        let definition = &i.packet.definition;
+        if let Some(version) = installed
+            .packets
+            .get(&(definition.name.clone(), definition.facet_name()))
+        {
+            conflicts.push(format!(
+                "\"{}\" is already installed in version {}, can not install version {}.",
+                i.name(),
+                version,
+                definition.version
+            ));
+            continue;
+        }
+
+        for d in i.system_directories() {
+            let path = system_path(&d);
+            let existing = usr_directory.join(&d);
+            if let Some(owner) = installed.files.get(&path) {
+                conflicts.push(format!(
+                    "\"{}\" is a directory in \"{}\" but a file in \"{}\".",
+                    path.to_string_lossy(),
+                    i.name(),
+                    owner
+                ));
+            } else if let Some((owner, false)) = claimed.get(&d) {
+                conflicts.push(format!(
+                    "\"{}\" is a directory in \"{}\" but a file in \"{}\".",
+                    path.to_string_lossy(),
+                    i.name(),
+                    owner
+                ));
+            } else if existing.symlink_metadata().is_ok() && !existing.is_dir() {
+                conflicts.push(format!(
+                    "\"{}\" of \"{}\" already exists and is not a directory.",
+                    path.to_string_lossy(),
+                    i.name()
+                ));
+            } else {
+                claimed.entry(d).or_insert_with(|| (i.name(), true));
+            }
+        }
+
+        for f in i.system_files() {
+            let path = system_path(f);
+            if let Some(owner) = installed.files.get(&path) {
+                conflicts.push(format!(
+                    "\"{}\" of \"{}\" is already installed by \"{}\".",
+                    path.to_string_lossy(),
+                    i.name(),
+                    owner
+                ));
+            } else if let Some((owner, is_directory)) = claimed.get(f) {
+                conflicts.push(if *is_directory {
+                    format!(
+                        "\"{}\" is a directory in \"{}\" but a file in \"{}\".",
+                        path.to_string_lossy(),
+                        owner,
+                        i.name()
+                    )
+                } else {
+                    format!(
+                        "\"{}\" is part of \"{}\" and \"{}\".",
+                        path.to_string_lossy(),
+                        owner,
+                        i.name()
+                    )
+                });
+            } else if usr_directory.join(f).symlink_metadata().is_ok() {
+                conflicts.push(format!(
+                    "\"{}\" of \"{}\" already exists.",
+                    path.to_string_lossy(),
+                    i.name()
+                ));
+            } else {
+                claimed.insert(f.clone(), (i.name(), false));
+            }
+        }
+    }
+
+    conflicts
+}
+
+/// Remove the empty ones of `directories`, deepest first
+fn remove_directories(mut directories: Vec<PathBuf>) {
+    directories.sort();
+    directories.dedup();
+    for d in directories.iter().rev() {
+        if let Err(e) = std::fs::remove_dir(d) {
+            tracing::debug!("Failed to remove \"{}\": {}", d.to_string_lossy(), e);
+        }
+    }
+}
+
+/// Prepare to install those of `packets` that are not installed in
+/// `root_directory` in the same version yet
+///
+/// # Errors
+/// Errors out if the installed packets or the packet files can not be read.
+pub fn installations(
+    root_directory: &Path,
+    packets: Vec<ResolvedPacket>,
+    repositories: &RepositorySet,
+) -> Result<Vec<Installation>> {
+    let installed = Installed::read(&root_directory.join("usr"))?;
+
+    packets
+        .into_iter()
+        .filter(|p| {
+            let key = (p.definition.name.clone(), p.definition.facet_name());
+            installed.packets.get(&key) != Some(&p.definition.version)
+        })
+        .map(|p| Installation::new(p, repositories))
+        .collect()
+}
+
+/// Install `installations` into `root_directory`
+///
+/// The metadata directory is locked while installing, so that concurrent
+/// installations into the same `root_directory` wait for each other.
+/// Nothing is changed if any `conflicts` are found. If installing a packet
+/// fails, all packets installed so far are removed again.
+///
+/// # Errors
+/// Errors out if there are conflicts or if installing a packet failed.
+pub fn install(root_directory: &Path, installations: &[Installation]) -> Result<()> {
+    let usr_directory = root_directory.join("usr");
+    let metadata_directory = usr_directory.join(METADATA_DIRECTORY);
+    let mut created_directories = [usr_directory.clone(), metadata_directory.clone()]
+        .into_iter()
+        .filter(|d| d.symlink_metadata().is_err())
+        .collect::<Vec<_>>();
+    let _lock = gng_packet_db::files::lock_directory(&metadata_directory)?;
+
+    let installed = Installed::read(&usr_directory)?;
+    let conflicts = conflicts(&usr_directory, &installed, installations);
+    if !conflicts.is_empty() {
+        remove_directories(created_directories);
+        return Err(eyre!(
+            "Nothing was installed due to conflicts:\n    {}",
+            conflicts.join("\n    ")
+        ));
+    }
+
+    for (count, i) in installations.iter().enumerate() {
+        tracing::info!(
+            "Installing \"{}\" from \"{}\"",
+            i.name(),
+            i.packet.repository_name
+        );
+        created_directories.extend(i.missing_directories(&usr_directory));
+        if let Err(e) = i.install(root_directory) {
+            for i in installations[..=count].iter().rev() {
+                i.uninstall(root_directory);
+            }
+            remove_directories(created_directories);
+            return Err(e.wrap_err("Installation failed, all new packets were removed again."));
+        }
+    }
+
+    Ok(())
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gng_core::Names;
+    use gng_packet_db::resolver::Request;
+    use gng_packet_db::{Repository, Trust};
+    use gng_packet_io::{BinaryFacet, ExistingPacketPolicy, PacketPolicy, PacketWriter};
+
+    /// A packet `name` in `version` containing `files` in `directory`
+    ///
+    /// The parent directories of `files` are part of the packet, too.
+    fn write_packet(directory: &Path, name: &str, version: &str, files: &[&str]) -> PathBuf {
+        let definition = BinaryPacketDefinition {
+            name: Name::try_from(name).unwrap(),
+            version: Version::try_from(version).unwrap(),
+            description: String::new(),
+            url: String::new(),
+            bug_url: String::new(),
+            dependencies: Names::default(),
+            needed_libraries: Vec::new(),
+            facet: BinaryFacet::Main,
+        };
+        let mut writer = PacketWriter::new(
+            directory,
+            &definition,
+            PacketPolicy::MustHaveContents,
+            ExistingPacketPolicy::Overwrite,
+        )
+        .unwrap();
+
+        let directories = files
+            .iter()
+            .flat_map(|f| Path::new(f).ancestors().skip(1))
+            .filter(|d| d.components().next().is_some())
+            .collect::<std::collections::BTreeSet<_>>();
+        for d in directories {
+            writer.add_directory(d, 0o755, 0, 0).unwrap();
+        }
+        for f in files {
+            writer
+                .add_buffer(Path::new(f), f.as_bytes(), 0o644, 0, 0)
+                .unwrap();
+        }
+        writer.finish().unwrap().unwrap()
+    }
+
+    /// A local repository in `directory` holding `packets`
+    fn repositories(directory: &Path, packets: &[(&str, &str, &[&str])]) -> RepositorySet {
+        let mut repository =
+            Repository::from_local_directory(directory, true, &Trust::none()).unwrap();
+        let mut update = repository.create_transaction();
+        for (name, version, files) in packets {
+            update
+                .add_packet_file(&write_packet(directory, name, version, files))
+                .unwrap();
+        }
+        repository.apply(update).unwrap();
+
+        let mut repositories = RepositorySet::new();
+        repositories.add("test", 0, repository).unwrap();
+        repositories
+    }
+
+    /// The temporary root directory and the repositories to install from
+    fn setup(packets: &[(&str, &str, &[&str])]) -> (tempfile::TempDir, PathBuf, RepositorySet) {
+        let directory = tempfile::tempdir().unwrap();
+        let repository_directory = directory.path().join("repository");
+        let root_directory = directory.path().join("root");
+        std::fs::create_dir(&repository_directory).unwrap();
+        std::fs::create_dir(&root_directory).unwrap();
+
+        let repositories = repositories(&repository_directory, packets);
+        (directory, root_directory, repositories)
+    }
+
+    fn prepare(
+        root_directory: &Path,
+        repositories: &RepositorySet,
+        requests: &[&str],
+    ) -> Vec<Installation> {
+        let requests = requests
+            .iter()
+            .map(|r| r.parse::<Request>().unwrap())
+            .collect::<Vec<_>>();
+        let resolution = gng_packet_db::resolver::resolve(&requests, repositories).unwrap();
+        installations(root_directory, resolution.packets, repositories).unwrap()
+    }
+
+    fn install_requests(
+        root_directory: &Path,
+        repositories: &RepositorySet,
+        requests: &[&str],
+    ) -> Result<()> {
+        install(
+            root_directory,
+            &prepare(root_directory, repositories, requests),
+        )
+    }
+
+    /// All paths below `directory`, relative to it
+    fn tree(directory: &Path) -> Vec<String> {
+        let mut result = Vec::new();
+        if let Ok(entries) = std::fs::read_dir(directory) {
+            for entry in entries {
+                let path = entry.unwrap().path();
+                let name = path.file_name().unwrap().to_string_lossy().to_string();
+                result.push(name.clone());
+                result.extend(tree(&path).into_iter().map(|p| format!("{}/{}", name, p)));
+            }
+        }
+        result.sort();
+        result
+    }
+
+    #[test]
+    fn install_packets() {
+        let (_directory, root, repositories) = setup(&[
+            ("foo", "1.0", &["bin/foo", "share/foo/data"]),
+            ("bar", "1.0", &["bin/bar"]),
+        ]);
+
+        install_requests(&root, &repositories, &["foo", "bar"]).unwrap();
+        assert_eq!(
+            tree(&root.join("usr")),
+            vec![
+                ".gng",
+                ".gng/bar.files",
+                ".gng/bar.meta",
+                ".gng/foo.files",
+                ".gng/foo.meta",
+                "bin",
+                "bin/bar",
+                "bin/foo",
+                "share",
+                "share/foo",
+                "share/foo/data",
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("usr/.gng/foo.files")).unwrap(),
+            "/usr/bin/foo\n/usr/share/foo/data"
+        );
+
+        let installed = Installed::read(&root.join("usr")).unwrap();
+        assert_eq!(installed.packets.len(), 2);
+        assert_eq!(
+            installed.files.get(Path::new("/usr/share/foo/data")),
+            Some(&"foo".to_string())
+        );
+
+        // Installing the same version again is a no-op:
+        assert!(prepare(&root, &repositories, &["foo"]).is_empty());
+    }
+
+    #[test]
+    fn install_waits_for_lock() {
+        let (_directory, root, repositories) = setup(&[("foo", "1.0", &["bin/foo"])]);
+        let installations = prepare(&root, &repositories, &["foo"]);
+
+        let lock = gng_packet_db::files::lock_directory(&root.join("usr").join(METADATA_DIRECTORY))
+            .unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let thread_root = root.clone();
+        let installer = std::thread::spawn(move || {
+            let result = install(&thread_root, &installations);
+            sender.send(()).unwrap();
+            result
+        });
+
+        assert!(receiver
+            .recv_timeout(std::time::Duration::from_millis(200))
+            .is_err());
+        assert!(!root.join("usr/bin/foo").exists());
+
+        drop(lock);
+        installer.join().unwrap().unwrap();
+        assert!(root.join("usr/bin/foo").is_file());
+    }
+
+    #[test]
+    fn install_already_installed_packet() {
+        let (_directory, root, repositories) =
+            setup(&[("foo", "1.0", &["bin/foo"]), ("foo", "2.0", &["bin/foo"])]);
+
+        install_requests(&root, &repositories, &["foo<2.0"]).unwrap();
+        let error = install_requests(&root, &repositories, &["foo"])
+            .unwrap_err()
+            .to_string();
+        assert!(error
+            .contains("\"foo\" is already installed in version 1.0, can not install version 2.0."));
+        assert!(std::fs::read_to_string(root.join("usr/.gng/foo.meta"))
+            .unwrap()
+            .contains("\"1.0\""));
+    }
+
+    #[test]
+    fn install_clashing_packets() {
+        let (_directory, root, repositories) = setup(&[
+            ("foo", "1.0", &["bin/tool", "lib/x"]),
+            ("bar", "1.0", &["bin/tool"]),
+            ("baz", "1.0", &["lib/x/y"]),
+        ]);
+
+        let error = install_requests(&root, &repositories, &["foo", "bar"])
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("\"/usr/bin/tool\" is part of \"bar\" and \"foo\"."));
+
+        let error = install_requests(&root, &repositories, &["foo", "baz"])
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("\"/usr/lib/x\" is a directory in \"baz\" but a file in \"foo\"."));
+
+        // Nothing was changed:
+        assert_eq!(tree(&root), Vec::<String>::new());
+
+        // Clashes with installed packets:
+        install_requests(&root, &repositories, &["foo"]).unwrap();
+        let error = install_requests(&root, &repositories, &["bar"])
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("\"/usr/bin/tool\" of \"bar\" is already installed by \"foo\"."));
+        let error = install_requests(&root, &repositories, &["baz"])
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("\"/usr/lib/x\" is a directory in \"baz\" but a file in \"foo\"."));
+    }
+
+    #[test]
+    fn install_over_existing_files() {
+        let (_directory, root, repositories) = setup(&[
+            ("foo", "1.0", &["bin/foo"]),
+            ("bar", "1.0", &["share/bar/data"]),
+        ]);
+        std::fs::create_dir_all(root.join("usr/bin")).unwrap();
+        std::fs::write(root.join("usr/bin/foo"), b"existing").unwrap();
+        std::fs::write(root.join("usr/share"), b"existing").unwrap();
+
+        let error = install_requests(&root, &repositories, &["foo", "bar"])
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("\"/usr/bin/foo\" of \"foo\" already exists."));
+        assert!(error.contains("\"/usr/share\" of \"bar\" already exists and is not a directory."));
+        assert_eq!(tree(&root.join("usr")), vec!["bin", "bin/foo", "share"]);
+    }
+
+    #[test]
+    fn install_rolls_back_on_failure() {
+        let (_directory, root, repositories) = setup(&[
+            ("foo", "1.0", &["bin/foo", "share/foo/data"]),
+            ("bar", "1.0", &["bin/bar", "lib/bar/data"]),
+        ]);
+        std::fs::create_dir_all(root.join("usr/bin")).unwrap();
+
+        let installations = prepare(&root, &repositories, &["foo", "bar"]);
+        let broken = &installations.last().unwrap().packet_file;
+        let data = std::fs::read(broken).unwrap();
+        std::fs::write(broken, &data[..data.len() / 2]).unwrap();
+
+        let error = install(&root, &installations).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Installation failed, all new packets were removed again."
+        );
+        assert_eq!(tree(&root.join("usr")), vec!["bin"]);
+    }
+}
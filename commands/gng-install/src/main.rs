@@ -0,0 +1,205 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! The `gng-install` binary.
+
+// Setup warnings/errors:
+#![forbid(unsafe_code)]
+#![deny(
+    bare_trait_objects,
+    unused_doc_comments,
+    unused_import_braces,
+    missing_docs
+)]
+// Clippy:
+#![warn(clippy::all, clippy::nursery, clippy::pedantic)]
+#![allow(clippy::module_name_repetitions, clippy::let_unit_value)]
+
+use std::path::PathBuf;
+
+use clap::Parser;
+use eyre::{eyre, Result, WrapErr};
+
+use gng_packet_db::resolver::Request;
+use gng_packet_db::signature::PublicKey;
+use gng_packet_db::{Repository, RepositorySet, Trust};
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// The location of a repository: `[NAME=]URL_OR_DIRECTORY`
+#[derive(Clone, Debug)]
+struct Location {
+    name: String,
+    location: String,
+}
+
+impl std::str::FromStr for Location {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, location) = s
+            .split_once('=')
+            .filter(|(n, _)| !n.is_empty() && !n.contains(|c| c == '/' || c == ':'))
+            .unwrap_or((s, s));
+        if location.is_empty() {
+            return Err(eyre!("No repository given in \"{}\".", s));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            location: location.to_string(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Parser)]
+#[clap(
+    name = "gng-install",
+    about = "Install GnG packets into a root directory."
+)]
+struct Args {
+    /// the root directory to install into
+    #[clap(long, value_name = "DIR")]
+    root: PathBuf,
+
+    /// a repository to take packets from, repositories given first win
+    #[clap(long = "repo", value_name = "[NAME=]URL_OR_DIR", required = true)]
+    repositories: Vec<Location>,
+
+    /// verify the repository data with this key, refusing data older than
+    /// what was seen before
+    #[clap(long = "trusted-key", value_name = "KEY")]
+    trusted_keys: Vec<PublicKey>,
+
+    /// the directory to remember the last verified repository data in,
+    /// defaults to "gng/trust" in the state directory of the user
+    #[clap(long, value_name = "DIR")]
+    state: Option<PathBuf>,
+
+    /// the directory to cache data of remote repositories in, defaults to
+    /// "gng" in the cache directory of the user
+    #[clap(long, value_name = "DIR")]
+    cache: Option<PathBuf>,
+
+    /// do not download anything, use cached data only
+    #[clap(long)]
+    offline: bool,
+
+    /// only show what would be installed
+    #[clap(long)]
+    dry_run: bool,
+
+    /// the packets to install
+    #[clap(value_name = "NAME[:FACET][REQUIREMENT]", required = true)]
+    packets: Vec<Request>,
+
+    #[clap(flatten)]
+    logging: gng_core::log::LogArgs,
+}
+
+// ----------------------------------------------------------------------
+// - Repositories:
+// ----------------------------------------------------------------------
+
+/// The cache directory of the user: `$XDG_CACHE_HOME/gng` or `~/.cache/gng`
+///
+/// Never a shared directory like `/tmp`: Other users could plant data there.
+fn default_cache_directory() -> Result<PathBuf> {
+    gng_core::user_directory("XDG_CACHE_HOME", ".cache")
+        .map(|c| c.join("gng"))
+        .ok_or_else(|| eyre!("No cache directory found, please pass \"--cache\"."))
+}
+
+/// The directory to remember verified repository data in
+///
+/// Never a shared directory: Others could pretend older data was seen. Never
+/// inside the root directory either: That is an image to ship, not a place
+/// for the trust state of this host.
+fn default_state_directory() -> Result<PathBuf> {
+    gng_core::user_directory("XDG_STATE_HOME", ".local/state")
+        .map(|s| s.join("gng").join("trust"))
+        .ok_or_else(|| eyre!("No state directory found, please pass \"--state\"."))
+}
+
+fn open_repository(args: &Args, location: &Location, trust: &Trust) -> Result<Repository> {
+    match url::Url::parse(&location.location) {
+        Ok(url) if url.scheme().len() > 1 => {
+            let cache = args
+                .cache
+                .clone()
+                .map_or_else(default_cache_directory, Ok)?;
+            Repository::from_url(&url, &cache, args.offline, trust)
+        }
+        _ => {
+            let directory = std::fs::canonicalize(&location.location).wrap_err(eyre!(
+                "Repository directory \"{}\" not found.",
+                location.location
+            ))?;
+            Repository::from_local_directory(&directory, false, trust)
+        }
+    }
+    .wrap_err(eyre!("Failed to open repository \"{}\".", location.name))
+}
+
+fn repository_set(args: &Args) -> Result<RepositorySet> {
+    let trust = if args.trusted_keys.is_empty() {
+        Trust::none()
+    } else {
+        let state = args
+            .state
+            .clone()
+            .map_or_else(default_state_directory, Ok)?;
+        Trust::new(args.trusted_keys.clone(), &state)
+    };
+
+    let mut repositories = RepositorySet::new();
+    for (index, location) in args.repositories.iter().enumerate() {
+        let priority = -i32::try_from(index).wrap_err("Too many repositories.")?;
+        repositories.add(
+            &location.name,
+            priority,
+            open_repository(args, location, &trust)?,
+        )?;
+    }
+    Ok(repositories)
+}
+
+// ----------------------------------------------------------------------
+// - Install:
+// ----------------------------------------------------------------------
+
+fn install(args: &Args) -> Result<()> {
+    let repositories = repository_set(args)?;
+    let resolution = gng_packet_db::resolver::resolve(&args.packets, &repositories)?;
+
+    if args.dry_run {
+        print!("{}", resolution);
+        return Ok(());
+    }
+
+    let installations = gng_install::installations(&args.root, resolution.packets, &repositories)?;
+    gng_install::install(&args.root, &installations)?;
+
+    for i in &installations {
+        println!("{} {}", i.name(), i.packet().definition.version);
+    }
+    Ok(())
+}
+
+// ----------------------------------------------------------------------
+// - Entry Point:
+// ----------------------------------------------------------------------
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let _app_span = args
+        .logging
+        .setup_logging()
+        .wrap_err("Failed to set up logging.")?;
+
+    tracing::debug!("Command line arguments: {:#?}", args);
+
+    install(&args)
+}
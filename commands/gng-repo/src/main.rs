@@ -15,76 +15,1003 @@
 #![warn(clippy::all, clippy::nursery, clippy::pedantic)]
 #![allow(clippy::module_name_repetitions, clippy::let_unit_value)]
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use clap::{builder::ValueParser, Parser};
-use eyre::{Result, WrapErr};
+use clap::{Parser, Subcommand};
+use eyre::{eyre, Result, WrapErr};
+use serde_json::json;
 
+use gng_core::Name;
+use gng_packet_db::index::Format;
+use gng_packet_db::resolver::Request;
+use gng_packet_db::signature::{PublicKey, SigningKey};
+use gng_packet_db::{
+    BinaryPacketDefinition, DeletedGarbage, Repository, ReverseDependencies, Trust,
+};
+
+// ----------------------------------------------------------------------
 // - Helper:
 // ----------------------------------------------------------------------
 
+/// The largest number of days a signature can stay valid for, in seconds
+/// this still fits into a `u64`
+const MAX_VALID_DAYS: u64 = u64::MAX / (24 * 60 * 60);
+
+#[derive(Clone, Debug, Subcommand)]
+enum Command {
+    /// Create a new, empty repository
+    Init {
+        /// the format to store the repository data in: "json" or "binary"
+        #[clap(long, value_name = "FORMAT", default_value = "json")]
+        format: Format,
+    },
+    /// Add packet files to the repository
+    Add {
+        /// Clear all existing data from the repository first
+        #[clap(long)]
+        clear: bool,
+
+        /// the packets to add to the repository
+        #[clap(value_name = "GNG_FILE", required = true)]
+        packets: Vec<PathBuf>,
+    },
+    /// Remove packets (or single facets or versions of packets) from the
+    /// repository
+    Remove {
+        /// the packets to remove
+        #[clap(value_name = "NAME[:FACET][REQUIREMENT]", required = true)]
+        packets: Vec<Request>,
+    },
+    /// Pin packet versions, so that they are kept independent of the
+    /// retention policy
+    Pin {
+        /// the packet versions to pin
+        #[clap(value_name = "NAME[:FACET]=VERSION", required = true)]
+        packets: Vec<Request>,
+    },
+    /// Unpin packet versions
+    Unpin {
+        /// the packet versions to unpin
+        #[clap(value_name = "NAME[:FACET]=VERSION", required = true)]
+        packets: Vec<Request>,
+    },
+    /// Show or change how many versions of each packet to keep
+    Retention {
+        /// the new retention policy: "all" or a number of versions
+        #[clap(value_name = "POLICY")]
+        retention: Option<gng_packet_db::Retention>,
+    },
+    /// List all packets in the repository
+    List,
+    /// Show the details of a packet
+    Show {
+        /// the packet to show
+        #[clap(value_name = "NAME[:FACET][REQUIREMENT]")]
+        packet: Request,
+    },
+    /// Search packets by name and description
+    Search {
+        /// the regular expression to search for
+        #[clap(value_name = "REGEX")]
+        pattern: regex::Regex,
+    },
+    /// Find the packets providing a file
+    Provides {
+        /// the file path or glob pattern to look for, patterns without a "/"
+        /// match file names only
+        #[clap(value_name = "PATH")]
+        path: String,
+    },
+    /// List the packets depending on a packet, in rebuild order
+    Rdeps {
+        /// only list packets depending directly on the packet
+        #[clap(long)]
+        direct: bool,
+        /// NAME is a file name, path or glob pattern: list the packets
+        /// depending on the packets providing matching files
+        #[clap(long, conflicts_with = "soname")]
+        file: bool,
+        /// NAME is a soname: list the packets linked against it, followed by
+        /// the packets depending on those
+        #[clap(long)]
+        soname: bool,
+        /// the packet (file or soname) to find the dependents of
+        #[clap(value_name = "NAME")]
+        name: String,
+    },
+    /// Verify that all packet files are present and match their hashes
+    Verify {
+        /// Also verify the signature of the repository data with this key
+        #[clap(long = "trusted-key", value_name = "KEY")]
+        trusted_keys: Vec<PublicKey>,
+        /// the directory to remember the last verified repository data in,
+        /// defaults to "gng/trust" in the state directory of the user
+        #[clap(long, value_name = "DIR")]
+        state: Option<PathBuf>,
+    },
+    /// Check the repository for consistency
+    ///
+    /// Fails if errors were found, warnings are only reported.
+    Check {
+        /// check for downgrades against this snapshot instead of the current
+        /// one
+        #[clap(long, value_name = "SNAPSHOT")]
+        against: Option<String>,
+        /// treat warnings as errors
+        #[clap(long)]
+        strict: bool,
+    },
+    /// Remove unreferenced packet files as well as missing and superseded
+    /// packets
+    Gc {
+        /// only report what would be removed
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Record the file lists of packets added before file lists were
+    /// recorded, reading them from the packet files
+    Backfill,
+    /// Show or change the format the repository data is stored in
+    Format {
+        /// the new format: "json" or "binary"
+        #[clap(value_name = "FORMAT")]
+        format: Option<Format>,
+    },
+    /// Export the repository data as JSON lines
+    Export {
+        /// the file to write to (default: standard output)
+        #[clap(long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Manage immutable snapshots of the repository
+    Snapshot {
+        #[clap(subcommand)]
+        command: SnapshotCommand,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum SnapshotCommand {
+    /// Create a new snapshot and make it current
+    Create {
+        /// the name of the snapshot (default: today's date)
+        #[clap(value_name = "NAME")]
+        name: Option<String>,
+    },
+    /// List all snapshots
+    List,
+    /// Show the packets added and removed between two snapshots
+    Diff {
+        /// the snapshot to compare from
+        #[clap(value_name = "FROM")]
+        from: String,
+        /// the snapshot to compare to (default: the repository itself)
+        #[clap(value_name = "TO")]
+        to: Option<String>,
+    },
+    /// Reset the repository to a snapshot and make that snapshot current
+    Rollback {
+        /// the snapshot to roll back to
+        #[clap(value_name = "NAME")]
+        name: String,
+    },
+}
+
 #[derive(Clone, Debug, Parser)]
 #[clap(name = "gng-repo", about = "A repository manager for GnG.")]
 struct Args {
     /// configuration file to read
-    #[clap(long, value_parser(ValueParser::os_string()), value_name = "FILE")]
+    #[clap(long, value_name = "FILE")]
     config: Option<PathBuf>,
 
-    /// Start from scratch: It is OK if there is no `repository.json` file
-    #[clap(long)]
-    from_scratch: bool,
+    /// the repository to use
+    #[clap(long, value_name = "REPO_DIR", default_value = ".")]
+    repository: PathBuf,
 
-    /// Clear all existing data from `repository.json`
+    /// Print results as JSON
     #[clap(long)]
-    clear: bool,
+    json: bool,
 
-    /// the repository to use
-    #[clap(value_parser(ValueParser::os_string()), value_name = "REPO_DIR")]
-    repository_directory: PathBuf,
+    /// Sign the repository data with the key in FILE when changing it
+    #[clap(long, value_name = "FILE")]
+    signing_key: Option<PathBuf>,
+
+    /// Number of days the signature of the repository data stays valid
+    ///
+    /// Signatures of snapshots do not expire, those of the pointer to the
+    /// current snapshot do.
+    #[clap(
+        long,
+        value_name = "DAYS",
+        default_value = "30",
+        requires = "signing_key",
+        value_parser = clap::value_parser!(u64).range(..=MAX_VALID_DAYS)
+    )]
+    valid_days: u64,
 
-    /// the packets to add to the repository
-    #[clap(value_parser(ValueParser::os_string()), value_name = "GNG_FILE")]
-    packets: Vec<PathBuf>,
+    #[clap(subcommand)]
+    command: Command,
 
     #[clap(flatten)]
     logging: gng_core::log::LogArgs,
 }
 
+fn full_name(packet: &BinaryPacketDefinition) -> String {
+    packet.facet_name().map_or_else(
+        || packet.name.to_string(),
+        |f| format!("{}:{}", packet.name, f),
+    )
+}
+
+fn is_pinned(repo: &Repository, packet: &BinaryPacketDefinition) -> Result<bool> {
+    repo.is_pinned(&packet.name, &packet.facet_name(), &packet.version)
+}
+
+fn packet_summary(
+    repo: &Repository,
+    packet: &BinaryPacketDefinition,
+    file: &Path,
+) -> Result<serde_json::Value> {
+    Ok(json!({
+        "name": packet.name,
+        "facet": packet.facet_name(),
+        "version": packet.version,
+        "description": packet.description,
+        "file": file,
+        "pinned": is_pinned(repo, packet)?,
+    }))
+}
+
+fn print_packets<'a>(
+    repo: &Repository,
+    packets: impl Iterator<Item = (&'a BinaryPacketDefinition, &'a Path)>,
+    as_json: bool,
+) -> Result<()> {
+    if as_json {
+        let packets = packets
+            .map(|(p, f)| packet_summary(repo, p, f))
+            .collect::<Result<Vec<_>>>()?;
+        println!("{}", serde_json::to_string_pretty(&packets)?);
+    } else {
+        for (p, f) in packets {
+            println!(
+                "{} {} {}{}",
+                full_name(p),
+                p.version,
+                f.to_string_lossy(),
+                if is_pinned(repo, p)? { " (pinned)" } else { "" }
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Find all packet versions matching `packets`
+fn matching_versions(
+    repo: &Repository,
+    packets: &[Request],
+) -> Result<Vec<(BinaryPacketDefinition, PathBuf)>> {
+    let mut result = Vec::new();
+    for p in packets {
+        let versions = repo.query_versions(&p.packet, &p.facet, &p.requirement)?;
+        if versions.is_empty() {
+            return Err(eyre!("Packet \"{}\" is not in the repository.", p));
+        }
+        result.extend(versions);
+    }
+    Ok(result)
+}
+
+fn open_repository(args: &Args) -> Result<Repository> {
+    Repository::from_local_directory(&args.repository, false, &Trust::none())
+}
+
+fn lock_repository(args: &Args) -> Result<gng_packet_db::repository::RepositoryLock> {
+    Repository::lock_local_directory(&args.repository)
+}
+
+fn signing_key(args: &Args) -> Result<Option<(SigningKey, std::time::Duration)>> {
+    args.signing_key
+        .as_ref()
+        .map(|key_file| {
+            Ok((
+                SigningKey::from_file(key_file)?,
+                std::time::Duration::from_secs(args.valid_days * 24 * 60 * 60),
+            ))
+        })
+        .transpose()
+}
+
+fn save_repository(args: &Args, repo: &mut Repository) -> Result<()> {
+    if let Some((key, validity)) = signing_key(args)? {
+        repo.save_signed_local_directory(&key, validity)
+    } else if args
+        .repository
+        .join(gng_packet_db::signature::SIGNATURE_FILE)
+        .exists()
+    {
+        // Saving would leave the old signature behind, which does not
+        // match the new repository data anymore:
+        Err(eyre!(
+            "The repository in \"{}\" is signed, please pass \"--signing-key\".",
+            args.repository.to_string_lossy()
+        ))
+    } else {
+        repo.save_local_directory()
+    }
+}
+
 // ----------------------------------------------------------------------
-// - Entry Point:
+// - Commands:
 // ----------------------------------------------------------------------
 
-/// Entry point of the `gng-build` binary.
-fn main() -> Result<()> {
-    let args = Args::parse();
+fn init(args: &Args, format: Format) -> Result<()> {
+    std::fs::create_dir_all(&args.repository).wrap_err(eyre!(
+        "Failed to create \"{}\".",
+        args.repository.to_string_lossy()
+    ))?;
+    let _lock = lock_repository(args)?;
 
-    let _app_span = args
-        .logging
-        .setup_logging()
-        .wrap_err("Failed to set up logging.")?;
+    if args.repository.join("repository.json").exists() {
+        return Err(eyre!(
+            "There already is a repository in \"{}\".",
+            args.repository.to_string_lossy()
+        ));
+    }
 
-    tracing::debug!("Command line arguments: {:#?}", args);
+    let mut repo = Repository::from_local_directory(&args.repository, true, &Trust::none())?;
+    let mut update = repo.create_transaction();
+    update.set_format(format);
+    repo.apply(update)?;
+    save_repository(args, &mut repo)?;
 
-    if args.packets.is_empty() {
-        tracing::warn!("No packets provided, nothing to do.");
-        return Ok(());
+    if args.json {
+        println!("{}", json!({ "repository": repo.url().as_str() }));
     }
+    Ok(())
+}
 
-    let mut repo = gng_packet_db::Repository::from_local_directory(
-        &args.repository_directory,
-        args.from_scratch,
-    )?;
+fn add(args: &Args, clear: bool, packets: &[PathBuf]) -> Result<()> {
+    let _lock = lock_repository(args)?;
+    let mut repo = open_repository(args)?;
 
     let mut update = repo.create_transaction();
-    if args.clear {
+    if clear {
         update.clear();
     }
-
-    for p in &args.packets {
+    for p in packets {
         update.add_packet_file(p)?;
     }
+    repo.apply(update)?;
+
+    save_repository(args, &mut repo)?;
+
+    if args.json {
+        println!("{}", json!({ "added": packets }));
+    }
+    Ok(())
+}
+
+fn remove(args: &Args, packets: &[Request]) -> Result<()> {
+    let _lock = lock_repository(args)?;
+    let mut repo = open_repository(args)?;
 
+    let removed = matching_versions(&repo, packets)?;
+
+    let mut update = repo.create_transaction();
+    for p in packets {
+        if p.facet.is_none() && p.requirement == gng_packet_db::VersionRequirement::any() {
+            update.remove(p.packet.clone());
+        } else {
+            update.remove_versions(p.packet.clone(), p.facet.clone(), &p.requirement);
+        }
+    }
     repo.apply(update)?;
 
-    repo.save_local_directory()
+    save_repository(args, &mut repo)?;
+
+    if args.json {
+        let removed = removed
+            .iter()
+            .map(|(p, f)| json!({ "name": full_name(p), "version": p.version, "file": f }))
+            .collect::<Vec<_>>();
+        println!("{}", json!({ "removed": removed }));
+    }
+    Ok(())
+}
+
+fn pin(args: &Args, packets: &[Request], do_pin: bool) -> Result<()> {
+    let _lock = lock_repository(args)?;
+    let mut repo = open_repository(args)?;
+
+    let versions = matching_versions(&repo, packets)?;
+
+    let mut update = repo.create_transaction();
+    for (p, _) in &versions {
+        if do_pin {
+            update.pin(p.name.clone(), p.facet_name(), p.version.clone());
+        } else {
+            update.unpin(p.name.clone(), p.facet_name(), p.version.clone());
+        }
+    }
+    repo.apply(update)?;
+
+    save_repository(args, &mut repo)?;
+
+    if args.json {
+        let versions = versions
+            .iter()
+            .map(|(p, _)| json!({ "name": full_name(p), "version": p.version }))
+            .collect::<Vec<_>>();
+        println!(
+            "{}",
+            json!({ if do_pin { "pinned" } else { "unpinned" }: versions })
+        );
+    }
+    Ok(())
+}
+
+fn retention(args: &Args, retention: Option<gng_packet_db::Retention>) -> Result<()> {
+    let _lock = lock_repository(args)?;
+    let mut repo = open_repository(args)?;
+
+    if let Some(retention) = retention {
+        let mut update = repo.create_transaction();
+        update.set_retention(retention);
+        repo.apply(update)?;
+
+        save_repository(args, &mut repo)?;
+    }
+
+    if args.json {
+        println!("{}", json!({ "retention": repo.retention().to_string() }));
+    } else {
+        println!("{}", repo.retention());
+    }
+    Ok(())
+}
+
+fn list(args: &Args) -> Result<()> {
+    let repo = open_repository(args)?;
+    print_packets(&repo, repo.entries()?, args.json)?;
+    Ok(())
+}
+
+fn show(args: &Args, packet: &Request) -> Result<()> {
+    let repo = open_repository(args)?;
+    let (definition, file) = repo
+        .query_version(&packet.packet, &packet.facet, &packet.requirement)?
+        .ok_or_else(|| eyre!("Packet \"{}\" not found.", packet))?;
+    let versions = repo
+        .query_versions(
+            &packet.packet,
+            &packet.facet,
+            &gng_packet_db::VersionRequirement::any(),
+        )?
+        .into_iter()
+        .map(|(p, _)| Ok((p.version.clone(), is_pinned(&repo, &p)?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    if args.json {
+        let mut value = serde_json::to_value(&definition)?;
+        value["file"] = json!(file);
+        value["pinned"] = json!(is_pinned(&repo, &definition)?);
+        value["versions"] = versions
+            .iter()
+            .map(|(v, pinned)| json!({ "version": v, "pinned": pinned }))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&value)?);
+    } else {
+        println!("Name:         {}", full_name(&definition));
+        println!("Version:      {}", definition.version);
+        println!("Description:  {}", definition.description);
+        println!("URL:          {}", definition.url);
+        println!("Bug URL:      {}", definition.bug_url);
+        println!("Dependencies: {}", definition.dependencies);
+        println!("File:         {}", file.to_string_lossy());
+        println!(
+            "Versions:     {}",
+            versions
+                .iter()
+                .map(|(v, pinned)| if *pinned {
+                    format!("{} (pinned)", v)
+                } else {
+                    v.to_string()
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn search(args: &Args, pattern: &regex::Regex) -> Result<()> {
+    let repo = open_repository(args)?;
+    print_packets(
+        &repo,
+        repo.entries()?
+            .filter(|(p, _)| pattern.is_match(&full_name(p)) || pattern.is_match(&p.description)),
+        args.json,
+    )?;
+    Ok(())
+}
+
+fn warn_about_missing_file_lists(repo: &Repository) -> Result<()> {
+    let count = repo.without_files()?.count();
+    if count > 0 {
+        tracing::warn!(
+            "{} packet(s) have no file list and are not searched, run \"gng-repo backfill\" to record them.",
+            count
+        );
+    }
+    Ok(())
+}
+
+fn provides(args: &Args, path: &str) -> Result<()> {
+    let repo = open_repository(args)?;
+    warn_about_missing_file_lists(&repo)?;
+    let found = repo.find_file(path)?;
+
+    if args.json {
+        let found = found
+            .iter()
+            .map(|(p, _, f)| {
+                json!({
+                    "name": p.name,
+                    "facet": p.facet_name(),
+                    "version": p.version,
+                    "path": f,
+                })
+            })
+            .collect::<Vec<_>>();
+        println!("{}", serde_json::to_string_pretty(&found)?);
+    } else {
+        for (p, _, f) in &found {
+            println!("{} {}: {}", full_name(p), p.version, f.to_string_lossy());
+        }
+    }
+
+    if found.is_empty() {
+        Err(eyre!("No packet provides \"{}\".", path))
+    } else {
+        Ok(())
+    }
+}
+
+fn rdeps(args: &Args, direct: bool, file: bool, soname: bool, name: &str) -> Result<()> {
+    let repo = open_repository(args)?;
+    let reverse_dependencies = ReverseDependencies::new(&repo)?;
+
+    let packets = if soname {
+        let linked = reverse_dependencies.linked_against(name);
+        if linked.is_empty() {
+            return Err(eyre!("No packet is linked against \"{}\".", name));
+        }
+        linked
+    } else if file {
+        warn_about_missing_file_lists(&repo)?;
+        let providers = gng_packet_db::reverse_dependencies::file_providers(&repo, name)?;
+        if providers.is_empty() {
+            return Err(eyre!("No packet provides \"{}\".", name));
+        }
+        providers
+    } else {
+        let packet = Name::try_from(name).wrap_err(eyre!("Invalid packet name \"{}\".", name))?;
+        if !reverse_dependencies.contains(&packet) {
+            return Err(eyre!("Packet \"{}\" is not in the repository.", name));
+        }
+        vec![packet]
+    };
+
+    let dependents = if soname {
+        if direct {
+            packets.clone()
+        } else {
+            reverse_dependencies.including_transitive(&packets)?
+        }
+    } else if direct {
+        let mut dependents = packets
+            .iter()
+            .flat_map(|p| reverse_dependencies.direct(p))
+            .filter(|p| !packets.contains(p))
+            .collect::<Vec<_>>();
+        dependents.sort();
+        dependents.dedup();
+        dependents
+    } else {
+        reverse_dependencies.transitive(&packets)?
+    };
+
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&json!({
+                "packets": packets,
+                "dependents": dependents,
+            }))?
+        );
+    } else {
+        for d in &dependents {
+            println!("{}", d);
+        }
+    }
+    Ok(())
+}
+
+/// The directory to remember verified repository data in
+///
+/// Never a shared directory: Others could pretend older data was seen.
+fn default_state_directory() -> Result<PathBuf> {
+    gng_core::user_directory("XDG_STATE_HOME", ".local/state")
+        .map(|s| s.join("gng").join("trust"))
+        .ok_or_else(|| eyre!("No state directory found, please pass \"--state\"."))
+}
+
+fn verify(args: &Args, trusted_keys: &[PublicKey], state: Option<&Path>) -> Result<()> {
+    let trust = if trusted_keys.is_empty() {
+        Trust::none()
+    } else {
+        let state = state.map_or_else(default_state_directory, |s| Ok(s.to_path_buf()))?;
+        Trust::new(trusted_keys.to_vec(), &state)
+    };
+    let repo = Repository::from_local_directory(&args.repository, false, &trust)?;
+
+    let problems = repo.verify_files();
+    if args.json {
+        let problems = problems
+            .iter()
+            .map(|(file, problem)| json!({ "file": file, "problem": problem }))
+            .collect::<Vec<_>>();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&json!({
+                "ok": problems.is_empty(),
+                "problems": problems,
+            }))?
+        );
+    } else {
+        for (file, problem) in &problems {
+            println!("{}: {}", file.to_string_lossy(), problem);
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!(
+            "{} packet file(s) failed verification.",
+            problems.len()
+        ))
+    }
+}
+
+fn check(args: &Args, against: Option<&str>, strict: bool) -> Result<()> {
+    let repo = open_repository(args)?;
+    let previous = match against {
+        Some(name) => Some(name.to_string()),
+        None => repo.current_snapshot()?,
+    }
+    .map(|name| Repository::from_local_snapshot(&args.repository, &name, &Trust::none()))
+    .transpose()?;
+
+    let problems = gng_packet_db::check::check(&repo, previous.as_ref())?;
+    let is_error = |p: &gng_packet_db::check::Problem| strict || p.is_error();
+    let errors = problems.iter().filter(|p| is_error(p)).count();
+
+    if args.json {
+        let problems = problems
+            .iter()
+            .map(|p| {
+                let mut value = serde_json::to_value(p)?;
+                value["severity"] = json!(if is_error(p) { "error" } else { "warning" });
+                value["message"] = json!(p.to_string());
+                Ok(value)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&json!({
+                "ok": errors == 0,
+                "errors": errors,
+                "warnings": problems.len() - errors,
+                "problems": problems,
+            }))?
+        );
+    } else {
+        for p in &problems {
+            println!("{}: {}", if is_error(p) { "error" } else { "warning" }, p);
+        }
+    }
+
+    if errors > 0 {
+        Err(eyre!("{} error(s) found in the repository.", errors))
+    } else {
+        Ok(())
+    }
+}
+
+fn gc(args: &Args, dry_run: bool) -> Result<()> {
+    let _lock = lock_repository(args)?;
+    let mut repo = open_repository(args)?;
+
+    let garbage = repo.find_garbage()?;
+    let deleted = if dry_run || garbage.is_empty() {
+        DeletedGarbage::default()
+    } else {
+        let mut update = repo.create_transaction();
+        update.remove_garbage(&garbage);
+        repo.apply(update)?;
+        save_repository(args, &mut repo)?;
+
+        repo.delete_garbage_files(&garbage)?
+    };
+
+    if args.json {
+        let packets = |packets: &[(BinaryPacketDefinition, PathBuf)]| {
+            packets
+                .iter()
+                .map(|(p, f)| json!({ "name": full_name(p), "version": p.version, "file": f }))
+                .collect::<Vec<_>>()
+        };
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&json!({
+                "dry_run": dry_run,
+                "unreferenced": garbage.unreferenced,
+                "missing": packets(&garbage.missing),
+                "superseded": packets(&garbage.superseded),
+                "deleted": deleted.deleted,
+                "failed": deleted
+                    .failed
+                    .iter()
+                    .map(|(f, e)| json!({ "file": f, "error": e }))
+                    .collect::<Vec<_>>(),
+            }))?
+        );
+    } else {
+        for f in &garbage.unreferenced {
+            println!("unreferenced {}", f.to_string_lossy());
+        }
+        for (p, f) in &garbage.missing {
+            println!(
+                "missing {} {} {}",
+                full_name(p),
+                p.version,
+                f.to_string_lossy()
+            );
+        }
+        for (p, f) in &garbage.superseded {
+            println!(
+                "superseded {} {} {}",
+                full_name(p),
+                p.version,
+                f.to_string_lossy()
+            );
+        }
+        for f in &deleted.deleted {
+            println!("deleted {}", f.to_string_lossy());
+        }
+        for (f, e) in &deleted.failed {
+            println!("failed {}: {}", f.to_string_lossy(), e);
+        }
+    }
+
+    if deleted.failed.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!(
+            "Failed to delete {} packet file(s).",
+            deleted.failed.len()
+        ))
+    }
+}
+
+fn backfill(args: &Args) -> Result<()> {
+    let _lock = lock_repository(args)?;
+    let mut repo = open_repository(args)?;
+
+    let mut update = repo.create_transaction();
+    let mut backfilled = Vec::new();
+    for (p, f) in repo.without_files()? {
+        let files = repo.read_installed_files(f)?;
+        update.set_files(p.name.clone(), p.facet_name(), p.version.clone(), files);
+        backfilled.push((p.clone(), f.to_path_buf()));
+    }
+
+    if args.json {
+        let backfilled = backfilled
+            .iter()
+            .map(|(p, f)| json!({ "name": full_name(p), "version": p.version, "file": f }))
+            .collect::<Vec<_>>();
+        println!("{}", serde_json::to_string_pretty(&backfilled)?);
+    } else {
+        for (p, f) in &backfilled {
+            println!("{} {} {}", full_name(p), p.version, f.to_string_lossy());
+        }
+    }
+
+    if backfilled.is_empty() {
+        return Ok(());
+    }
+    repo.apply(update)?;
+    save_repository(args, &mut repo)
+}
+
+fn format(args: &Args, format: Option<Format>) -> Result<()> {
+    let _lock = lock_repository(args)?;
+    let mut repo = open_repository(args)?;
+
+    if let Some(format) = format {
+        let mut update = repo.create_transaction();
+        update.set_format(format);
+        repo.apply(update)?;
+        save_repository(args, &mut repo)?;
+    }
+
+    if args.json {
+        println!("{}", json!({ "format": repo.format().to_string() }));
+    } else {
+        println!("{}", repo.format());
+    }
+    Ok(())
+}
+
+fn export(args: &Args, output: Option<&Path>) -> Result<()> {
+    let repo = open_repository(args)?;
+    let data = repo.to_json_lines()?;
+
+    output.map_or_else(
+        || {
+            use std::io::Write;
+            std::io::stdout()
+                .write_all(&data)
+                .wrap_err("Failed to write to standard output.")
+        },
+        |output| {
+            std::fs::write(output, &data)
+                .wrap_err(eyre!("Failed to write \"{}\".", output.to_string_lossy()))
+        },
+    )
+}
+
+fn snapshot_create(args: &Args, name: Option<&str>) -> Result<()> {
+    let _lock = lock_repository(args)?;
+    let repo = open_repository(args)?;
+
+    let signing = signing_key(args)?;
+    let name = repo.create_snapshot(name, signing.as_ref().map(|(key, v)| (key, *v)))?;
+
+    if args.json {
+        println!("{}", json!({ "created": name }));
+    } else {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+fn snapshot_list(args: &Args) -> Result<()> {
+    let repo = open_repository(args)?;
+    let snapshots = repo.snapshots()?;
+    let current = repo.current_snapshot()?;
+
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&json!({
+                "snapshots": snapshots,
+                "current": current,
+            }))?
+        );
+    } else {
+        for s in &snapshots {
+            println!(
+                "{}{}",
+                s,
+                if current.as_ref() == Some(s) {
+                    " (current)"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+    Ok(())
+}
+
+fn snapshot_diff(args: &Args, from: &str, to: Option<&str>) -> Result<()> {
+    let from = Repository::from_local_snapshot(&args.repository, from, &Trust::none())?;
+    let to = match to {
+        Some(to) => Repository::from_local_snapshot(&args.repository, to, &Trust::none())?,
+        None => open_repository(args)?,
+    };
+
+    let difference = from.diff(&to)?;
+    if args.json {
+        let packets = |packets: &[(BinaryPacketDefinition, PathBuf)]| {
+            packets
+                .iter()
+                .map(|(p, f)| json!({ "name": full_name(p), "version": p.version, "file": f }))
+                .collect::<Vec<_>>()
+        };
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&json!({
+                "added": packets(&difference.added),
+                "removed": packets(&difference.removed),
+            }))?
+        );
+    } else {
+        for (p, f) in &difference.removed {
+            println!("- {} {} {}", full_name(p), p.version, f.to_string_lossy());
+        }
+        for (p, f) in &difference.added {
+            println!("+ {} {} {}", full_name(p), p.version, f.to_string_lossy());
+        }
+    }
+    Ok(())
+}
+
+fn snapshot_rollback(args: &Args, name: &str) -> Result<()> {
+    let _lock = lock_repository(args)?;
+    let mut repo = open_repository(args)?;
+
+    repo.rollback_to_snapshot(name)?;
+    save_repository(args, &mut repo)?;
+    let signing = signing_key(args)?;
+    repo.set_current_snapshot(name, signing.as_ref().map(|(key, v)| (key, *v)))?;
+
+    if args.json {
+        println!("{}", json!({ "current": name }));
+    }
+    Ok(())
+}
+
+// ----------------------------------------------------------------------
+// - Entry Point:
+// ----------------------------------------------------------------------
+
+/// Entry point of the `gng-repo` binary.
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let _app_span = args
+        .logging
+        .setup_logging()
+        .wrap_err("Failed to set up logging.")?;
+
+    tracing::debug!("Command line arguments: {:#?}", args);
+
+    match &args.command {
+        Command::Init { format: f } => init(&args, *f),
+        Command::Add { clear, packets } => add(&args, *clear, packets),
+        Command::Remove { packets } => remove(&args, packets),
+        Command::Pin { packets } => pin(&args, packets, true),
+        Command::Unpin { packets } => pin(&args, packets, false),
+        Command::Retention { retention: r } => retention(&args, *r),
+        Command::List => list(&args),
+        Command::Show { packet } => show(&args, packet),
+        Command::Search { pattern } => search(&args, pattern),
+        Command::Provides { path } => provides(&args, path),
+        Command::Rdeps {
+            direct,
+            file,
+            soname,
+            name,
+        } => rdeps(&args, *direct, *file, *soname, name),
+        Command::Verify {
+            trusted_keys,
+            state,
+        } => verify(&args, trusted_keys, state.as_deref()),
+        Command::Check { against, strict } => check(&args, against.as_deref(), *strict),
+        Command::Gc { dry_run } => gc(&args, *dry_run),
+        Command::Backfill => backfill(&args),
+        Command::Format { format: f } => format(&args, *f),
+        Command::Export { output } => export(&args, output.as_deref()),
+        Command::Snapshot { command } => match command {
+            SnapshotCommand::Create { name } => snapshot_create(&args, name.as_deref()),
+            SnapshotCommand::List => snapshot_list(&args),
+            SnapshotCommand::Diff { from, to } => snapshot_diff(&args, from, to.as_deref()),
+            SnapshotCommand::Rollback { name } => snapshot_rollback(&args, name),
+        },
+    }
 }
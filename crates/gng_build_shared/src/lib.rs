@@ -103,4 +103,6 @@ pub const BUILD_SCRIPT: &str = "build.lua";
 // ----------------------------------------------------------------------
 
 mod source_packet;
-pub use source_packet::{FacetDefinition, PacketDefinition, SourceDefinition, SourcePacket};
+pub use source_packet::{
+    AttributeDefinition, FacetDefinition, PacketDefinition, SourceDefinition, SourcePacket,
+};
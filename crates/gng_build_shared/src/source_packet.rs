@@ -22,8 +22,9 @@ pub struct SourceDefinition {
 
     /// Does this source file need unpacking?
     pub unpack: bool,
-    // /// Validation values:
-    // pub hash: Hash,
+    /// The expected SHA256 of the source, as hex string
+    #[serde(default)]
+    pub sha256: Option<String>,
 }
 
 impl std::fmt::Display for SourceDefinition {
@@ -70,7 +71,10 @@ pub struct PacketDefinition {
     #[serde(default)]
     pub dependencies: Names,
 
-    /// Glob-patterns for `files` to include in the `Packet`
+    /// Glob-patterns or filter expressions for `files` to include in the `Packet`
+    ///
+    /// Entries containing a `(` are filter expressions, e.g.
+    /// `glob("lib/**") and not mime("text/*")`.
     #[serde(default)]
     pub files: Vec<String>,
 
@@ -78,6 +82,24 @@ pub struct PacketDefinition {
     pub facet: Option<FacetDefinition>,
 }
 
+// ----------------------------------------------------------------------
+// - AttributeDefinition:
+// ----------------------------------------------------------------------
+
+/// Attributes to set on packaged files
+#[derive(Clone, Debug, Default, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct AttributeDefinition {
+    /// The `mode` as octal string, e.g. "4755"
+    #[serde(default)]
+    pub mode: Option<String>,
+    /// The `user` owning the file (name or numeric id)
+    #[serde(default)]
+    pub user: Option<String>,
+    /// The `group` owning the file (name or numeric id)
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
 // ----------------------------------------------------------------------
 // - SourcePacket:
 // ----------------------------------------------------------------------
@@ -109,6 +131,15 @@ pub struct SourcePacket {
     pub sources: Vec<SourceDefinition>,
     /// The different `packets` to generate from the sources.
     pub packets: Vec<PacketDefinition>,
+
+    /// File `attributes` to set during packaging, by glob pattern
+    ///
+    /// When several patterns match a file, the most specific one wins. That
+    /// is decided by the length of the pattern string alone: `lib/foo/*`
+    /// wins over `lib/**`, but so does `lib/*/*/*` over `lib/foo/bar`. Of
+    /// patterns with the same length, the last in sort order wins.
+    #[serde(default)]
+    pub attributes: std::collections::BTreeMap<String, AttributeDefinition>,
 }
 
 impl std::fmt::Display for SourcePacket {
@@ -113,6 +113,20 @@ pub fn validate_executable(path: &Path) -> Result<PathBuf> {
     }
 }
 
+/// The directory of the user named in the environment `variable` (e.g.
+/// `XDG_CACHE_HOME`), or `fallback` below the home directory of the user
+///
+/// Relative paths are ignored, as the XDG base directory specification asks.
+#[must_use]
+pub fn user_directory(variable: &str, fallback: &str) -> Option<PathBuf> {
+    let absolute = |v: std::ffi::OsString| Some(PathBuf::from(v)).filter(|p| p.is_absolute());
+    std::env::var_os(variable).and_then(absolute).or_else(|| {
+        std::env::var_os("HOME")
+            .and_then(absolute)
+            .map(|h| h.join(fallback))
+    })
+}
+
 /// Return `true` if the program is run by the `root` user.
 #[must_use]
 pub fn is_root() -> bool {
@@ -163,6 +177,7 @@ pub fn start_alphanumerical_char(input: &str) -> bool {
 // ----------------------------------------------------------------------
 
 pub mod log;
+pub mod time;
 
 mod name;
 pub use name::{Name, Names};
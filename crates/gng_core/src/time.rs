@@ -0,0 +1,73 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Formatting of points in time
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// The civil date (year, month, day) `days` after 1970-01-01
+///
+/// See <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+const fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// ----------------------------------------------------------------------
+// - Functions:
+// ----------------------------------------------------------------------
+
+/// Format `seconds` since the epoch as UTC date, e.g. "2021-01-31"
+#[must_use]
+pub fn date(seconds: u64) -> String {
+    let (year, month, day) = civil_from_days(seconds / 86_400);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Format `seconds` since the epoch as UTC timestamp, e.g. "2021-01-31T12:00:00Z"
+#[must_use]
+pub fn timestamp(seconds: u64) -> String {
+    let time = seconds % 86_400;
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        date(seconds),
+        time / 3600,
+        (time % 3600) / 60,
+        time % 60
+    )
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(11_016 * 86_400), "2000-02-29");
+        assert_eq!(date(20_742 * 86_400 + 86_399), "2026-10-16");
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(timestamp(951_825_600), "2000-02-29T12:00:00Z");
+        assert_eq!(timestamp(1_612_094_400), "2021-01-31T12:00:00Z");
+        assert_eq!(timestamp(1_640_995_199), "2021-12-31T23:59:59Z");
+    }
+}
@@ -0,0 +1,266 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Override the mode and ownership of paths before they get packaged
+
+use crate::path::Path;
+
+use eyre::{eyre, WrapErr};
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// Parse the contents of a `passwd` or `group` file into a name to id map
+fn parse_id_file(contents: &str) -> eyre::Result<std::collections::BTreeMap<String, u32>> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| {
+            let mut fields = l.split(':');
+            let name = fields.next().unwrap_or_default();
+            let id = fields
+                .nth(1)
+                .ok_or_else(|| eyre!("Line \"{}\" has too few fields.", l))?;
+            let id = id
+                .parse::<u32>()
+                .wrap_err(eyre!("Line \"{}\" has an invalid id.", l))?;
+            Ok((name.to_string(), id))
+        })
+        .collect()
+}
+
+fn read_id_file(path: &std::path::Path) -> eyre::Result<std::collections::BTreeMap<String, u32>> {
+    let contents = std::fs::read_to_string(path)
+        .wrap_err(eyre!("Failed to read \"{}\".", path.to_string_lossy()))?;
+    parse_id_file(&contents).wrap_err(eyre!("Failed to parse \"{}\".", path.to_string_lossy()))
+}
+
+fn lookup(
+    map: &std::collections::BTreeMap<String, u32>,
+    name: &str,
+    kind: &str,
+) -> eyre::Result<u32> {
+    name.parse::<u32>()
+        .ok()
+        .or_else(|| map.get(name).copied())
+        .ok_or_else(|| eyre!("Unknown {} \"{}\".", kind, name))
+}
+
+// ----------------------------------------------------------------------
+// - UserDatabase:
+// ----------------------------------------------------------------------
+
+/// The users and groups known at build time
+#[derive(Clone, Debug, Default)]
+pub struct UserDatabase {
+    users: std::collections::BTreeMap<String, u32>,
+    groups: std::collections::BTreeMap<String, u32>,
+}
+
+impl UserDatabase {
+    /// Read the users and groups from a `passwd` and a `group` file
+    ///
+    /// Only numeric ids are known for a file that is not given.
+    ///
+    /// # Errors
+    /// Returns an error if one of the files can not be read or parsed.
+    pub fn from_files(
+        passwd: Option<&std::path::Path>,
+        group: Option<&std::path::Path>,
+    ) -> eyre::Result<Self> {
+        Ok(Self {
+            users: passwd.map(read_id_file).transpose()?.unwrap_or_default(),
+            groups: group.map(read_id_file).transpose()?.unwrap_or_default(),
+        })
+    }
+
+    /// Parse the users and groups from the contents of a `passwd` and a `group` file
+    ///
+    /// # Errors
+    /// Returns an error if one of the contents can not be parsed.
+    pub fn parse(passwd: &str, group: &str) -> eyre::Result<Self> {
+        Ok(Self {
+            users: parse_id_file(passwd).wrap_err("Failed to parse passwd data.")?,
+            groups: parse_id_file(group).wrap_err("Failed to parse group data.")?,
+        })
+    }
+
+    /// The user id of the user `name` (which may also be a numeric user id)
+    ///
+    /// # Errors
+    /// Returns an error if the user is unknown.
+    pub fn user_id(&self, name: &str) -> eyre::Result<u32> {
+        lookup(&self.users, name, "user")
+    }
+
+    /// The group id of the group `name` (which may also be a numeric group id)
+    ///
+    /// # Errors
+    /// Returns an error if the group is unknown.
+    pub fn group_id(&self, name: &str) -> eyre::Result<u32> {
+        lookup(&self.groups, name, "group")
+    }
+}
+
+// ----------------------------------------------------------------------
+// - AttributeOverride:
+// ----------------------------------------------------------------------
+
+/// Attributes to set on all paths matching a glob pattern
+#[derive(Clone, Debug)]
+pub struct AttributeOverride {
+    glob: glob::Pattern,
+    mode: Option<u32>,
+    user_id: Option<u32>,
+    group_id: Option<u32>,
+}
+
+impl AttributeOverride {
+    /// Constructor
+    ///
+    /// `mode` is an octal string (e.g. "4755"), `user` and `group` are names
+    /// looked up in `users` or numeric ids.
+    ///
+    /// # Errors
+    /// Returns an error if the pattern or mode are invalid, or if the user or
+    /// group are unknown.
+    pub fn new(
+        glob: &str,
+        mode: Option<&str>,
+        user: Option<&str>,
+        group: Option<&str>,
+        users: &UserDatabase,
+    ) -> eyre::Result<Self> {
+        Ok(Self {
+            glob: glob::Pattern::new(glob)
+                .map_err(|e| eyre!("Failed to create GLOB pattern: {}", e))?,
+            mode: mode.map(parse_mode).transpose()?,
+            user_id: user.map(|u| users.user_id(u)).transpose()?,
+            group_id: group.map(|g| users.group_id(g)).transpose()?,
+        })
+    }
+
+    fn apply(&self, path: &mut Path) {
+        if !path
+            .as_path()
+            .to_str()
+            .map_or(false, |p| self.glob.matches(p))
+        {
+            return;
+        }
+
+        tracing::trace!(
+            "Overriding attributes of \"{}\" with {:?}.",
+            path.as_path().to_string_lossy(),
+            self
+        );
+        if let Some(mode) = self.mode {
+            path.set_mode(mode);
+        }
+        if let Some(user_id) = self.user_id {
+            path.set_user_id(user_id);
+        }
+        if let Some(group_id) = self.group_id {
+            path.set_group_id(group_id);
+        }
+    }
+}
+
+/// Parse an octal `mode` string
+///
+/// # Errors
+/// Returns an error if `mode` is not an octal number up to 0o7777.
+pub fn parse_mode(mode: &str) -> eyre::Result<u32> {
+    match u32::from_str_radix(mode.strip_prefix("0o").unwrap_or(mode), 8) {
+        Ok(m) if m <= 0o7777 => Ok(m),
+        _ => Err(eyre!("Invalid mode \"{}\".", mode)),
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Functions:
+// ----------------------------------------------------------------------
+
+/// Apply all matching `overrides` to `path`, later overrides win
+#[must_use]
+pub fn apply(mut path: Path, overrides: &[AttributeOverride]) -> Path {
+    for o in overrides {
+        o.apply(&mut path);
+    }
+    path
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWD: &str = "root:x:0:0:root:/root:/bin/bash\n\
+                          # comment\n\
+                          \n\
+                          messagebus:x:81:81:System Message Bus:/:/usr/bin/nologin\n";
+    const GROUP: &str = "root:x:0:root\nwheel:x:998:\nmessagebus:x:81:\n";
+
+    #[test]
+    fn user_database() {
+        let users = UserDatabase::parse(PASSWD, GROUP).unwrap();
+        assert_eq!(users.user_id("root").unwrap(), 0);
+        assert_eq!(users.user_id("messagebus").unwrap(), 81);
+        assert_eq!(users.user_id("1000").unwrap(), 1000);
+        assert!(users.user_id("wheel").is_err());
+        assert_eq!(users.group_id("wheel").unwrap(), 998);
+        assert!(users.group_id("nobody").is_err());
+
+        assert!(UserDatabase::parse("root:x\n", GROUP).is_err());
+        assert!(UserDatabase::parse(PASSWD, "root:x:zero:\n").is_err());
+    }
+
+    #[test]
+    fn parse_mode_ok() {
+        assert_eq!(parse_mode("4755").unwrap(), 0o4755);
+        assert_eq!(parse_mode("0o640").unwrap(), 0o640);
+        assert!(parse_mode("8").is_err());
+        assert!(parse_mode("17777").is_err());
+        assert!(parse_mode("").is_err());
+    }
+
+    #[test]
+    fn apply_overrides() {
+        let users = UserDatabase::parse(PASSWD, GROUP).unwrap();
+        let overrides = vec![
+            AttributeOverride::new("lib/**", None, Some("root"), Some("wheel"), &users).unwrap(),
+            AttributeOverride::new("lib/foo/helper", Some("4750"), None, None, &users).unwrap(),
+            AttributeOverride::new("lib/foo/*", None, None, Some("messagebus"), &users).unwrap(),
+        ];
+
+        let helper = apply(
+            Path::new_file_from_buffer(
+                Vec::new(),
+                std::path::Path::new("lib/foo/helper"),
+                0o755,
+                1000,
+                1000,
+            ),
+            &overrides,
+        );
+        assert_eq!(helper.mode(), 0o4750);
+        assert_eq!(helper.user_id(), 0);
+        assert_eq!(helper.group_id(), 81);
+
+        let other = apply(
+            Path::new_directory(std::path::Path::new("share/foo"), 0o755, 1000, 1000),
+            &overrides,
+        );
+        assert_eq!(other.mode(), 0o755);
+        assert_eq!(other.user_id(), 1000);
+        assert_eq!(other.group_id(), 1000);
+
+        assert!(AttributeOverride::new("lib/**", None, Some("nobody"), None, &users).is_err());
+        assert!(AttributeOverride::new("lib/**", Some("999"), None, None, &users).is_err());
+    }
+}
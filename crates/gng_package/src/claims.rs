@@ -0,0 +1,280 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Make sure every path is claimed by exactly one `Packet`
+
+use crate::path::Path;
+use crate::{ClaimPolicy, PacketDefinition};
+
+use gng_core::Name;
+
+use eyre::eyre;
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// The part of a glob `pattern` before the first wildcard
+fn literal_prefix(pattern: &str) -> &str {
+    pattern
+        .find(|c| matches!(c, '*' | '?' | '[' | '{'))
+        .map_or(pattern, |pos| &pattern[..pos])
+}
+
+/// How well do the glob patterns of `packet` fit `path`?
+///
+/// This is the length of the longest common prefix of `path` and the literal
+/// prefix of any of the glob patterns of `packet`.
+fn score(packet: &PacketDefinition, path: &str) -> usize {
+    packet
+        .filter
+        .glob_patterns()
+        .iter()
+        .map(|p| {
+            literal_prefix(p)
+                .chars()
+                .zip(path.chars())
+                .take_while(|(a, b)| a == b)
+                .count()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// The `Packet` out of `packets` that `path` most likely is meant to go into
+fn suggest<'a>(packets: impl Iterator<Item = &'a PacketDefinition>, path: &Path) -> Option<Name> {
+    let path = path.as_path().to_string_lossy();
+    packets
+        .map(|p| (score(p, &path), p))
+        .filter(|(s, _)| *s > 0)
+        // max_by_key returns the last maximum, but we want the first:
+        .fold(
+            None,
+            |acc: Option<(usize, &PacketDefinition)>, (s, p)| match acc {
+                Some((best, _)) if best >= s => acc,
+                _ => Some((s, p)),
+            },
+        )
+        .map(|(_, p)| p.data.name.clone())
+}
+
+fn suggestion_text(suggestion: Option<&Name>) -> String {
+    suggestion.map_or_else(String::new, |n| format!(" Did you mean packet \"{n}\"?"))
+}
+
+// ----------------------------------------------------------------------
+// - ClaimIssue:
+// ----------------------------------------------------------------------
+
+/// A problem with the claims made on one path
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ClaimIssue {
+    /// No `Packet` claimed the path
+    Unclaimed {
+        /// The path
+        path: std::path::PathBuf,
+        /// The `Packet` the path most likely should go into
+        suggestion: Option<Name>,
+    },
+    /// More than one `Packet` claimed the path
+    Ambiguous {
+        /// The path
+        path: std::path::PathBuf,
+        /// The `Packet`s claiming the path
+        packets: Vec<Name>,
+        /// The `Packet` the path most likely should go into
+        suggestion: Option<Name>,
+    },
+}
+
+impl std::fmt::Display for ClaimIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unclaimed { path, suggestion } => write!(
+                f,
+                "\"{}\" is not claimed by any packet.{}",
+                path.to_string_lossy(),
+                suggestion_text(suggestion.as_ref())
+            ),
+            Self::Ambiguous {
+                path,
+                packets,
+                suggestion,
+            } => write!(
+                f,
+                "\"{}\" is claimed by several packets ({}).{}",
+                path.to_string_lossy(),
+                packets
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                suggestion_text(suggestion.as_ref())
+            ),
+        }
+    }
+}
+
+impl ClaimIssue {
+    const fn policy(&self, unclaimed: ClaimPolicy, ambiguous: ClaimPolicy) -> ClaimPolicy {
+        match self {
+            Self::Unclaimed { .. } => unclaimed,
+            Self::Ambiguous { .. } => ambiguous,
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Functions:
+// ----------------------------------------------------------------------
+
+/// Check which of the `packets` claim `path`
+///
+/// Directories are not checked: They get stored into all packets that store
+/// anything inside of them.
+#[must_use]
+pub fn check(path: &Path, packets: &[PacketDefinition]) -> Option<ClaimIssue> {
+    if path.is_dir() {
+        return None;
+    }
+
+    let claimants = packets
+        .iter()
+        .filter(|p| p.filter.matches(path))
+        .collect::<Vec<_>>();
+
+    match claimants.len() {
+        0 => Some(ClaimIssue::Unclaimed {
+            path: path.as_path().to_path_buf(),
+            suggestion: suggest(packets.iter(), path),
+        }),
+        1 => None,
+        _ => Some(ClaimIssue::Ambiguous {
+            path: path.as_path().to_path_buf(),
+            packets: claimants.iter().map(|p| p.data.name.clone()).collect(),
+            suggestion: suggest(claimants.into_iter(), path),
+        }),
+    }
+}
+
+/// Report `issues` as warnings or errors, depending on the policies
+///
+/// # Errors
+/// Returns an error listing all issues with a `ClaimPolicy::Fail` policy.
+pub fn report(
+    issues: &[ClaimIssue],
+    unclaimed: ClaimPolicy,
+    ambiguous: ClaimPolicy,
+) -> eyre::Result<()> {
+    let mut errors = Vec::new();
+    for i in issues {
+        match i.policy(unclaimed, ambiguous) {
+            ClaimPolicy::Warn => tracing::warn!("{}", i),
+            ClaimPolicy::Fail => errors.push(i.to_string()),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!("Packaging failed:\n    {}", errors.join("\n    ")))
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_util::packet;
+
+    fn path(input: &str) -> Path {
+        Path::new_file_from_buffer(Vec::new(), std::path::Path::new(input), 0o644, 0, 0)
+    }
+
+    fn name(input: &str) -> Name {
+        Name::try_from(input).unwrap()
+    }
+
+    #[test]
+    fn literal_prefix_ok() {
+        assert_eq!(literal_prefix("lib/foo/*.so"), "lib/foo/");
+        assert_eq!(literal_prefix("include/**"), "include/");
+        assert_eq!(literal_prefix("bin/foo"), "bin/foo");
+        assert_eq!(literal_prefix("*"), "");
+    }
+
+    #[test]
+    fn check_claims() {
+        let packets = vec![
+            packet("foo", &["bin/*", "lib/foo/*.so"]),
+            packet("foo_dev", &["include/**", "lib/foo/*.a"]),
+            packet("foo_all", &["lib/foo/**"]),
+        ];
+
+        assert_eq!(check(&path("bin/foo"), &packets), None);
+        assert_eq!(
+            check(
+                &Path::new_directory(std::path::Path::new("share"), 0o755, 0, 0),
+                &packets
+            ),
+            None
+        );
+        assert_eq!(check(&path("lib/foo/libfoo.la"), &packets), None);
+        assert_eq!(
+            check(&path("lib/foo/libfoo.so"), &packets),
+            Some(ClaimIssue::Ambiguous {
+                path: std::path::PathBuf::from("lib/foo/libfoo.so"),
+                packets: vec![name("foo"), name("foo_all")],
+                suggestion: Some(name("foo"))
+            })
+        );
+        assert_eq!(check(&path("include/foo/bar.h"), &packets), None);
+        assert_eq!(
+            check(&path("incl.h"), &packets),
+            Some(ClaimIssue::Unclaimed {
+                path: std::path::PathBuf::from("incl.h"),
+                suggestion: Some(name("foo_dev"))
+            })
+        );
+        assert_eq!(
+            check(&path("share/doc"), &packets),
+            Some(ClaimIssue::Unclaimed {
+                path: std::path::PathBuf::from("share/doc"),
+                suggestion: None
+            })
+        );
+    }
+
+    #[test]
+    fn report_claims() {
+        let issues = vec![
+            ClaimIssue::Unclaimed {
+                path: std::path::PathBuf::from("share/doc"),
+                suggestion: None,
+            },
+            ClaimIssue::Ambiguous {
+                path: std::path::PathBuf::from("lib/foo.so"),
+                packets: vec![name("foo"), name("bar")],
+                suggestion: Some(name("foo")),
+            },
+        ];
+
+        assert!(report(&issues, ClaimPolicy::Warn, ClaimPolicy::Warn).is_ok());
+        assert_eq!(
+            report(&issues, ClaimPolicy::Warn, ClaimPolicy::Fail)
+                .unwrap_err()
+                .to_string(),
+            "Packaging failed:\n    \"lib/foo.so\" is claimed by several packets (foo, bar). Did you mean packet \"foo\"?"
+        );
+        assert_eq!(
+            report(&issues, ClaimPolicy::Fail, ClaimPolicy::Warn)
+                .unwrap_err()
+                .to_string(),
+            "Packaging failed:\n    \"share/doc\" is not claimed by any packet."
+        );
+    }
+}
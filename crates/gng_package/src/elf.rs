@@ -0,0 +1,275 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Find the shared libraries ELF files are linked against
+
+use crate::path::Path;
+use crate::PacketDefinition;
+
+use gng_core::Name;
+
+use std::io::Read;
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// The section type of the dynamic section
+const SHT_DYNAMIC: u32 = 6;
+
+/// The tag ending the dynamic section
+const DT_NULL: u64 = 0;
+
+/// The tag of a needed library
+const DT_NEEDED: u64 = 1;
+
+/// The layout of an ELF file, as given in its identification bytes
+struct Layout {
+    is_64_bit: bool,
+    is_little_endian: bool,
+}
+
+impl Layout {
+    fn new(data: &[u8]) -> Option<Self> {
+        if !data.starts_with(b"\x7fELF") {
+            return None;
+        }
+        let is_64_bit = match data.get(4)? {
+            1 => false,
+            2 => true,
+            _ => return None,
+        };
+        let is_little_endian = match data.get(5)? {
+            1 => true,
+            2 => false,
+            _ => return None,
+        };
+        Some(Self {
+            is_64_bit,
+            is_little_endian,
+        })
+    }
+
+    /// The unsigned number of `size` bytes at `offset` in `data`
+    fn number(&self, data: &[u8], offset: usize, size: usize) -> Option<u64> {
+        let bytes = data.get(offset..offset.checked_add(size)?)?;
+        let fold = |n: u64, b: &u8| (n << 8) | u64::from(*b);
+        Some(if self.is_little_endian {
+            bytes.iter().rev().fold(0, fold)
+        } else {
+            bytes.iter().fold(0, fold)
+        })
+    }
+
+    /// The address sized number at `offset` in `data`
+    fn address(&self, data: &[u8], offset: usize) -> Option<usize> {
+        let size = if self.is_64_bit { 8 } else { 4 };
+        usize::try_from(self.number(data, offset, size)?).ok()
+    }
+
+    /// The `offset` and `size` of a section in `data`
+    fn section_range(&self, data: &[u8], header: usize) -> Option<std::ops::Range<usize>> {
+        let (offset, size) = if self.is_64_bit {
+            (
+                self.address(data, header + 0x18)?,
+                self.address(data, header + 0x20)?,
+            )
+        } else {
+            (
+                self.address(data, header + 0x10)?,
+                self.address(data, header + 0x14)?,
+            )
+        };
+        let range = offset..offset.checked_add(size)?;
+        (range.end <= data.len()).then(|| range)
+    }
+}
+
+/// The sonames the ELF file `data` is linked against, in file order
+///
+/// The dynamic section is found through the section headers, so this
+/// returns `None` for files that are no ELF files, have no dynamic section
+/// or had their section headers removed.
+fn needed_libraries(data: &[u8]) -> Option<Vec<String>> {
+    let layout = Layout::new(data)?;
+
+    let (section_offset, entry_size, count) = if layout.is_64_bit {
+        (
+            layout.address(data, 0x28)?,
+            layout.number(data, 0x3a, 2)?,
+            layout.number(data, 0x3c, 2)?,
+        )
+    } else {
+        (
+            layout.address(data, 0x20)?,
+            layout.number(data, 0x2e, 2)?,
+            layout.number(data, 0x30, 2)?,
+        )
+    };
+    let header = |index: u64| {
+        section_offset.checked_add(usize::try_from(index.checked_mul(entry_size)?).ok()?)
+    };
+    let link_offset = if layout.is_64_bit { 0x28 } else { 0x18 };
+
+    let dynamic = (0..count)
+        .filter_map(header)
+        .find(|h| layout.number(data, h + 4, 4) == Some(u64::from(SHT_DYNAMIC)))?;
+    let strings = layout.section_range(
+        data,
+        header(layout.number(data, dynamic + link_offset, 4)?)?,
+    )?;
+    let strings = &data[strings];
+
+    let tag_size = if layout.is_64_bit { 8 } else { 4 };
+    let mut result = Vec::new();
+    for entry in layout.section_range(data, dynamic)?.step_by(2 * tag_size) {
+        let tag = layout.number(data, entry, tag_size)?;
+        if tag == DT_NULL {
+            break;
+        }
+        if tag == DT_NEEDED {
+            let name = strings.get(layout.address(data, entry + tag_size)?..)?;
+            let name = &name[..name.iter().position(|b| *b == 0)?];
+            result.push(String::from_utf8(name.to_vec()).ok()?);
+        }
+    }
+    Some(result)
+}
+
+/// Find the libraries the file `path` is linked against
+///
+/// # Errors
+/// Returns an error if the file contents can not be read.
+fn find_needed_libraries(path: &Path) -> eyre::Result<Vec<String>> {
+    let contents = match path.file_contents() {
+        Some(c) if c.head(4)? == b"\x7fELF" => c,
+        _ => return Ok(Vec::new()),
+    };
+
+    let mut data = Vec::new();
+    contents.reader()?.read_to_end(&mut data)?;
+    Ok(needed_libraries(&data).unwrap_or_default())
+}
+
+// ----------------------------------------------------------------------
+// - NeededLibraries:
+// ----------------------------------------------------------------------
+
+/// The libraries the ELF files of each `Packet` are linked against
+#[derive(Debug, Default)]
+pub struct NeededLibraries {
+    libraries: std::collections::BTreeMap<Name, std::collections::BTreeSet<String>>,
+}
+
+impl NeededLibraries {
+    /// Record the libraries `path` is linked against for the first of the
+    /// `packets` claiming it
+    ///
+    /// # Errors
+    /// Returns an error if the file contents can not be read.
+    pub fn record(&mut self, path: &Path, packets: &[PacketDefinition]) -> eyre::Result<()> {
+        let packet = match packets.iter().find(|p| p.filter.matches(path)) {
+            Some(p) => &p.data.name,
+            None => return Ok(()),
+        };
+
+        let libraries = find_needed_libraries(path)?;
+        if !libraries.is_empty() {
+            tracing::trace!(
+                "\"{}\" is linked against {}.",
+                path.as_path().to_string_lossy(),
+                libraries.join(", ")
+            );
+            self.libraries
+                .entry(packet.clone())
+                .or_default()
+                .extend(libraries);
+        }
+        Ok(())
+    }
+
+    /// The libraries `packet` is linked against, sorted
+    #[must_use]
+    pub fn get(&self, packet: &Name) -> Vec<String> {
+        self.libraries
+            .get(packet)
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect()
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A minimal 64 bit little endian ELF file with a dynamic section
+    /// needing `libraries`
+    fn elf(libraries: &[&str]) -> Vec<u8> {
+        let mut strings = vec![0_u8];
+        let mut dynamic = Vec::new();
+        for l in libraries {
+            dynamic.extend_from_slice(&DT_NEEDED.to_le_bytes());
+            dynamic.extend_from_slice(&(strings.len() as u64).to_le_bytes());
+            strings.extend_from_slice(l.as_bytes());
+            strings.push(0);
+        }
+        dynamic.extend_from_slice(&[0_u8; 16]);
+
+        let strings_offset = 64;
+        let dynamic_offset = strings_offset + strings.len();
+        let headers_offset = dynamic_offset + dynamic.len();
+
+        let mut data = vec![0_u8; 64];
+        data[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        data[0x28..0x30].copy_from_slice(&(headers_offset as u64).to_le_bytes());
+        data[0x3a..0x3c].copy_from_slice(&64_u16.to_le_bytes());
+        data[0x3c..0x3e].copy_from_slice(&3_u16.to_le_bytes());
+        data.extend(strings.iter());
+        data.extend(dynamic.iter());
+
+        let section = |kind: u32, offset: usize, size: usize, link: u32| {
+            let mut header = vec![0_u8; 64];
+            header[4..8].copy_from_slice(&kind.to_le_bytes());
+            header[0x18..0x20].copy_from_slice(&(offset as u64).to_le_bytes());
+            header[0x20..0x28].copy_from_slice(&(size as u64).to_le_bytes());
+            header[0x28..0x2c].copy_from_slice(&link.to_le_bytes());
+            header
+        };
+        data.extend(section(0, 0, 0, 0));
+        data.extend(section(3, strings_offset, strings.len(), 0));
+        data.extend(section(SHT_DYNAMIC, dynamic_offset, dynamic.len(), 1));
+        data
+    }
+
+    #[test]
+    fn needed_libraries_of_elf_files() {
+        assert_eq!(
+            needed_libraries(&elf(&["libssl.so.3", "libc.so.6"])),
+            Some(vec!["libssl.so.3".to_string(), "libc.so.6".to_string()])
+        );
+        assert_eq!(needed_libraries(&elf(&[])), Some(Vec::new()));
+    }
+
+    #[test]
+    fn needed_libraries_of_other_files() {
+        assert_eq!(needed_libraries(b"#!/bin/sh\n"), None);
+        assert_eq!(needed_libraries(b"\x7fELF\x02\x01"), None);
+
+        // Section headers removed:
+        let mut stripped = elf(&["libssl.so.3"]);
+        stripped.truncate(stripped.len() - 3 * 64);
+        assert_eq!(needed_libraries(&stripped), None);
+
+        // Dynamic section reaching beyond the end of the file:
+        let mut broken = elf(&["libssl.so.3"]);
+        let size = broken.len() - 64 + 0x20;
+        broken[size..size + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(needed_libraries(&broken), None);
+    }
+}
@@ -5,17 +5,73 @@
 
 use crate::path::Path;
 
+pub mod expression;
+
 // ----------------------------------------------------------------------
 // - Filter:
 // ----------------------------------------------------------------------
 
 /// The `Filter` trait
-pub trait Filter {
+pub trait Filter: Send + Sync {
     /// Check whether a `path` matches and should thus go into the `Packet`
     /// or not.
     fn matches(&self, path: &Path) -> bool;
+
+    /// A description of the `Filter`, using the filter expression syntax.
+    fn description(&self) -> String;
+
+    /// Explain why `path` matches, or return `None` if it does not match.
+    fn explain(&self, path: &Path) -> Option<String> {
+        self.matches(path).then(|| self.description())
+    }
+
+    /// The glob patterns that make a `Path` match this `Filter`
+    ///
+    /// This is used to guess which `Packet` a `Path` was meant to go into.
+    fn glob_patterns(&self) -> Vec<String> {
+        Vec::new()
+    }
 }
 
+impl<F: Filter + ?Sized> Filter for Box<F> {
+    fn matches(&self, path: &Path) -> bool {
+        (**self).matches(path)
+    }
+
+    fn description(&self) -> String {
+        (**self).description()
+    }
+
+    fn explain(&self, path: &Path) -> Option<String> {
+        (**self).explain(path)
+    }
+
+    fn glob_patterns(&self) -> Vec<String> {
+        (**self).glob_patterns()
+    }
+}
+
+impl<F: Filter + ?Sized> Filter for std::sync::Arc<F> {
+    fn matches(&self, path: &Path) -> bool {
+        (**self).matches(path)
+    }
+
+    fn description(&self) -> String {
+        (**self).description()
+    }
+
+    fn explain(&self, path: &Path) -> Option<String> {
+        (**self).explain(path)
+    }
+
+    fn glob_patterns(&self) -> Vec<String> {
+        (**self).glob_patterns()
+    }
+}
+
+/// A boxed `Filter`
+pub type BoxedFilter = Box<dyn Filter>;
+
 // ----------------------------------------------------------------------
 // - GlobFilter:
 // ----------------------------------------------------------------------
@@ -39,6 +95,26 @@ impl Filter for GlobFilter {
             .to_str()
             .map_or(false, |path| self.globs.iter().any(|p| p.matches(path)))
     }
+
+    fn description(&self) -> String {
+        self.globs
+            .iter()
+            .map(|g| format!("glob({:?})", g.as_str()))
+            .collect::<Vec<_>>()
+            .join(" or ")
+    }
+
+    fn explain(&self, path: &Path) -> Option<String> {
+        let path = path.as_path().to_str()?;
+        self.globs
+            .iter()
+            .find(|p| p.matches(path))
+            .map(|g| format!("glob({:?})", g.as_str()))
+    }
+
+    fn glob_patterns(&self) -> Vec<String> {
+        self.globs.iter().map(|g| g.as_str().to_string()).collect()
+    }
 }
 
 // ----------------------------------------------------------------------
@@ -63,6 +139,20 @@ impl<L: Filter, R: Filter> Filter for AndFilter<L, R> {
     fn matches(&self, path: &Path) -> bool {
         self.left.matches(path) && self.right.matches(path)
     }
+
+    fn description(&self) -> String {
+        format!(
+            "({}) and ({})",
+            self.left.description(),
+            self.right.description()
+        )
+    }
+
+    fn glob_patterns(&self) -> Vec<String> {
+        let mut result = self.left.glob_patterns();
+        result.append(&mut self.right.glob_patterns());
+        result
+    }
 }
 
 // ----------------------------------------------------------------------
@@ -87,6 +177,357 @@ impl<L: Filter, R: Filter> Filter for OrFilter<L, R> {
     fn matches(&self, path: &Path) -> bool {
         self.left.matches(path) || self.right.matches(path)
     }
+
+    fn description(&self) -> String {
+        format!(
+            "({}) or ({})",
+            self.left.description(),
+            self.right.description()
+        )
+    }
+
+    fn explain(&self, path: &Path) -> Option<String> {
+        self.left.explain(path).or_else(|| self.right.explain(path))
+    }
+
+    fn glob_patterns(&self) -> Vec<String> {
+        let mut result = self.left.glob_patterns();
+        result.append(&mut self.right.glob_patterns());
+        result
+    }
+}
+
+// ----------------------------------------------------------------------
+// - NotFilter:
+// ----------------------------------------------------------------------
+
+/// A `Filter` that matches when `inner` does not match.
+pub struct NotFilter<F: Filter> {
+    inner: F,
+}
+
+impl<F: Filter> NotFilter<F> {
+    /// Constructor
+    #[must_use]
+    pub const fn new(inner: F) -> Self {
+        Self { inner }
+    }
+}
+
+impl<F: Filter> Filter for NotFilter<F> {
+    fn matches(&self, path: &Path) -> bool {
+        !self.inner.matches(path)
+    }
+
+    fn description(&self) -> String {
+        format!("not ({})", self.inner.description())
+    }
+}
+
+// ----------------------------------------------------------------------
+// - RegexFilter:
+// ----------------------------------------------------------------------
+
+/// A `Filter` that matches the `Path` against a `regex::Regex`
+pub struct RegexFilter {
+    regex: regex::Regex,
+}
+
+impl RegexFilter {
+    /// Constructor
+    #[must_use]
+    pub const fn new(regex: regex::Regex) -> Self {
+        Self { regex }
+    }
+}
+
+impl Filter for RegexFilter {
+    fn matches(&self, path: &Path) -> bool {
+        path.as_path()
+            .to_str()
+            .map_or(false, |path| self.regex.is_match(path))
+    }
+
+    fn description(&self) -> String {
+        format!("regex({:?})", self.regex.as_str())
+    }
+}
+
+// ----------------------------------------------------------------------
+// - FileTypeFilter:
+// ----------------------------------------------------------------------
+
+/// The types of `Path` a `FileTypeFilter` can match
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FileType {
+    /// A normal file
+    File,
+    /// A directory
+    Directory,
+    /// A symbolic link
+    Link,
+}
+
+/// A `Filter` that matches a `FileType`
+pub struct FileTypeFilter {
+    file_type: FileType,
+}
+
+impl FileTypeFilter {
+    /// Constructor
+    #[must_use]
+    pub const fn new(file_type: FileType) -> Self {
+        Self { file_type }
+    }
+}
+
+impl Filter for FileTypeFilter {
+    fn matches(&self, path: &Path) -> bool {
+        match self.file_type {
+            FileType::File => path.is_file(),
+            FileType::Directory => path.is_dir(),
+            FileType::Link => path.is_link(),
+        }
+    }
+
+    fn description(&self) -> String {
+        match self.file_type {
+            FileType::File => "type(\"f\")".to_string(),
+            FileType::Directory => "type(\"d\")".to_string(),
+            FileType::Link => "type(\"l\")".to_string(),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// - ModeFilter:
+// ----------------------------------------------------------------------
+
+/// A `Filter` that matches when all the bits of `mask` are set in the mode
+pub struct ModeFilter {
+    mask: u32,
+}
+
+impl ModeFilter {
+    /// Constructor
+    #[must_use]
+    pub const fn new(mask: u32) -> Self {
+        Self { mask }
+    }
+}
+
+impl Filter for ModeFilter {
+    fn matches(&self, path: &Path) -> bool {
+        path.mode() & self.mask == self.mask
+    }
+
+    fn description(&self) -> String {
+        format!("mode({:#o})", self.mask)
+    }
+}
+
+// ----------------------------------------------------------------------
+// - SizeFilter:
+// ----------------------------------------------------------------------
+
+/// A `Filter` that matches when the size is within `minimum` and `maximum` (inclusive)
+///
+/// Note that the size of anything but normal files is 0.
+pub struct SizeFilter {
+    minimum: u64,
+    maximum: u64,
+}
+
+impl SizeFilter {
+    /// Constructor
+    #[must_use]
+    pub const fn new(minimum: u64, maximum: u64) -> Self {
+        Self { minimum, maximum }
+    }
+}
+
+impl Filter for SizeFilter {
+    fn matches(&self, path: &Path) -> bool {
+        (self.minimum..=self.maximum).contains(&path.size())
+    }
+
+    fn description(&self) -> String {
+        format!("size({}, {})", self.minimum, self.maximum)
+    }
+}
+
+// ----------------------------------------------------------------------
+// - UserFilter:
+// ----------------------------------------------------------------------
+
+/// A `Filter` that matches the owning user id
+pub struct UserFilter {
+    user_id: u32,
+}
+
+impl UserFilter {
+    /// Constructor
+    #[must_use]
+    pub const fn new(user_id: u32) -> Self {
+        Self { user_id }
+    }
+}
+
+impl Filter for UserFilter {
+    fn matches(&self, path: &Path) -> bool {
+        path.user_id() == self.user_id
+    }
+
+    fn description(&self) -> String {
+        format!("uid({})", self.user_id)
+    }
+}
+
+// ----------------------------------------------------------------------
+// - GroupFilter:
+// ----------------------------------------------------------------------
+
+/// A `Filter` that matches the owning group id
+pub struct GroupFilter {
+    group_id: u32,
+}
+
+impl GroupFilter {
+    /// Constructor
+    #[must_use]
+    pub const fn new(group_id: u32) -> Self {
+        Self { group_id }
+    }
+}
+
+impl Filter for GroupFilter {
+    fn matches(&self, path: &Path) -> bool {
+        path.group_id() == self.group_id
+    }
+
+    fn description(&self) -> String {
+        format!("gid({})", self.group_id)
+    }
+}
+
+// ----------------------------------------------------------------------
+// - ExecutableFilter:
+// ----------------------------------------------------------------------
+
+/// A `Filter` that matches files that are executable by anyone
+#[derive(Default)]
+pub struct ExecutableFilter {}
+
+impl Filter for ExecutableFilter {
+    fn matches(&self, path: &Path) -> bool {
+        path.is_file() && (path.mode() & 0o111 != 0)
+    }
+
+    fn description(&self) -> String {
+        "executable()".to_string()
+    }
+}
+
+// ----------------------------------------------------------------------
+// - ElfFilter:
+// ----------------------------------------------------------------------
+
+/// A `Filter` that matches ELF files
+#[derive(Default)]
+pub struct ElfFilter {}
+
+impl Filter for ElfFilter {
+    fn matches(&self, path: &Path) -> bool {
+        path.file_contents().map_or(false, |c| match c.head(4) {
+            Ok(head) => head == b"\x7fELF",
+            Err(e) => {
+                tracing::debug!(
+                    "Failed to read header of \"{}\": {}",
+                    path.as_path().to_string_lossy(),
+                    e
+                );
+                false
+            }
+        })
+    }
+
+    fn description(&self) -> String {
+        "elf()".to_string()
+    }
+}
+
+// ----------------------------------------------------------------------
+// - MimeFilter:
+// ----------------------------------------------------------------------
+
+/// A `Filter` that matches the mime type (e.g. `text/plain`) against a `glob::Pattern`
+pub struct MimeFilter {
+    pattern: glob::Pattern,
+}
+
+impl MimeFilter {
+    /// Constructor
+    #[must_use]
+    pub const fn new(pattern: glob::Pattern) -> Self {
+        Self { pattern }
+    }
+}
+
+impl Filter for MimeFilter {
+    fn matches(&self, path: &Path) -> bool {
+        match path.mime_type() {
+            Ok(mime_type) => self.pattern.matches(&mime_type),
+            Err(e) => {
+                tracing::debug!(
+                    "Failed to detect mime type of \"{}\": {}",
+                    path.as_path().to_string_lossy(),
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    fn description(&self) -> String {
+        format!("mime({:?})", self.pattern.as_str())
+    }
+}
+
+// ----------------------------------------------------------------------
+// - MagicFilter:
+// ----------------------------------------------------------------------
+
+/// A `Filter` that matches the `file` output against a `regex::Regex`
+pub struct MagicFilter {
+    regex: regex::Regex,
+}
+
+impl MagicFilter {
+    /// Constructor
+    #[must_use]
+    pub const fn new(regex: regex::Regex) -> Self {
+        Self { regex }
+    }
+}
+
+impl Filter for MagicFilter {
+    fn matches(&self, path: &Path) -> bool {
+        match path.magic() {
+            Ok(magic) => self.regex.is_match(&magic),
+            Err(e) => {
+                tracing::debug!(
+                    "Failed to detect file type of \"{}\": {}",
+                    path.as_path().to_string_lossy(),
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    fn description(&self) -> String {
+        format!("magic({:?})", self.regex.as_str())
+    }
 }
 
 // ----------------------------------------------------------------------
@@ -101,6 +542,10 @@ impl Filter for AlwaysTrue {
     fn matches(&self, _path: &Path) -> bool {
         true
     }
+
+    fn description(&self) -> String {
+        "true".to_string()
+    }
 }
 
 // ----------------------------------------------------------------------
@@ -115,6 +560,10 @@ impl Filter for AlwaysFalse {
     fn matches(&self, _path: &Path) -> bool {
         false
     }
+
+    fn description(&self) -> String {
+        "false".to_string()
+    }
 }
 
 // ----------------------------------------------------------------------
@@ -123,7 +572,10 @@ impl Filter for AlwaysFalse {
 
 #[cfg(test)]
 mod tests {
-    use super::{AlwaysFalse, AlwaysTrue, AndFilter, Filter, OrFilter};
+    use super::{
+        AlwaysFalse, AlwaysTrue, AndFilter, ElfFilter, ExecutableFilter, FileType, FileTypeFilter,
+        Filter, GlobFilter, ModeFilter, NotFilter, OrFilter, RegexFilter, SizeFilter,
+    };
 
     use crate::path::Path;
 
@@ -179,4 +631,108 @@ mod tests {
             OrFilter::new(AlwaysTrue::default(), AlwaysTrue::default()).matches(&path("/usr/foo"))
         );
     }
+
+    // Name:
+    #[test]
+    fn not_filter() {
+        assert!(NotFilter::new(AlwaysFalse::default()).matches(&path("/usr/foo")));
+        assert!(!NotFilter::new(AlwaysTrue::default()).matches(&path("/usr/foo")));
+    }
+
+    #[test]
+    fn regex_filter() {
+        let filter = RegexFilter::new(regex::Regex::new(r"^lib/.*\.so(\.[0-9]+)*$").unwrap());
+        assert!(filter.matches(&path("lib/libfoo.so")));
+        assert!(filter.matches(&path("lib/libfoo.so.1.2")));
+        assert!(!filter.matches(&path("lib/libfoo.a")));
+        assert!(!filter.matches(&path("bin/lib/libfoo.so")));
+    }
+
+    #[test]
+    fn file_type_filter() {
+        let dir = Path::new_directory(std::path::Path::new("lib"), 0o755, 0, 0);
+        let link = Path::new_link(
+            std::path::Path::new("lib64"),
+            std::path::Path::new("lib"),
+            0,
+            0,
+        );
+
+        assert!(FileTypeFilter::new(FileType::File).matches(&path("lib/foo")));
+        assert!(!FileTypeFilter::new(FileType::File).matches(&dir));
+        assert!(FileTypeFilter::new(FileType::Directory).matches(&dir));
+        assert!(!FileTypeFilter::new(FileType::Directory).matches(&link));
+        assert!(FileTypeFilter::new(FileType::Link).matches(&link));
+    }
+
+    #[test]
+    fn mode_filter() {
+        assert!(ModeFilter::new(0o111).matches(&path("bin/foo")));
+        assert!(ModeFilter::new(0o755).matches(&path("bin/foo")));
+        assert!(!ModeFilter::new(0o4000).matches(&path("bin/foo")));
+        assert!(ExecutableFilter::default().matches(&path("bin/foo")));
+        assert!(!ExecutableFilter::default().matches(&Path::new_directory(
+            std::path::Path::new("bin"),
+            0o755,
+            0,
+            0
+        )));
+    }
+
+    #[test]
+    fn size_filter() {
+        assert!(SizeFilter::new(0, 42).matches(&path("foo")));
+        assert!(SizeFilter::new(42, 42).matches(&path("foo")));
+        assert!(!SizeFilter::new(43, 100).matches(&path("foo")));
+        assert!(!SizeFilter::new(0, 41).matches(&path("foo")));
+    }
+
+    #[test]
+    fn elf_filter() {
+        let elf = Path::new_file_from_buffer(
+            b"\x7fELF\x02\x01\x01".to_vec(),
+            std::path::Path::new("bin/foo"),
+            0o755,
+            0,
+            0,
+        );
+        let script = Path::new_file_from_buffer(
+            b"#!/bin/sh\n".to_vec(),
+            std::path::Path::new("bin/bar"),
+            0o755,
+            0,
+            0,
+        );
+        assert!(ElfFilter::default().matches(&elf));
+        assert!(!ElfFilter::default().matches(&script));
+    }
+
+    #[test]
+    fn filter_description() {
+        let filter = OrFilter::new(
+            GlobFilter::new(crate::strings_to_globs(&["bin/*".to_string()]).unwrap()),
+            NotFilter::new(ElfFilter::default()),
+        );
+        assert_eq!(filter.description(), "(glob(\"bin/*\")) or (not (elf()))");
+        assert_eq!(
+            ModeFilter::new(0o111).description(),
+            "mode(0o111)".to_string()
+        );
+    }
+
+    #[test]
+    fn filter_explain() {
+        let filter = OrFilter::new(
+            GlobFilter::new(
+                crate::strings_to_globs(&["lib/*.a".to_string(), "bin/*".to_string()]).unwrap(),
+            ),
+            AlwaysTrue::default(),
+        );
+        assert_eq!(
+            filter.explain(&path("bin/foo")),
+            Some("glob(\"bin/*\")".to_string())
+        );
+        assert_eq!(filter.explain(&path("share/foo")), Some("true".to_string()));
+        assert_eq!(AlwaysFalse::default().explain(&path("bin/foo")), None);
+    }
 }
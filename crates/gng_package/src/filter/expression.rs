@@ -0,0 +1,471 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! A small textual language to describe `Filter`s
+//!
+//! ```text
+//! expression := term ( "or" term )*
+//! term       := factor ( "and" factor )*
+//! factor     := "not" factor | "(" expression ")" | "true" | "false" | predicate
+//! predicate  := NAME "(" [ argument ( "," argument )* ] ")"
+//! argument   := STRING | NUMBER
+//! ```
+//!
+//! Supported predicates are:
+//!
+//! * `glob(PATTERN)`: The path matches the glob `PATTERN`
+//! * `regex(REGEX)`: The path matches the regular expression `REGEX`
+//! * `mime(PATTERN)`: The mime type (e.g. `text/plain`) matches the glob `PATTERN`
+//! * `magic(REGEX)`: The `file` output matches the regular expression `REGEX`
+//! * `type(TYPE)`: The path is of `TYPE` `"f"` (file), `"d"` (directory) or `"l"` (link)
+//! * `mode(MASK)`: All bits of `MASK` are set in the mode (e.g. `mode(0o4000)`)
+//! * `size(MIN, MAX)`: The size is between `MIN` and `MAX` bytes (inclusive)
+//! * `uid(ID)` and `gid(ID)`: The path is owned by user or group `ID`
+//! * `executable()`: The path is a file that is executable
+//! * `elf()`: The path is an ELF file
+//!
+//! Strings are enclosed in double quotes. Inside a string `\"` stands for a
+//! double quote and `\\` for a single backslash. All other backslashes are
+//! kept as they are, so `regex("\.a$")` works as expected.
+//!
+//! Numbers can be given in decimal, octal (`0o` prefix) or hex (`0x` prefix).
+//!
+//! The `files` of a packet definition mix plain glob patterns and filter
+//! expressions, see `parse_files`.
+
+use crate::filter::{
+    AlwaysFalse, AlwaysTrue, AndFilter, BoxedFilter, ElfFilter, ExecutableFilter, FileType,
+    FileTypeFilter, GlobFilter, GroupFilter, MagicFilter, MimeFilter, ModeFilter, NotFilter,
+    OrFilter, RegexFilter, SizeFilter, UserFilter,
+};
+
+use eyre::{eyre, WrapErr};
+
+// ----------------------------------------------------------------------
+// - Lexer:
+// ----------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Identifier(String),
+    String(String),
+    Number(u64),
+    OpenParenthesis,
+    CloseParenthesis,
+    Comma,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Identifier(i) => write!(f, "\"{i}\""),
+            Self::String(s) => write!(f, "string {s:?}"),
+            Self::Number(n) => write!(f, "number {n}"),
+            Self::OpenParenthesis => write!(f, "\"(\""),
+            Self::CloseParenthesis => write!(f, "\")\""),
+            Self::Comma => write!(f, "\",\""),
+        }
+    }
+}
+
+fn parse_number(input: &str, position: usize) -> eyre::Result<u64> {
+    let (digits, radix) = input.strip_prefix("0o").map_or_else(
+        || {
+            input
+                .strip_prefix("0x")
+                .map_or((input, 10), |hex| (hex, 16))
+        },
+        |octal| (octal, 8),
+    );
+    u64::from_str_radix(digits, radix)
+        .map_err(|e| eyre!("Invalid number \"{input}\" at position {position}: {e}"))
+}
+
+fn tokenize(input: &str) -> eyre::Result<Vec<(usize, Token)>> {
+    let mut result = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((position, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => result.push((position, Token::OpenParenthesis)),
+            ')' => result.push((position, Token::CloseParenthesis)),
+            ',' => result.push((position, Token::Comma)),
+            '"' => {
+                let unterminated = || eyre!("Unterminated string starting at position {position}.");
+                let mut value = String::new();
+                loop {
+                    match chars.next().ok_or_else(unterminated)? {
+                        (_, '"') => break,
+                        (_, '\\') => match chars.next().ok_or_else(unterminated)? {
+                            (_, c @ ('"' | '\\')) => value.push(c),
+                            (_, c) => {
+                                value.push('\\');
+                                value.push(c);
+                            }
+                        },
+                        (_, c) => value.push(c),
+                    }
+                }
+                result.push((position, Token::String(value)));
+            }
+            c if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut value = String::from(c);
+                while let Some((_, c)) =
+                    chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_')
+                {
+                    value.push(c);
+                }
+
+                if c.is_ascii_digit() {
+                    result.push((position, Token::Number(parse_number(&value, position)?)));
+                } else {
+                    result.push((position, Token::Identifier(value)));
+                }
+            }
+            c => return Err(eyre!("Unexpected character '{c}' at position {position}.")),
+        }
+    }
+
+    Ok(result)
+}
+
+// ----------------------------------------------------------------------
+// - Parser:
+// ----------------------------------------------------------------------
+
+enum Argument {
+    String(String),
+    Number(u64),
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    current: usize,
+    end_position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.current).map(|(_, t)| t)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.current)
+            .map_or(self.end_position, |(p, _)| *p)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let result = self.tokens.get(self.current).map(|(_, t)| t.clone());
+        self.current += 1;
+        result
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Identifier(i)) if i == keyword)
+    }
+
+    fn expect(&mut self, expected: &Token) -> eyre::Result<()> {
+        let position = self.position();
+        match self.next() {
+            Some(t) if &t == expected => Ok(()),
+            Some(t) => Err(eyre!(
+                "Expected {expected} but found {t} at position {position}."
+            )),
+            None => Err(eyre!("Expected {expected} but reached the end of input.")),
+        }
+    }
+
+    fn expression(&mut self) -> eyre::Result<BoxedFilter> {
+        let mut result = self.term()?;
+        while self.is_keyword("or") {
+            self.next();
+            result = Box::new(OrFilter::new(result, self.term()?));
+        }
+        Ok(result)
+    }
+
+    fn term(&mut self) -> eyre::Result<BoxedFilter> {
+        let mut result = self.factor()?;
+        while self.is_keyword("and") {
+            self.next();
+            result = Box::new(AndFilter::new(result, self.factor()?));
+        }
+        Ok(result)
+    }
+
+    fn factor(&mut self) -> eyre::Result<BoxedFilter> {
+        let position = self.position();
+        match self.next() {
+            Some(Token::OpenParenthesis) => {
+                let result = self.expression()?;
+                self.expect(&Token::CloseParenthesis)?;
+                Ok(result)
+            }
+            Some(Token::Identifier(i)) => match i.as_str() {
+                "not" => Ok(Box::new(NotFilter::new(self.factor()?))),
+                "true" => Ok(Box::new(AlwaysTrue::default())),
+                "false" => Ok(Box::new(AlwaysFalse::default())),
+                _ => {
+                    let arguments = self.arguments()?;
+                    predicate(&i, &arguments)
+                        .wrap_err(eyre!("Invalid predicate \"{i}\" at position {position}."))
+                }
+            },
+            Some(t) => Err(eyre!("Unexpected {t} at position {position}.")),
+            None => Err(eyre!("Unexpected end of input.")),
+        }
+    }
+
+    fn arguments(&mut self) -> eyre::Result<Vec<Argument>> {
+        self.expect(&Token::OpenParenthesis)?;
+
+        let mut result = Vec::new();
+        if self.peek() == Some(&Token::CloseParenthesis) {
+            self.next();
+            return Ok(result);
+        }
+
+        loop {
+            let position = self.position();
+            match self.next() {
+                Some(Token::String(s)) => result.push(Argument::String(s)),
+                Some(Token::Number(n)) => result.push(Argument::Number(n)),
+                Some(t) => return Err(eyre!("Unexpected {t} at position {position}.")),
+                None => return Err(eyre!("Unexpected end of input.")),
+            }
+
+            let position = self.position();
+            match self.next() {
+                Some(Token::Comma) => {}
+                Some(Token::CloseParenthesis) => return Ok(result),
+                Some(t) => return Err(eyre!("Unexpected {t} at position {position}.")),
+                None => return Err(eyre!("Unexpected end of input.")),
+            }
+        }
+    }
+}
+
+fn string_argument(arguments: &[Argument]) -> eyre::Result<&str> {
+    match arguments {
+        [Argument::String(s)] => Ok(s),
+        _ => Err(eyre!("Expected one string argument.")),
+    }
+}
+
+fn number_argument(arguments: &[Argument]) -> eyre::Result<u64> {
+    match arguments {
+        [Argument::Number(n)] => Ok(*n),
+        _ => Err(eyre!("Expected one number argument.")),
+    }
+}
+
+fn id_argument(arguments: &[Argument]) -> eyre::Result<u32> {
+    u32::try_from(number_argument(arguments)?).wrap_err("Id is out of range.")
+}
+
+fn no_arguments(arguments: &[Argument]) -> eyre::Result<()> {
+    if arguments.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!("Expected no arguments."))
+    }
+}
+
+fn predicate(name: &str, arguments: &[Argument]) -> eyre::Result<BoxedFilter> {
+    match name {
+        "glob" => Ok(Box::new(GlobFilter::new(crate::strings_to_globs(&[
+            string_argument(arguments)?.to_string(),
+        ])?))),
+        "regex" => Ok(Box::new(RegexFilter::new(
+            regex::Regex::new(string_argument(arguments)?).wrap_err("Failed to create RegEx.")?,
+        ))),
+        "mime" => Ok(Box::new(MimeFilter::new(
+            glob::Pattern::new(string_argument(arguments)?)
+                .map_err(|e| eyre!("Failed to create GLOB pattern: {}", e))?,
+        ))),
+        "magic" => Ok(Box::new(MagicFilter::new(
+            regex::Regex::new(string_argument(arguments)?).wrap_err("Failed to create RegEx.")?,
+        ))),
+        "type" => {
+            let file_type = match string_argument(arguments)? {
+                "f" => FileType::File,
+                "d" => FileType::Directory,
+                "l" => FileType::Link,
+                t => {
+                    return Err(eyre!(
+                        "Unknown type \"{t}\", expected \"f\", \"d\" or \"l\"."
+                    ))
+                }
+            };
+            Ok(Box::new(FileTypeFilter::new(file_type)))
+        }
+        "mode" => Ok(Box::new(ModeFilter::new(
+            u32::try_from(number_argument(arguments)?).wrap_err("Mode is out of range.")?,
+        ))),
+        "size" => match arguments {
+            [Argument::Number(minimum), Argument::Number(maximum)] => {
+                Ok(Box::new(SizeFilter::new(*minimum, *maximum)))
+            }
+            _ => Err(eyre!("Expected two number arguments.")),
+        },
+        "uid" => Ok(Box::new(UserFilter::new(id_argument(arguments)?))),
+        "gid" => Ok(Box::new(GroupFilter::new(id_argument(arguments)?))),
+        "executable" => {
+            no_arguments(arguments)?;
+            Ok(Box::new(ExecutableFilter::default()))
+        }
+        "elf" => {
+            no_arguments(arguments)?;
+            Ok(Box::new(ElfFilter::default()))
+        }
+        _ => Err(eyre!("Unknown predicate.")),
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Functions:
+// ----------------------------------------------------------------------
+
+/// Parse a filter `expression` into a `Filter`
+///
+/// # Errors
+///
+/// Returns an error if the `expression` is not valid.
+pub fn parse(expression: &str) -> eyre::Result<BoxedFilter> {
+    let mut parser = Parser {
+        tokens: tokenize(expression)?,
+        current: 0,
+        end_position: expression.len(),
+    };
+
+    let result = parser.expression()?;
+    parser.peek().map_or(Ok(result), |t| {
+        Err(eyre!(
+            "Unexpected {t} at position {} after end of expression.",
+            parser.position()
+        ))
+    })
+}
+
+/// Parse the `files` entries of a packet definition into one `Filter`
+///
+/// Each entry is either a plain glob pattern or a filter expression. An entry
+/// containing a `(` is a filter expression, all others are glob patterns. A
+/// glob pattern that contains a literal `(` can be written as `glob("...")`.
+/// The resulting filter matches if any of the entries match.
+///
+/// # Errors
+///
+/// Returns an error if one of the `entries` is not valid.
+pub fn parse_files(entries: &[String]) -> eyre::Result<BoxedFilter> {
+    let (expressions, globs): (Vec<_>, Vec<_>) = entries.iter().partition(|e| e.contains('('));
+
+    let globs = globs
+        .iter()
+        .map(|g| glob::Pattern::new(g).wrap_err(eyre!("Invalid glob pattern \"{}\".", g)))
+        .collect::<eyre::Result<Vec<_>>>()?;
+
+    expressions
+        .iter()
+        .try_fold(Box::new(GlobFilter::new(globs)) as BoxedFilter, |f, e| {
+            Ok(Box::new(OrFilter::new(
+                f,
+                parse(e).wrap_err(eyre!("Invalid filter expression \"{}\".", e))?,
+            )) as BoxedFilter)
+        })
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, parse_files};
+
+    use crate::path::Path;
+
+    fn file(input: &str, mode: u32, size: usize) -> Path {
+        Path::new_file_from_buffer(vec![b'x'; size], std::path::Path::new(input), mode, 0, 100)
+    }
+
+    #[test]
+    fn filter_expression_ok() {
+        let filter = parse(r#"glob("lib/**") and not (regex("\.a$") or size(0, 0))"#).unwrap();
+        assert!(filter.matches(&file("lib/libfoo.so", 0o755, 10)));
+        assert!(!filter.matches(&file("lib/libfoo.a", 0o644, 10)));
+        assert!(!filter.matches(&file("lib/empty", 0o644, 0)));
+        assert!(!filter.matches(&file("bin/foo", 0o755, 10)));
+
+        let filter = parse("mode(0o4000) or gid(0x64) and executable()").unwrap();
+        assert!(filter.matches(&file("bin/su", 0o4755, 10)));
+        assert!(filter.matches(&file("bin/foo", 0o755, 10)));
+        assert!(!filter.matches(&file("bin/foo", 0o644, 10)));
+
+        let filter = parse("true and not false").unwrap();
+        assert!(filter.matches(&file("foo", 0o644, 1)));
+
+        let filter = parse(r#"type("d")"#).unwrap();
+        assert!(!filter.matches(&file("foo", 0o644, 1)));
+        assert!(filter.matches(&Path::new_directory(
+            std::path::Path::new("foo"),
+            0o755,
+            0,
+            0
+        )));
+    }
+
+    #[test]
+    fn filter_expression_not_ok() {
+        assert!(parse("").is_err());
+        assert!(parse("glob(").is_err());
+        assert!(parse(r#"glob("foo""#).is_err());
+        assert!(parse(r#"glob("foo)"#).is_err());
+        assert!(parse(r#"glob("foo") and"#).is_err());
+        assert!(parse(r#"glob("foo") glob("bar")"#).is_err());
+        assert!(parse("glob(42)").is_err());
+        assert!(parse(r#"type("x")"#).is_err());
+        assert!(parse(r#"unknown("x")"#).is_err());
+        assert!(parse("mode(0o9)").is_err());
+        assert!(parse("size(1)").is_err());
+        assert!(parse("elf(1)").is_err());
+        assert!(parse("uid(99999999999)").is_err());
+        assert!(parse("glob(\"foo\") $").is_err());
+        assert!(parse(r#"glob("foo\"#).is_err());
+        assert!(parse(r#"glob("foo\")"#).is_err());
+    }
+
+    #[test]
+    fn files_with_globs_and_expressions() {
+        let files = parse_files(&[
+            "bin/*".to_string(),
+            r#"glob("lib/**") and not regex("\.a$")"#.to_string(),
+        ])
+        .unwrap();
+        assert!(files.matches(&file("bin/foo", 0o755, 1)));
+        assert!(files.matches(&file("lib/libfoo.so", 0o755, 1)));
+        assert!(!files.matches(&file("lib/libfoo.a", 0o644, 1)));
+        assert!(!files.matches(&file("share/foo", 0o644, 1)));
+
+        assert!(!parse_files(&[]).unwrap().matches(&file("foo", 0o644, 1)));
+        assert!(parse_files(&["[".to_string()]).is_err());
+        assert!(parse_files(&["glob(".to_string()]).is_err());
+    }
+
+    #[test]
+    fn filter_expression_escapes() {
+        let filter = parse(r#"glob("a\"b\\c")"#).unwrap();
+        assert_eq!(filter.description(), r#"glob("a\"b\\c")"#);
+        assert!(filter.matches(&file("a\"b\\c", 0o644, 1)));
+
+        let filter = parse(r#"regex("\.a$")"#).unwrap();
+        assert!(filter.matches(&file("lib/libfoo.a", 0o644, 1)));
+        assert!(!filter.matches(&file("lib/libfoo_a", 0o644, 1)));
+
+        let filter = parse(r#"regex("^bin/\w+$")"#).unwrap();
+        assert!(filter.matches(&file("bin/foo", 0o755, 1)));
+        assert!(!filter.matches(&file("bin/foo.sh", 0o755, 1)));
+    }
+}
@@ -0,0 +1,253 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Find the interpreters scripts need at runtime
+
+use crate::path::Path;
+use crate::{InterpreterPolicy, PacketDefinition};
+
+use gng_core::{Name, Names};
+
+use eyre::eyre;
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// The maximum length of a shebang line that is considered
+const SHEBANG_LENGTH: usize = 256;
+
+/// Extract the interpreter from the first line of a script
+///
+/// `/usr/bin/env` is looked through and interpreters in `/bin` and `/sbin` are
+/// moved into `/usr`.
+fn parse_shebang(head: &[u8]) -> Option<std::path::PathBuf> {
+    let line = head.strip_prefix(b"#!")?;
+    let line = line.split(|b| *b == b'\n').next()?;
+    let line = std::str::from_utf8(line).ok()?;
+
+    let mut words = line.split_whitespace();
+    let mut interpreter = std::path::PathBuf::from(words.next()?);
+
+    if interpreter.file_name() == Some(std::ffi::OsStr::new("env")) {
+        let command = words.find(|w| !w.starts_with('-') && !w.contains('='))?;
+        interpreter = std::path::Path::new("/usr/bin").join(command);
+    }
+
+    if !interpreter.is_absolute() {
+        return Some(interpreter);
+    }
+
+    for (old, new) in [("/bin", "/usr/bin"), ("/sbin", "/usr/sbin")] {
+        if let Ok(rest) = interpreter.strip_prefix(old) {
+            return Some(std::path::Path::new(new).join(rest));
+        }
+    }
+    Some(interpreter)
+}
+
+/// Find the interpreter of an executable script `path`
+///
+/// # Errors
+/// Returns an error if the file contents can not be read.
+fn find_interpreter(path: &Path) -> eyre::Result<Option<std::path::PathBuf>> {
+    if !path.is_file() || path.mode() & 0o111 == 0 {
+        return Ok(None);
+    }
+
+    let head = path
+        .file_contents()
+        .expect("Files have contents!")
+        .head(SHEBANG_LENGTH)?;
+    Ok(parse_shebang(&head))
+}
+
+// ----------------------------------------------------------------------
+// - Interpreters:
+// ----------------------------------------------------------------------
+
+#[derive(Debug)]
+struct InterpreterUse {
+    script: std::path::PathBuf,
+    packet: Name,
+    interpreter: std::path::PathBuf,
+}
+
+/// Interpreters used by scripts and the paths provided by the `Packet`s of
+/// this build
+#[derive(Debug, Default)]
+pub struct Interpreters {
+    uses: Vec<InterpreterUse>,
+    provided: std::collections::BTreeMap<std::path::PathBuf, Name>,
+}
+
+impl Interpreters {
+    /// Record `path` as provided by the first of the `packets` claiming it,
+    /// and record the interpreter it needs (if any)
+    ///
+    /// # Errors
+    /// Returns an error if the file contents can not be read.
+    pub fn record(&mut self, path: &Path, packets: &[PacketDefinition]) -> eyre::Result<()> {
+        let packet = match packets.iter().find(|p| p.filter.matches(path)) {
+            Some(p) => &p.data.name,
+            None => return Ok(()),
+        };
+
+        self.provided.insert(
+            std::path::Path::new("/usr").join(path.as_path()),
+            packet.clone(),
+        );
+
+        if let Some(interpreter) = find_interpreter(path)? {
+            tracing::trace!(
+                "\"{}\" needs interpreter \"{}\".",
+                path.as_path().to_string_lossy(),
+                interpreter.to_string_lossy()
+            );
+            self.uses.push(InterpreterUse {
+                script: path.as_path().to_path_buf(),
+                packet: packet.clone(),
+                interpreter,
+            });
+        }
+        Ok(())
+    }
+
+    /// Find the dependencies each `Packet` needs for its interpreters
+    ///
+    /// Interpreters are looked up in the `Packet`s of this build first and
+    /// in `providers` second. No `Packet` depends on itself.
+    ///
+    /// # Errors
+    /// Returns an error listing unresolvable interpreters if `policy` is
+    /// `InterpreterPolicy::Fail`.
+    pub fn resolve(
+        &self,
+        providers: &std::collections::BTreeMap<std::path::PathBuf, Name>,
+        policy: InterpreterPolicy,
+    ) -> eyre::Result<std::collections::BTreeMap<Name, Names>> {
+        let mut result = std::collections::BTreeMap::<Name, Names>::new();
+        let mut errors = Vec::new();
+
+        for u in &self.uses {
+            match self
+                .provided
+                .get(&u.interpreter)
+                .or_else(|| providers.get(&u.interpreter))
+            {
+                Some(provider) if *provider == u.packet => {}
+                Some(provider) => {
+                    result
+                        .entry(u.packet.clone())
+                        .or_default()
+                        .insert(provider.clone());
+                }
+                None => {
+                    let message = format!(
+                        "Interpreter \"{}\" of \"{}\" is not provided by any known packet.",
+                        u.interpreter.to_string_lossy(),
+                        u.script.to_string_lossy()
+                    );
+                    match policy {
+                        InterpreterPolicy::Warn => tracing::warn!("{}", message),
+                        InterpreterPolicy::Fail => errors.push(message),
+                    }
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(result)
+        } else {
+            Err(eyre!("Packaging failed:\n    {}", errors.join("\n    ")))
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_util::packet;
+
+    fn script(path: &str, contents: &[u8], mode: u32) -> Path {
+        Path::new_file_from_buffer(contents.to_vec(), std::path::Path::new(path), mode, 0, 0)
+    }
+
+    fn name(input: &str) -> Name {
+        Name::try_from(input).unwrap()
+    }
+
+    #[test]
+    fn parse_shebang_ok() {
+        let check = |input: &[u8]| parse_shebang(input).map(|p| p.to_string_lossy().to_string());
+
+        assert_eq!(
+            check(b"#!/usr/bin/python3\n"),
+            Some("/usr/bin/python3".into())
+        );
+        assert_eq!(check(b"#!/bin/sh -e\necho"), Some("/usr/bin/sh".into()));
+        assert_eq!(check(b"#! /sbin/foo"), Some("/usr/sbin/foo".into()));
+        assert_eq!(
+            check(b"#!/usr/bin/env perl -w\n"),
+            Some("/usr/bin/perl".into())
+        );
+        assert_eq!(
+            check(b"#!/usr/bin/env -S LANG=C bash\n"),
+            Some("/usr/bin/bash".into())
+        );
+        assert_eq!(check(b"#!/usr/bin/env\n"), None);
+        assert_eq!(check(b"\x7fELF"), None);
+        assert_eq!(check(b""), None);
+    }
+
+    #[test]
+    fn interpreters_resolve() {
+        let packets = vec![
+            packet("foo", &["bin/*"]),
+            packet("foo_python", &["lib/python/**"]),
+        ];
+        let mut interpreters = Interpreters::default();
+        for p in [
+            script("bin/foo", b"#!/usr/bin/foo_shell\n", 0o755),
+            script("bin/foo_shell", b"\x7fELF", 0o755),
+            script("bin/bar", b"#!/usr/bin/env perl\n", 0o755),
+            script("lib/python/foo.py", b"#!/usr/bin/python3\n", 0o755),
+            script("lib/python/bar.py", b"#!/usr/bin/unknown\n", 0o644),
+        ] {
+            interpreters.record(&p, &packets).unwrap();
+        }
+
+        let providers = [
+            (std::path::PathBuf::from("/usr/bin/perl"), name("perl")),
+            (std::path::PathBuf::from("/usr/bin/python3"), name("python")),
+        ]
+        .into_iter()
+        .collect();
+
+        let result = interpreters
+            .resolve(&providers, InterpreterPolicy::Fail)
+            .unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[&name("foo")], Names::from(name("perl")));
+        assert_eq!(result[&name("foo_python")], Names::from(name("python")));
+
+        assert_eq!(
+            interpreters
+                .resolve(&std::collections::BTreeMap::new(), InterpreterPolicy::Fail)
+                .unwrap_err()
+                .to_string(),
+            "Packaging failed:\n    \
+             Interpreter \"/usr/bin/perl\" of \"bin/bar\" is not provided by any known packet.\n    \
+             Interpreter \"/usr/bin/python3\" of \"lib/python/foo.py\" is not provided by any known packet."
+        );
+        assert!(interpreters
+            .resolve(&std::collections::BTreeMap::new(), InterpreterPolicy::Warn)
+            .unwrap()
+            .is_empty());
+    }
+}
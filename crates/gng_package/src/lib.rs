@@ -21,14 +21,25 @@ use eyre::{eyre, WrapErr};
 // - Modules:
 // ----------------------------------------------------------------------
 
+pub mod attributes;
+pub(crate) mod claims;
 pub(crate) mod deterministic_directory_iterator;
+pub(crate) mod elf;
 pub mod filter;
+pub(crate) mod interpreters;
+pub(crate) mod owned_directories;
 pub(crate) mod packager;
 pub(crate) mod path;
+pub mod report;
+pub mod sbom;
+pub(crate) mod symlinks;
+#[cfg(test)]
+pub(crate) mod test_util;
 
 // Re-export:
 pub use gng_packet_io::{
     BinaryFacet, BinaryFacetDefinition, BinaryFacetUsage, BinaryPacketDefinition,
+    ExistingPacketPolicy,
 };
 
 // ----------------------------------------------------------------------
@@ -37,14 +48,156 @@ pub use gng_packet_io::{
 
 pub use gng_core::{Name, Names, Version};
 
-use std::rc::Rc;
+use std::sync::Arc;
+
+/// How to handle problems found during packaging
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ClaimPolicy {
+    /// Log a warning and continue
+    Warn,
+    /// Fail packaging
+    Fail,
+}
+
+impl std::str::FromStr for ClaimPolicy {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(Self::Warn),
+            "fail" => Ok(Self::Fail),
+            _ => Err(eyre!(
+                "Unknown policy \"{}\", expected \"warn\" or \"fail\".",
+                s
+            )),
+        }
+    }
+}
+
+/// How to handle scripts whose interpreter no known packet provides
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InterpreterPolicy {
+    /// Log a warning and package the script without a dependency
+    Warn,
+    /// Fail packaging
+    Fail,
+}
+
+impl std::str::FromStr for InterpreterPolicy {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(Self::Warn),
+            "fail" => Ok(Self::Fail),
+            _ => Err(eyre!(
+                "Unknown policy \"{}\", expected \"warn\" or \"fail\".",
+                s
+            )),
+        }
+    }
+}
+
+/// How to handle absolute symlinks into the install directory of the build
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LinkPolicy {
+    /// Redirect the link to `/usr` and make it relative
+    Fix,
+    /// Fail packaging
+    Reject,
+}
+
+impl std::str::FromStr for LinkPolicy {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fix" => Ok(Self::Fix),
+            "reject" => Ok(Self::Reject),
+            _ => Err(eyre!(
+                "Unknown policy \"{}\", expected \"fix\" or \"reject\".",
+                s
+            )),
+        }
+    }
+}
+
+/// Options used while packaging
+#[derive(Clone, Debug)]
+pub struct PackagingOptions {
+    /// The directory to write packets into
+    pub output_directory: std::path::PathBuf,
+    /// What to do about packet files that exist already
+    pub existing_packets: ExistingPacketPolicy,
+    /// Write packets concurrently, using one worker thread per packet
+    ///
+    /// The resulting packets are byte-identical to those written serially.
+    pub parallel: bool,
+    /// Record which packet claimed which path and why
+    pub explain: bool,
+    /// What to do about files that no packet claims
+    pub unclaimed_files: ClaimPolicy,
+    /// What to do about files that several packets claim
+    pub ambiguous_files: ClaimPolicy,
+    /// The directory the build installed into, as seen by the build
+    pub install_prefix: Option<std::path::PathBuf>,
+    /// The directory the build worked in, as seen by the build
+    pub work_prefix: Option<std::path::PathBuf>,
+    /// What to do about absolute links into `install_prefix`
+    pub staging_links: LinkPolicy,
+    /// Packets providing interpreters, by absolute path of the interpreter
+    ///
+    /// Interpreters provided by the packets being built are found automatically.
+    pub interpreter_providers: std::collections::BTreeMap<std::path::PathBuf, Name>,
+    /// What to do about scripts whose interpreter is not provided by any known packet
+    pub unresolved_interpreters: InterpreterPolicy,
+    /// Mode and ownership overrides, applied in order
+    pub attribute_overrides: Vec<attributes::AttributeOverride>,
+    /// Information about the build to put into SBOMs, `None` to skip SBOMs
+    pub build_information: Option<sbom::BuildInformation>,
+    /// The SBOM formats to generate
+    pub sbom_formats: Vec<sbom::SbomFormat>,
+}
+
+impl Default for PackagingOptions {
+    fn default() -> Self {
+        Self {
+            output_directory: std::path::PathBuf::from("."),
+            existing_packets: ExistingPacketPolicy::Overwrite,
+            parallel: true,
+            explain: false,
+            unclaimed_files: ClaimPolicy::Warn,
+            ambiguous_files: ClaimPolicy::Warn,
+            install_prefix: None,
+            work_prefix: None,
+            staging_links: LinkPolicy::Fix,
+            interpreter_providers: std::collections::BTreeMap::new(),
+            unresolved_interpreters: InterpreterPolicy::Fail,
+            attribute_overrides: Vec::new(),
+            build_information: None,
+            sbom_formats: vec![sbom::SbomFormat::Spdx],
+        }
+    }
+}
+
+/// The result of packaging
+#[derive(Debug, Default)]
+pub struct PackagingResult {
+    /// The packet files that were written
+    pub packets: Vec<std::path::PathBuf>,
+    /// A report on which packet claimed which path (if requested)
+    pub report: Option<report::Report>,
+    /// The SBOM documents embedded into all packets
+    pub sbom: Vec<sbom::SbomDocument>,
+}
 
 /// A definition of one `Packet`
+#[derive(Clone)]
 pub struct PacketDefinition {
     data: gng_packet_io::BinaryPacketDefinition,
 
     merged_facets: Names,
-    filter: Rc<dyn filter::Filter>,
+    filter: Arc<dyn filter::Filter>,
     is_empty: bool,
 }
 
@@ -53,7 +206,7 @@ impl PacketDefinition {
     pub fn new(
         data: gng_packet_io::BinaryPacketDefinition,
         merged_facets: Names,
-        filter: Rc<dyn filter::Filter>,
+        filter: Arc<dyn filter::Filter>,
         is_empty: bool,
     ) -> Self {
         Self {
@@ -68,12 +221,12 @@ impl PacketDefinition {
 /// A definition of one `Facet`
 pub struct FacetDefinition {
     name: Option<Name>,
-    filter: Rc<dyn filter::Filter>,
+    filter: Arc<dyn filter::Filter>,
 }
 
 impl FacetDefinition {
     /// Constructor
-    pub fn new(name: Option<Name>, filter: Rc<dyn filter::Filter>) -> Self {
+    pub fn new(name: Option<Name>, filter: Arc<dyn filter::Filter>) -> Self {
         Self { name, filter }
     }
 }
@@ -81,19 +234,51 @@ impl FacetDefinition {
 // - Functions:
 // ----------------------------------------------------------------------
 
-/// Package up the directory `package_root_directory`
+/// Apply all transformations to a `path` found in the install directory
+fn prepare_path(path: path::Path, options: &PackagingOptions) -> eyre::Result<path::Path> {
+    Ok(attributes::apply(
+        symlinks::normalize(path, options)?,
+        &options.attribute_overrides,
+    ))
+}
+
+/// Generate the SBOM documents requested in `options`
+fn generate_sbom(
+    packets: &[PacketDefinition],
+    files: &[sbom::FileRecord],
+    options: &PackagingOptions,
+) -> eyre::Result<Vec<sbom::SbomDocument>> {
+    let build = match &options.build_information {
+        Some(b) => b,
+        None => return Ok(Vec::new()),
+    };
+    let packets = packets.iter().map(|p| p.data.clone()).collect::<Vec<_>>();
+
+    options
+        .sbom_formats
+        .iter()
+        .map(|f| sbom::generate(*f, build, &packets, files))
+        .collect()
+}
+
+/// Package up the directory `package_usr_directory`
+///
+/// Directories that also exist in `base_usr_directory` (the `usr` directory
+/// the build was run on) are provided by other packets and are not packaged.
 ///
 /// # Errors
-/// Error out if the `package_root` is not a directory.
+/// Error out if the `package_usr_directory` is not a directory.
 #[tracing::instrument(level = "debug", skip(packets, facets))]
 pub fn package(
     package_usr_directory: &std::path::Path,
+    base_usr_directory: &std::path::Path,
     packets: &[PacketDefinition],
     facets: &[FacetDefinition],
-) -> eyre::Result<Vec<std::path::PathBuf>> {
+    options: &PackagingOptions,
+) -> eyre::Result<PackagingResult> {
     if packets.is_empty() || facets.is_empty() {
         tracing::warn!("Packet generation SKIPPED: No packets/facets, so nothing to do.");
-        return Ok(Vec::new());
+        return Ok(PackagingResult::default());
     }
 
     tracing::info!(
@@ -101,15 +286,94 @@ pub fn package(
         &package_usr_directory.to_string_lossy()
     );
 
-    let mut packager = crate::packager::create_packager(packets, facets)?;
+    std::fs::create_dir_all(&options.output_directory).wrap_err(eyre!(
+        "Failed to create output directory \"{}\".",
+        options.output_directory.to_string_lossy()
+    ))?;
+
+    let owned_directories = Arc::new(crate::owned_directories::OwnedDirectories::scan(
+        package_usr_directory,
+        base_usr_directory,
+        options,
+    )?);
 
+    let mut claim_issues = Vec::new();
+    let mut interpreters = interpreters::Interpreters::default();
+    let mut needed_libraries = elf::NeededLibraries::default();
+    let mut file_records = Vec::new();
     for it in crate::deterministic_directory_iterator::DeterministicDirectoryIterator::new(
         package_usr_directory,
     )? {
-        packager.package(&it?)?;
+        let it = prepare_path(it?, options)?;
+        claim_issues.extend(claims::check(&it, packets));
+        interpreters.record(&it, packets)?;
+        needed_libraries.record(&it, packets)?;
+        if options.build_information.is_some() {
+            if let Some(p) = packets.iter().find(|p| p.filter.matches(&it)) {
+                file_records.extend(sbom::FileRecord::new(&it, &p.data.name)?);
+            }
+        }
+    }
+    claims::report(
+        &claim_issues,
+        options.unclaimed_files,
+        options.ambiguous_files,
+    )?;
+
+    let interpreter_dependencies = interpreters.resolve(
+        &options.interpreter_providers,
+        options.unresolved_interpreters,
+    )?;
+    let packets = packets
+        .iter()
+        .map(|p| {
+            let mut p = p.clone();
+            if let Some(dependencies) = interpreter_dependencies.get(&p.data.name) {
+                tracing::info!(
+                    "Packet \"{}\" depends on \"{}\" for interpreters.",
+                    &p.data.name,
+                    dependencies
+                );
+                p.data
+                    .dependencies
+                    .merge(&dependencies.into_iter().cloned().collect::<Vec<_>>());
+            }
+            p.data.needed_libraries = needed_libraries.get(&p.data.name);
+            p
+        })
+        .collect::<Vec<_>>();
+
+    let sbom = generate_sbom(&packets, &file_records, options)?;
+
+    let mut packager =
+        crate::packager::create_packager(&packets, facets, &owned_directories, &sbom, options)?;
+    let mut report = options.explain.then(report::Report::default);
+
+    for it in crate::deterministic_directory_iterator::DeterministicDirectoryIterator::new(
+        package_usr_directory,
+    )? {
+        let it = prepare_path(it?, options)?;
+        if it.is_dir() && !owned_directories.is_owned(it.as_path()) {
+            tracing::trace!(
+                "Skipping directory \"{}\": Not created by this build.",
+                it.as_path().to_string_lossy()
+            );
+            continue;
+        }
+        if let Some(report) = &mut report {
+            report.entries.push(report::ReportEntry::new(
+                it.as_path(),
+                packager.candidates(&it),
+            ));
+        }
+        packager.package(&it)?;
     }
 
-    packager.finish()
+    Ok(PackagingResult {
+        packets: packager.finish()?,
+        report,
+        sbom,
+    })
 }
 
 /// Turn a `String` slice into a `Vec<glob::Pattern>`
@@ -0,0 +1,207 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Keep track of directories that were created by the build.
+
+use crate::deterministic_directory_iterator::DeterministicDirectoryIterator;
+use crate::path::Path;
+
+// ----------------------------------------------------------------------
+// - OwnedDirectories:
+// ----------------------------------------------------------------------
+
+/// The set of directories that got created by the build
+///
+/// Directories found in the base root directory (containing the dependencies
+/// of the build) are owned by the packets providing them, so they must not be
+/// claimed again by the packets of this build.
+#[derive(Debug, Default)]
+pub struct OwnedDirectories {
+    directories: std::collections::BTreeMap<std::path::PathBuf, Path>,
+}
+
+impl OwnedDirectories {
+    /// Scan `package_usr_directory` for directories that do not exist in `base_usr_directory`
+    ///
+    /// The directories get the same transformations as all other paths
+    /// found in the install directory, so attribute overrides apply to
+    /// directories that get packaged implicitly as parents of other paths.
+    ///
+    /// # Errors
+    /// Returns an error if `package_usr_directory` can not get scanned.
+    #[tracing::instrument(level = "debug", skip(options))]
+    pub fn scan(
+        package_usr_directory: &std::path::Path,
+        base_usr_directory: &std::path::Path,
+        options: &crate::PackagingOptions,
+    ) -> eyre::Result<Self> {
+        let mut directories = std::collections::BTreeMap::new();
+
+        for it in DeterministicDirectoryIterator::new(package_usr_directory)? {
+            let it = crate::prepare_path(it?, options)?;
+            if !it.is_dir() {
+                continue;
+            }
+
+            if base_usr_directory.join(it.as_path()).is_dir() {
+                tracing::trace!(
+                    "Directory \"{}\" is provided by the base root.",
+                    it.as_path().to_string_lossy()
+                );
+            } else {
+                directories.insert(it.as_path().to_path_buf(), it);
+            }
+        }
+
+        Ok(Self { directories })
+    }
+
+    /// Is `path` a directory that was created by the build?
+    #[must_use]
+    pub fn is_owned(&self, path: &std::path::Path) -> bool {
+        self.directories.contains_key(path)
+    }
+
+    /// The owned parent directories of `path`, outermost directory first
+    #[must_use]
+    pub fn owned_parents(&self, path: &std::path::Path) -> Vec<&Path> {
+        let mut result = path
+            .ancestors()
+            .skip(1)
+            .filter_map(|p| self.directories.get(p))
+            .collect::<Vec<_>>();
+        result.reverse();
+        result
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(path: &std::path::Path) {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(path)
+            .unwrap();
+    }
+
+    #[test]
+    fn owned_directories_scan() {
+        let tmp = tempfile::Builder::new()
+            .prefix("owned-dirs-")
+            .rand_bytes(8)
+            .tempdir()
+            .expect("Failed to create temporary directory.");
+
+        let base = tmp.path().join("base");
+        let install = tmp.path().join("install");
+
+        std::fs::create_dir_all(base.join("bin")).unwrap();
+        std::fs::create_dir_all(base.join("lib")).unwrap();
+
+        std::fs::create_dir_all(install.join("bin")).unwrap();
+        std::fs::create_dir_all(install.join("lib/foo/plugins")).unwrap();
+        std::fs::create_dir_all(install.join("share/foo")).unwrap();
+        touch(&install.join("bin/foo"));
+        touch(&install.join("lib/foo/plugins/bar.so"));
+
+        let owned =
+            OwnedDirectories::scan(&install, &base, &crate::PackagingOptions::default()).unwrap();
+
+        assert!(!owned.is_owned(std::path::Path::new("bin")));
+        assert!(!owned.is_owned(std::path::Path::new("lib")));
+        assert!(owned.is_owned(std::path::Path::new("lib/foo")));
+        assert!(owned.is_owned(std::path::Path::new("lib/foo/plugins")));
+        assert!(owned.is_owned(std::path::Path::new("share")));
+        assert!(owned.is_owned(std::path::Path::new("share/foo")));
+        assert!(!owned.is_owned(std::path::Path::new("bin/foo")));
+
+        assert_eq!(
+            owned.owned_parents(std::path::Path::new("bin/foo")),
+            Vec::<&Path>::new()
+        );
+        assert_eq!(
+            owned
+                .owned_parents(std::path::Path::new("lib/foo/plugins/bar.so"))
+                .iter()
+                .map(|p| p.as_path().to_path_buf())
+                .collect::<Vec<_>>(),
+            vec![
+                std::path::PathBuf::from("lib/foo"),
+                std::path::PathBuf::from("lib/foo/plugins")
+            ]
+        );
+    }
+
+    #[test]
+    fn owned_directories_missing_base() {
+        let tmp = tempfile::Builder::new()
+            .prefix("owned-dirs-mb-")
+            .rand_bytes(8)
+            .tempdir()
+            .expect("Failed to create temporary directory.");
+
+        std::fs::create_dir_all(tmp.path().join("install/bin")).unwrap();
+
+        let owned = OwnedDirectories::scan(
+            &tmp.path().join("install"),
+            &tmp.path().join("base"),
+            &crate::PackagingOptions::default(),
+        )
+        .unwrap();
+        assert!(owned.is_owned(std::path::Path::new("bin")));
+    }
+
+    #[test]
+    fn owned_directories_attribute_overrides() {
+        let tmp = tempfile::Builder::new()
+            .prefix("owned-dirs-ao-")
+            .rand_bytes(8)
+            .tempdir()
+            .expect("Failed to create temporary directory.");
+
+        let install = tmp.path().join("install");
+        std::fs::create_dir_all(install.join("lib/foo/plugins")).unwrap();
+        touch(&install.join("lib/foo/plugins/bar.so"));
+
+        let users = crate::attributes::UserDatabase::parse(
+            "root:x:0:0:root:/root:/bin/bash\n",
+            "root:x:0:root\nwheel:x:998:\n",
+        )
+        .unwrap();
+        let options = crate::PackagingOptions {
+            attribute_overrides: vec![crate::attributes::AttributeOverride::new(
+                "lib/foo",
+                Some("0750"),
+                None,
+                Some("wheel"),
+                &users,
+            )
+            .unwrap()],
+            ..crate::PackagingOptions::default()
+        };
+
+        let owned = OwnedDirectories::scan(&install, &tmp.path().join("base"), &options).unwrap();
+        let parents = owned.owned_parents(std::path::Path::new("lib/foo/plugins/bar.so"));
+        assert_eq!(
+            parents
+                .iter()
+                .map(|p| p.as_path().to_path_buf())
+                .collect::<Vec<_>>(),
+            vec![
+                std::path::PathBuf::from("lib"),
+                std::path::PathBuf::from("lib/foo"),
+                std::path::PathBuf::from("lib/foo/plugins")
+            ]
+        );
+        assert_eq!((parents[1].mode(), parents[1].group_id()), (0o750, 998));
+        assert_ne!(parents[2].group_id(), 998);
+    }
+}
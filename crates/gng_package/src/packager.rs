@@ -3,22 +3,37 @@
 
 //! The code to control the `Packet` creation process.
 
+use crate::owned_directories::OwnedDirectories;
 use crate::{FacetDefinition, PacketDefinition};
 
+use std::sync::Arc;
+
 pub mod filtered;
 pub mod storage;
 pub mod switching;
+pub mod threaded;
 
 // ----------------------------------------------------------------------
 // - Helper:
 // ----------------------------------------------------------------------
 
-#[tracing::instrument(level = "debug", skip(packet, facet))]
+#[tracing::instrument(level = "debug", skip(packet, facet, owned_directories, sbom, options))]
 fn storage_packager(
     packet: &PacketDefinition,
     facet: &FacetDefinition,
+    owned_directories: &Arc<OwnedDirectories>,
+    sbom: &[crate::sbom::SbomDocument],
+    options: &crate::PackagingOptions,
 ) -> eyre::Result<BoxedPackager> {
-    Ok(Box::new(storage::StoragePackager::new(packet, facet)?))
+    let packager =
+        storage::StoragePackager::new(packet, facet, owned_directories.clone(), sbom, options)?;
+    if options.parallel {
+        Ok(Box::new(threaded::ThreadedPackager::new(Box::new(
+            packager,
+        ))?))
+    } else {
+        Ok(Box::new(packager))
+    }
 }
 
 // ----------------------------------------------------------------------
@@ -41,14 +56,22 @@ pub trait Packager {
     /// # Errors
     /// Return an `eyre::Result` when something goes wrong.
     fn finish(&mut self) -> eyre::Result<Vec<std::path::PathBuf>>;
+
+    /// The candidates that would claim `path`, in order of priority
+    ///
+    /// `Packager`s at the leaves of the tree are a nameless candidate without
+    /// any filters, `Packager`s higher up fill in the details.
+    fn candidates(&self, _path: &crate::path::Path) -> Vec<crate::report::Candidate> {
+        vec![crate::report::Candidate::default()]
+    }
 }
 
 /// A boxed `Packager`
 pub type BoxedPackager = Box<dyn Packager>;
 
 /// A factory for a `Packager`
-pub type PackagerFactory =
-    dyn Fn(&PacketDefinition, &FacetDefinition) -> eyre::Result<BoxedPackager>;
+pub type PackagerFactory<'a> =
+    dyn Fn(&PacketDefinition, &FacetDefinition) -> eyre::Result<BoxedPackager> + 'a;
 
 // ----------------------------------------------------------------------
 // - Helper:
@@ -58,7 +81,7 @@ pub type PackagerFactory =
 fn setup_faceted_action(
     packet: &PacketDefinition,
     facets: &[FacetDefinition],
-    packager_factory: &PackagerFactory,
+    packager_factory: &PackagerFactory<'_>,
 ) -> eyre::Result<BoxedPackager> {
     let children = facets
         .iter()
@@ -91,14 +114,24 @@ fn setup_faceted_action(
 
 /// Create a packet function for a set of `PacketDefinition`s and `FacetDefinition`s
 ///
+/// The `sbom` documents are embedded into every packet.
+///
 /// # Errors
 /// Returns an `eyre::Result` when something goes wrong.
-#[tracing::instrument(level = "trace", skip(packets, facets))]
+#[tracing::instrument(
+    level = "trace",
+    skip(packets, facets, owned_directories, sbom, options)
+)]
 pub fn create_packager(
     packets: &[PacketDefinition],
     facets: &[FacetDefinition],
+    owned_directories: &Arc<OwnedDirectories>,
+    sbom: &[crate::sbom::SbomDocument],
+    options: &crate::PackagingOptions,
 ) -> eyre::Result<BoxedPackager> {
-    create_packager_with_factory(packets, facets, &storage_packager)
+    create_packager_with_factory(packets, facets, &|p, f| {
+        storage_packager(p, f, owned_directories, sbom, options)
+    })
 }
 
 /// Create a packet function for a set of `PacketDefinition`s and `FacetDefinition`s
@@ -109,7 +142,7 @@ pub fn create_packager(
 fn create_packager_with_factory(
     packets: &[PacketDefinition],
     facets: &[FacetDefinition],
-    packager_factory: &PackagerFactory,
+    packager_factory: &PackagerFactory<'_>,
 ) -> eyre::Result<BoxedPackager> {
     tracing::debug!(
         "Creating packagers for {} packets and {} facets.",
@@ -134,3 +167,161 @@ fn create_packager_with_factory(
     tracing::trace!("Master packager created for {} packets.", children.len());
     Ok(Box::new(switching::SwitchingPackager::new(children)))
 }
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::filter::AlwaysTrue;
+    use crate::report::Candidate;
+    use crate::test_util::packet;
+
+    struct NullPackager {}
+
+    impl Packager for NullPackager {
+        fn package(&mut self, _path: &crate::path::Path) -> eyre::Result<bool> {
+            Ok(true)
+        }
+
+        fn finish(&mut self) -> eyre::Result<Vec<std::path::PathBuf>> {
+            Ok(Vec::new())
+        }
+
+        fn debug_name(&self) -> String {
+            "[ Null ]".to_string()
+        }
+    }
+
+    #[test]
+    fn packager_candidates() {
+        let packets = vec![packet("foo", &["bin/*"]), packet("all", &["**"])];
+        let facets = vec![FacetDefinition::new(None, Arc::new(AlwaysTrue::default()))];
+        let packager = create_packager_with_factory(&packets, &facets, &|_, _| {
+            Ok(Box::new(NullPackager {}) as BoxedPackager)
+        })
+        .unwrap();
+
+        let path = crate::path::Path::new_file_from_buffer(
+            Vec::new(),
+            std::path::Path::new("bin/foo"),
+            0o755,
+            0,
+            0,
+        );
+        assert_eq!(
+            packager.candidates(&path),
+            vec![
+                Candidate {
+                    name: "foo".to_string(),
+                    filters: vec!["glob(\"bin/*\")".to_string(), "true".to_string()],
+                },
+                Candidate {
+                    name: "all".to_string(),
+                    filters: vec!["glob(\"**\")".to_string(), "true".to_string()],
+                },
+            ]
+        );
+    }
+
+    fn package_tree(parallel: bool) -> Vec<(String, Vec<u8>, String)> {
+        use sha2::Digest;
+
+        let tmp = tempfile::Builder::new()
+            .prefix("packager-")
+            .rand_bytes(8)
+            .tempdir()
+            .expect("Failed to create temporary directory.");
+        let base = tmp.path().join("base");
+        let install = tmp.path().join("install");
+        let output = tmp.path().join("output");
+        std::fs::create_dir_all(&base).unwrap();
+        std::fs::create_dir_all(install.join("bin")).unwrap();
+        std::fs::create_dir_all(install.join("share/doc/foo")).unwrap();
+        std::fs::write(install.join("bin/foo"), b"foo binary").unwrap();
+        std::fs::write(install.join("bin/bar"), b"bar binary").unwrap();
+        std::fs::write(install.join("share/doc/foo/README"), b"Read me!").unwrap();
+
+        let packets = vec![packet("foo", &["bin/**"]), packet("doc", &["share/**"])];
+        let facets = vec![FacetDefinition::new(None, Arc::new(AlwaysTrue::default()))];
+        let options = crate::PackagingOptions {
+            output_directory: output,
+            parallel,
+            ..crate::PackagingOptions::default()
+        };
+
+        let result = crate::package(&install, &base, &packets, &facets, &options).unwrap();
+        result
+            .packets
+            .iter()
+            .map(|p| {
+                let data = std::fs::read(p).unwrap();
+                let hash = format!("{:x}", sha2::Sha256::digest(&data));
+                (
+                    p.file_name().unwrap().to_string_lossy().to_string(),
+                    data,
+                    hash,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn package_script_with_interpreter_outside_of_build() {
+        let tmp = tempfile::Builder::new()
+            .prefix("packager-")
+            .rand_bytes(8)
+            .tempdir()
+            .expect("Failed to create temporary directory.");
+        let base = tmp.path().join("base");
+        let install = tmp.path().join("install");
+        std::fs::create_dir_all(&base).unwrap();
+        std::fs::create_dir_all(install.join("bin")).unwrap();
+        std::fs::write(install.join("bin/foo"), b"#!/usr/bin/perl\nprint 1;\n").unwrap();
+        std::fs::set_permissions(
+            install.join("bin/foo"),
+            std::os::unix::fs::PermissionsExt::from_mode(0o755),
+        )
+        .unwrap();
+
+        let packets = vec![packet("foo", &["bin/**"])];
+        let facets = vec![FacetDefinition::new(None, Arc::new(AlwaysTrue::default()))];
+        let options = crate::PackagingOptions {
+            output_directory: tmp.path().join("output"),
+            ..crate::PackagingOptions::default()
+        };
+
+        // Unresolved interpreters are errors by default:
+        assert!(crate::package(&install, &base, &packets, &facets, &options).is_err());
+
+        let options = crate::PackagingOptions {
+            interpreter_providers: std::iter::once((
+                std::path::PathBuf::from("/usr/bin/perl"),
+                crate::Name::try_from("perl").unwrap(),
+            ))
+            .collect(),
+            ..options
+        };
+        let result = crate::package(&install, &base, &packets, &facets, &options).unwrap();
+        assert_eq!(result.packets.len(), 1);
+    }
+
+    #[test]
+    fn parallel_packaging_matches_serial_packaging() {
+        let serial = package_tree(false);
+        let parallel = package_tree(true);
+
+        assert_eq!(serial.len(), 2);
+        assert_eq!(
+            serial.iter().map(|(n, _, h)| (n, h)).collect::<Vec<_>>(),
+            parallel.iter().map(|(n, _, h)| (n, h)).collect::<Vec<_>>()
+        );
+        assert!(serial
+            .iter()
+            .zip(parallel.iter())
+            .all(|((_, s, _), (_, p, _))| s == p));
+    }
+}
@@ -6,7 +6,7 @@
 use crate::filter::Filter;
 use crate::packager::{BoxedPackager, Packager};
 
-use std::rc::Rc;
+use std::sync::Arc;
 
 // ----------------------------------------------------------------------
 // - FilteredPackager:
@@ -16,13 +16,13 @@ use std::rc::Rc;
 pub struct FilteredPackager {
     /// Debug message for `FilteredPackager`
     pub debug: String,
-    filter: Rc<dyn Filter>,
+    filter: Arc<dyn Filter>,
     packager: BoxedPackager,
 }
 
 impl FilteredPackager {
     /// Constructor
-    pub fn new(debug: String, filter: Rc<dyn Filter>, packager: BoxedPackager) -> Self {
+    pub fn new(debug: String, filter: Arc<dyn Filter>, packager: BoxedPackager) -> Self {
         Self {
             debug,
             filter,
@@ -51,4 +51,20 @@ impl Packager for FilteredPackager {
     fn debug_name(&self) -> String {
         format!("[ Filtering {} ]", self.debug)
     }
+
+    fn candidates(&self, path: &crate::path::Path) -> Vec<crate::report::Candidate> {
+        self.filter.explain(path).map_or_else(Vec::new, |reason| {
+            self.packager
+                .candidates(path)
+                .into_iter()
+                .map(|mut c| {
+                    if c.name.is_empty() {
+                        c.name.clone_from(&self.debug);
+                    }
+                    c.filters.insert(0, reason.clone());
+                    c
+                })
+                .collect()
+        })
+    }
 }
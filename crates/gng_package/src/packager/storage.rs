@@ -3,11 +3,14 @@
 
 //! A `Packager` that stores data into a `Packet`
 
+use crate::owned_directories::OwnedDirectories;
 use crate::path::Path;
 use crate::{packager::Packager, FacetDefinition, PacketDefinition};
 
 use gng_packet_io::{PacketPolicy, PacketWriter};
 
+use std::sync::Arc;
+
 // ----------------------------------------------------------------------
 // - Helper:
 // ----------------------------------------------------------------------
@@ -30,6 +33,8 @@ const fn find_policy(packet: &PacketDefinition, facet: &FacetDefinition) -> Pack
 pub struct StoragePackager {
     debug: String,
     writer: PacketWriter,
+    owned_directories: Arc<OwnedDirectories>,
+    stored_directories: std::collections::HashSet<std::path::PathBuf>,
 }
 
 impl StoragePackager {
@@ -38,21 +43,56 @@ impl StoragePackager {
     /// # Errors
     ///
     /// Returns an error if one happens.
-    pub fn new(packet: &PacketDefinition, facet: &FacetDefinition) -> eyre::Result<Self> {
+    pub fn new(
+        packet: &PacketDefinition,
+        facet: &FacetDefinition,
+        owned_directories: Arc<OwnedDirectories>,
+        sbom: &[crate::sbom::SbomDocument],
+        options: &crate::PackagingOptions,
+    ) -> eyre::Result<Self> {
         let data = packet.data.clone();
 
         // FIXME: Add Facets!
 
+        let mut writer = PacketWriter::new(
+            &options.output_directory,
+            &data,
+            find_policy(packet, facet),
+            options.existing_packets,
+        )?;
+        for document in sbom {
+            writer.add_metadata(document.format.extension(), document.data.clone())?;
+        }
+
         Ok(Self {
             debug: packet.data.name.combine(&facet.name),
 
-            writer: PacketWriter::new(
-                std::path::Path::new("."),
-                &data,
-                find_policy(packet, facet),
-            )?,
+            writer,
+            owned_directories,
+            stored_directories: std::collections::HashSet::new(),
         })
     }
+
+    fn store_directory(&mut self, path: &Path) -> eyre::Result<()> {
+        if self.stored_directories.insert(path.as_path().to_path_buf()) {
+            self.writer.add_directory(
+                path.as_path(),
+                path.mode(),
+                u64::from(path.user_id()),
+                u64::from(path.group_id()),
+            )
+        } else {
+            Ok(())
+        }
+    }
+
+    fn store_parent_directories(&mut self, path: &Path) -> eyre::Result<()> {
+        let owned_directories = self.owned_directories.clone();
+        for p in owned_directories.owned_parents(path.as_path()) {
+            self.store_directory(p)?;
+        }
+        Ok(())
+    }
 }
 
 impl Packager for StoragePackager {
@@ -64,12 +104,12 @@ impl Packager for StoragePackager {
         let user_id = u64::from(path.user_id());
         let group_id = u64::from(path.group_id());
 
+        // Parent directories created by this build need to be in the packet,
+        // so that the installer creates them with the right mode and owner:
+        self.store_parent_directories(path)?;
+
         match path.leaf_type() {
-            // FIXME: Only store directories that were created in this run!
-            "d" => self
-                .writer
-                .add_directory(path.as_path(), mode, user_id, group_id)
-                .map(|_| true),
+            "d" => self.store_directory(path).map(|()| true),
             "l" => self
                 .writer
                 .add_link(
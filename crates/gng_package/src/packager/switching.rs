@@ -50,4 +50,11 @@ impl Packager for SwitchingPackager {
     fn debug_name(&self) -> String {
         format!("[ Switching with {} children ]", self.children.len())
     }
+
+    fn candidates(&self, path: &crate::path::Path) -> Vec<crate::report::Candidate> {
+        self.children
+            .iter()
+            .flat_map(|c| c.candidates(path))
+            .collect()
+    }
 }
@@ -0,0 +1,220 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! A `Packager` that runs another `Packager` in a worker thread
+
+use crate::packager::Packager;
+use crate::path::Path;
+
+use eyre::eyre;
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// The number of `Path`s that may be queued up for a worker thread
+const QUEUE_LENGTH: usize = 256;
+
+/// A boxed `Packager` that can be moved into a worker thread
+pub type SendablePackager = Box<dyn Packager + Send>;
+
+type WorkerResult = eyre::Result<Vec<std::path::PathBuf>>;
+
+enum Message {
+    Package(Path),
+    Finish,
+}
+
+fn run_worker(
+    mut packager: SendablePackager,
+    receiver: &std::sync::mpsc::Receiver<Message>,
+) -> WorkerResult {
+    tracing::trace!("Worker for {} is up.", packager.debug_name());
+
+    for message in receiver {
+        match message {
+            Message::Package(path) => {
+                if !packager.package(&path)? {
+                    return Err(eyre!(
+                        "{} refused to package \"{}\".",
+                        packager.debug_name(),
+                        path.as_path().to_string_lossy()
+                    ));
+                }
+            }
+            Message::Finish => {
+                tracing::trace!("Worker for {} is finishing.", packager.debug_name());
+                return packager.finish();
+            }
+        }
+    }
+
+    Err(eyre!(
+        "{} was dropped before it was finished.",
+        packager.debug_name()
+    ))
+}
+
+// ----------------------------------------------------------------------
+// - ThreadedPackager:
+// ----------------------------------------------------------------------
+
+/// A `Packager` that hands all `Path`s on to a `Packager` running in a worker thread
+///
+/// The wrapped `Packager` must accept all `Path`s it is handed, so this is meant
+/// to wrap the `Packager`s at the leaves of the `Packager` tree.
+pub struct ThreadedPackager {
+    debug: String,
+    sender: Option<std::sync::mpsc::SyncSender<Message>>,
+    worker: Option<std::thread::JoinHandle<WorkerResult>>,
+}
+
+impl ThreadedPackager {
+    /// Constructor
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the worker thread could not get started.
+    pub fn new(packager: SendablePackager) -> eyre::Result<Self> {
+        let debug = packager.debug_name();
+        let (sender, receiver) = std::sync::mpsc::sync_channel(QUEUE_LENGTH);
+
+        let worker = std::thread::Builder::new()
+            .name(format!("packager {debug}"))
+            .spawn(move || run_worker(packager, &receiver))?;
+
+        Ok(Self {
+            debug,
+            sender: Some(sender),
+            worker: Some(worker),
+        })
+    }
+
+    fn join_worker(&mut self) -> WorkerResult {
+        self.sender = None;
+        self.worker.take().map_or_else(
+            || Err(eyre!("{} was already finished.", self.debug_name())),
+            |w| {
+                w.join()
+                    .map_err(|_| eyre!("Worker thread of {} panicked.", self.debug_name()))?
+            },
+        )
+    }
+
+    fn send(&mut self, message: Message) -> eyre::Result<()> {
+        let sent = self
+            .sender
+            .as_ref()
+            .map_or(false, |s| s.send(message).is_ok());
+        if sent {
+            Ok(())
+        } else {
+            // The worker quit early: Report why.
+            self.join_worker()?;
+            Err(eyre!("Worker thread of {} is gone.", self.debug_name()))
+        }
+    }
+}
+
+impl Packager for ThreadedPackager {
+    #[tracing::instrument(level = "trace", skip(self))]
+    fn package(&mut self, path: &Path) -> eyre::Result<bool> {
+        tracing::trace!("Packaging in {}.", &self.debug_name());
+        self.send(Message::Package(path.clone()))?;
+        Ok(true)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    fn finish(&mut self) -> eyre::Result<Vec<std::path::PathBuf>> {
+        tracing::trace!("Finishing in {}.", &self.debug_name());
+        self.send(Message::Finish)?;
+        self.join_worker()
+    }
+
+    fn debug_name(&self) -> String {
+        format!("[ Threaded {} ]", self.debug)
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct RecordingPackager {
+        recorded: std::sync::Arc<std::sync::Mutex<Vec<std::path::PathBuf>>>,
+        fail_on: Option<std::path::PathBuf>,
+    }
+
+    impl Packager for RecordingPackager {
+        fn package(&mut self, path: &Path) -> eyre::Result<bool> {
+            if self.fail_on.as_deref() == Some(path.as_path()) {
+                return Err(eyre!("Failed on purpose"));
+            }
+            self.recorded
+                .lock()
+                .unwrap()
+                .push(path.as_path().to_path_buf());
+            Ok(true)
+        }
+
+        fn finish(&mut self) -> eyre::Result<Vec<std::path::PathBuf>> {
+            Ok(vec![std::path::PathBuf::from("result.gng")])
+        }
+
+        fn debug_name(&self) -> String {
+            "[ Recording ]".to_string()
+        }
+    }
+
+    fn path(input: &str) -> Path {
+        Path::new_file_from_buffer(b"test".to_vec(), std::path::Path::new(input), 0o644, 0, 0)
+    }
+
+    #[test]
+    fn threaded_packager_keeps_order() {
+        let recorded = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut packager = ThreadedPackager::new(Box::new(RecordingPackager {
+            recorded: recorded.clone(),
+            fail_on: None,
+        }))
+        .unwrap();
+
+        let expected = (0..1000)
+            .map(|i| std::path::PathBuf::from(format!("file_{i}")))
+            .collect::<Vec<_>>();
+        for p in &expected {
+            assert!(packager.package(&path(&p.to_string_lossy())).unwrap());
+        }
+
+        assert_eq!(
+            packager.finish().unwrap(),
+            vec![std::path::PathBuf::from("result.gng")]
+        );
+        assert_eq!(*recorded.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn threaded_packager_reports_worker_errors() {
+        let recorded = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut packager = ThreadedPackager::new(Box::new(RecordingPackager {
+            recorded,
+            fail_on: Some(std::path::PathBuf::from("file_1")),
+        }))
+        .unwrap();
+
+        let mut result = Ok(true);
+        for i in 0..1000 {
+            result = packager.package(&path(&format!("file_{i}")));
+            if result.is_err() {
+                break;
+            }
+        }
+        let result = result.and_then(|_| packager.finish().map(|_| true));
+
+        assert_eq!(result.unwrap_err().to_string(), "Failed on purpose");
+    }
+}
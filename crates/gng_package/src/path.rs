@@ -7,11 +7,9 @@
 // - Helper:
 // ----------------------------------------------------------------------
 
-fn create_cookie() -> eyre::Result<filemagic::Magic> {
-    let cookie = filemagic::Magic::open(filemagic::flags::Flags::default()).map_err(|e| {
-        gng_core::Error::Runtime {
-            message: format!("File type detection setup failed: {}", e),
-        }
+fn create_cookie(flags: filemagic::flags::Flags) -> eyre::Result<filemagic::Magic> {
+    let cookie = filemagic::Magic::open(flags).map_err(|e| gng_core::Error::Runtime {
+        message: format!("File type detection setup failed: {}", e),
     })?;
     cookie
         .load::<String>(&[])
@@ -35,27 +33,68 @@ pub enum FileContents {
     OnDisk(std::path::PathBuf),
 }
 
+type Cookie = std::cell::RefCell<Option<filemagic::Magic>>;
+
 impl FileContents {
     fn magic(&self) -> eyre::Result<String> {
         thread_local! {
-            static COOKIE: std::cell::RefCell<Option<filemagic::Magic>> = std::cell::RefCell::new(None);
+            static COOKIE: Cookie = std::cell::RefCell::new(None);
+        }
+        COOKIE.with(|c| self.run_cookie(c, filemagic::flags::Flags::default()))
+    }
+
+    fn mime_type(&self) -> eyre::Result<String> {
+        thread_local! {
+            static COOKIE: Cookie = std::cell::RefCell::new(None);
         }
-        COOKIE.with(|c| {
-            if c.borrow().is_none() {
-                *c.borrow_mut() = Some(create_cookie()?);
-            };
-
-            let c = c.borrow();
-            let c = c
-                .as_ref()
-                .expect("COOKIE was set before, so this should not be None");
-
-            Ok(match self {
-                Self::OnDisk(p) => c.file(p).unwrap_or_default(),
-                Self::Buffer(b) => c.buffer(b).unwrap_or_default(),
-            })
+        COOKIE.with(|c| self.run_cookie(c, filemagic::flags::Flags::MIME_TYPE))
+    }
+
+    fn run_cookie(&self, cookie: &Cookie, flags: filemagic::flags::Flags) -> eyre::Result<String> {
+        if cookie.borrow().is_none() {
+            *cookie.borrow_mut() = Some(create_cookie(flags)?);
+        };
+
+        let c = cookie.borrow();
+        let c = c
+            .as_ref()
+            .expect("COOKIE was set before, so this should not be None");
+
+        Ok(match self {
+            Self::OnDisk(p) => c.file(p).unwrap_or_default(),
+            Self::Buffer(b) => c.buffer(b).unwrap_or_default(),
         })
     }
+
+    /// A reader for the contents
+    ///
+    /// # Errors
+    /// Returns an error if the contents could not get opened.
+    pub fn reader(&self) -> eyre::Result<Box<dyn std::io::Read + '_>> {
+        match self {
+            Self::Buffer(b) => Ok(Box::new(std::io::Cursor::new(b))),
+            Self::OnDisk(p) => Ok(Box::new(std::io::BufReader::new(std::fs::File::open(p)?))),
+        }
+    }
+
+    /// Read up to `length` bytes from the start of the contents
+    ///
+    /// # Errors
+    /// Returns an error if the contents could not get read from disk.
+    pub fn head(&self, length: usize) -> eyre::Result<Vec<u8>> {
+        use std::io::Read;
+
+        match self {
+            Self::Buffer(b) => Ok(b[..std::cmp::min(length, b.len())].to_vec()),
+            Self::OnDisk(p) => {
+                let mut result = Vec::with_capacity(length);
+                std::fs::File::open(p)?
+                    .take(length as u64)
+                    .read_to_end(&mut result)?;
+                Ok(result)
+            }
+        }
+    }
 }
 
 // ----------------------------------------------------------------------
@@ -136,6 +175,14 @@ impl PathLeaf {
         }
     }
 
+    fn mime_type(&self) -> eyre::Result<String> {
+        match self {
+            Self::File { contents, size: _ } => contents.mime_type(),
+            Self::Link { target: _ } => Ok("inode/symlink".to_string()),
+            Self::Directory {} => Ok("inode/directory".to_string()),
+        }
+    }
+
     const fn file_contents(&self) -> Option<&FileContents> {
         match self {
             Self::File {
@@ -162,6 +209,38 @@ impl std::fmt::Debug for PathLeaf {
     }
 }
 
+// ----------------------------------------------------------------------
+// - Cached:
+// ----------------------------------------------------------------------
+
+/// A value computed on first use, ignored when comparing `Path`s
+#[derive(Default)]
+struct Cached(std::sync::Mutex<Option<String>>);
+
+impl Cached {
+    fn get_or_try_init(&self, init: impl FnOnce() -> eyre::Result<String>) -> eyre::Result<String> {
+        let mut value = self.0.lock().expect("Cache lock was poisoned.");
+        if value.is_none() {
+            *value = Some(init()?);
+        }
+        Ok(value.as_ref().expect("Value was just set.").clone())
+    }
+}
+
+impl Clone for Cached {
+    fn clone(&self) -> Self {
+        Self(std::sync::Mutex::new(
+            self.0.lock().expect("Cache lock was poisoned.").clone(),
+        ))
+    }
+}
+
+impl PartialEq for Cached {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
 // ----------------------------------------------------------------------
 // - Path:
 // ----------------------------------------------------------------------
@@ -180,7 +259,9 @@ pub struct Path {
     /// The leaf node on the directory
     leaf_type: PathLeaf,
     /// The ***magic***
-    magic: Option<String>,
+    magic: Cached,
+    /// The mime type
+    mime_type: Cached,
 }
 
 impl Path {
@@ -202,7 +283,8 @@ impl Path {
                 size,
                 contents: FileContents::OnDisk(on_disk.to_path_buf()),
             },
-            magic: None,
+            magic: Cached::default(),
+            mime_type: Cached::default(),
         }
     }
 
@@ -225,7 +307,8 @@ impl Path {
                 size,
                 contents: FileContents::Buffer(buffer),
             },
-            magic: None,
+            magic: Cached::default(),
+            mime_type: Cached::default(),
         }
     }
 
@@ -245,7 +328,8 @@ impl Path {
             leaf_type: PathLeaf::Link {
                 target: target.to_path_buf(),
             },
-            magic: None,
+            magic: Cached::default(),
+            mime_type: Cached::default(),
         }
     }
 
@@ -263,7 +347,8 @@ impl Path {
             user_id,
             group_id,
             leaf_type: PathLeaf::Directory {},
-            magic: None,
+            magic: Cached::default(),
+            mime_type: Cached::default(),
         }
     }
 
@@ -309,6 +394,21 @@ impl Path {
         self.group_id
     }
 
+    /// Set the `mode` of the leaf
+    pub fn set_mode(&mut self, mode: u32) {
+        self.mode = mode;
+    }
+
+    /// Set the `user_id` of the leaf
+    pub fn set_user_id(&mut self, user_id: u32) {
+        self.user_id = user_id;
+    }
+
+    /// Set the `group_id` of the leaf
+    pub fn set_group_id(&mut self, group_id: u32) {
+        self.group_id = group_id;
+    }
+
     /// The `size` of the leaf. Will be 0 for anything but normal files.
     #[must_use]
     pub const fn size(&self) -> u64 {
@@ -346,11 +446,24 @@ impl Path {
     }
 
     /// Get the ***magic***
-    pub fn magic(&mut self) -> eyre::Result<String> {
-        if self.magic.is_none() {
-            self.magic = Some(self.leaf_type.magic()?);
-        }
-        Ok(self.magic.as_ref().expect("Magic was just set.").clone())
+    ///
+    /// The file type detection runs only once per `Path`.
+    ///
+    /// # Errors
+    /// Returns an error if the file type detection fails.
+    pub fn magic(&self) -> eyre::Result<String> {
+        self.magic.get_or_try_init(|| self.leaf_type.magic())
+    }
+
+    /// Get the mime type
+    ///
+    /// The file type detection runs only once per `Path`.
+    ///
+    /// # Errors
+    /// Returns an error if the file type detection fails.
+    pub fn mime_type(&self) -> eyre::Result<String> {
+        self.mime_type
+            .get_or_try_init(|| self.leaf_type.mime_type())
     }
 
     /// Turn the `Path` into a String
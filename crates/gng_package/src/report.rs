@@ -0,0 +1,201 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! A report explaining which packet claimed which path during packaging
+
+use eyre::WrapErr;
+
+// ----------------------------------------------------------------------
+// - Candidate:
+// ----------------------------------------------------------------------
+
+/// A packet (or facet of a packet) that wants to claim a path
+#[derive(Clone, Debug, Default, Eq, PartialEq, serde::Serialize)]
+pub struct Candidate {
+    /// The name of the packet, including the facet name if any
+    pub name: String,
+    /// The filters that matched the path, outermost filter first
+    pub filters: Vec<String>,
+}
+
+impl std::fmt::Display for Candidate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.filters.is_empty() {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{} [{}]", self.name, self.filters.join(", "))
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// - ReportEntry:
+// ----------------------------------------------------------------------
+
+/// The packaging decision made for one path
+#[derive(Clone, Debug, Eq, PartialEq, serde::Serialize)]
+pub struct ReportEntry {
+    /// The path (relative to the `usr` directory)
+    pub path: std::path::PathBuf,
+    /// The candidate that actually got the path, if any
+    pub claimed_by: Option<Candidate>,
+    /// Other candidates that would have taken the path, in order of priority
+    pub other_candidates: Vec<Candidate>,
+}
+
+impl ReportEntry {
+    /// Constructor: The first of the `candidates` claims `path`
+    #[must_use]
+    pub fn new(path: &std::path::Path, candidates: Vec<Candidate>) -> Self {
+        let mut candidates = candidates.into_iter();
+        Self {
+            path: path.to_path_buf(),
+            claimed_by: candidates.next(),
+            other_candidates: candidates.collect(),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Report:
+// ----------------------------------------------------------------------
+
+/// A report on all packaging decisions made
+#[derive(Clone, Debug, Default, Eq, PartialEq, serde::Serialize)]
+pub struct Report {
+    /// The entries of the report, in packaging order
+    pub entries: Vec<ReportEntry>,
+}
+
+impl Report {
+    /// Render the `Report` as JSON
+    ///
+    /// # Errors
+    /// Returns an error if serialization fails.
+    pub fn to_json(&self) -> eyre::Result<String> {
+        serde_json::to_string_pretty(self).wrap_err("Failed to serialize packaging report.")
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rows = self
+            .entries
+            .iter()
+            .map(|e| {
+                let (packet, matched_by) = e.claimed_by.as_ref().map_or_else(
+                    || ("-".to_string(), "-".to_string()),
+                    |c| (c.name.clone(), c.filters.join(", ")),
+                );
+                let others = if e.other_candidates.is_empty() {
+                    "-".to_string()
+                } else {
+                    e.other_candidates
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                };
+                [
+                    e.path.to_string_lossy().to_string(),
+                    packet,
+                    matched_by,
+                    others,
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let header = [
+            "PATH".to_string(),
+            "PACKET".to_string(),
+            "MATCHED BY".to_string(),
+            "OTHER CANDIDATES".to_string(),
+        ];
+        let mut widths = header.clone().map(|h| h.chars().count());
+        for r in &rows {
+            for (w, c) in widths.iter_mut().zip(r.iter()) {
+                *w = std::cmp::max(*w, c.chars().count());
+            }
+        }
+
+        for r in std::iter::once(&header).chain(rows.iter()) {
+            writeln!(
+                f,
+                "{:w0$}  {:w1$}  {:w2$}  {}",
+                r[0],
+                r[1],
+                r[2],
+                r[3],
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(name: &str, filters: &[&str]) -> Candidate {
+        Candidate {
+            name: name.to_string(),
+            filters: filters.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    #[test]
+    fn report_entry_new() {
+        let entry = ReportEntry::new(
+            std::path::Path::new("bin/foo"),
+            vec![
+                candidate("foo", &["glob(\"bin/*\")"]),
+                candidate("bar", &[]),
+            ],
+        );
+        assert_eq!(
+            entry.claimed_by,
+            Some(candidate("foo", &["glob(\"bin/*\")"]))
+        );
+        assert_eq!(entry.other_candidates, vec![candidate("bar", &[])]);
+
+        let entry = ReportEntry::new(std::path::Path::new("bin/foo"), Vec::new());
+        assert_eq!(entry.claimed_by, None);
+        assert_eq!(entry.other_candidates, Vec::new());
+    }
+
+    #[test]
+    fn report_render() {
+        let report = Report {
+            entries: vec![
+                ReportEntry::new(
+                    std::path::Path::new("bin/foo"),
+                    vec![
+                        candidate("foo", &["glob(\"bin/*\")"]),
+                        candidate("bar", &[]),
+                    ],
+                ),
+                ReportEntry::new(std::path::Path::new("share"), Vec::new()),
+            ],
+        };
+
+        assert_eq!(
+            report.to_string(),
+            "PATH     PACKET  MATCHED BY     OTHER CANDIDATES\n\
+             bin/foo  foo     glob(\"bin/*\")  bar\n\
+             share    -       -              -\n"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["entries"][0]["path"], "bin/foo");
+        assert_eq!(json["entries"][0]["claimed_by"]["name"], "foo");
+        assert_eq!(json["entries"][0]["other_candidates"][0]["name"], "bar");
+        assert_eq!(json["entries"][1]["claimed_by"], serde_json::Value::Null);
+    }
+}
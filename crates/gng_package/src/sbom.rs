@@ -0,0 +1,666 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Generate software bills of materials for the `Packet`s of a build
+//!
+//! The SBOMs describe the whole build: There is one document per format
+//! listing all `Packet`s built, and the same document is embedded into every
+//! `Packet` of the build. Facets are not described separately, the files of
+//! a facet are listed under the `Packet` the facet belongs to.
+
+use crate::path::Path;
+use crate::{Name, Version};
+
+use eyre::{eyre, WrapErr};
+use serde_json::json;
+use sha1::Digest;
+
+use std::io::Read;
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// Turn `input` into something usable in an SPDX identifier
+fn spdx_id(kind: &str, input: &str) -> String {
+    let input = input
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+    format!("SPDXRef-{kind}-{input}")
+}
+
+/// The SPDX package verification code: The SHA1 of all sorted file SHA1s
+fn verification_code<'a>(files: impl Iterator<Item = &'a FileRecord>) -> String {
+    let mut sha1s = files.map(|f| f.sha1.as_str()).collect::<Vec<_>>();
+    sha1s.sort_unstable();
+
+    let mut hasher = sha1::Sha1::new();
+    for s in sha1s {
+        hasher.update(s.as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+const fn or_no_assertion(input: &str) -> &str {
+    if input.is_empty() {
+        "NOASSERTION"
+    } else {
+        input
+    }
+}
+
+/// All dependencies of `packets` that are not built alongside them
+fn external_dependencies(packets: &[crate::BinaryPacketDefinition]) -> Vec<Name> {
+    let mut result = packets
+        .iter()
+        .flat_map(|p| p.dependencies.into_iter())
+        .filter(|d| !packets.iter().any(|p| &p.name == *d))
+        .cloned()
+        .collect::<Vec<_>>();
+    result.sort();
+    result.dedup();
+    result
+}
+
+// ----------------------------------------------------------------------
+// - BuildInformation:
+// ----------------------------------------------------------------------
+
+/// An upstream source used by a build
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SourceInformation {
+    /// The `url` the recipe gives for the source
+    pub url: String,
+    /// The SHA256 the recipe declares for the source (if any)
+    pub sha256: Option<String>,
+}
+
+/// Information about the build that produced the `Packet`s
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BuildInformation {
+    /// The `name` of the source packet
+    pub name: Name,
+    /// The `version` of the source packet
+    pub version: Version,
+    /// The SPDX license expression of the source packet
+    pub license: String,
+    /// The `url` of the upstream project
+    pub url: String,
+    /// The upstream `sources`
+    pub sources: Vec<SourceInformation>,
+    /// The creation time in seconds since the epoch
+    ///
+    /// Use a fixed value (e.g. `SOURCE_DATE_EPOCH`) to keep packets reproducible.
+    pub created: u64,
+}
+
+// ----------------------------------------------------------------------
+// - SbomFormat:
+// ----------------------------------------------------------------------
+
+/// The supported SBOM formats
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SbomFormat {
+    /// SPDX 2.3 in JSON
+    Spdx,
+    /// `CycloneDX` 1.4 in JSON
+    CycloneDx,
+}
+
+impl SbomFormat {
+    /// The file extension used for this format
+    #[must_use]
+    pub const fn extension(&self) -> &'static str {
+        match self {
+            Self::Spdx => "spdx.json",
+            Self::CycloneDx => "cdx.json",
+        }
+    }
+}
+
+impl std::str::FromStr for SbomFormat {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "spdx" => Ok(Self::Spdx),
+            "cyclonedx" => Ok(Self::CycloneDx),
+            _ => Err(eyre!(
+                "Unknown SBOM format \"{}\", expected \"spdx\" or \"cyclonedx\".",
+                s
+            )),
+        }
+    }
+}
+
+/// A generated SBOM document
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SbomDocument {
+    /// The `format` of the document
+    pub format: SbomFormat,
+    /// The serialized document
+    pub data: Vec<u8>,
+}
+
+// ----------------------------------------------------------------------
+// - FileRecord:
+// ----------------------------------------------------------------------
+
+/// A file stored in a `Packet`, with its digests
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FileRecord {
+    /// The path of the file relative to `/usr`
+    pub path: std::path::PathBuf,
+    /// The `Packet` the file is stored in
+    ///
+    /// This is the packet name only: Files stored in a facet of the
+    /// `Packet` are recorded under the `Packet` itself.
+    pub packet: Name,
+    /// The SHA1 of the file contents
+    pub sha1: String,
+    /// The SHA256 of the file contents
+    pub sha256: String,
+}
+
+impl FileRecord {
+    /// Hash the contents of `path`, which is stored in `packet`
+    ///
+    /// Returns `None` for anything but files.
+    ///
+    /// # Errors
+    /// Returns an error if the file contents can not be read.
+    pub fn new(path: &Path, packet: &Name) -> eyre::Result<Option<Self>> {
+        let contents = match path.file_contents() {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+
+        let mut reader = contents.reader().wrap_err(eyre!(
+            "Failed to open \"{}\" for hashing.",
+            path.as_path().to_string_lossy()
+        ))?;
+        let mut sha1 = sha1::Sha1::new();
+        let mut sha256 = sha2::Sha256::new();
+        let mut buffer = vec![0_u8; 64 * 1024];
+        loop {
+            let count = reader.read(&mut buffer).wrap_err(eyre!(
+                "Failed to read \"{}\" for hashing.",
+                path.as_path().to_string_lossy()
+            ))?;
+            if count == 0 {
+                break;
+            }
+            sha1.update(&buffer[..count]);
+            sha256.update(&buffer[..count]);
+        }
+
+        Ok(Some(Self {
+            path: path.as_path().to_path_buf(),
+            packet: packet.clone(),
+            sha1: format!("{:x}", sha1.finalize()),
+            sha256: format!("{:x}", sha256.finalize()),
+        }))
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Generators:
+// ----------------------------------------------------------------------
+
+fn spdx_file_id(index: usize) -> String {
+    format!("SPDXRef-File-{}", index)
+}
+
+/// The SPDX package of `packet` and its relationships
+fn spdx_packet(
+    build: &BuildInformation,
+    packet: &crate::BinaryPacketDefinition,
+    files: &[FileRecord],
+    source_id: &str,
+) -> (serde_json::Value, Vec<serde_json::Value>) {
+    let id = spdx_id("Packet", &packet.name.to_string());
+    let packet_files = files
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| f.packet == packet.name)
+        .collect::<Vec<_>>();
+
+    let mut package = json!({
+        "SPDXID": id,
+        "name": packet.name.to_string(),
+        "versionInfo": packet.version.to_string(),
+        "downloadLocation": "NOASSERTION",
+        "homepage": or_no_assertion(&packet.url),
+        "description": packet.description,
+        "licenseDeclared": or_no_assertion(&build.license),
+        "licenseConcluded": "NOASSERTION",
+        "copyrightText": "NOASSERTION",
+        "filesAnalyzed": !packet_files.is_empty(),
+        "primaryPackagePurpose": "INSTALL",
+    });
+    if !packet_files.is_empty() {
+        package["packageVerificationCode"] = json!({
+            "packageVerificationCodeValue":
+                verification_code(packet_files.iter().map(|(_, f)| *f)),
+        });
+        package["hasFiles"] = packet_files
+            .iter()
+            .map(|(i, _)| json!(spdx_file_id(*i)))
+            .collect();
+    }
+
+    let relationships = std::iter::once(json!({
+        "spdxElementId": id,
+        "relationshipType": "GENERATED_FROM",
+        "relatedSpdxElement": source_id,
+    }))
+    .chain(packet.dependencies.into_iter().map(|d| {
+        json!({
+            "spdxElementId": id,
+            "relationshipType": "DEPENDS_ON",
+            "relatedSpdxElement": spdx_id("Packet", &d.to_string()),
+        })
+    }))
+    .collect();
+
+    (package, relationships)
+}
+
+fn spdx(
+    build: &BuildInformation,
+    packets: &[crate::BinaryPacketDefinition],
+    files: &[FileRecord],
+) -> serde_json::Value {
+    let source_id = spdx_id("Source", &build.name.to_string());
+
+    let mut packages = vec![json!({
+        "SPDXID": source_id,
+        "name": build.name.to_string(),
+        "versionInfo": build.version.to_string(),
+        "downloadLocation": "NOASSERTION",
+        "homepage": or_no_assertion(&build.url),
+        "licenseDeclared": or_no_assertion(&build.license),
+        "licenseConcluded": "NOASSERTION",
+        "copyrightText": "NOASSERTION",
+        "filesAnalyzed": false,
+        "primaryPackagePurpose": "SOURCE",
+    })];
+    let mut relationships = vec![json!({
+        "spdxElementId": "SPDXRef-DOCUMENT",
+        "relationshipType": "DESCRIBES",
+        "relatedSpdxElement": source_id,
+    })];
+
+    for (index, s) in build.sources.iter().enumerate() {
+        let id = format!("SPDXRef-Upstream-{}", index);
+        let mut package = json!({
+            "SPDXID": id,
+            "name": s.url,
+            "downloadLocation": s.url,
+            "filesAnalyzed": false,
+            "primaryPackagePurpose": "ARCHIVE",
+        });
+        if let Some(sha256) = &s.sha256 {
+            package["checksums"] = json!([{ "algorithm": "SHA256", "checksumValue": sha256 }]);
+        }
+        packages.push(package);
+        relationships.push(json!({
+            "spdxElementId": source_id,
+            "relationshipType": "GENERATED_FROM",
+            "relatedSpdxElement": id,
+        }));
+    }
+
+    for p in packets {
+        let (package, packet_relationships) = spdx_packet(build, p, files, &source_id);
+        packages.push(package);
+        relationships.extend(packet_relationships);
+    }
+
+    for d in external_dependencies(packets) {
+        packages.push(json!({
+            "SPDXID": spdx_id("Packet", &d.to_string()),
+            "name": d.to_string(),
+            "downloadLocation": "NOASSERTION",
+            "filesAnalyzed": false,
+        }));
+    }
+
+    let spdx_files = files
+        .iter()
+        .enumerate()
+        .map(|(index, f)| {
+            json!({
+                "SPDXID": spdx_file_id(index),
+                "fileName": format!("./usr/{}", f.path.to_string_lossy()),
+                "checksums": [
+                    { "algorithm": "SHA1", "checksumValue": f.sha1 },
+                    { "algorithm": "SHA256", "checksumValue": f.sha256 },
+                ],
+                "licenseConcluded": "NOASSERTION",
+                "copyrightText": "NOASSERTION",
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": format!("{}-{}", build.name, build.version),
+        "documentNamespace": format!(
+            "https://spdx.org/spdxdocs/gng/{}-{}-{}",
+            build.name,
+            build.version,
+            verification_code(files.iter())
+        ),
+        "creationInfo": {
+            "created": gng_core::time::timestamp(build.created),
+            "creators": ["Tool: gng-build"],
+        },
+        "packages": packages,
+        "files": spdx_files,
+        "relationships": relationships,
+    })
+}
+
+fn cyclone_dx(
+    build: &BuildInformation,
+    packets: &[crate::BinaryPacketDefinition],
+    files: &[FileRecord],
+) -> serde_json::Value {
+    let packet_ref = |name: &Name| format!("packet:{}", name);
+
+    let mut source = json!({
+        "type": "application",
+        "bom-ref": format!("source:{}", build.name),
+        "name": build.name.to_string(),
+        "version": build.version.to_string(),
+        "externalReferences": build
+            .sources
+            .iter()
+            .map(|s| {
+                let mut reference = json!({ "type": "distribution", "url": s.url });
+                if let Some(sha256) = &s.sha256 {
+                    reference["hashes"] = json!([{ "alg": "SHA-256", "content": sha256 }]);
+                }
+                reference
+            })
+            .chain((!build.url.is_empty()).then(|| json!({ "type": "website", "url": build.url })))
+            .collect::<Vec<_>>(),
+    });
+    if !build.license.is_empty() {
+        source["licenses"] = json!([{ "expression": build.license }]);
+    }
+
+    let mut components = packets
+        .iter()
+        .map(|p| {
+            json!({
+                "type": "application",
+                "bom-ref": packet_ref(&p.name),
+                "name": p.name.to_string(),
+                "version": p.version.to_string(),
+                "description": p.description,
+                "components": files
+                    .iter()
+                    .filter(|f| f.packet == p.name)
+                    .map(|f| json!({
+                        "type": "file",
+                        "bom-ref": format!("file:{}:{}", p.name, f.path.to_string_lossy()),
+                        "name": format!("usr/{}", f.path.to_string_lossy()),
+                        "hashes": [
+                            { "alg": "SHA-1", "content": f.sha1 },
+                            { "alg": "SHA-256", "content": f.sha256 },
+                        ],
+                    }))
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<_>>();
+    components.extend(external_dependencies(packets).iter().map(|d| {
+        json!({
+            "type": "application",
+            "bom-ref": packet_ref(d),
+            "name": d.to_string(),
+        })
+    }));
+
+    let dependencies = packets
+        .iter()
+        .map(|p| {
+            json!({
+                "ref": packet_ref(&p.name),
+                "dependsOn": p.dependencies.into_iter().map(packet_ref).collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.4",
+        "version": 1,
+        "metadata": {
+            "timestamp": gng_core::time::timestamp(build.created),
+            "tools": [{ "vendor": "gng", "name": "gng-build" }],
+            "component": source,
+        },
+        "components": components,
+        "dependencies": dependencies,
+    })
+}
+
+// ----------------------------------------------------------------------
+// - Functions:
+// ----------------------------------------------------------------------
+
+/// Generate a SBOM document in `format`
+///
+/// The document covers the whole build, see the module documentation.
+///
+/// # Errors
+/// Returns an error if the document can not be serialized.
+pub fn generate(
+    format: SbomFormat,
+    build: &BuildInformation,
+    packets: &[crate::BinaryPacketDefinition],
+    files: &[FileRecord],
+) -> eyre::Result<SbomDocument> {
+    let document = match format {
+        SbomFormat::Spdx => spdx(build, packets, files),
+        SbomFormat::CycloneDx => cyclone_dx(build, packets, files),
+    };
+    Ok(SbomDocument {
+        format,
+        data: serde_json::to_vec_pretty(&document).wrap_err("Failed to serialize SBOM.")?,
+    })
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Names;
+
+    fn name(input: &str) -> Name {
+        Name::try_from(input).unwrap()
+    }
+
+    fn packet(packet_name: &str, dependencies: &[&str]) -> crate::BinaryPacketDefinition {
+        crate::BinaryPacketDefinition {
+            name: name(packet_name),
+            version: Version::try_from("1.0").unwrap(),
+            description: format!("The {} packet", packet_name),
+            url: String::new(),
+            bug_url: String::new(),
+            dependencies: dependencies.iter().fold(Names::default(), |mut n, d| {
+                n.insert(name(d));
+                n
+            }),
+            needed_libraries: Vec::new(),
+            facet: crate::BinaryFacet::Main,
+        }
+    }
+
+    fn build() -> BuildInformation {
+        BuildInformation {
+            name: name("foo"),
+            version: Version::try_from("1.0").unwrap(),
+            license: "GPL-3.0-or-later".to_string(),
+            url: "https://example.org/foo".to_string(),
+            sources: vec![SourceInformation {
+                url: "https://example.org/foo-1.0.tar.gz".to_string(),
+                sha256: Some("ab".repeat(32)),
+            }],
+            created: 1_612_094_400,
+        }
+    }
+
+    fn files() -> Vec<FileRecord> {
+        [("bin/foo", "foo"), ("lib/libfoo.so.1", "foo_lib")]
+            .iter()
+            .map(|(path, packet)| {
+                FileRecord::new(
+                    &Path::new_file_from_buffer(
+                        path.as_bytes().to_vec(),
+                        std::path::Path::new(path),
+                        0o755,
+                        0,
+                        0,
+                    ),
+                    &name(packet),
+                )
+                .unwrap()
+                .unwrap()
+            })
+            .collect()
+    }
+
+    fn parse(document: &SbomDocument) -> serde_json::Value {
+        serde_json::from_slice(&document.data).unwrap()
+    }
+
+    #[test]
+    fn file_records() {
+        let record = FileRecord::new(
+            &Path::new_file_from_buffer(b"abc".to_vec(), std::path::Path::new("a"), 0o644, 0, 0),
+            &name("foo"),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(record.sha1, "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(
+            record.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        assert!(FileRecord::new(
+            &Path::new_directory(std::path::Path::new("a"), 0o755, 0, 0),
+            &name("foo")
+        )
+        .unwrap()
+        .is_none());
+    }
+
+    #[test]
+    fn spdx_document() {
+        let packets = vec![packet("foo", &["foo_lib", "glibc"]), packet("foo_lib", &[])];
+        let document = generate(SbomFormat::Spdx, &build(), &packets, &files()).unwrap();
+        let json = parse(&document);
+
+        assert_eq!(json["spdxVersion"], "SPDX-2.3");
+        assert_eq!(json["creationInfo"]["created"], "2021-01-31T12:00:00Z");
+
+        let packages = json["packages"].as_array().unwrap();
+        assert_eq!(
+            packages
+                .iter()
+                .map(|p| p["SPDXID"].as_str().unwrap())
+                .collect::<Vec<_>>(),
+            vec![
+                "SPDXRef-Source-foo",
+                "SPDXRef-Upstream-0",
+                "SPDXRef-Packet-foo",
+                "SPDXRef-Packet-foo-lib",
+                "SPDXRef-Packet-glibc",
+            ]
+        );
+        assert_eq!(packages[0]["licenseDeclared"], "GPL-3.0-or-later");
+        assert_eq!(
+            packages[1]["checksums"][0]["checksumValue"],
+            "ab".repeat(32)
+        );
+        assert_eq!(packages[2]["hasFiles"], json!(["SPDXRef-File-0"]));
+
+        let files = json["files"].as_array().unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[1]["fileName"], "./usr/lib/libfoo.so.1");
+
+        let depends_on = json["relationships"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|r| r["relationshipType"] == "DEPENDS_ON")
+            .map(|r| r["relatedSpdxElement"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            depends_on,
+            vec!["SPDXRef-Packet-foo-lib", "SPDXRef-Packet-glibc"]
+        );
+
+        // Generation is deterministic:
+        assert_eq!(
+            generate(SbomFormat::Spdx, &build(), &packets, &self::files()).unwrap(),
+            document
+        );
+    }
+
+    #[test]
+    fn cyclone_dx_document() {
+        let packets = vec![packet("foo", &["foo_lib", "glibc"]), packet("foo_lib", &[])];
+        let json = parse(&generate(SbomFormat::CycloneDx, &build(), &packets, &files()).unwrap());
+
+        assert_eq!(json["bomFormat"], "CycloneDX");
+        assert_eq!(
+            json["metadata"]["component"]["licenses"][0]["expression"],
+            "GPL-3.0-or-later"
+        );
+        assert_eq!(
+            json["metadata"]["component"]["externalReferences"][0]["hashes"][0]["content"],
+            "ab".repeat(32)
+        );
+
+        let components = json["components"].as_array().unwrap();
+        assert_eq!(components.len(), 3);
+        assert_eq!(
+            components[1]["components"][0]["name"],
+            "usr/lib/libfoo.so.1"
+        );
+        assert_eq!(components[2]["bom-ref"], "packet:glibc");
+        assert_eq!(
+            json["dependencies"][0]["dependsOn"],
+            json!(["packet:foo_lib", "packet:glibc"])
+        );
+    }
+
+    #[test]
+    fn sbom_format_from_str() {
+        assert_eq!("spdx".parse::<SbomFormat>().unwrap(), SbomFormat::Spdx);
+        assert_eq!(
+            "cyclonedx".parse::<SbomFormat>().unwrap(),
+            SbomFormat::CycloneDx
+        );
+        assert!("swid".parse::<SbomFormat>().is_err());
+    }
+}
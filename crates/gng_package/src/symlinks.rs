@@ -0,0 +1,200 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Normalize symlinks before they get packaged
+
+use crate::path::Path;
+use crate::{LinkPolicy, PackagingOptions};
+
+use eyre::eyre;
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// Turn `target` (relative to `usr`) into a path relative to the directory
+/// containing `link` (also relative to `usr`)
+fn relative_target(link: &std::path::Path, target: &std::path::Path) -> std::path::PathBuf {
+    let link_directory = link
+        .parent()
+        .map_or_else(Vec::new, |p| p.components().collect::<Vec<_>>());
+    let target = target.components().collect::<Vec<_>>();
+
+    let common = link_directory
+        .iter()
+        .zip(target.iter())
+        .take_while(|(l, t)| l == t)
+        .count();
+
+    let result = std::iter::repeat(std::path::Component::ParentDir)
+        .take(link_directory.len() - common)
+        .chain(target[common..].iter().copied())
+        .collect::<std::path::PathBuf>();
+
+    if result.as_os_str().is_empty() {
+        std::path::PathBuf::from(".")
+    } else {
+        result
+    }
+}
+
+fn link_error(path: &Path, target: &std::path::Path, directory: &str) -> eyre::Report {
+    eyre!(
+        "Link \"{}\" points to \"{}\" in the {} directory of the build.",
+        path.as_path().to_string_lossy(),
+        target.to_string_lossy(),
+        directory
+    )
+}
+
+// ----------------------------------------------------------------------
+// - Functions:
+// ----------------------------------------------------------------------
+
+/// Normalize a symlink `path`
+///
+/// Absolute links into `/usr` are made relative. Absolute links into the
+/// install directory of the build are redirected to `/usr` and made relative,
+/// or rejected, depending on `options.staging_links`. Links into the work
+/// directory of the build can not be fixed and are always rejected.
+///
+/// Anything that is not a link gets returned as is.
+///
+/// # Errors
+/// Returns an error if a link is rejected.
+pub fn normalize(path: Path, options: &PackagingOptions) -> eyre::Result<Path> {
+    let target = match path.link_target() {
+        Some(t) if t.is_absolute() => t,
+        _ => return Ok(path),
+    };
+
+    if let Some(work) = &options.work_prefix {
+        if target.starts_with(work) {
+            return Err(link_error(&path, &target, "work"));
+        }
+    }
+
+    let usr_target = match &options.install_prefix {
+        Some(install) if target.starts_with(install) => {
+            if options.staging_links == LinkPolicy::Reject {
+                return Err(link_error(&path, &target, "install"));
+            }
+            target
+                .strip_prefix(install)
+                .expect("Prefix was checked")
+                .to_path_buf()
+        }
+        _ => match target.strip_prefix("/usr") {
+            Ok(t) => t.to_path_buf(),
+            Err(_) => return Ok(path),
+        },
+    };
+
+    let new_target = relative_target(path.as_path(), &usr_target);
+    tracing::debug!(
+        "Rewriting link \"{}\": \"{}\" => \"{}\".",
+        path.as_path().to_string_lossy(),
+        target.to_string_lossy(),
+        new_target.to_string_lossy()
+    );
+
+    Ok(Path::new_link(
+        path.as_path(),
+        &new_target,
+        path.user_id(),
+        path.group_id(),
+    ))
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(staging_links: LinkPolicy) -> PackagingOptions {
+        PackagingOptions {
+            install_prefix: Some(std::path::PathBuf::from("/gng/install")),
+            work_prefix: Some(std::path::PathBuf::from("/gng/work")),
+            staging_links,
+            ..PackagingOptions::default()
+        }
+    }
+
+    fn link(path: &str, target: &str) -> Path {
+        Path::new_link(
+            std::path::Path::new(path),
+            std::path::Path::new(target),
+            0,
+            0,
+        )
+    }
+
+    fn normalized_target(path: &str, target: &str, policy: LinkPolicy) -> eyre::Result<String> {
+        normalize(link(path, target), &options(policy))
+            .map(|p| p.link_target().unwrap().to_string_lossy().to_string())
+    }
+
+    #[test]
+    fn relative_target_ok() {
+        let check = |link: &str, target: &str| {
+            relative_target(std::path::Path::new(link), std::path::Path::new(target))
+                .to_string_lossy()
+                .to_string()
+        };
+        assert_eq!(check("lib/libfoo.so", "lib/libfoo.so.1"), "libfoo.so.1");
+        assert_eq!(check("bin/foo", "lib/foo/foo"), "../lib/foo/foo");
+        assert_eq!(check("foo", "bin/foo"), "bin/foo");
+        assert_eq!(check("lib/a/b/link", "lib"), "../..");
+        assert_eq!(check("lib/link", "lib"), ".");
+    }
+
+    #[test]
+    fn normalize_links() {
+        assert_eq!(
+            normalized_target("lib/libfoo.so", "libfoo.so.1", LinkPolicy::Fix).unwrap(),
+            "libfoo.so.1"
+        );
+        assert_eq!(
+            normalized_target("lib/libfoo.so", "/usr/lib/libfoo.so.1", LinkPolicy::Fix).unwrap(),
+            "libfoo.so.1"
+        );
+        assert_eq!(
+            normalized_target("bin/sh", "/usr/bin/bash", LinkPolicy::Reject).unwrap(),
+            "bash"
+        );
+        assert_eq!(
+            normalized_target(
+                "lib/libfoo.so",
+                "/gng/install/lib/libfoo.so.1",
+                LinkPolicy::Fix
+            )
+            .unwrap(),
+            "libfoo.so.1"
+        );
+        assert_eq!(
+            normalized_target("share/foo/mtab", "/etc/mtab", LinkPolicy::Fix).unwrap(),
+            "/etc/mtab"
+        );
+
+        assert!(normalized_target(
+            "lib/libfoo.so",
+            "/gng/install/lib/libfoo.so.1",
+            LinkPolicy::Reject
+        )
+        .is_err());
+        assert!(normalized_target("lib/foo.conf", "/gng/work/foo.conf", LinkPolicy::Fix).is_err());
+    }
+
+    #[test]
+    fn normalize_non_links() {
+        let file =
+            Path::new_file_from_buffer(Vec::new(), std::path::Path::new("bin/foo"), 0o755, 0, 0);
+        assert_eq!(
+            normalize(file.clone(), &options(LinkPolicy::Reject)).unwrap(),
+            file
+        );
+    }
+}
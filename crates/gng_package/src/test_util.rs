@@ -0,0 +1,36 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Helpers shared by the tests of this crate
+
+use crate::filter::GlobFilter;
+use crate::PacketDefinition;
+
+use gng_core::{Name, Names, Version};
+use gng_packet_io::{BinaryFacet, BinaryPacketDefinition};
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// A packet definition for `name` claiming the paths matching `globs`
+pub fn packet(name: &str, globs: &[&str]) -> PacketDefinition {
+    PacketDefinition::new(
+        BinaryPacketDefinition {
+            name: Name::try_from(name).unwrap(),
+            version: Version::try_from("1.0").unwrap(),
+            description: String::new(),
+            url: String::new(),
+            bug_url: String::new(),
+            dependencies: Names::default(),
+            needed_libraries: Vec::new(),
+            facet: BinaryFacet::Main,
+        },
+        Names::default(),
+        std::sync::Arc::new(GlobFilter::new(
+            crate::strings_to_globs(&globs.iter().map(ToString::to_string).collect::<Vec<_>>())
+                .unwrap(),
+        )),
+        false,
+    )
+}
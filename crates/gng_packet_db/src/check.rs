@@ -0,0 +1,373 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Check a `Repository` for consistency, e.g. before publishing it
+
+use crate::Repository;
+
+use gng_core::{Name, Version};
+use gng_packet_io::BinaryFacet;
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+fn full_name(packet: &gng_packet_io::BinaryPacketDefinition) -> String {
+    packet.facet_name().map_or_else(
+        || packet.name.to_string(),
+        |f| format!("{}:{}", packet.name, f),
+    )
+}
+
+// ----------------------------------------------------------------------
+// - Problem:
+// ----------------------------------------------------------------------
+
+/// A problem found by `check`
+#[derive(Clone, Debug, Eq, PartialEq, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Problem {
+    /// A packet depends on a packet that is not in the repository
+    MissingDependency {
+        /// The packet name including the facet name (if any)
+        packet: String,
+        /// The packet version
+        version: Version,
+        /// The missing dependency
+        dependency: Name,
+    },
+    /// A facet definition extends a facet that is not in the repository
+    MissingExtension {
+        /// The packet name including the facet name (if any)
+        packet: String,
+        /// The packet version
+        version: Version,
+        /// The missing packet defining the extended facet
+        extends: Name,
+    },
+    /// Several packets install the same file
+    FileConflict {
+        /// The installed file
+        path: std::path::PathBuf,
+        /// The packet names including the facet names (if any)
+        packets: Vec<String>,
+    },
+    /// The newest version of a packet is older than in the previous snapshot
+    Downgrade {
+        /// The packet name including the facet name (if any)
+        packet: String,
+        /// The packet version
+        version: Version,
+        /// The packet version in the previous snapshot
+        previous: Version,
+    },
+    /// The files installed by a packet are unknown, so they can not be
+    /// checked for conflicts
+    MissingFileList {
+        /// The packet name including the facet name (if any)
+        packet: String,
+        /// The packet version
+        version: Version,
+    },
+    /// A packet file is missing or does not match its hash
+    BrokenPacketFile {
+        /// The packet file, relative to the repository
+        file: std::path::PathBuf,
+        /// A description of the problem
+        problem: String,
+    },
+}
+
+impl Problem {
+    /// Does this problem make the repository unfit for publishing?
+    ///
+    /// Downgrades are warnings only: They happen on purpose when rolling
+    /// back a broken update. Missing file lists are warnings as well: They
+    /// are normal for packets added before file lists were recorded.
+    #[must_use]
+    pub const fn is_error(&self) -> bool {
+        !matches!(self, Self::Downgrade { .. } | Self::MissingFileList { .. })
+    }
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingDependency {
+                packet,
+                version,
+                dependency,
+            } => write!(
+                f,
+                "\"{} {}\" depends on \"{}\", which is not in the repository.",
+                packet, version, dependency
+            ),
+            Self::MissingExtension {
+                packet,
+                version,
+                extends,
+            } => write!(
+                f,
+                "The facet defined by \"{} {}\" extends \"{}\", which is not in the repository.",
+                packet, version, extends
+            ),
+            Self::FileConflict { path, packets } => write!(
+                f,
+                "\"{}\" is installed by several packets: {}.",
+                path.to_string_lossy(),
+                packets.join(", ")
+            ),
+            Self::Downgrade {
+                packet,
+                version,
+                previous,
+            } => write!(
+                f,
+                "\"{}\" was downgraded from {} to {}.",
+                packet, previous, version
+            ),
+            Self::MissingFileList { packet, version } => write!(
+                f,
+                "The files of \"{} {}\" are unknown, run \"gng-repo backfill\" to record them.",
+                packet, version
+            ),
+            Self::BrokenPacketFile { file, problem } => {
+                write!(f, "Packet file \"{}\": {}", file.to_string_lossy(), problem)
+            }
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Functions:
+// ----------------------------------------------------------------------
+
+/// Check the newest versions of all packets in `repository` for consistency
+///
+/// Dependencies and extended facets must be in the repository, no two
+/// packets may install the same file and all packet files must be present
+/// and match their hashes. If a `previous` snapshot of the repository is
+/// given, no packet may have gotten older since. Packets without file list
+/// are reported, as they can not be checked for file conflicts.
+///
+/// Returns all problems found, errors before warnings.
+///
+/// # Errors
+///
+/// Fail if the packets of a binary index can not be decoded.
+#[tracing::instrument(level = "debug", skip(repository, previous))]
+pub fn check(repository: &Repository, previous: Option<&Repository>) -> eyre::Result<Vec<Problem>> {
+    let mut problems = Vec::new();
+
+    let packets = repository
+        .newest()?
+        .map(|(p, _)| p)
+        .filter(|p| p.facet_name().is_none())
+        .map(|p| &p.name)
+        .collect::<std::collections::BTreeSet<_>>();
+
+    let mut files = std::collections::BTreeMap::<_, Vec<_>>::new();
+    let mut missing_file_lists = Vec::new();
+    for (p, _) in repository.newest()? {
+        for d in &p.dependencies {
+            if !packets.contains(d) {
+                problems.push(Problem::MissingDependency {
+                    packet: full_name(p),
+                    version: p.version.clone(),
+                    dependency: d.clone(),
+                });
+            }
+        }
+        if let BinaryFacet::Definition(definition) = &p.facet {
+            if let Some(extends) = &definition.extends {
+                if !packets.contains(extends) {
+                    problems.push(Problem::MissingExtension {
+                        packet: full_name(p),
+                        version: p.version.clone(),
+                        extends: extends.clone(),
+                    });
+                }
+            }
+        }
+        match repository.files(&p.name, &p.facet_name(), &p.version)? {
+            Some(packet_files) => {
+                for f in packet_files {
+                    files.entry(f).or_default().push(full_name(p));
+                }
+            }
+            None => missing_file_lists.push(Problem::MissingFileList {
+                packet: full_name(p),
+                version: p.version.clone(),
+            }),
+        }
+    }
+
+    problems.extend(
+        files
+            .into_iter()
+            .filter(|(_, packets)| packets.len() > 1)
+            .map(|(path, packets)| Problem::FileConflict {
+                path: path.clone(),
+                packets,
+            }),
+    );
+
+    problems.extend(
+        repository
+            .verify_files()
+            .into_iter()
+            .map(|(file, problem)| Problem::BrokenPacketFile { file, problem }),
+    );
+    problems.extend(missing_file_lists);
+
+    if let Some(previous) = previous {
+        for (old, _) in previous.newest()? {
+            if let Some((new, _)) = repository.query(&old.name, &old.facet_name())? {
+                if new.version < old.version {
+                    problems.push(Problem::Downgrade {
+                        packet: full_name(&new),
+                        version: new.version,
+                        previous: old.version.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(problems)
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_util::{facet, packet};
+
+    use gng_packet_io::BinaryPacketDefinition;
+
+    /// A repository in `directory` with `packets` installing `files`
+    ///
+    /// Packets without `files` have no file list. All packet files are
+    /// created.
+    fn repository(
+        directory: &std::path::Path,
+        packets: Vec<(BinaryPacketDefinition, Option<&[&str]>)>,
+    ) -> Repository {
+        let files = packets
+            .iter()
+            .filter_map(|(p, f)| {
+                Some((
+                    p.name.clone(),
+                    p.facet_name(),
+                    p.version.clone(),
+                    f.as_ref()?
+                        .iter()
+                        .map(std::path::PathBuf::from)
+                        .collect::<Vec<_>>(),
+                ))
+            })
+            .collect::<Vec<_>>();
+        let mut repo = Repository::from_definitions(
+            &url::Url::from_file_path(directory.join("repository.json")).unwrap(),
+            packets.into_iter().map(|(p, _)| p).collect(),
+        );
+
+        let mut update = repo.create_transaction();
+        for (name, facet, version, files) in files {
+            update.set_files(name, facet, version, files);
+        }
+        repo.apply(update).unwrap();
+
+        repo.save(&directory.join("repository.json")).unwrap();
+        for (_, file) in repo.entries().unwrap() {
+            std::fs::write(directory.join(file), b"packet").unwrap();
+        }
+        repo
+    }
+
+    #[test]
+    fn healthy_repository() {
+        let directory = tempfile::tempdir().unwrap();
+        let repo = repository(
+            directory.path(),
+            vec![
+                (packet("glibc", "2.35", &[]), Some(&["/usr/lib/libc.so.6"])),
+                (
+                    packet("ssl", "3.0", &["glibc"]),
+                    Some(&["/usr/lib/libssl.so.3"]),
+                ),
+                (
+                    packet("ssl", "1.1", &["glibc"]),
+                    Some(&["/usr/lib/libssl.so.3"]),
+                ),
+            ],
+        );
+        assert_eq!(check(&repo, None).unwrap(), vec![]);
+        assert_eq!(check(&repo, Some(&repo)).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn problems() {
+        let directory = tempfile::tempdir().unwrap();
+        let previous = repository(
+            directory.path(),
+            vec![(packet("ssl", "3.0", &[]), Some(&[]))],
+        );
+        let repo = repository(
+            directory.path(),
+            vec![
+                (
+                    packet("ssl", "1.1", &["glibc"]),
+                    Some(&["/usr/lib/libssl.so"]),
+                ),
+                (packet("ssl:dev", "1.1", &[]), Some(&["/usr/lib/libssl.so"])),
+                (facet("dev", Some("headers")), Some(&[])),
+                // A packet without file list:
+                (packet("zlib", "1.2", &[]), None),
+            ],
+        );
+        std::fs::remove_file(directory.path().join("dev-1.0.gng")).unwrap();
+
+        let problems = check(&repo, Some(&previous)).unwrap();
+        let messages = problems.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(messages.len(), 6, "{:#?}", messages);
+        assert_eq!(
+            messages[0],
+            "The facet defined by \"dev 1.0\" extends \"headers\", which is not in the repository."
+        );
+        assert_eq!(
+            messages[1],
+            "\"ssl 1.1\" depends on \"glibc\", which is not in the repository."
+        );
+        assert_eq!(
+            messages[2],
+            "\"/usr/lib/libssl.so\" is installed by several packets: ssl, ssl:dev."
+        );
+        assert!(messages[3].starts_with("Packet file \"dev-1.0.gng\": "));
+        assert_eq!(
+            messages[4],
+            "The files of \"zlib 1.2\" are unknown, run \"gng-repo backfill\" to record them."
+        );
+        assert_eq!(messages[5], "\"ssl\" was downgraded from 3.0 to 1.1.");
+
+        assert_eq!(
+            problems.iter().filter(|p| p.is_error()).count(),
+            4,
+            "{:#?}",
+            problems
+        );
+        assert_eq!(
+            serde_json::to_value(&problems[5]).unwrap(),
+            serde_json::json!({
+                "kind": "downgrade",
+                "packet": "ssl",
+                "version": "1.1",
+                "previous": "3.0",
+            })
+        );
+    }
+}
@@ -0,0 +1,168 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! File system helpers
+
+use eyre::{eyre, WrapErr};
+
+use std::io::{Read, Write};
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// Calculate the SHA256 hash of the file at `path` as lower-case hex
+///
+/// # Errors
+///
+/// Fail if the file can not be read.
+pub fn file_hash(path: &std::path::Path) -> eyre::Result<String> {
+    use sha2::Digest;
+
+    let mut file = std::fs::File::open(path)
+        .wrap_err(eyre!("Failed to open \"{}\".", path.to_string_lossy()))?;
+    let mut hasher = sha2::Sha256::new();
+    let mut buffer = [0_u8; 16 * 1024];
+    loop {
+        let count = file
+            .read(&mut buffer)
+            .wrap_err(eyre!("Failed to read \"{}\".", path.to_string_lossy()))?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Calculate the SHA256 hash of `data` as lower-case hex
+#[must_use]
+pub fn data_hash(data: &[u8]) -> String {
+    use sha2::Digest;
+
+    format!("{:x}", sha2::Sha256::digest(data))
+}
+
+/// Write `data` to `path` via a temporary file, so that readers never see
+/// partially written files, not even after a crash
+///
+/// # Errors
+///
+/// Fail if the file can not be written.
+///
+/// # Panics
+///
+/// Panics if `path` has no parent directory.
+pub fn write_atomically(path: &std::path::Path, data: &[u8]) -> eyre::Result<()> {
+    write_atomically_with(path, |file| {
+        file.write_all(data)
+            .wrap_err(eyre!("Failed to write \"{}\".", path.to_string_lossy()))
+    })
+}
+
+/// Like `write_atomically`, but `write` fills the temporary file
+///
+/// `path` is left untouched if `write` fails.
+///
+/// # Errors
+///
+/// Fail if `write` fails or the file can not be written.
+///
+/// # Panics
+///
+/// Panics if `path` has no parent directory.
+pub fn write_atomically_with(
+    path: &std::path::Path,
+    write: impl FnOnce(&mut std::fs::File) -> eyre::Result<()>,
+) -> eyre::Result<()> {
+    let directory = path.parent().expect("Files have a parent directory");
+    std::fs::create_dir_all(directory).wrap_err(eyre!(
+        "Failed to create directory \"{}\".",
+        directory.to_string_lossy()
+    ))?;
+
+    let mut file_name = std::ffi::OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(format!(".{}.tmp", std::process::id()));
+    let temporary = directory.join(file_name);
+
+    let result = || -> eyre::Result<()> {
+        let mut file = std::fs::File::create(&temporary).wrap_err(eyre!(
+            "Failed to create \"{}\".",
+            temporary.to_string_lossy()
+        ))?;
+        write(&mut file)?;
+        file.sync_all().wrap_err(eyre!(
+            "Failed to write \"{}\".",
+            temporary.to_string_lossy()
+        ))?;
+        std::fs::rename(&temporary, path).wrap_err(eyre!(
+            "Failed to move \"{}\" into place.",
+            path.to_string_lossy()
+        ))?;
+
+        // Make sure the rename itself is persisted:
+        std::fs::File::open(directory)
+            .and_then(|d| d.sync_all())
+            .wrap_err(eyre!(
+                "Failed to sync directory \"{}\".",
+                directory.to_string_lossy()
+            ))
+    }();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temporary);
+    }
+    result
+}
+
+// ----------------------------------------------------------------------
+// - DirectoryLock:
+// ----------------------------------------------------------------------
+
+/// An advisory lock on a directory
+///
+/// The lock is released when this is dropped.
+#[derive(Debug)]
+pub struct DirectoryLock {
+    _directory: std::fs::File,
+}
+
+/// Lock `directory`, creating it if necessary
+///
+/// This waits for the lock to become available. Should the previous holder
+/// of the lock have removed the directory, it is created and locked again.
+///
+/// # Errors
+///
+/// Fail if the directory can not be created or locked.
+pub fn lock_directory(directory: &std::path::Path) -> eyre::Result<DirectoryLock> {
+    use fs2::FileExt;
+    use std::os::unix::fs::MetadataExt;
+
+    loop {
+        std::fs::create_dir_all(directory).wrap_err(eyre!(
+            "Failed to create directory \"{}\".",
+            directory.to_string_lossy()
+        ))?;
+        let file = std::fs::File::open(directory)
+            .wrap_err(eyre!("Failed to open \"{}\".", directory.to_string_lossy()))?;
+        if file.try_lock_exclusive().is_err() {
+            tracing::info!("Waiting for lock on \"{}\".", directory.to_string_lossy());
+            file.lock_exclusive()
+                .wrap_err(eyre!("Failed to lock \"{}\".", directory.to_string_lossy()))?;
+        }
+
+        let locked = file
+            .metadata()
+            .wrap_err(eyre!("Failed to lock \"{}\".", directory.to_string_lossy()))?;
+        match std::fs::metadata(directory) {
+            Ok(current) if current.dev() == locked.dev() && current.ino() == locked.ino() => {
+                return Ok(DirectoryLock { _directory: file });
+            }
+            _ => tracing::debug!(
+                "\"{}\" was replaced while waiting for the lock.",
+                directory.to_string_lossy()
+            ),
+        }
+    }
+}
@@ -0,0 +1,702 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! A compact binary format for repository data
+//!
+//! Layout, all integers are little endian `u32`s:
+//!
+//! * `GNGIDX04`: Magic bytes, including the format version
+//! * Size of the header `H`, number of entries `N`, number of installed
+//!   files `F` and size of the string data `S`
+//! * `H` bytes of JSON data: The repository header
+//! * Name table: `N` rows of packet name, facet name (or `u32::MAX`) and
+//!   entry number, sorted by packet name, facet and version
+//! * Packet file table: `N` rows of packet file path and entry number,
+//!   sorted by path
+//! * Installed file table: `F` rows of installed file path and entry number,
+//!   sorted by path
+//! * `N + 1` offsets of the entries into the entry data
+//! * `S` bytes of string data: The names and paths the tables refer to, each
+//!   a length followed by UTF-8 data
+//! * Entry data: The zstd compressed, bincode encoded entries
+//!
+//! Only the entries are compressed, everything else is at an offset that
+//! follows from the sizes at the start of the data. Looking up packets by
+//! name, packet file or installed file thus only touches the tables and
+//! decodes the matching entries.
+//!
+//! The whole index is kept in memory: It is read in one go to verify its
+//! signature anyway. `Index::parse` checks all tables once, which takes time
+//! linear in their size, but decodes no entries.
+
+use gng_core::Name;
+use gng_packet_io::BinaryPacketDefinition;
+
+use eyre::{eyre, WrapErr};
+
+use std::io::Read;
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// The magic bytes shared by all versions of the binary index
+const MAGIC_PREFIX: &[u8; 6] = b"GNGIDX";
+
+const MAGIC: &[u8; 8] = b"GNGIDX04";
+
+/// The size of the magic bytes and the sizes following them
+const PREAMBLE_SIZE: usize = MAGIC.len() + 4 * 4;
+
+/// The size of a row in the name table
+const NAME_ROW_SIZE: usize = 3 * 4;
+
+/// The size of a row in the file tables
+const FILE_ROW_SIZE: usize = 2 * 4;
+
+/// Marks a packet without facet in the name table
+const NO_FACET: u32 = u32::MAX;
+
+/// The size of the biggest decoded entry that is accepted
+const MAX_ENTRY_SIZE: u64 = 64 * 1024 * 1024;
+
+fn to_u32(value: usize) -> eyre::Result<u32> {
+    u32::try_from(value).map_err(|_| eyre!("Repository data is too big for the binary index."))
+}
+
+fn push_u32(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+
+/// The `u32` at `position` in `data`, which must be big enough
+fn u32_at(data: &[u8], position: usize) -> u32 {
+    let mut bytes = [0_u8; 4];
+    bytes.copy_from_slice(&data[position..position + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+/// The string at `position` in the string data `strings`
+fn string_at(strings: &[u8], position: u32) -> eyre::Result<&str> {
+    let start = (position as usize)
+        .checked_add(4)
+        .filter(|s| *s <= strings.len())
+        .ok_or_else(|| eyre!("Binary index is corrupt."))?;
+    let end = start
+        .checked_add(u32_at(strings, start - 4) as usize)
+        .filter(|e| *e <= strings.len())
+        .ok_or_else(|| eyre!("Binary index is corrupt."))?;
+    std::str::from_utf8(&strings[start..end]).wrap_err("Binary index is corrupt.")
+}
+
+/// The UTF-8 representation of `path`
+fn path_str(path: &std::path::Path) -> eyre::Result<&str> {
+    path.to_str()
+        .ok_or_else(|| eyre!("Path \"{}\" is not valid UTF-8.", path.to_string_lossy()))
+}
+
+/// The number of rows in `0..count` for which `is_before` holds, which must
+/// hold for a prefix of the rows only
+fn partition(count: usize, is_before: impl Fn(usize) -> bool) -> usize {
+    let (mut low, mut high) = (0, count);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if is_before(middle) {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    low
+}
+
+/// Does `data` hold a binary index?
+///
+/// This is true for all versions of the binary index, including those that
+/// `Index::parse` does not support.
+#[must_use]
+pub fn is_index(data: &[u8]) -> bool {
+    data.starts_with(MAGIC_PREFIX)
+}
+
+/// The strings of an index under construction
+#[derive(Default)]
+struct Strings {
+    data: Vec<u8>,
+    positions: std::collections::HashMap<String, u32>,
+}
+
+impl Strings {
+    /// The position of `value` in the string data, adding it if necessary
+    fn add(&mut self, value: &str) -> eyre::Result<u32> {
+        if let Some(position) = self.positions.get(value) {
+            return Ok(*position);
+        }
+        let position = to_u32(self.data.len())?;
+        push_u32(&mut self.data, to_u32(value.len())?);
+        self.data.extend_from_slice(value.as_bytes());
+        self.positions.insert(value.to_string(), position);
+        Ok(position)
+    }
+}
+// ----------------------------------------------------------------------
+// - Format:
+// ----------------------------------------------------------------------
+
+/// The format repository data is stored in
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    /// One JSON object per line
+    JsonLines,
+    /// The compact binary index described in this module
+    Binary,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Self::JsonLines
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::JsonLines),
+            "binary" => Ok(Self::Binary),
+            _ => Err(eyre!(
+                "Unknown repository format \"{}\", expected \"json\" or \"binary\".",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::JsonLines => write!(f, "json"),
+            Self::Binary => write!(f, "binary"),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Record:
+// ----------------------------------------------------------------------
+
+/// A packet in the binary index
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Record {
+    /// The packet definition
+    pub packet: BinaryPacketDefinition,
+    /// The packet file, relative to the repository
+    pub file: std::path::PathBuf,
+    /// The SHA256 hash of the packet file (if known)
+    pub sha256: Option<String>,
+    /// Is this packet version pinned?
+    pub pinned: bool,
+    /// The files installed by the packet, `None` if they are unknown
+    pub files: Option<Vec<std::path::PathBuf>>,
+}
+
+impl Record {
+    fn key(&self) -> (&Name, Option<Name>, &gng_core::Version) {
+        (
+            &self.packet.name,
+            self.packet.facet_name(),
+            &self.packet.version,
+        )
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Index:
+// ----------------------------------------------------------------------
+
+/// Encode `header` (as JSON) and `records` into a binary index
+///
+/// # Errors
+///
+/// Fail if the data can not be encoded.
+pub fn write(header: &[u8], records: &[Record]) -> eyre::Result<Vec<u8>> {
+    let mut strings = Strings::default();
+
+    let mut names = records.iter().enumerate().collect::<Vec<_>>();
+    names.sort_by(|(_, a), (_, b)| a.key().cmp(&b.key()));
+    let mut name_table = Vec::new();
+    for (i, r) in names {
+        push_u32(&mut name_table, strings.add(&r.packet.name.to_string())?);
+        let facet = match r.packet.facet_name() {
+            Some(f) => strings.add(&f.to_string())?,
+            None => NO_FACET,
+        };
+        push_u32(&mut name_table, facet);
+        push_u32(&mut name_table, to_u32(i)?);
+    }
+
+    let mut files = Vec::new();
+    let mut installed = Vec::new();
+    for (i, r) in records.iter().enumerate() {
+        files.push((path_str(&r.file)?, i));
+        for f in r.files.iter().flatten() {
+            installed.push((path_str(f)?, i));
+        }
+    }
+    files.sort_unstable();
+    installed.sort_unstable();
+    let mut file_tables = Vec::new();
+    for (path, i) in files.iter().chain(installed.iter()) {
+        push_u32(&mut file_tables, strings.add(path)?);
+        push_u32(&mut file_tables, to_u32(*i)?);
+    }
+
+    let mut entry_data = Vec::new();
+    let mut offsets = vec![0];
+    for r in records {
+        let payload = bincode::serialize(r).wrap_err("Failed to encode packet.")?;
+        entry_data
+            .extend(zstd::bulk::compress(&payload, 0).wrap_err("Failed to compress packet.")?);
+        offsets.push(to_u32(entry_data.len())?);
+    }
+
+    let mut data = MAGIC.to_vec();
+    for size in [
+        header.len(),
+        records.len(),
+        installed.len(),
+        strings.data.len(),
+    ] {
+        push_u32(&mut data, to_u32(size)?);
+    }
+    data.extend_from_slice(header);
+    data.extend(name_table);
+    data.extend(file_tables);
+    for offset in offsets {
+        push_u32(&mut data, offset);
+    }
+    data.extend(strings.data);
+    data.extend(entry_data);
+    Ok(data)
+}
+
+/// A binary index
+///
+/// The index works on any data that can be seen as a byte slice, e.g. the
+/// contents of a file or a memory map of it. Only the tables are checked up
+/// front, entries are decoded when they are accessed.
+pub struct Index<D = Vec<u8>> {
+    data: D,
+    header: std::ops::Range<usize>,
+    names: std::ops::Range<usize>,
+    files: std::ops::Range<usize>,
+    installed: std::ops::Range<usize>,
+    offsets: std::ops::Range<usize>,
+    strings: std::ops::Range<usize>,
+    entries: std::ops::Range<usize>,
+}
+
+impl<D: AsRef<[u8]>> Index<D> {
+    /// Check the binary index in `data`
+    ///
+    /// # Errors
+    ///
+    /// Fail if `data` is not a valid binary index.
+    pub fn parse(data: D) -> eyre::Result<Self> {
+        let bytes = data.as_ref();
+        if !is_index(bytes) || bytes.len() < MAGIC.len() {
+            return Err(eyre!("Data is not a binary repository index."));
+        }
+        if !bytes.starts_with(MAGIC) {
+            return Err(eyre!(
+                "Unsupported binary index version \"{}\", expected \"{}\".",
+                String::from_utf8_lossy(&bytes[..MAGIC.len()]),
+                String::from_utf8_lossy(MAGIC)
+            ));
+        }
+        if bytes.len() < PREAMBLE_SIZE {
+            return Err(eyre!("Binary index is truncated."));
+        }
+        let size = |i: usize| u32_at(bytes, MAGIC.len() + 4 * i) as usize;
+        let (count, installed_count) = (size(1), size(2));
+
+        let mut position = PREAMBLE_SIZE;
+        let mut section = |size: Option<usize>| {
+            let end = size
+                .and_then(|s| position.checked_add(s))
+                .filter(|e| *e <= bytes.len())
+                .ok_or_else(|| eyre!("Binary index is truncated."))?;
+            let range = position..end;
+            position = end;
+            Ok::<_, eyre::Report>(range)
+        };
+        let header = section(Some(size(0)))?;
+        let names = section(count.checked_mul(NAME_ROW_SIZE))?;
+        let files = section(count.checked_mul(FILE_ROW_SIZE))?;
+        let installed = section(installed_count.checked_mul(FILE_ROW_SIZE))?;
+        let offsets = section(count.checked_add(1).and_then(|c| c.checked_mul(4)))?;
+        let strings = section(Some(size(3)))?;
+        let entries = position..bytes.len();
+
+        let index = Self {
+            data,
+            header,
+            names,
+            files,
+            installed,
+            offsets,
+            strings,
+            entries,
+        };
+        index.check()?;
+        Ok(index)
+    }
+
+    /// Check the tables of the index
+    fn check(&self) -> eyre::Result<()> {
+        let corrupt = || eyre!("Binary index is corrupt.");
+        let strings = self.section(&self.strings);
+        let count = self.len();
+
+        let offsets = (0..=count).map(|i| self.offset(i)).collect::<Vec<_>>();
+        if offsets[0] != 0
+            || offsets.windows(2).any(|w| w[0] > w[1])
+            || offsets[count] != self.entries.len()
+        {
+            return Err(corrupt());
+        }
+
+        // Every entry must be in the name and the packet file table once:
+        for (table, row_size) in [(&self.names, NAME_ROW_SIZE), (&self.files, FILE_ROW_SIZE)] {
+            let mut seen = vec![false; count];
+            for row in 0..count {
+                let entry = self.row_entry(table, row_size, row);
+                if entry >= count || std::mem::replace(&mut seen[entry], true) {
+                    return Err(corrupt());
+                }
+            }
+        }
+
+        let mut previous = None;
+        for row in 0..count {
+            let name = string_at(strings, self.row_value(&self.names, NAME_ROW_SIZE, row, 0))?;
+            let facet = match self.row_value(&self.names, NAME_ROW_SIZE, row, 1) {
+                NO_FACET => None,
+                position => Some(string_at(strings, position)?),
+            };
+            if previous > Some((name, facet)) {
+                return Err(corrupt());
+            }
+            previous = Some((name, facet));
+        }
+
+        for table in [&self.files, &self.installed] {
+            let mut previous = None;
+            for row in 0..table.len() / FILE_ROW_SIZE {
+                let path = string_at(strings, self.row_value(table, FILE_ROW_SIZE, row, 0))?;
+                if previous > Some(path) || self.row_entry(table, FILE_ROW_SIZE, row) >= count {
+                    return Err(corrupt());
+                }
+                previous = Some(path);
+            }
+        }
+        Ok(())
+    }
+
+    fn section(&self, range: &std::ops::Range<usize>) -> &[u8] {
+        &self.data.as_ref()[range.clone()]
+    }
+
+    /// The value in `column` of `row` in `table`
+    fn row_value(
+        &self,
+        table: &std::ops::Range<usize>,
+        row_size: usize,
+        row: usize,
+        column: usize,
+    ) -> u32 {
+        u32_at(self.section(table), row * row_size + column * 4)
+    }
+
+    /// The entry number in `row` of `table`, which is always the last column
+    fn row_entry(&self, table: &std::ops::Range<usize>, row_size: usize, row: usize) -> usize {
+        self.row_value(table, row_size, row, row_size / 4 - 1) as usize
+    }
+
+    /// The string at `position`, which was checked by `parse`
+    fn string(&self, position: u32) -> &str {
+        string_at(self.section(&self.strings), position).expect("Strings were checked on parse")
+    }
+
+    /// The offset of entry `index` into the entry data
+    fn offset(&self, index: usize) -> usize {
+        u32_at(self.section(&self.offsets), index * 4) as usize
+    }
+
+    /// The packet and facet name in `row` of the name table
+    fn name_key(&self, row: usize) -> (&str, Option<&str>) {
+        let name = self.string(self.row_value(&self.names, NAME_ROW_SIZE, row, 0));
+        let facet = match self.row_value(&self.names, NAME_ROW_SIZE, row, 1) {
+            NO_FACET => None,
+            position => Some(self.string(position)),
+        };
+        (name, facet)
+    }
+
+    /// The path and entry number in `row` of the file `table`
+    fn file_row(&self, table: &std::ops::Range<usize>, row: usize) -> (&str, usize) {
+        (
+            self.string(self.row_value(table, FILE_ROW_SIZE, row, 0)),
+            self.row_entry(table, FILE_ROW_SIZE, row),
+        )
+    }
+
+    /// The JSON encoded repository header
+    #[must_use]
+    pub fn header(&self) -> &[u8] {
+        self.section(&self.header)
+    }
+
+    /// The number of packets in the index
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.names.len() / NAME_ROW_SIZE
+    }
+
+    /// Is the index empty?
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Decode entry number `index`
+    ///
+    /// # Errors
+    ///
+    /// Fail if the entry can not be decoded.
+    pub fn record(&self, index: usize) -> eyre::Result<Record> {
+        let error = || eyre!("Failed to decode packet {} of the binary index.", index);
+        if index >= self.len() {
+            return Err(error());
+        }
+        let compressed = &self.section(&self.entries)[self.offset(index)..self.offset(index + 1)];
+
+        let mut payload = Vec::new();
+        zstd::stream::read::Decoder::with_buffer(compressed)
+            .and_then(|d| d.take(MAX_ENTRY_SIZE + 1).read_to_end(&mut payload))
+            .wrap_err_with(error)?;
+        if payload.len() as u64 > MAX_ENTRY_SIZE {
+            return Err(eyre!("Packet {} of the binary index is too big.", index));
+        }
+        bincode::deserialize(&payload).wrap_err_with(error)
+    }
+
+    /// Decode all entries
+    ///
+    /// # Errors
+    ///
+    /// Fail if an entry can not be decoded.
+    pub fn records(&self) -> eyre::Result<Vec<Record>> {
+        (0..self.len()).map(|i| self.record(i)).collect()
+    }
+
+    /// All versions of the `facet` of the `packet`, oldest first
+    ///
+    /// # Errors
+    ///
+    /// Fail if an entry can not be decoded.
+    pub fn lookup(&self, packet: &Name, facet: &Option<Name>) -> eyre::Result<Vec<Record>> {
+        let key = (packet.as_bytes(), facet.as_ref().map(Name::as_bytes));
+        let row_key = |row| {
+            let (name, facet) = self.name_key(row);
+            (name.as_bytes(), facet.map(str::as_bytes))
+        };
+        let start = partition(self.len(), |row| row_key(row) < key);
+        let end = partition(self.len(), |row| row_key(row) <= key);
+        (start..end)
+            .map(|row| self.record(self.row_entry(&self.names, NAME_ROW_SIZE, row)))
+            .collect()
+    }
+
+    /// The entry for the packet `file`
+    ///
+    /// # Errors
+    ///
+    /// Fail if an entry can not be decoded.
+    pub fn find_file(&self, file: &std::path::Path) -> eyre::Result<Option<Record>> {
+        let file = match file.to_str() {
+            Some(f) => f,
+            None => return Ok(None),
+        };
+        let row = partition(self.len(), |row| self.file_row(&self.files, row).0 < file);
+        if row < self.len() && self.file_row(&self.files, row).0 == file {
+            self.record(self.file_row(&self.files, row).1).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The files installed by all packets with the number of the entry
+    /// installing them, sorted by path
+    pub fn installed_files(&self) -> impl Iterator<Item = (&std::path::Path, usize)> {
+        (0..self.installed.len() / FILE_ROW_SIZE).map(move |row| {
+            let (path, entry) = self.file_row(&self.installed, row);
+            (std::path::Path::new(path), entry)
+        })
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, facet: Option<&str>, version: &str) -> Record {
+        let full_name = facet.map_or_else(|| name.to_string(), |f| format!("{}:{}", name, f));
+        Record {
+            packet: crate::test_util::packet(&full_name, version, &[]),
+            file: std::path::PathBuf::from(format!("z/{}-{}.gng", name, version)),
+            sha256: (version == "1.0").then(|| "00ff".to_string()),
+            pinned: version == "2.0",
+            files: Some(vec![std::path::PathBuf::from(format!("/usr/bin/{}", name))]),
+        }
+    }
+
+    #[test]
+    fn round_trip_and_lookup() {
+        let records = vec![
+            record("foo", None, "2.0"),
+            record("bar", None, "1.0"),
+            record("foo", Some("dev"), "1.0"),
+            record("foo", None, "1.0"),
+            record("foo_bar", None, "1.0"),
+        ];
+        let data = write(b"{\"generation\":3}", &records).unwrap();
+        assert!(is_index(&data));
+        assert!(!is_index(b"{\"generation\":3}\n"));
+
+        // The header is stored uncompressed right after the sizes:
+        assert_eq!(
+            &data[PREAMBLE_SIZE..PREAMBLE_SIZE + 16],
+            b"{\"generation\":3}"
+        );
+
+        let index = Index::parse(&data[..]).unwrap();
+        assert_eq!(index.header(), b"{\"generation\":3}");
+        assert_eq!(index.len(), 5);
+        assert_eq!(index.records().unwrap(), records);
+
+        let foo = Name::try_from("foo").unwrap();
+        assert_eq!(
+            index.lookup(&foo, &None).unwrap(),
+            vec![records[3].clone(), records[0].clone()]
+        );
+        assert_eq!(
+            index
+                .lookup(&foo, &Some(Name::try_from("dev").unwrap()))
+                .unwrap(),
+            vec![records[2].clone()]
+        );
+        assert_eq!(
+            index
+                .lookup(&Name::try_from("baz").unwrap(), &None)
+                .unwrap(),
+            vec![]
+        );
+
+        assert_eq!(
+            index
+                .find_file(std::path::Path::new("z/bar-1.0.gng"))
+                .unwrap(),
+            Some(records[1].clone())
+        );
+        assert_eq!(
+            index
+                .find_file(std::path::Path::new("z/baz-1.0.gng"))
+                .unwrap(),
+            None
+        );
+
+        assert_eq!(
+            index
+                .installed_files()
+                .map(|(p, i)| (p.to_string_lossy().to_string(), i))
+                .collect::<Vec<_>>(),
+            vec![
+                ("/usr/bin/bar".to_string(), 1),
+                ("/usr/bin/foo".to_string(), 0),
+                ("/usr/bin/foo".to_string(), 2),
+                ("/usr/bin/foo".to_string(), 3),
+                ("/usr/bin/foo_bar".to_string(), 4),
+            ]
+        );
+
+        let empty = Index::parse(write(b"{}", &[]).unwrap()).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.lookup(&foo, &None).unwrap(), vec![]);
+        assert_eq!(empty.installed_files().count(), 0);
+    }
+
+    #[test]
+    fn decode_entries_lazily() {
+        let records = vec![record("foo", None, "1.0"), record("bar", None, "1.0")];
+        let mut data = write(b"{}", &records).unwrap();
+
+        // Break the entry data of "foo":
+        let index = Index::parse(&data[..]).unwrap();
+        let start = index.entries.start + index.offset(0);
+        data[start..start + 4].copy_from_slice(b"junk");
+
+        let index = Index::parse(&data[..]).unwrap();
+        assert!(index.record(0).is_err());
+        assert!(index.records().is_err());
+        assert!(index
+            .lookup(&Name::try_from("foo").unwrap(), &None)
+            .is_err());
+        assert_eq!(
+            index
+                .lookup(&Name::try_from("bar").unwrap(), &None)
+                .unwrap(),
+            vec![records[1].clone()]
+        );
+    }
+
+    #[test]
+    fn reject_corrupt_data() {
+        let data = write(b"{}", &[record("foo", None, "1.0")]).unwrap();
+        assert!(Index::parse(&data[..PREAMBLE_SIZE + 4]).is_err());
+        assert!(Index::parse(&data[..10]).is_err());
+        assert!(Index::parse(&b"GNGIDX04"[..]).is_err());
+        assert!(is_index(b"GNGIDX03"));
+        assert_eq!(
+            Index::parse([&b"GNGIDX03"[..], &data[8..]].concat())
+                .map(|_| ())
+                .unwrap_err()
+                .to_string(),
+            "Unsupported binary index version \"GNGIDX03\", expected \"GNGIDX04\"."
+        );
+
+        // Entry count does not match the tables:
+        let mut wrong_count = data.clone();
+        wrong_count[12] = wrong_count[12].wrapping_add(1);
+        assert!(Index::parse(wrong_count).is_err());
+
+        // Entry data is cut short:
+        assert!(Index::parse(&data[..data.len() - 4]).is_err());
+
+        // Entry number out of range:
+        let mut wrong_entry = data.clone();
+        let entry = PREAMBLE_SIZE + 2 + 2 * 4;
+        wrong_entry[entry..entry + 4].copy_from_slice(&7_u32.to_le_bytes());
+        assert!(Index::parse(wrong_entry).is_err());
+
+        // String reference out of range:
+        let mut wrong_string = data;
+        let name = PREAMBLE_SIZE + 2;
+        wrong_string[name..name + 4].copy_from_slice(&0x00ff_0000_u32.to_le_bytes());
+        assert!(Index::parse(wrong_string).is_err());
+    }
+}
@@ -19,13 +19,28 @@
 // - Modules:
 // ----------------------------------------------------------------------
 
+pub mod check;
+pub mod files;
+pub mod index;
+mod remote;
 pub mod repository;
+pub mod repository_set;
+pub mod requirement;
+pub mod resolver;
+pub mod reverse_dependencies;
+pub mod signature;
+#[cfg(test)]
+pub(crate) mod test_util;
 
 // ----------------------------------------------------------------------
 // - Exports:
 // ----------------------------------------------------------------------
 
-pub use repository::Repository;
+pub use repository::{DeletedGarbage, Difference, Garbage, Repository, Retention};
+pub use repository_set::RepositorySet;
+pub use requirement::VersionRequirement;
+pub use reverse_dependencies::ReverseDependencies;
+pub use signature::Trust;
 
 // Reexport other crates:
 pub use gng_packet_io::{
@@ -0,0 +1,434 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Access to repositories served over HTTP(S), backed by a local cache
+
+use eyre::{eyre, WrapErr};
+
+use crate::files::{write_atomically, write_atomically_with};
+
+use std::io::{Read, Write};
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// How long to wait for a connection to be established
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// How long to wait for each read or write on an established connection
+///
+/// This is no limit on the total time of a transfer, so big packets can still
+/// be downloaded over slow connections, but stalled transfers are aborted.
+const TRANSFER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+fn read_body(response: ureq::Response) -> eyre::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .read_to_end(&mut data)
+        .wrap_err("Failed to read response body.")?;
+    Ok(data)
+}
+
+/// Copy the body of `response` into `file`, returning its SHA256 hash
+fn download_body(response: ureq::Response, file: &mut std::fs::File) -> eyre::Result<String> {
+    use sha2::Digest;
+
+    let mut hasher = sha2::Sha256::new();
+    let mut reader = response.into_reader();
+    let mut buffer = vec![0_u8; 64 * 1024];
+    loop {
+        let count = reader
+            .read(&mut buffer)
+            .wrap_err("Failed to read response body.")?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count]);
+        file.write_all(&buffer[..count])
+            .wrap_err("Failed to write response body.")?;
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// ----------------------------------------------------------------------
+// - Remote:
+// ----------------------------------------------------------------------
+
+/// Where and how to access a remote repository
+#[derive(Clone, Debug)]
+pub struct Remote {
+    cache_directory: std::path::PathBuf,
+    offline: bool,
+}
+
+impl Remote {
+    /// Create a `Remote` for `url`, caching data below `cache_root`
+    pub fn new(url: &url::Url, cache_root: &std::path::Path, offline: bool) -> Self {
+        Self {
+            cache_directory: cache_root.join(crate::signature::url_key(url)),
+            offline,
+        }
+    }
+
+    fn agent() -> ureq::Agent {
+        ureq::AgentBuilder::new()
+            .timeout_connect(CONNECT_TIMEOUT)
+            .timeout_read(TRANSFER_TIMEOUT)
+            .timeout_write(TRANSFER_TIMEOUT)
+            .build()
+    }
+
+    /// Make sure the repository metadata file at `url` is in the cache as
+    /// `file_name`
+    ///
+    /// Uses the cached copy when offline or if the server is not reachable.
+    /// Returns the path to the cached file.
+    pub fn fetch_metadata(
+        &self,
+        url: &url::Url,
+        file_name: &str,
+    ) -> eyre::Result<std::path::PathBuf> {
+        let cached = self.cache_directory.join(file_name);
+        let etag_file = self.cache_directory.join(format!("{}.etag", file_name));
+
+        if self.offline {
+            return if cached.is_file() {
+                Ok(cached)
+            } else {
+                Err(eyre!(
+                    "Repository \"{}\" is not cached and can not be fetched while offline.",
+                    url.as_str()
+                ))
+            };
+        }
+
+        let etag = cached
+            .is_file()
+            .then(|| std::fs::read_to_string(&etag_file).ok())
+            .flatten();
+
+        let mut request = Self::agent().request_url("GET", url);
+        if let Some(etag) = &etag {
+            request = request.set("If-None-Match", etag.trim());
+        }
+
+        match request.call() {
+            Ok(response) if response.status() == 304 => {
+                tracing::debug!("Repository \"{}\" is unchanged.", url.as_str());
+                Ok(cached)
+            }
+            Ok(response) => {
+                let new_etag = response.header("ETag").map(ToString::to_string);
+                let data = read_body(response)
+                    .wrap_err(eyre!("Failed to download \"{}\".", url.as_str()))?;
+                write_atomically(&cached, &data)?;
+                match new_etag {
+                    Some(etag) => write_atomically(&etag_file, etag.as_bytes())?,
+                    None => {
+                        let _ = std::fs::remove_file(&etag_file);
+                    }
+                }
+                Ok(cached)
+            }
+            Err(ureq::Error::Transport(e)) if cached.is_file() => {
+                tracing::warn!(
+                    "Failed to reach \"{}\", using cached data: {}",
+                    url.as_str(),
+                    e
+                );
+                Ok(cached)
+            }
+            Err(e) => Err(eyre!(e).wrap_err(eyre!("Failed to fetch \"{}\".", url.as_str()))),
+        }
+    }
+
+    /// Make sure the packet `file` (relative to `base_url`) is in the cache
+    /// and has the SHA256 hash `hash`
+    ///
+    /// The packet is streamed into the cache and only moved into place once
+    /// its hash was verified. Returns the path to the cached packet file.
+    pub fn fetch_packet(
+        &self,
+        base_url: &url::Url,
+        file: &std::path::Path,
+        hash: &str,
+    ) -> eyre::Result<std::path::PathBuf> {
+        if file
+            .components()
+            .any(|c| !matches!(c, std::path::Component::Normal(_)))
+        {
+            return Err(eyre!(
+                "Packet file \"{}\" is not a plain relative path.",
+                file.to_string_lossy()
+            ));
+        }
+
+        let cached = self.cache_directory.join("packets").join(file);
+        if cached.is_file() {
+            if crate::files::file_hash(&cached)? == hash {
+                return Ok(cached);
+            }
+            tracing::warn!(
+                "Cached packet \"{}\" is corrupt, discarding it.",
+                cached.to_string_lossy()
+            );
+            std::fs::remove_file(&cached)
+                .wrap_err(eyre!("Failed to remove \"{}\".", cached.to_string_lossy()))?;
+        }
+
+        let url = base_url
+            .join(&file.to_string_lossy())
+            .wrap_err(eyre!("Invalid packet file \"{}\".", file.to_string_lossy()))?;
+        if self.offline {
+            return Err(eyre!(
+                "Packet \"{}\" is not cached and can not be fetched while offline.",
+                url.as_str()
+            ));
+        }
+
+        let response = Self::agent()
+            .request_url("GET", &url)
+            .call()
+            .map_err(|e| eyre!(e))
+            .wrap_err(eyre!("Failed to fetch \"{}\".", url.as_str()))?;
+        write_atomically_with(&cached, |file| {
+            let actual = download_body(response, file)
+                .wrap_err(eyre!("Failed to download \"{}\".", url.as_str()))?;
+            if actual == hash {
+                Ok(())
+            } else {
+                Err(eyre!(
+                    "Hash mismatch for \"{}\": Expected {}, got {}.",
+                    url.as_str(),
+                    hash,
+                    actual
+                ))
+            }
+        })?;
+        Ok(cached)
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    use std::io::{BufRead, Write};
+    use std::sync::{Arc, Mutex};
+
+    /// A minimal HTTP server serving static files, with `ETag` support
+    pub struct TestServer {
+        pub url: url::Url,
+        files: Arc<Mutex<std::collections::HashMap<String, Vec<u8>>>>,
+        requests: Arc<Mutex<Vec<(String, u16)>>>,
+    }
+
+    impl TestServer {
+        pub fn start() -> Self {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = url::Url::parse(&format!("http://{}/repo/", listener.local_addr().unwrap()))
+                .unwrap();
+            let files = Arc::new(Mutex::new(std::collections::HashMap::new()));
+            let requests = Arc::new(Mutex::new(Vec::new()));
+
+            let thread_files = files.clone();
+            let thread_requests = requests.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let stream = match stream {
+                        Ok(s) => s,
+                        Err(_) => break,
+                    };
+                    Self::serve(stream, &thread_files, &thread_requests);
+                }
+            });
+
+            Self {
+                url,
+                files,
+                requests,
+            }
+        }
+
+        fn serve(
+            mut stream: std::net::TcpStream,
+            files: &Mutex<std::collections::HashMap<String, Vec<u8>>>,
+            requests: &Mutex<Vec<(String, u16)>>,
+        ) {
+            let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let path = request_line
+                .split_whitespace()
+                .nth(1)
+                .unwrap_or_default()
+                .to_string();
+
+            let mut if_none_match = None;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((key, value)) = line.split_once(':') {
+                    if key.eq_ignore_ascii_case("if-none-match") {
+                        if_none_match = Some(value.trim().to_string());
+                    }
+                }
+            }
+
+            let data = files.lock().unwrap().get(&path).cloned();
+            let (status, headers, body) = data.map_or_else(
+                || (404, String::new(), Vec::new()),
+                |data| {
+                    let etag = {
+                        use sha2::Digest;
+                        format!("\"{:x}\"", sha2::Sha256::digest(&data))
+                    };
+                    if if_none_match.as_deref() == Some(etag.as_str()) {
+                        (304, format!("ETag: {}\r\n", etag), Vec::new())
+                    } else {
+                        (200, format!("ETag: {}\r\n", etag), data)
+                    }
+                },
+            );
+            requests.lock().unwrap().push((path, status));
+
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {} X\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                headers,
+                body.len()
+            );
+            let _ = stream.write_all(&body);
+        }
+
+        pub fn put(&self, path: &str, data: &[u8]) {
+            self.files
+                .lock()
+                .unwrap()
+                .insert(format!("{}{}", self.url.path(), path), data.to_vec());
+        }
+
+        pub fn take_requests(&self) -> Vec<(String, u16)> {
+            std::mem::take(&mut *self.requests.lock().unwrap())
+        }
+    }
+
+    fn hash(data: &[u8]) -> String {
+        use sha2::Digest;
+        format!("{:x}", sha2::Sha256::digest(data))
+    }
+
+    #[test]
+    fn fetch_metadata_with_etag() {
+        let server = TestServer::start();
+        let cache = tempfile::tempdir().unwrap();
+        let index_url = server.url.join("repository.json").unwrap();
+        server.put("repository.json", b"first\n");
+
+        let remote = Remote::new(&server.url, cache.path(), false);
+        let path = remote
+            .fetch_metadata(&index_url, "repository.json")
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"first\n");
+        assert_eq!(
+            server.take_requests(),
+            vec![("/repo/repository.json".to_string(), 200)]
+        );
+
+        // Unchanged: Conditional request
+        let path = remote
+            .fetch_metadata(&index_url, "repository.json")
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"first\n");
+        assert_eq!(
+            server.take_requests(),
+            vec![("/repo/repository.json".to_string(), 304)]
+        );
+
+        // Changed:
+        server.put("repository.json", b"second\n");
+        let path = remote
+            .fetch_metadata(&index_url, "repository.json")
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second\n");
+
+        // Offline:
+        server.take_requests();
+        let offline = Remote::new(&server.url, cache.path(), true);
+        let path = offline
+            .fetch_metadata(&index_url, "repository.json")
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second\n");
+        assert_eq!(server.take_requests(), vec![]);
+
+        let empty_cache = tempfile::tempdir().unwrap();
+        let offline = Remote::new(&server.url, empty_cache.path(), true);
+        assert!(offline
+            .fetch_metadata(&index_url, "repository.json")
+            .is_err());
+    }
+
+    #[test]
+    fn fetch_packet_verifies_hash() {
+        let server = TestServer::start();
+        let cache = tempfile::tempdir().unwrap();
+        server.put("foo-1.0.gng", b"packet data");
+        let file = std::path::Path::new("foo-1.0.gng");
+
+        let remote = Remote::new(&server.url, cache.path(), false);
+        assert!(remote
+            .fetch_packet(&server.url, file, &hash(b"other data"))
+            .is_err());
+        // Nothing is left behind in the cache:
+        let packets = cache
+            .path()
+            .join(crate::signature::url_key(&server.url))
+            .join("packets");
+        assert_eq!(std::fs::read_dir(&packets).unwrap().count(), 0);
+
+        let path = remote
+            .fetch_packet(&server.url, file, &hash(b"packet data"))
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"packet data");
+        server.take_requests();
+
+        // Served from cache, even when offline:
+        let offline = Remote::new(&server.url, cache.path(), true);
+        assert_eq!(
+            offline
+                .fetch_packet(&server.url, file, &hash(b"packet data"))
+                .unwrap(),
+            path
+        );
+        assert_eq!(server.take_requests(), vec![]);
+
+        // Corrupt cache entries are fetched again:
+        std::fs::write(&path, b"garbage").unwrap();
+        assert!(offline
+            .fetch_packet(&server.url, file, &hash(b"packet data"))
+            .is_err());
+        remote
+            .fetch_packet(&server.url, file, &hash(b"packet data"))
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"packet data");
+
+        assert!(remote
+            .fetch_packet(
+                &server.url,
+                std::path::Path::new("../foo-1.0.gng"),
+                &hash(b"packet data")
+            )
+            .is_err());
+    }
+}
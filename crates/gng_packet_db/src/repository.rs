@@ -3,12 +3,14 @@
 
 //! A directory based packet DB
 
+use crate::index::{Format, Record};
+use crate::signature::{RepositoryMetadata, SigningKey, SIGNATURE_FILE};
+use crate::{Trust, VersionRequirement};
+
 use gng_core::{Name, Names};
 
 use eyre::{eyre, WrapErr};
 
-use std::io::{BufRead, Write};
-
 // ----------------------------------------------------------------------
 // - Helper:
 // ----------------------------------------------------------------------
@@ -65,20 +67,451 @@ fn relative_file_path(
     Ok((file_path.to_path_buf(), abs_file_path))
 }
 
+/// The name of the lock file used for read-modify-write cycles
+const LOCK_FILE: &str = "repository.lock";
+
+/// The directory holding the snapshots of a repository
+const SNAPSHOT_DIRECTORY: &str = "snapshots";
+
+/// The file in `SNAPSHOT_DIRECTORY` holding the name of the current snapshot
+const CURRENT_SNAPSHOT: &str = "current";
+
+/// The file in `SNAPSHOT_DIRECTORY` holding the signature of `CURRENT_SNAPSHOT`
+const CURRENT_SNAPSHOT_SIGNATURE: &str = "current.sig";
+
+/// The signature of repository data that is being saved
+///
+/// This is moved over `SIGNATURE_FILE` once the repository data is in place.
+const PENDING_SIGNATURE_FILE: &str = "repository.sig.new";
+
+/// The signature of `CURRENT_SNAPSHOT` while it is being changed
+///
+/// This is moved over `CURRENT_SNAPSHOT_SIGNATURE` once the new pointer is
+/// in place.
+const PENDING_CURRENT_SNAPSHOT_SIGNATURE: &str = "current.sig.new";
+
+/// Make sure `name` can be used as the name of a snapshot
+fn check_snapshot_name(name: &str) -> eyre::Result<()> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || name == CURRENT_SNAPSHOT
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+    {
+        Err(eyre!("Invalid snapshot name \"{}\".", name))
+    } else {
+        Ok(())
+    }
+}
+
+/// The name of the snapshot in the contents of a `CURRENT_SNAPSHOT` file
+fn parse_snapshot_pointer(data: Vec<u8>) -> eyre::Result<String> {
+    let name = String::from_utf8(data)
+        .wrap_err("The current snapshot name is not UTF-8.")?
+        .trim()
+        .to_string();
+    check_snapshot_name(&name)?;
+    Ok(name)
+}
+
+/// The snapshot selected by the fragment of `url` (`#snapshot=NAME`)
+fn snapshot_from_url(url: &url::Url) -> eyre::Result<Option<String>> {
+    match url.fragment() {
+        None | Some("") => Ok(None),
+        Some(fragment) => fragment
+            .strip_prefix("snapshot=")
+            .map(|name| Some(name.to_string()))
+            .ok_or_else(|| {
+                eyre!(
+                    "Unsupported fragment in repository URL \"{}\", expected \"#snapshot=NAME\".",
+                    url.as_str()
+                )
+            }),
+    }
+}
+
+/// The URL identifying the snapshot `name` of the repository at `base_url`
+fn snapshot_url(base_url: &url::Url, name: &str) -> url::Url {
+    let mut url = base_url.clone();
+    url.set_fragment(Some(&format!("snapshot={}", name)));
+    url
+}
+
+/// Sign `entries_file` into `signature_file`
+fn sign_entries_file(
+    entries_file: &std::path::Path,
+    signature_file: &std::path::Path,
+    serial: u64,
+    key: &SigningKey,
+    validity: Option<std::time::Duration>,
+) -> eyre::Result<()> {
+    sign_hash(
+        &crate::files::file_hash(entries_file)?,
+        signature_file,
+        serial,
+        key,
+        validity,
+    )
+}
+
+/// Sign data with the SHA256 hash `sha256` into `signature_file`
+fn sign_hash(
+    sha256: &str,
+    signature_file: &std::path::Path,
+    serial: u64,
+    key: &SigningKey,
+    validity: Option<std::time::Duration>,
+) -> eyre::Result<()> {
+    let timestamp = crate::signature::now();
+    let mut metadata = RepositoryMetadata::new(
+        serial,
+        timestamp,
+        validity.map_or(u64::MAX, |v| timestamp.saturating_add(v.as_secs())),
+        sha256,
+    );
+    metadata.sign(key);
+    metadata.save(signature_file)
+}
+
+/// Verify `data` of a local repository with `signature_file`
+///
+/// Signed saves write the signature of the new data to `pending_file`
+/// before replacing the data and only then move it over `signature_file`.
+/// Data that does not match `signature_file` is thus checked against the
+/// pending signature, too. Checking `signature_file` once more covers the
+/// pending signature having been moved into place in the meantime.
+fn check_local_signature(
+    trust: &Trust,
+    url: &url::Url,
+    data: &[u8],
+    signature_file: &std::path::Path,
+    pending_file: Option<&std::path::Path>,
+) -> eyre::Result<()> {
+    let result = trust.check(url, data, signature_file);
+    if result.is_ok() || !trust.is_enabled() {
+        return result;
+    }
+
+    pending_file.map_or(result, |pending_file| {
+        trust
+            .check(url, data, pending_file)
+            .or_else(|_| trust.check(url, data, signature_file))
+    })
+}
+
+/// The serial of the signature in `signature_file`, 0 if there is none
+fn signature_serial(signature_file: &std::path::Path) -> eyre::Result<u64> {
+    if signature_file.exists() {
+        Ok(RepositoryMetadata::load(signature_file)?.serial)
+    } else {
+        Ok(0)
+    }
+}
+
+/// The packets in a repository file
+enum Data {
+    /// The decoded packets of a JSON lines file
+    Entries(Vec<Entry>),
+    /// A binary index, packets are decoded when needed
+    Index(crate::index::Index),
+}
+
+/// Decode all packets in `index`
+fn decode_index(index: &crate::index::Index) -> eyre::Result<Vec<Entry>> {
+    index
+        .records()?
+        .into_iter()
+        .map(Entry::from_record)
+        .collect()
+}
+
+/// Read the `Header` and all `Entry`s from `entries_file`
+///
+/// The format of the file is detected automatically.
+fn read_entries(entries_file: &std::path::Path) -> eyre::Result<(Header, Vec<Entry>)> {
+    let (header, data) = read_data(entries_file)?;
+    match data {
+        Data::Entries(entries) => Ok((header, entries)),
+        Data::Index(index) => Ok((
+            header,
+            decode_index(&index).wrap_err(eyre!(
+                "Parsing data in repository file \"{}\" failed.",
+                entries_file.to_string_lossy()
+            ))?,
+        )),
+    }
+}
+
+/// Read the raw contents of `entries_file`
+fn read_raw_data(entries_file: &std::path::Path) -> eyre::Result<Vec<u8>> {
+    std::fs::read(entries_file).wrap_err(eyre!(
+        "Failed to read repository data in \"{}\".",
+        entries_file.to_string_lossy()
+    ))
+}
+
+/// Read the `Header` and the packets from `entries_file`
+///
+/// The format of the file is detected automatically. Binary indices are
+/// only checked, not decoded.
+fn read_data(entries_file: &std::path::Path) -> eyre::Result<(Header, Data)> {
+    parse_data(entries_file, read_raw_data(entries_file)?)
+}
+
+/// Parse the `Header` and the packets from `data` read from `entries_file`
+fn parse_data(entries_file: &std::path::Path, data: Vec<u8>) -> eyre::Result<(Header, Data)> {
+    let parse_error = || {
+        eyre!(
+            "Parsing data in repository file \"{}\" failed.",
+            entries_file.to_string_lossy()
+        )
+    };
+
+    if crate::index::is_index(&data) {
+        let index = crate::index::Index::parse(data).wrap_err_with(parse_error)?;
+        let mut header = serde_json::from_slice::<Header>(index.header())
+            .wrap_err("Failed to read header")
+            .wrap_err_with(parse_error)?;
+        header.format = Format::Binary;
+        return Ok((header, Data::Index(index)));
+    }
+
+    let data = String::from_utf8(data)
+        .wrap_err("Repository data is not UTF-8")
+        .wrap_err_with(parse_error)?;
+    let mut lines = data.lines().peekable();
+    let header = lines
+        .peek()
+        .and_then(|l| serde_json::from_str::<Header>(l).ok())
+        .map_or_else(Header::default, |h| {
+            lines.next();
+            h
+        });
+
+    let entries = lines
+        .map(|l| Entry::from_json(l).wrap_err_with(parse_error))
+        .collect::<eyre::Result<Vec<_>>>()?;
+    Ok((header, Data::Entries(entries)))
+}
+
+/// Find all packet files below `directory`, relative to `base_dir`
+///
+/// Hidden files and directories are skipped.
+fn find_packet_files(
+    base_dir: &std::path::Path,
+    directory: &std::path::Path,
+    result: &mut Vec<std::path::PathBuf>,
+) -> eyre::Result<()> {
+    for entry in std::fs::read_dir(directory).wrap_err(eyre!(
+        "Failed to read directory \"{}\".",
+        directory.to_string_lossy()
+    ))? {
+        let path = entry
+            .wrap_err(eyre!(
+                "Failed to read directory \"{}\".",
+                directory.to_string_lossy()
+            ))?
+            .path();
+        if path
+            .file_name()
+            .map_or(true, |n| n.to_string_lossy().starts_with('.'))
+        {
+            continue;
+        }
+
+        if path.is_dir() {
+            find_packet_files(base_dir, &path, result)?;
+        } else if path.is_file()
+            && path
+                .extension()
+                .map_or(false, |e| e.eq_ignore_ascii_case("gng"))
+        {
+            result.push(
+                path.strip_prefix(base_dir)
+                    .expect("Path was found below base_dir")
+                    .to_path_buf(),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The files a packet installs into the system, as absolute paths
+///
+/// Directories and the packet metadata in `.gng` are skipped.
+fn installed_files(contents: &[gng_packet_io::ContentInfo]) -> Vec<std::path::PathBuf> {
+    let usr_directory = std::path::Path::new("/usr");
+    let mut files = contents
+        .iter()
+        .filter(|c| !matches!(c.content_type, gng_packet_io::ContentType::Directory {}))
+        .map(|c| {
+            c.path
+                .components()
+                .filter(|c| matches!(c, std::path::Component::Normal(_)))
+                .collect::<std::path::PathBuf>()
+        })
+        .filter(|p| !p.starts_with(".gng") && p.components().next().is_some())
+        .map(|p| usr_directory.join(p))
+        .collect::<Vec<_>>();
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// The key used to look up `Entry`s: packet name and facet name
+type IndexKey = (Name, Option<Name>);
+
+/// Map packet and facet names to indices into `packets`, oldest version first
+fn build_index(packets: &[Entry]) -> std::collections::BTreeMap<IndexKey, Vec<usize>> {
+    let mut index = std::collections::BTreeMap::<IndexKey, Vec<usize>>::new();
+    for (i, e) in packets.iter().enumerate() {
+        index
+            .entry((e.packet_data.name.clone(), e.packet_data.facet_name()))
+            .or_default()
+            .push(i);
+    }
+    for indices in index.values_mut() {
+        indices.sort_by(|a, b| {
+            packets[*a]
+                .packet_data
+                .version
+                .cmp(&packets[*b].packet_data.version)
+        });
+    }
+    index
+}
+
+/// The packets of a `Repository`, decoded into memory
+#[derive(Default)]
+struct Packets {
+    entries: Vec<Entry>,
+    index: std::collections::BTreeMap<IndexKey, Vec<usize>>,
+}
+
+impl Packets {
+    fn new(entries: Vec<Entry>) -> Self {
+        Self {
+            index: build_index(&entries),
+            entries,
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Retention:
+// ----------------------------------------------------------------------
+
+/// How many versions of a packet to keep in a repository
+///
+/// Pinned versions are always kept.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Retention {
+    /// Keep all versions
+    All,
+    /// Keep the given number of newest versions
+    Newest(usize),
+}
+
+impl Retention {
+    const fn is_all(&self) -> bool {
+        matches!(self, Self::All)
+    }
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self::All
+    }
+}
+
+impl std::str::FromStr for Retention {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Self::All),
+            _ => match s.parse::<usize>() {
+                Ok(count) if count > 0 => Ok(Self::Newest(count)),
+                _ => Err(eyre!(
+                    "Unknown retention \"{}\", expected \"all\" or a number of versions.",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for Retention {
+    type Error = eyre::Report;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Retention> for String {
+    fn from(retention: Retention) -> Self {
+        retention.to_string()
+    }
+}
+
+impl std::fmt::Display for Retention {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::All => write!(f, "all"),
+            Self::Newest(count) => write!(f, "{}", count),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Header:
+// ----------------------------------------------------------------------
+
+/// The first line of a repository file
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+struct Header {
+    /// Increased by one whenever the repository file is written
+    generation: u64,
+    /// The retention policy of the repository
+    #[serde(default, skip_serializing_if = "Retention::is_all")]
+    retention: Retention,
+    /// The format of the repository file
+    #[serde(skip)]
+    format: Format,
+}
+
 // ----------------------------------------------------------------------
 // - Entry:
 // ----------------------------------------------------------------------
 
 #[derive(Clone, Debug, Eq, serde::Deserialize, serde::Serialize)]
 struct Entry {
-    // FIXME: Add a file hash!
     #[serde(rename = "packet")]
     packet_data: gng_packet_io::BinaryPacketDefinition,
     #[serde(rename = "file")]
     file_path: std::path::PathBuf, // relative to the DB file!
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pinned: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    files: Option<Vec<std::path::PathBuf>>, // absolute paths in the installed system
 }
 
 impl Entry {
+    fn key(&self) -> IndexKey {
+        (self.packet_data.name.clone(), self.packet_data.facet_name())
+    }
+
+    fn matches(&self, key: &IndexKey, requirement: &VersionRequirement) -> bool {
+        self.packet_data.name == key.0
+            && self.packet_data.facet_name() == key.1
+            && requirement.matches(&self.packet_data.version)
+    }
+
     fn from_packet_file(
         repository_url: &url::Url,
         file_path: &std::path::Path,
@@ -89,10 +522,17 @@ impl Entry {
             "Failed to read packet data from \"{}\".",
             &abs_file_path.to_string_lossy(),
         ))?;
+        let (_, contents) = packet_reader.contents().wrap_err(eyre!(
+            "Failed to read packet contents from \"{}\".",
+            &abs_file_path.to_string_lossy(),
+        ))?;
 
         Ok(Self {
             packet_data,
             file_path: rel_file_path,
+            sha256: Some(crate::files::file_hash(&abs_file_path)?),
+            pinned: false,
+            files: Some(installed_files(&contents)),
         })
     }
 
@@ -104,6 +544,30 @@ impl Entry {
             Err(eyre!("File path read from json is not relative"))
         }
     }
+
+    fn from_record(record: Record) -> eyre::Result<Self> {
+        if record.file.is_relative() {
+            Ok(Self {
+                packet_data: record.packet,
+                file_path: record.file,
+                sha256: record.sha256,
+                pinned: record.pinned,
+                files: record.files,
+            })
+        } else {
+            Err(eyre!("File path read from binary index is not relative"))
+        }
+    }
+
+    fn to_record(&self) -> Record {
+        Record {
+            packet: self.packet_data.clone(),
+            file: self.file_path.clone(),
+            sha256: self.sha256.clone(),
+            pinned: self.pinned,
+            files: self.files.clone(),
+        }
+    }
 }
 
 impl PartialEq for Entry {
@@ -133,26 +597,110 @@ pub struct Update {
     repository_url: url::Url,
     to_apply: Vec<Entry>,
     to_remove: Names,
+    to_remove_versions: Vec<(IndexKey, VersionRequirement)>,
+    to_pin: Vec<(IndexKey, gng_core::Version, bool)>,
+    to_set_files: Vec<(IndexKey, gng_core::Version, Vec<std::path::PathBuf>)>,
+    retention: Option<Retention>,
+    format: Option<Format>,
     do_clear: bool,
 }
 
 impl Update {
-    /// Remove a packet from the Repository
+    /// Remove a packet (including all its facets) from the Repository
     #[tracing::instrument(level = "debug", skip(self))]
     pub fn remove(&mut self, name: Name) {
         self.to_remove.insert(name);
     }
 
+    /// Remove all versions of the `facet` of a packet from the Repository
+    ///
+    /// A `facet` of `None` removes the main packet only.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn remove_facet(&mut self, name: Name, facet: Option<Name>) {
+        self.remove_versions(name, facet, &VersionRequirement::any());
+    }
+
+    /// Remove the versions of the `facet` of a packet matching `requirement`
+    /// from the Repository
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn remove_versions(
+        &mut self,
+        name: Name,
+        facet: Option<Name>,
+        requirement: &VersionRequirement,
+    ) {
+        self.to_remove_versions
+            .push(((name, facet), requirement.clone()));
+    }
+
+    /// Pin a `version` of the `facet` of a packet, so that it is kept
+    /// independent of the retention policy
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn pin(&mut self, name: Name, facet: Option<Name>, version: gng_core::Version) {
+        self.to_pin.push(((name, facet), version, true));
+    }
+
+    /// Unpin a `version` of the `facet` of a packet
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn unpin(&mut self, name: Name, facet: Option<Name>, version: gng_core::Version) {
+        self.to_pin.push(((name, facet), version, false));
+    }
+
+    /// Record the `files` installed by a `version` of the `facet` of a packet
+    ///
+    /// This fills in the file lists of packets added before file lists were
+    /// recorded, see `Repository::read_installed_files`.
+    #[tracing::instrument(level = "debug", skip(self, files))]
+    pub fn set_files(
+        &mut self,
+        name: Name,
+        facet: Option<Name>,
+        version: gng_core::Version,
+        files: Vec<std::path::PathBuf>,
+    ) {
+        self.to_set_files.push(((name, facet), version, files));
+    }
+
+    /// Change the retention policy of the repository
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn set_retention(&mut self, retention: Retention) {
+        self.retention = Some(retention);
+    }
+
+    /// Change the format the repository data is saved in
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn set_format(&mut self, format: Format) {
+        self.format = Some(format);
+    }
+
     /// Clear all data from the repository
     #[tracing::instrument(level = "debug", skip(self))]
     pub fn clear(&mut self) {
         self.do_clear = true;
         self.to_apply = Vec::new();
         self.to_remove = Names::default();
+        self.to_remove_versions = Vec::new();
+        self.to_pin = Vec::new();
+        self.to_set_files = Vec::new();
+    }
+
+    /// Remove the entries of missing and superseded packets found in
+    /// `garbage` from the Repository
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn remove_garbage(&mut self, garbage: &Garbage) {
+        for (packet, _) in garbage.missing.iter().chain(garbage.superseded.iter()) {
+            self.remove_versions(
+                packet.name.clone(),
+                packet.facet_name(),
+                &VersionRequirement::exact(&packet.version),
+            );
+        }
     }
 
+    /// Add `entry`, replacing the same version of the packet
+    ///
+    /// Replaced versions are sorted out in `Repository::apply`.
     fn add_entry(&mut self, entry: Entry) {
-        self.remove(entry.packet_data.name.clone());
         self.to_apply.push(entry);
     }
 
@@ -188,26 +736,84 @@ impl Update {
             entries_file.to_string_lossy()
         );
 
-        let reader = std::fs::File::open(entries_file).wrap_err(eyre!(
-            "Failed to open repository data in \"{}\".",
-            entries_file.to_string_lossy()
-        ))?;
-        let reader = std::io::BufReader::new(reader);
-
-        for l in reader.lines() {
-            let l = l.wrap_err(eyre!(
-                "Failed to read data entry from \"{}\".",
-                entries_file.to_string_lossy()
-            ))?;
-            self.add_entry(Entry::from_json(&l).wrap_err(eyre!(
-                "Parsing data in repository file \"{}\" failed.",
-                entries_file.to_string_lossy()
-            ))?);
+        for e in read_entries(entries_file)?.1 {
+            self.add_entry(e);
         }
         Ok(())
     }
 }
 
+// ----------------------------------------------------------------------
+// - Garbage:
+// ----------------------------------------------------------------------
+
+/// The garbage found in a local repository by `Repository::find_garbage`
+///
+/// All file paths are relative to the repository.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Garbage {
+    /// Packet files in the repository directory that are not referenced
+    pub unreferenced: Vec<std::path::PathBuf>,
+    /// Packets whose packet file does not exist
+    pub missing: Vec<(gng_packet_io::BinaryPacketDefinition, std::path::PathBuf)>,
+    /// Packets beyond the retention policy of the repository
+    pub superseded: Vec<(gng_packet_io::BinaryPacketDefinition, std::path::PathBuf)>,
+}
+
+impl Garbage {
+    /// Is there no garbage at all?
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.unreferenced.is_empty() && self.missing.is_empty() && self.superseded.is_empty()
+    }
+}
+
+/// The result of `Repository::delete_garbage_files`
+///
+/// All file paths are relative to the repository.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DeletedGarbage {
+    /// Packet files that were deleted
+    pub deleted: Vec<std::path::PathBuf>,
+    /// Packet files that could not be deleted, with the reason
+    pub failed: Vec<(std::path::PathBuf, String)>,
+}
+
+// ----------------------------------------------------------------------
+// - Difference:
+// ----------------------------------------------------------------------
+
+/// The difference between two repositories as reported by `Repository::diff`
+///
+/// All file paths are relative to the repository.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Difference {
+    /// Packets only found in the other repository
+    pub added: Vec<(gng_packet_io::BinaryPacketDefinition, std::path::PathBuf)>,
+    /// Packets only found in this repository
+    pub removed: Vec<(gng_packet_io::BinaryPacketDefinition, std::path::PathBuf)>,
+}
+
+impl Difference {
+    /// Are both repositories the same?
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+// ----------------------------------------------------------------------
+// - RepositoryLock:
+// ----------------------------------------------------------------------
+
+/// An advisory lock on a local repository
+///
+/// The lock is released when this is dropped.
+#[derive(Debug)]
+pub struct RepositoryLock {
+    _file: std::fs::File,
+}
+
 // ----------------------------------------------------------------------
 // - Repository:
 // ----------------------------------------------------------------------
@@ -216,7 +822,13 @@ impl Update {
 pub struct Repository {
     base_url: url::Url,
 
-    packets: Vec<Entry>,
+    packets: once_cell::unsync::OnceCell<Packets>,
+    binary_index: Option<crate::index::Index>,
+    remote: Option<crate::remote::Remote>,
+    generation: u64,
+    retention: Retention,
+    format: Format,
+    snapshot: Option<String>,
 }
 
 impl Repository {
@@ -226,12 +838,20 @@ impl Repository {
     pub fn new(base_url: &url::Url) -> Self {
         Self {
             base_url: base_url.clone(),
-            packets: Vec::new(),
+            packets: once_cell::unsync::OnceCell::with_value(Packets::default()),
+            binary_index: None,
+            remote: None,
+            generation: 0,
+            retention: Retention::All,
+            format: Format::JsonLines,
+            snapshot: None,
         }
     }
 
     /// Open a local directory containing a repository file.
     ///
+    /// The repository data is verified according to `trust`.
+    ///
     /// # Errors
     ///
     /// Fail is something goes wrong.
@@ -239,134 +859,2614 @@ impl Repository {
     pub fn from_local_directory(
         repository_directory: &std::path::Path,
         create_if_missing: bool,
+        trust: &Trust,
+    ) -> eyre::Result<Self> {
+        Self::open_local(repository_directory, create_if_missing, trust, None)
+    }
+
+    /// Open the snapshot `name` of the repository in a local directory
+    ///
+    /// The name `current` selects the current snapshot. The snapshot data is
+    /// verified according to `trust`. Snapshots can not be saved.
+    ///
+    /// # Errors
+    ///
+    /// Fail if the snapshot does not exist or fails verification.
+    #[tracing::instrument(level = "debug")]
+    pub fn from_local_snapshot(
+        repository_directory: &std::path::Path,
+        name: &str,
+        trust: &Trust,
+    ) -> eyre::Result<Self> {
+        Self::open_local(repository_directory, false, trust, Some(name))
+    }
+
+    fn open_local(
+        repository_directory: &std::path::Path,
+        create_if_missing: bool,
+        trust: &Trust,
+        snapshot: Option<&str>,
     ) -> eyre::Result<Self> {
+        let repository_directory = std::env::current_dir()
+            .wrap_err("Failed to get the current directory.")?
+            .join(repository_directory);
         let entries_file = repository_directory.join("repository.json");
 
-        let mut repo = Self {
-            base_url: url::Url::from_file_path(&entries_file).map_err(|_| {
-                eyre!(
-                    "Failed fo convert entries file \"{}\" to URL",
+        let mut repo = Self::new(&url::Url::from_file_path(&entries_file).map_err(|()| {
+            eyre!(
+                "Failed fo convert entries file \"{}\" to URL",
+                entries_file.to_string_lossy()
+            )
+        })?);
+
+        let (entries_file, signature_file, pending_file, url) = if let Some(name) = snapshot {
+            let snapshot_directory = repository_directory.join(SNAPSHOT_DIRECTORY);
+            let name = if name == CURRENT_SNAPSHOT {
+                let current_file = snapshot_directory.join(CURRENT_SNAPSHOT);
+                if !current_file.exists() {
+                    return Err(eyre!(
+                        "Repository \"{}\" has no current snapshot.",
+                        repository_directory.to_string_lossy()
+                    ));
+                }
+                let current = std::fs::read(&current_file).wrap_err(eyre!(
+                    "Failed to read \"{}\".",
+                    current_file.to_string_lossy()
+                ))?;
+                check_local_signature(
+                    trust,
+                    &snapshot_url(&repo.base_url, CURRENT_SNAPSHOT),
+                    &current,
+                    &snapshot_directory.join(CURRENT_SNAPSHOT_SIGNATURE),
+                    Some(&snapshot_directory.join(PENDING_CURRENT_SNAPSHOT_SIGNATURE)),
+                )?;
+                parse_snapshot_pointer(current)?
+            } else {
+                name.to_string()
+            };
+            check_snapshot_name(&name)?;
+
+            let entries_file = snapshot_directory.join(format!("{}.json", name));
+            if !entries_file.is_file() {
+                return Err(eyre!(
+                    "Snapshot \"{}\" not found in repository \"{}\".",
+                    name,
+                    repository_directory.to_string_lossy()
+                ));
+            }
+            let signature_file = snapshot_directory.join(format!("{}.sig", name));
+            let url = snapshot_url(&repo.base_url, &name);
+            repo.snapshot = Some(name);
+            (entries_file, signature_file, None, url)
+        } else {
+            // Make sure the file exists:
+            if create_if_missing && !entries_file.exists() {
+                tracing::debug!(
+                    "Creating empty repository data file \"{}\"",
                     entries_file.to_string_lossy()
-                )
-            })?,
-            packets: Vec::new(),
+                );
+                repo.save(&entries_file)?;
+            }
+            let url = repo.base_url.clone();
+            (
+                entries_file,
+                repository_directory.join(SIGNATURE_FILE),
+                Some(repository_directory.join(PENDING_SIGNATURE_FILE)),
+                url,
+            )
         };
 
-        // Make sire the file exists:
-        if create_if_missing && !entries_file.exists() {
-            tracing::debug!(
-                "Creating empty repository data file \"{}\"",
-                entries_file.to_string_lossy()
-            );
-            repo.save(&entries_file)?;
-        }
+        let data = read_raw_data(&entries_file)?;
+        check_local_signature(trust, &url, &data, &signature_file, pending_file.as_deref())?;
 
-        let mut trans = repo.create_transaction();
-        trans.read_entries_file(&entries_file)?;
-        repo.apply(trans).map(|_| repo)
+        repo.load(&entries_file, data)?;
+        Ok(repo)
     }
 
-    /// Create a new transaction.
-    #[must_use]
-    #[tracing::instrument(level = "debug", skip(self))]
-    pub fn create_transaction(&mut self) -> Update {
-        Update {
-            repository_url: self.base_url.clone(),
-            to_apply: Vec::new(),
-            to_remove: Names::default(),
-            do_clear: false,
+    /// Load `data` read from `entries_file` into this empty repository
+    ///
+    /// Binary indices are kept as they are: Queries decode the packets they
+    /// need, everything else decodes all packets on first use.
+    fn load(&mut self, entries_file: &std::path::Path, data: Vec<u8>) -> eyre::Result<()> {
+        let (header, data) = parse_data(entries_file, data)?;
+        match data {
+            Data::Entries(entries) => {
+                let mut trans = self.create_transaction();
+                for e in entries {
+                    trans.add_entry(e);
+                }
+                self.apply(trans)?;
+            }
+            Data::Index(index) => {
+                self.packets = once_cell::unsync::OnceCell::new();
+                self.binary_index = Some(index);
+            }
         }
+        self.generation = header.generation;
+        self.retention = header.retention;
+        self.format = header.format;
+        Ok(())
     }
 
-    /// Apply a transaction to this repository.
-    ///
-    /// # Errors
-    /// Errors out when the update does not match with this repository.
-    #[tracing::instrument(level = "debug", skip(self, update))]
-    pub fn apply(&mut self, update: Update) -> eyre::Result<()> {
-        if update.repository_url != self.base_url {
-            return Err(eyre!("Updated has wrong repository URL!"));
-        }
+    /// All packets, decoding them from the binary index first if necessary
+    fn packets(&self) -> eyre::Result<&Packets> {
+        self.packets.get_or_try_init(|| {
+            self.binary_index
+                .as_ref()
+                .map_or_else(|| Ok(Vec::new()), decode_index)
+                .map(Packets::new)
+                .wrap_err(eyre!(
+                    "Failed to decode the packets of repository \"{}\".",
+                    self.base_url.as_str()
+                ))
+        })
+    }
 
-        let mut new_packets = if update.do_clear {
-            Vec::new()
+    /// The binary index to serve queries from while the packets are not
+    /// decoded
+    fn indexed(&self) -> Option<&crate::index::Index> {
+        if self.packets.get().is_some() {
+            None
         } else {
-            self.packets
-                .iter()
-                .filter(|f| !update.to_remove.contains(&f.packet_data.name))
-                .cloned()
-                .collect::<Vec<_>>()
-        };
-
-        new_packets.extend_from_slice(&update.to_apply[..]);
-
-        new_packets.sort();
-
-        self.packets = new_packets;
-
-        Ok(())
+            self.binary_index.as_ref()
+        }
     }
 
-    /// Query a packet/facet name combination
-    #[tracing::instrument(level = "trace", skip(self))]
-    #[must_use]
-    pub fn query(
+    /// All versions of a packet/facet name combination, oldest first
+    ///
+    /// Versions in a binary index are decoded on demand.
+    fn versions(
         &self,
         packet: &Name,
-        facet: &Option<Name>,
-    ) -> Option<(gng_packet_io::BinaryPacketDefinition, std::path::PathBuf)> {
-        None
+        facet: Option<&Name>,
+    ) -> eyre::Result<Vec<std::borrow::Cow<'_, Entry>>> {
+        let facet = facet.cloned();
+        if let Some(index) = self.indexed() {
+            return index
+                .lookup(packet, &facet)
+                .and_then(|records| {
+                    records
+                        .into_iter()
+                        .map(|r| Entry::from_record(r).map(std::borrow::Cow::Owned))
+                        .collect()
+                })
+                .wrap_err(eyre!(
+                    "Failed to read \"{}\" from repository \"{}\".",
+                    packet.combine(&facet),
+                    self.base_url.as_str()
+                ));
+        }
+
+        let packets = self.packets()?;
+        Ok(packets
+            .index
+            .get(&(packet.clone(), facet))
+            .into_iter()
+            .flatten()
+            .map(|i| std::borrow::Cow::Borrowed(&packets.entries[*i]))
+            .collect())
     }
 
-    /// Save the entries to a file.
+    /// Lock the local repository in `repository_directory`
+    ///
+    /// Hold the lock while reading, modifying and saving the repository to
+    /// keep others from changing it in the meantime. This waits for the lock
+    /// to become available.
     ///
     /// # Errors
-    /// Errors out if the file can not get written.
-    #[tracing::instrument(level = "debug", skip(self))]
-    pub fn save(&self, entries_file: &std::path::Path) -> eyre::Result<()> {
-        tracing::debug!(
-            "Saving repository data into \"{}\"",
-            entries_file.to_string_lossy()
-        );
+    ///
+    /// Fail if the lock file can not be created or locked.
+    #[tracing::instrument(level = "debug")]
+    pub fn lock_local_directory(
+        repository_directory: &std::path::Path,
+    ) -> eyre::Result<RepositoryLock> {
+        use fs2::FileExt;
 
-        let writer = std::fs::OpenOptions::new()
+        let lock_file = repository_directory.join(LOCK_FILE);
+        let file = std::fs::OpenOptions::new()
             .write(true)
             .create(true)
-            .open(entries_file)
+            .truncate(false)
+            .open(&lock_file)
             .wrap_err(eyre!(
-                "Failed to open repository file \"{}\" for writing",
-                entries_file.to_string_lossy()
+                "Failed to open lock file \"{}\".",
+                lock_file.to_string_lossy()
             ))?;
-        let mut writer = std::io::BufWriter::new(writer);
 
-        for e in &self.packets {
-            let mut data = serde_json::to_vec(&e).wrap_err(eyre!(
-                "Failed to serialize repository data for file \"{}\"",
-                entries_file.to_string_lossy(),
-            ))?;
-            data.push(b'\n');
+        if file.try_lock_exclusive().is_err() {
+            tracing::info!(
+                "Waiting for lock on \"{}\".",
+                repository_directory.to_string_lossy()
+            );
+            file.lock_exclusive()
+                .wrap_err(eyre!("Failed to lock \"{}\".", lock_file.to_string_lossy()))?;
+        }
+        Ok(RepositoryLock { _file: file })
+    }
 
-            writer.write_all(&data).wrap_err(eyre!(
-                "Failed to write data into \"{}\"",
-                entries_file.to_string_lossy()
-            ))?;
+    /// Open a repository served over HTTP(S)
+    ///
+    /// `url` is the URL of the repository directory (or of its `repository.json`).
+    /// A snapshot of the repository is selected with a `#snapshot=NAME`
+    /// fragment. Downloaded data is kept below `cache_directory`. The repository
+    /// data is revalidated with the server unless `offline` is set; the cached
+    /// copy is used when the server can not be reached. The repository data is
+    /// verified according to `trust`.
+    ///
+    /// # Errors
+    ///
+    /// Fail if the repository data is neither available from the server nor
+    /// from the cache or if it fails verification.
+    #[tracing::instrument(level = "debug")]
+    pub fn from_remote(
+        url: &url::Url,
+        cache_directory: &std::path::Path,
+        offline: bool,
+        trust: &Trust,
+    ) -> eyre::Result<Self> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(eyre!(
+                "Repository URL \"{}\" is not a HTTP(S) URL.",
+                url.as_str()
+            ));
+        }
+
+        let snapshot = snapshot_from_url(url)?;
+
+        let is_file_url = std::path::Path::new(url.path())
+            .extension()
+            .map_or(false, |e| e.eq_ignore_ascii_case("json"));
+        let base_url = if is_file_url {
+            let mut base_url = url.clone();
+            base_url.set_fragment(None);
+            base_url
+        } else {
+            let mut directory = url.clone();
+            if !directory.path().ends_with('/') {
+                directory.set_path(&format!("{}/", url.path()));
+            }
+            directory
+                .join("repository.json")
+                .wrap_err("Failed to build repository file URL.")?
+        };
+
+        let remote = crate::remote::Remote::new(&base_url, cache_directory, offline);
+        let fetch = |file_name: &str| {
+            let file_url = base_url
+                .join(file_name)
+                .wrap_err(eyre!("Failed to build URL for \"{}\".", file_name))?;
+            remote.fetch_metadata(&file_url, file_name)
+        };
+
+        let snapshot = if let Some(name) = snapshot {
+            let name = if name == CURRENT_SNAPSHOT {
+                let current = std::fs::read(fetch(&format!(
+                    "{}/{}",
+                    SNAPSHOT_DIRECTORY, CURRENT_SNAPSHOT
+                ))?)
+                .wrap_err("Failed to read the current snapshot name.")?;
+                if trust.is_enabled() {
+                    let trust_url = snapshot_url(&base_url, CURRENT_SNAPSHOT);
+                    let signature_file = fetch(&format!(
+                        "{}/{}",
+                        SNAPSHOT_DIRECTORY, CURRENT_SNAPSHOT_SIGNATURE
+                    ))?;
+                    let result = trust.check(&trust_url, &current, &signature_file);
+                    if result.is_err() {
+                        // The server might be in the middle of changing the
+                        // current snapshot, see `set_current_snapshot`:
+                        fetch(&format!(
+                            "{}/{}",
+                            SNAPSHOT_DIRECTORY, PENDING_CURRENT_SNAPSHOT_SIGNATURE
+                        ))
+                        .and_then(|pending_file| trust.check(&trust_url, &current, &pending_file))
+                        .or(result)?;
+                    }
+                }
+                parse_snapshot_pointer(current)?
+            } else {
+                name
+            };
+            check_snapshot_name(&name)?;
+            Some(name)
+        } else {
+            None
+        };
+
+        let (entries_name, signature_name, trust_url) = snapshot.as_ref().map_or_else(
+            || {
+                (
+                    "repository.json".to_string(),
+                    SIGNATURE_FILE.to_string(),
+                    base_url.clone(),
+                )
+            },
+            |name| {
+                (
+                    format!("{}/{}.json", SNAPSHOT_DIRECTORY, name),
+                    format!("{}/{}.sig", SNAPSHOT_DIRECTORY, name),
+                    snapshot_url(&base_url, name),
+                )
+            },
+        );
+
+        let entries_file = fetch(&entries_name)?;
+        let data = read_raw_data(&entries_file)?;
+        if trust.is_enabled() {
+            let signature_file = fetch(&signature_name)?;
+            let result = trust.check(&trust_url, &data, &signature_file);
+            if result.is_err() && snapshot.is_none() {
+                // The server might be in the middle of a signed save, see
+                // `check_local_signature`:
+                fetch(PENDING_SIGNATURE_FILE)
+                    .and_then(|pending_file| trust.check(&trust_url, &data, &pending_file))
+                    .or(result)?;
+            } else {
+                result?;
+            }
+        }
+
+        let mut repo = Self::new(&base_url);
+        repo.load(&entries_file, data)?;
+        repo.remote = Some(remote);
+        repo.snapshot = snapshot;
+        Ok(repo)
+    }
+
+    /// Open the repository at `url`
+    ///
+    /// `file://` URLs must point to a local repository directory, `http(s)://`
+    /// URLs are handled by `from_remote`. A snapshot of the repository is
+    /// selected with a `#snapshot=NAME` fragment.
+    ///
+    /// # Errors
+    ///
+    /// Fail if the repository can not be opened.
+    pub fn from_url(
+        url: &url::Url,
+        cache_directory: &std::path::Path,
+        offline: bool,
+        trust: &Trust,
+    ) -> eyre::Result<Self> {
+        if url.scheme() == "file" {
+            let directory = url
+                .to_file_path()
+                .map_err(|()| eyre!("Failed to turn URL \"{}\" into a file path.", url.as_str()))?;
+            snapshot_from_url(url)?.map_or_else(
+                || Self::from_local_directory(&directory, false, trust),
+                |name| Self::from_local_snapshot(&directory, &name, trust),
+            )
+        } else {
+            Self::from_remote(url, cache_directory, offline, trust)
+        }
+    }
+
+    /// Create a repository containing `packets`
+    #[cfg(test)]
+    pub(crate) fn from_definitions(
+        base_url: &url::Url,
+        packets: Vec<gng_packet_io::BinaryPacketDefinition>,
+    ) -> Self {
+        let mut repository = Self::new(base_url);
+        let mut update = repository.create_transaction();
+        update.to_apply = packets
+            .into_iter()
+            .map(|packet_data| Entry {
+                file_path: std::path::PathBuf::from(format!(
+                    "{}-{}.gng",
+                    packet_data.name.combine(&packet_data.facet_name()),
+                    packet_data.version
+                )),
+                packet_data,
+                sha256: None,
+                pinned: false,
+                files: None,
+            })
+            .collect();
+        repository.apply(update).expect("URL matches");
+        repository
+    }
+
+    /// The URL of the repository
+    #[must_use]
+    pub const fn url(&self) -> &url::Url {
+        &self.base_url
+    }
+
+    /// The name of the snapshot this repository was opened from
+    #[must_use]
+    pub fn snapshot(&self) -> Option<&str> {
+        self.snapshot.as_deref()
+    }
+
+    /// Create a new transaction.
+    #[must_use]
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn create_transaction(&mut self) -> Update {
+        Update {
+            repository_url: self.base_url.clone(),
+            to_apply: Vec::new(),
+            to_remove: Names::default(),
+            to_remove_versions: Vec::new(),
+            to_pin: Vec::new(),
+            to_set_files: Vec::new(),
+            retention: None,
+            format: None,
+            do_clear: false,
+        }
+    }
+
+    /// Apply a transaction to this repository.
+    ///
+    /// # Errors
+    /// Errors out when the update does not match with this repository.
+    #[tracing::instrument(level = "debug", skip(self, update))]
+    pub fn apply(&mut self, update: Update) -> eyre::Result<()> {
+        if update.repository_url != self.base_url {
+            return Err(eyre!("Updated has wrong repository URL!"));
+        }
+
+        // Later entries replace earlier entries with the same version:
+        let to_apply = update
+            .to_apply
+            .into_iter()
+            .map(|e| ((e.key(), e.packet_data.version.clone()), e))
+            .collect::<std::collections::BTreeMap<_, _>>();
+
+        let (mut new_packets, pinned) = if update.do_clear {
+            (Vec::new(), std::collections::BTreeSet::new())
+        } else {
+            let packets = &self.packets()?.entries;
+            let pinned = packets
+                .iter()
+                .filter(|e| e.pinned)
+                .map(|e| (e.key(), e.packet_data.version.clone()))
+                .collect::<std::collections::BTreeSet<_>>();
+            let packets = packets
+                .iter()
+                .filter(|e| {
+                    !update.to_remove.contains(&e.packet_data.name)
+                        && !to_apply.contains_key(&(e.key(), e.packet_data.version.clone()))
+                        && !update
+                            .to_remove_versions
+                            .iter()
+                            .any(|(key, requirement)| e.matches(key, requirement))
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            (packets, pinned)
+        };
+
+        new_packets.extend(to_apply.into_values());
+
+        // Keep pins of replaced entries, then apply pin changes:
+        for e in &mut new_packets {
+            e.pinned = e.pinned || pinned.contains(&(e.key(), e.packet_data.version.clone()));
         }
+        for (key, version, pin) in &update.to_pin {
+            for e in new_packets
+                .iter_mut()
+                .filter(|e| e.matches(key, &VersionRequirement::exact(version)))
+            {
+                e.pinned = *pin;
+            }
+        }
+        for (key, version, files) in update.to_set_files {
+            for e in new_packets
+                .iter_mut()
+                .filter(|e| e.matches(&key, &VersionRequirement::exact(&version)))
+            {
+                e.files = Some(files.clone());
+            }
+        }
+
+        new_packets.sort();
+
+        if let Some(retention) = update.retention {
+            self.retention = retention;
+        }
+        if let Some(format) = update.format {
+            self.format = format;
+        }
+
+        self.packets = once_cell::unsync::OnceCell::with_value(Packets::new(new_packets));
+        self.binary_index = None;
+
         Ok(())
     }
 
-    /// Save the entries to a file in a default location.
+    /// All packets in the repository with their file path relative to the
+    /// repository
     ///
     /// # Errors
-    /// Errors out if the file can not get written.
+    ///
+    /// Fail if the packets of a binary index can not be decoded.
+    pub fn entries(
+        &self,
+    ) -> eyre::Result<
+        impl Iterator<Item = (&gng_packet_io::BinaryPacketDefinition, &std::path::Path)>,
+    > {
+        Ok(self
+            .packets()?
+            .entries
+            .iter()
+            .map(|e| (&e.packet_data, e.file_path.as_path())))
+    }
+
+    /// The newest version of each packet/facet name combination with its
+    /// file path relative to the repository
+    ///
+    /// # Errors
+    ///
+    /// Fail if the packets of a binary index can not be decoded.
+    pub fn newest(
+        &self,
+    ) -> eyre::Result<
+        impl Iterator<Item = (&gng_packet_io::BinaryPacketDefinition, &std::path::Path)>,
+    > {
+        let packets = self.packets()?;
+        Ok(packets
+            .index
+            .values()
+            .filter_map(|indices| indices.last())
+            .map(|i| &packets.entries[*i])
+            .map(|e| (&e.packet_data, e.file_path.as_path())))
+    }
+
+    /// The files installed by a version of a packet/facet name combination
+    ///
+    /// This is `None` for unknown packets and for packets added before file
+    /// lists were recorded.
+    ///
+    /// # Errors
+    ///
+    /// Fail if the packets of a binary index can not be decoded.
+    pub fn files(
+        &self,
+        packet: &Name,
+        facet: &Option<Name>,
+        version: &gng_core::Version,
+    ) -> eyre::Result<Option<&[std::path::PathBuf]>> {
+        let packets = self.packets()?;
+        Ok(packets
+            .index
+            .get(&(packet.clone(), facet.clone()))
+            .into_iter()
+            .flatten()
+            .map(|i| &packets.entries[*i])
+            .find(|e| e.packet_data.version == *version)
+            .and_then(|e| e.files.as_deref()))
+    }
+
+    /// All packets without a file list with their file path relative to the
+    /// repository
+    ///
+    /// These packets were added before file lists were recorded. They are
+    /// invisible to `find_file` and to file conflict checks until their file
+    /// lists are filled in with `Update::set_files`.
+    ///
+    /// # Errors
+    ///
+    /// Fail if the packets of a binary index can not be decoded.
+    pub fn without_files(
+        &self,
+    ) -> eyre::Result<
+        impl Iterator<Item = (&gng_packet_io::BinaryPacketDefinition, &std::path::Path)>,
+    > {
+        Ok(self
+            .packets()?
+            .entries
+            .iter()
+            .filter(|e| e.files.is_none())
+            .map(|e| (&e.packet_data, e.file_path.as_path())))
+    }
+
+    /// Read the files installed by the packet `file` from the packet file
+    ///
+    /// # Errors
+    ///
+    /// Fail if the packet file can not be fetched or read.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn read_installed_files(
+        &self,
+        file: &std::path::Path,
+    ) -> eyre::Result<Vec<std::path::PathBuf>> {
+        let path = self.packet_file(file)?;
+        let (_, contents) = gng_packet_io::PacketReader::new(&path)
+            .contents()
+            .wrap_err(eyre!(
+                "Failed to read packet contents from \"{}\".",
+                path.to_string_lossy(),
+            ))?;
+        Ok(installed_files(&contents))
+    }
+
+    /// Find the packets providing files matching `pattern`
+    ///
+    /// A `pattern` containing a `/` is matched against the full path of the
+    /// installed files (e.g. `/usr/bin/foo` or `/usr/lib/*.so`), all other
+    /// patterns are matched against the file name only (e.g. `libssl.so.3`).
+    /// `*` never matches a `/`.
+    ///
+    /// Returns the packet definitions, the packet file paths relative to the
+    /// repository and the matching files.
+    ///
+    /// # Errors
+    ///
+    /// Fail if `pattern` is not a valid glob pattern.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn find_file(
+        &self,
+        pattern: &str,
+    ) -> eyre::Result<
+        Vec<(
+            gng_packet_io::BinaryPacketDefinition,
+            std::path::PathBuf,
+            std::path::PathBuf,
+        )>,
+    > {
+        let glob = glob::Pattern::new(pattern)
+            .map_err(|e| eyre!("Invalid file pattern \"{}\": {}", pattern, e))?;
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..glob::MatchOptions::new()
+        };
+        let full_path = pattern.contains('/');
+        let matches = |path: &std::path::Path| {
+            if full_path {
+                glob.matches_path_with(path, options)
+            } else {
+                path.file_name()
+                    .map_or(false, |n| glob.matches_with(&n.to_string_lossy(), options))
+            }
+        };
+
+        if let Some(index) = self.indexed() {
+            let mut found = std::collections::BTreeMap::<usize, Vec<std::path::PathBuf>>::new();
+            for (file, i) in index.installed_files().filter(|(f, _)| matches(f)) {
+                found.entry(i).or_default().push(file.to_path_buf());
+            }
+            let mut result = Vec::new();
+            for (i, files) in found {
+                let e = Entry::from_record(index.record(i)?)?;
+                result.extend(
+                    files
+                        .into_iter()
+                        .map(|f| (e.packet_data.clone(), e.file_path.clone(), f)),
+                );
+            }
+            return Ok(result);
+        }
+
+        Ok(self
+            .packets()?
+            .entries
+            .iter()
+            .flat_map(|e| {
+                e.files
+                    .iter()
+                    .flatten()
+                    .filter(|f| matches(f))
+                    .map(move |f| (e.packet_data.clone(), e.file_path.clone(), f.clone()))
+            })
+            .collect())
+    }
+
+    /// The retention policy of the repository
+    #[must_use]
+    pub const fn retention(&self) -> Retention {
+        self.retention
+    }
+
+    /// Is this version of a packet/facet name combination pinned?
+    ///
+    /// # Errors
+    ///
+    /// Fail if the packet can not be decoded.
+    pub fn is_pinned(
+        &self,
+        packet: &Name,
+        facet: &Option<Name>,
+        version: &gng_core::Version,
+    ) -> eyre::Result<bool> {
+        Ok(self
+            .versions(packet, facet.as_ref())?
+            .iter()
+            .any(|e| e.pinned && e.packet_data.version == *version))
+    }
+
+    /// Entries that are not needed to fulfill the retention policy
+    ///
+    /// These are all but the newest versions of a packet/facet name
+    /// combination, excluding pinned versions.
+    ///
+    /// # Errors
+    ///
+    /// Fail if the packets of a binary index can not be decoded.
+    pub fn superseded(
+        &self,
+    ) -> eyre::Result<Vec<(gng_packet_io::BinaryPacketDefinition, std::path::PathBuf)>> {
+        self.superseded_entries(|_| true)
+    }
+
+    /// The entries beyond the retention policy, only counting those that are
+    /// `available`
+    fn superseded_entries(
+        &self,
+        available: impl Fn(&Entry) -> bool,
+    ) -> eyre::Result<Vec<(gng_packet_io::BinaryPacketDefinition, std::path::PathBuf)>> {
+        let keep = match self.retention {
+            Retention::All => return Ok(Vec::new()),
+            Retention::Newest(count) => count,
+        };
+
+        let packets = self.packets()?;
+        Ok(packets
+            .index
+            .values()
+            .flat_map(|indices| {
+                indices
+                    .iter()
+                    .rev()
+                    .map(|i| &packets.entries[*i])
+                    .filter(|e| available(e))
+                    .skip(keep)
+            })
+            .filter(|e| !e.pinned)
+            .map(|e| (e.packet_data.clone(), e.file_path.clone()))
+            .collect())
+    }
+
+    fn local_directory(&self) -> eyre::Result<std::path::PathBuf> {
+        if self.remote.is_some() {
+            return Err(eyre!(
+                "Repository \"{}\" is not a local repository.",
+                self.base_url.as_str()
+            ));
+        }
+        self.base_url
+            .to_file_path()
+            .ok()
+            .and_then(|f| f.parent().map(std::path::Path::to_path_buf))
+            .ok_or_else(|| {
+                eyre!(
+                    "Failed to turn base URL \"{}\" into a directory.",
+                    self.base_url.as_str()
+                )
+            })
+    }
+
+    /// Find unreferenced packet files, missing packet files and superseded
+    /// packets in a local repository
+    ///
+    /// # Errors
+    ///
+    /// Fail if this is not a local repository or its directory can not be
+    /// read.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn find_garbage(&self) -> eyre::Result<Garbage> {
+        let directory = self.local_directory()?;
+
+        let packets = &self.packets()?.entries;
+        let snapshot_files = self.snapshot_files()?;
+        let mut unreferenced = Vec::new();
+        find_packet_files(&directory, &directory, &mut unreferenced)?;
+        unreferenced
+            .retain(|f| !packets.iter().any(|e| e.file_path == *f) && !snapshot_files.contains(f));
+        unreferenced.sort();
+
+        let is_available = |e: &Entry| directory.join(&e.file_path).is_file();
+
+        let missing = packets
+            .iter()
+            .filter(|e| !is_available(e))
+            .map(|e| (e.packet_data.clone(), e.file_path.clone()))
+            .collect::<Vec<_>>();
+
+        // Missing packets do not count towards the retention policy:
+        let superseded = self.superseded_entries(is_available)?;
+
+        Ok(Garbage {
+            unreferenced,
+            missing,
+            superseded,
+        })
+    }
+
+    /// Delete the unreferenced and superseded packet files in `garbage`
+    ///
+    /// Files that are (still) referenced by the repository or by any of its
+    /// snapshots are kept, so call this after the `Update::remove_garbage`
+    /// changes were applied and saved.
+    ///
+    /// Returns the deleted files and the files that could not be deleted.
+    /// A file that can not be deleted does not stop the deletion of the
+    /// others.
+    ///
+    /// # Errors
+    ///
+    /// Fail if this is not a local repository or its data can not be read.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn delete_garbage_files(&self, garbage: &Garbage) -> eyre::Result<DeletedGarbage> {
+        let directory = self.local_directory()?;
+        let packets = &self.packets()?.entries;
+        let snapshot_files = self.snapshot_files()?;
+
+        let mut result = DeletedGarbage::default();
+        for file in garbage
+            .unreferenced
+            .iter()
+            .chain(garbage.superseded.iter().map(|(_, f)| f))
+        {
+            let path = directory.join(file);
+            if packets.iter().any(|e| e.file_path == *file)
+                || snapshot_files.contains(file)
+                || !path.is_file()
+            {
+                continue;
+            }
+            match std::fs::remove_file(&path) {
+                Ok(()) => result.deleted.push(file.clone()),
+                Err(e) => result.failed.push((file.clone(), e.to_string())),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Check that all packet files are available and match their hashes
+    ///
+    /// Returns the packet files that failed the check, with a description of
+    /// the problem.
+    #[tracing::instrument(level = "debug", skip(self))]
+    #[must_use]
+    pub fn verify_files(&self) -> Vec<(std::path::PathBuf, String)> {
+        let packets = match self.packets() {
+            Ok(packets) => packets,
+            Err(e) => {
+                let file = std::path::Path::new(self.base_url.path())
+                    .file_name()
+                    .map(std::path::PathBuf::from)
+                    .unwrap_or_default();
+                return vec![(file, format!("{:#}", e))];
+            }
+        };
+        packets
+            .entries
+            .iter()
+            .filter_map(|e| {
+                // `packet_file` verifies the hash if one is known:
+                let result = self.packet_file(&e.file_path).and_then(|path| {
+                    e.sha256
+                        .as_ref()
+                        .map_or_else(|| crate::files::file_hash(&path).map(|_| ()), |_| Ok(()))
+                });
+                result
+                    .err()
+                    .map(|err| (e.file_path.clone(), format!("{:#}", err)))
+            })
+            .collect()
+    }
+
+    /// Query the newest version of a packet/facet name combination
+    ///
+    /// Returns the packet definition and the packet file path relative to
+    /// the repository.
+    ///
+    /// # Errors
+    ///
+    /// Fail if a packet of a binary index can not be decoded.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn query(
+        &self,
+        packet: &Name,
+        facet: &Option<Name>,
+    ) -> eyre::Result<Option<(gng_packet_io::BinaryPacketDefinition, std::path::PathBuf)>> {
+        self.query_version(packet, facet, &VersionRequirement::any())
+    }
+
+    /// Query all versions of a packet/facet name combination that fulfill
+    /// `requirement`, newest first
+    ///
+    /// Returns the packet definitions and the packet file paths relative to
+    /// the repository.
+    ///
+    /// # Errors
+    ///
+    /// Fail if a packet of a binary index can not be decoded.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn query_versions(
+        &self,
+        packet: &Name,
+        facet: &Option<Name>,
+        requirement: &VersionRequirement,
+    ) -> eyre::Result<Vec<(gng_packet_io::BinaryPacketDefinition, std::path::PathBuf)>> {
+        Ok(self
+            .versions(packet, facet.as_ref())?
+            .iter()
+            .rev()
+            .filter(|e| requirement.matches(&e.packet_data.version))
+            .map(|e| (e.packet_data.clone(), e.file_path.clone()))
+            .collect())
+    }
+
+    /// Query the newest version of a packet/facet name combination that
+    /// fulfills `requirement`
+    ///
+    /// Returns the packet definition and the packet file path relative to
+    /// the repository.
+    ///
+    /// # Errors
+    ///
+    /// Fail if a packet of a binary index can not be decoded.
     #[tracing::instrument(level = "trace", skip(self))]
-    pub fn save_local_directory(&self) -> eyre::Result<()> {
-        let entries_file = self.base_url.to_file_path().map_err(|_| {
+    pub fn query_version(
+        &self,
+        packet: &Name,
+        facet: &Option<Name>,
+        requirement: &VersionRequirement,
+    ) -> eyre::Result<Option<(gng_packet_io::BinaryPacketDefinition, std::path::PathBuf)>> {
+        Ok(self
+            .versions(packet, facet.as_ref())?
+            .iter()
+            .rev()
+            .find(|e| requirement.matches(&e.packet_data.version))
+            .map(|e| (e.packet_data.clone(), e.file_path.clone())))
+    }
+
+    /// Get a local path to the packet `file` as returned by `query`
+    ///
+    /// Packets of remote repositories are downloaded into the cache (if they
+    /// are not there yet). All packet files are verified against the hash
+    /// recorded in the repository data, if there is one.
+    ///
+    /// # Errors
+    ///
+    /// Fail if the file is not part of the repository or can not be fetched.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn packet_file(&self, file: &std::path::Path) -> eyre::Result<std::path::PathBuf> {
+        let entry = match self.indexed() {
+            Some(index) => index
+                .find_file(file)?
+                .map(Entry::from_record)
+                .transpose()?
+                .map(std::borrow::Cow::Owned),
+            None => self
+                .packets()?
+                .entries
+                .iter()
+                .find(|e| e.file_path == file)
+                .map(std::borrow::Cow::Borrowed),
+        }
+        .ok_or_else(|| {
             eyre!(
-                "Failed to turn base URL \"{}\" into a file path.",
+                "\"{}\" is not part of repository \"{}\".",
+                file.to_string_lossy(),
                 self.base_url.as_str()
             )
         })?;
 
-        self.save(&entries_file)
+        self.remote.as_ref().map_or_else(
+            || {
+                let (_, path) = relative_file_path(&self.base_url, file)?;
+                if let Some(expected) = &entry.sha256 {
+                    let actual = crate::files::file_hash(&path)?;
+                    if actual != *expected {
+                        return Err(eyre!(
+                            "Hash mismatch for \"{}\": Expected {}, got {}.",
+                            path.to_string_lossy(),
+                            expected,
+                            actual
+                        ));
+                    }
+                }
+                Ok(path)
+            },
+            |remote| {
+                let hash = entry.sha256.as_ref().ok_or_else(|| {
+                    eyre!(
+                        "Packet \"{}\" has no hash, so it can not be downloaded safely.",
+                        file.to_string_lossy()
+                    )
+                })?;
+                remote.fetch_packet(&self.base_url, file, hash)
+            },
+        )
+    }
+
+    /// The format the repository data is saved in
+    #[must_use]
+    pub const fn format(&self) -> Format {
+        self.format
+    }
+
+    const fn header(&self) -> Header {
+        Header {
+            generation: self.generation,
+            retention: self.retention,
+            format: self.format,
+        }
+    }
+
+    /// The repository data as JSON lines, independent of the format it is
+    /// saved in
+    ///
+    /// # Errors
+    /// Errors out if the data can not be serialized.
+    pub fn to_json_lines(&self) -> eyre::Result<Vec<u8>> {
+        let mut data = serde_json::to_vec(&self.header())?;
+        data.push(b'\n');
+        for e in &self.packets()?.entries {
+            data.extend(serde_json::to_vec(&e).wrap_err("Failed to serialize repository data")?);
+            data.push(b'\n');
+        }
+        Ok(data)
+    }
+
+    /// Save the entries to a file.
+    ///
+    /// # Errors
+    /// Errors out if the file can not get written.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn save(&self, entries_file: &std::path::Path) -> eyre::Result<()> {
+        let data = self.serialize(entries_file)?;
+        crate::files::write_atomically(entries_file, &data)
+    }
+
+    /// Serialize the entries for saving them into `entries_file`
+    fn serialize(&self, entries_file: &std::path::Path) -> eyre::Result<Vec<u8>> {
+        tracing::debug!(
+            "Saving repository data into \"{}\" as {}",
+            entries_file.to_string_lossy(),
+            self.format
+        );
+
+        match self.format {
+            Format::JsonLines => self.to_json_lines(),
+            Format::Binary => crate::index::write(
+                &serde_json::to_vec(&self.header())?,
+                &self
+                    .packets()?
+                    .entries
+                    .iter()
+                    .map(Entry::to_record)
+                    .collect::<Vec<_>>(),
+            ),
+        }
+        .wrap_err(eyre!(
+            "Failed to serialize repository data for file \"{}\"",
+            entries_file.to_string_lossy(),
+        ))
+    }
+
+    /// Save the entries to a file in a default location.
+    ///
+    /// This fails if somebody else saved the repository since it was read.
+    ///
+    /// # Errors
+    /// Errors out if the file can not get written or was modified concurrently.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn save_local_directory(&mut self) -> eyre::Result<()> {
+        self.save_local_directory_with(|_, _| Ok(()))
+    }
+
+    /// Save the entries like `save_local_directory` does, calling
+    /// `before_write` with the entries file and the new data right before
+    /// replacing the entries file
+    fn save_local_directory_with(
+        &mut self,
+        before_write: impl FnOnce(&std::path::Path, &[u8]) -> eyre::Result<()>,
+    ) -> eyre::Result<()> {
+        if let Some(snapshot) = &self.snapshot {
+            return Err(eyre!(
+                "Snapshot \"{}\" of repository \"{}\" can not be changed.",
+                snapshot,
+                self.base_url.as_str()
+            ));
+        }
+
+        let entries_file = self.base_url.to_file_path().map_err(|()| {
+            eyre!(
+                "Failed to turn base URL \"{}\" into a file path.",
+                self.base_url.as_str()
+            )
+        })?;
+
+        let generation = if entries_file.exists() {
+            read_data(&entries_file)?.0.generation
+        } else {
+            0
+        };
+        if generation != self.generation {
+            return Err(eyre!(
+                "Repository \"{}\" was modified concurrently (generation {}, expected {}).",
+                self.base_url.as_str(),
+                generation,
+                self.generation
+            ));
+        }
+
+        self.generation += 1;
+        let result = self.serialize(&entries_file).and_then(|data| {
+            before_write(&entries_file, &data)?;
+            crate::files::write_atomically(&entries_file, &data)
+        });
+        if result.is_err() {
+            self.generation -= 1;
+        }
+        result
+    }
+
+    /// Save the entries and write their signature to `PENDING_SIGNATURE_FILE`
+    ///
+    /// The pending signature is written first, so that readers can verify
+    /// the new data as soon as it is in place.
+    fn save_pending_signed_local_directory(
+        &mut self,
+        key: &SigningKey,
+        validity: std::time::Duration,
+    ) -> eyre::Result<()> {
+        self.save_local_directory_with(|entries_file, data| {
+            let signature_file = entries_file.with_file_name(SIGNATURE_FILE);
+            let pending_file = entries_file.with_file_name(PENDING_SIGNATURE_FILE);
+
+            // An interrupted save might have left a pending signature with
+            // a higher serial behind, that readers might have seen already:
+            let serial = std::cmp::max(
+                signature_serial(&signature_file)?,
+                signature_serial(&pending_file)?,
+            ) + 1;
+            sign_hash(
+                &crate::files::data_hash(data),
+                &pending_file,
+                serial,
+                key,
+                Some(validity),
+            )
+        })
+    }
+
+    /// Save the entries to a file in the default location and sign them
+    ///
+    /// The serial number of the signature is one higher than that of the
+    /// previous signature. The signature is valid for `validity`.
+    ///
+    /// The signature of the new data is written next to the old one before
+    /// the data is replaced and moved into place afterwards. Readers not
+    /// holding the lock fall back to that pending signature, so the
+    /// repository verifies at all times, even after a crash in between.
+    ///
+    /// # Errors
+    /// Errors out if the files can not get written.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn save_signed_local_directory(
+        &mut self,
+        key: &SigningKey,
+        validity: std::time::Duration,
+    ) -> eyre::Result<()> {
+        self.save_pending_signed_local_directory(key, validity)?;
+
+        let entries_file = self
+            .base_url
+            .to_file_path()
+            .expect("Saving worked, so this is a file URL");
+        let directory = entries_file
+            .parent()
+            .expect("Files have a parent directory");
+        let pending_file = directory.join(PENDING_SIGNATURE_FILE);
+        std::fs::rename(&pending_file, directory.join(SIGNATURE_FILE)).wrap_err(eyre!(
+            "Failed to move \"{}\" into place.",
+            pending_file.to_string_lossy()
+        ))?;
+        std::fs::File::open(directory)
+            .and_then(|d| d.sync_all())
+            .wrap_err(eyre!(
+                "Failed to sync directory \"{}\".",
+                directory.to_string_lossy()
+            ))
+    }
+
+    fn snapshot_directory(&self) -> eyre::Result<std::path::PathBuf> {
+        Ok(self.local_directory()?.join(SNAPSHOT_DIRECTORY))
+    }
+
+    /// The names of all snapshots of a local repository, sorted by name
+    ///
+    /// # Errors
+    ///
+    /// Fail if this is not a local repository or its snapshots can not be
+    /// read.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn snapshots(&self) -> eyre::Result<Vec<String>> {
+        let directory = self.snapshot_directory()?;
+        if !directory.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut snapshots = Vec::new();
+        for entry in std::fs::read_dir(&directory).wrap_err(eyre!(
+            "Failed to read directory \"{}\".",
+            directory.to_string_lossy()
+        ))? {
+            let path = entry
+                .wrap_err(eyre!(
+                    "Failed to read directory \"{}\".",
+                    directory.to_string_lossy()
+                ))?
+                .path();
+            if path.extension().map_or(false, |e| e == "json") {
+                if let Some(name) = path.file_stem().map(|n| n.to_string_lossy().to_string()) {
+                    if check_snapshot_name(&name).is_ok() {
+                        snapshots.push(name);
+                    }
+                }
+            }
+        }
+        snapshots.sort();
+        Ok(snapshots)
+    }
+
+    /// The name of the current snapshot of a local repository
+    ///
+    /// # Errors
+    ///
+    /// Fail if this is not a local repository or the current snapshot can
+    /// not be read.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn current_snapshot(&self) -> eyre::Result<Option<String>> {
+        let current = self.snapshot_directory()?.join(CURRENT_SNAPSHOT);
+        if !current.exists() {
+            return Ok(None);
+        }
+        let data = std::fs::read(&current)
+            .wrap_err(eyre!("Failed to read \"{}\".", current.to_string_lossy()))?;
+        parse_snapshot_pointer(data).map(Some)
+    }
+
+    /// Make the snapshot `name` of a local repository the current snapshot
+    ///
+    /// The pointer to the current snapshot is signed with the key of
+    /// `signing` if given, valid for its duration. The serial number of that
+    /// signature is one higher than that of the previous one, so a mirror can
+    /// not point clients back to older snapshots.
+    ///
+    /// Like signed saves, the signature of the new pointer is written next to
+    /// the old one first and moved into place once the pointer is updated.
+    ///
+    /// # Errors
+    ///
+    /// Fail if the snapshot does not exist or can not be made current.
+    #[tracing::instrument(level = "debug", skip(self, signing))]
+    pub fn set_current_snapshot(
+        &self,
+        name: &str,
+        signing: Option<(&SigningKey, std::time::Duration)>,
+    ) -> eyre::Result<()> {
+        check_snapshot_name(name)?;
+        let directory = self.snapshot_directory()?;
+        if !directory.join(format!("{}.json", name)).is_file() {
+            return Err(eyre!("Snapshot \"{}\" not found.", name));
+        }
+        let current_file = directory.join(CURRENT_SNAPSHOT);
+        let data = format!("{}\n", name);
+
+        let (key, validity) = if let Some(signing) = signing {
+            signing
+        } else {
+            return crate::files::write_atomically(&current_file, data.as_bytes());
+        };
+
+        // Sign the new pointer before it is written, so that readers can
+        // verify it at all times, even after a crash in between:
+        let signature_file = directory.join(CURRENT_SNAPSHOT_SIGNATURE);
+        let pending_file = directory.join(PENDING_CURRENT_SNAPSHOT_SIGNATURE);
+        let serial = std::cmp::max(
+            signature_serial(&signature_file)?,
+            signature_serial(&pending_file)?,
+        ) + 1;
+        sign_hash(
+            &crate::files::data_hash(data.as_bytes()),
+            &pending_file,
+            serial,
+            key,
+            Some(validity),
+        )?;
+        crate::files::write_atomically(&current_file, data.as_bytes())?;
+
+        std::fs::rename(&pending_file, &signature_file).wrap_err(eyre!(
+            "Failed to move \"{}\" into place.",
+            pending_file.to_string_lossy()
+        ))?;
+        std::fs::File::open(&directory)
+            .and_then(|d| d.sync_all())
+            .wrap_err(eyre!(
+                "Failed to sync directory \"{}\".",
+                directory.to_string_lossy()
+            ))
+    }
+
+    /// Create an immutable snapshot of a local repository and make it the
+    /// current snapshot
+    ///
+    /// Snapshots are named after the current date (UTC) unless a `name` is
+    /// given, with a counter appended for further snapshots on the same day.
+    ///
+    /// The snapshot is signed with the key of `signing` if given. Snapshots
+    /// never change, so their signatures do not expire. The pointer to the
+    /// current snapshot is signed as described in `set_current_snapshot`.
+    ///
+    /// Returns the name of the new snapshot.
+    ///
+    /// # Errors
+    ///
+    /// Fail if this is not a local repository, the name is invalid or
+    /// taken or the snapshot can not be written.
+    #[tracing::instrument(level = "debug", skip(self, signing))]
+    pub fn create_snapshot(
+        &self,
+        name: Option<&str>,
+        signing: Option<(&SigningKey, std::time::Duration)>,
+    ) -> eyre::Result<String> {
+        let directory = self.snapshot_directory()?;
+        let exists = |name: &str| directory.join(format!("{}.json", name)).exists();
+
+        let name = if let Some(name) = name {
+            check_snapshot_name(name)?;
+            if exists(name) {
+                return Err(eyre!("Snapshot \"{}\" already exists.", name));
+            }
+            name.to_string()
+        } else {
+            let date = gng_core::time::date(crate::signature::now());
+            let mut name = date.clone();
+            let mut counter = 1;
+            while exists(&name) {
+                counter += 1;
+                name = format!("{}.{}", date, counter);
+            }
+            name
+        };
+
+        let entries_file = directory.join(format!("{}.json", name));
+        self.save(&entries_file)?;
+        if let Some((key, _)) = signing {
+            sign_entries_file(
+                &entries_file,
+                &directory.join(format!("{}.sig", name)),
+                1,
+                key,
+                None,
+            )?;
+        }
+        self.set_current_snapshot(&name, signing)?;
+        Ok(name)
+    }
+
+    /// Replace the packets and retention policy of the repository with those
+    /// of the snapshot `name`
+    ///
+    /// Save the repository and then make the snapshot current with
+    /// `set_current_snapshot` to complete the rollback.
+    ///
+    /// # Errors
+    ///
+    /// Fail if the snapshot can not be read.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn rollback_to_snapshot(&mut self, name: &str) -> eyre::Result<()> {
+        check_snapshot_name(name)?;
+        let entries_file = self.snapshot_directory()?.join(format!("{}.json", name));
+        if !entries_file.is_file() {
+            return Err(eyre!("Snapshot \"{}\" not found.", name));
+        }
+        let (header, entries) = read_entries(&entries_file)?;
+
+        let mut update = self.create_transaction();
+        update.clear();
+        for e in entries {
+            update.add_entry(e);
+        }
+        update.set_retention(header.retention);
+        self.apply(update)
+    }
+
+    /// The packet files referenced by any snapshot of a local repository
+    fn snapshot_files(&self) -> eyre::Result<std::collections::BTreeSet<std::path::PathBuf>> {
+        let directory = self.snapshot_directory()?;
+        let mut files = std::collections::BTreeSet::new();
+        for name in self.snapshots()? {
+            files.extend(
+                read_entries(&directory.join(format!("{}.json", name)))?
+                    .1
+                    .into_iter()
+                    .map(|e| e.file_path),
+            );
+        }
+        Ok(files)
+    }
+
+    /// The packets that were added to and removed from this repository to
+    /// get to `other`
+    ///
+    /// # Errors
+    ///
+    /// Fail if the packets of a binary index can not be decoded.
+    pub fn diff(&self, other: &Self) -> eyre::Result<Difference> {
+        let key = |e: &Entry| {
+            (
+                e.key(),
+                e.packet_data.version.clone(),
+                e.file_path.clone(),
+                e.sha256.clone(),
+            )
+        };
+        let only_in = |a: &Self, b: &Self| -> eyre::Result<_> {
+            let keys = b
+                .packets()?
+                .entries
+                .iter()
+                .map(key)
+                .collect::<std::collections::BTreeSet<_>>();
+            Ok(a.packets()?
+                .entries
+                .iter()
+                .filter(|e| !keys.contains(&key(e)))
+                .map(|e| (e.packet_data.clone(), e.file_path.clone()))
+                .collect::<Vec<_>>())
+        };
+
+        Ok(Difference {
+            added: only_in(other, self)?,
+            removed: only_in(self, other)?,
+        })
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_util::name;
+
+    use gng_core::Version;
+
+    fn entry(packet: &str, facet: Option<&str>, version: &str) -> Entry {
+        let full_name = facet.map_or_else(|| packet.to_string(), |f| format!("{}:{}", packet, f));
+        Entry {
+            packet_data: crate::test_util::packet(&full_name, version, &[]),
+            file_path: std::path::PathBuf::from(format!("{}-{}.gng", packet, version)),
+            sha256: None,
+            pinned: false,
+            files: None,
+        }
+    }
+
+    fn repository(entries: Vec<Entry>) -> Repository {
+        let mut repo = Repository::new(&url::Url::parse("file:///repo/repository.json").unwrap());
+        let mut update = repo.create_transaction();
+        update.to_apply = entries;
+        repo.apply(update).unwrap();
+        repo
+    }
+
+    fn queried_version(
+        repo: &Repository,
+        packet: &str,
+        facet: Option<&str>,
+        requirement: &str,
+    ) -> Option<String> {
+        repo.query_version(
+            &name(packet),
+            &facet.map(name),
+            &requirement.parse().unwrap(),
+        )
+        .unwrap()
+        .map(|(p, _)| p.version.to_string())
+    }
+
+    #[test]
+    fn query_newest() {
+        let repo = repository(vec![
+            entry("foo", None, "1.0"),
+            entry("foo", None, "1.2"),
+            entry("foo", None, "1.1"),
+            entry("foo", Some("dev"), "1.0"),
+            entry("bar", None, "2.0"),
+        ]);
+
+        let (packet, path) = repo.query(&name("foo"), &None).unwrap().unwrap();
+        assert_eq!(packet.version.to_string(), "1.2");
+        assert_eq!(path, std::path::PathBuf::from("foo-1.2.gng"));
+
+        let (packet, _) = repo
+            .query(&name("foo"), &Some(name("dev")))
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet.facet_name(), Some(name("dev")));
+        assert_eq!(packet.version.to_string(), "1.0");
+
+        assert!(repo
+            .query(&name("foo"), &Some(name("doc")))
+            .unwrap()
+            .is_none());
+        assert!(repo.query(&name("baz"), &None).unwrap().is_none());
+    }
+
+    #[test]
+    fn query_with_requirement() {
+        let repo = repository(vec![
+            entry("foo", None, "1.0"),
+            entry("foo", None, "1.1"),
+            entry("foo", None, "2.0"),
+        ]);
+
+        assert_eq!(
+            queried_version(&repo, "foo", None, "<2.0"),
+            Some("1.1".into())
+        );
+        assert_eq!(
+            queried_version(&repo, "foo", None, "=1.0"),
+            Some("1.0".into())
+        );
+        assert_eq!(queried_version(&repo, "foo", None, "*"), Some("2.0".into()));
+        assert_eq!(queried_version(&repo, "foo", None, ">2.0"), None);
+        assert_eq!(queried_version(&repo, "foo", Some("dev"), "*"), None);
+    }
+
+    #[test]
+    fn query_after_removal() {
+        let mut repo = repository(vec![entry("foo", None, "1.0"), entry("bar", None, "1.0")]);
+
+        let mut update = repo.create_transaction();
+        update.remove(name("foo"));
+        repo.apply(update).unwrap();
+
+        assert!(repo.query(&name("foo"), &None).unwrap().is_none());
+        assert!(repo.query(&name("bar"), &None).unwrap().is_some());
+    }
+
+    #[test]
+    fn remote_repository() {
+        use sha2::Digest;
+
+        let server = crate::remote::tests::TestServer::start();
+        let cache = tempfile::tempdir().unwrap();
+
+        let mut foo = entry("foo", None, "1.0");
+        foo.sha256 = Some(format!("{:x}", sha2::Sha256::digest(b"foo packet")));
+        let bar = entry("bar", None, "1.0");
+        let index = [&foo, &bar]
+            .iter()
+            .map(|e| serde_json::to_string(e).unwrap() + "\n")
+            .collect::<String>();
+        server.put("repository.json", index.as_bytes());
+        server.put("foo-1.0.gng", b"foo packet");
+        server.put("bar-1.0.gng", b"bar packet");
+
+        let repo =
+            Repository::from_remote(&server.url, cache.path(), false, &Trust::none()).unwrap();
+        assert_eq!(
+            repo.url().as_str(),
+            server.url.join("repository.json").unwrap().as_str()
+        );
+        let (_, file) = repo.query(&name("foo"), &None).unwrap().unwrap();
+        let path = repo.packet_file(&file).unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), b"foo packet");
+
+        // No hash: Refuse to download
+        let (_, file) = repo.query(&name("bar"), &None).unwrap().unwrap();
+        assert!(repo.packet_file(&file).is_err());
+        assert!(repo
+            .packet_file(std::path::Path::new("baz-1.0.gng"))
+            .is_err());
+
+        // Offline from cache:
+        server.take_requests();
+        let repo = Repository::from_url(&server.url, cache.path(), true, &Trust::none()).unwrap();
+        let (_, file) = repo.query(&name("foo"), &None).unwrap().unwrap();
+        assert_eq!(
+            std::fs::read(repo.packet_file(&file).unwrap()).unwrap(),
+            b"foo packet"
+        );
+        assert_eq!(server.take_requests(), vec![]);
+    }
+
+    #[test]
+    fn signed_local_repository() {
+        let directory = tempfile::tempdir().unwrap();
+        let key = "01".repeat(32).parse::<SigningKey>().unwrap();
+        let other_key = "02".repeat(32).parse::<SigningKey>().unwrap();
+        let trust = Trust::without_rollback_protection(vec![key.public_key()]);
+
+        let mut repo =
+            Repository::from_local_directory(directory.path(), true, &Trust::none()).unwrap();
+        assert!(Repository::from_local_directory(directory.path(), false, &trust).is_err());
+
+        repo.save_signed_local_directory(&key, std::time::Duration::from_secs(3600))
+            .unwrap();
+        Repository::from_local_directory(directory.path(), false, &trust).unwrap();
+        assert!(Repository::from_local_directory(
+            directory.path(),
+            false,
+            &Trust::without_rollback_protection(vec![other_key.public_key()])
+        )
+        .is_err());
+
+        repo.save_signed_local_directory(&key, std::time::Duration::from_secs(3600))
+            .unwrap();
+        let metadata = RepositoryMetadata::load(&directory.path().join(SIGNATURE_FILE)).unwrap();
+        assert_eq!(metadata.serial, 2);
+
+        // Tampering with the data:
+        std::fs::write(
+            directory.path().join("repository.json"),
+            serde_json::to_string(&entry("foo", None, "1.0")).unwrap(),
+        )
+        .unwrap();
+        assert!(Repository::from_local_directory(directory.path(), false, &trust).is_err());
+    }
+
+    #[test]
+    fn interrupted_signed_save() {
+        let directory = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+        let key = "01".repeat(32).parse::<SigningKey>().unwrap();
+        let trust = Trust::new(vec![key.public_key()], state.path());
+        let validity = std::time::Duration::from_secs(3600);
+
+        let mut repo =
+            Repository::from_local_directory(directory.path(), true, &Trust::none()).unwrap();
+        repo.save_signed_local_directory(&key, validity).unwrap();
+        Repository::from_local_directory(directory.path(), false, &trust).unwrap();
+
+        // Crash after the new data was moved into place, before its
+        // signature was:
+        let mut update = repo.create_transaction();
+        update.add_entry(entry("foo", None, "1.0"));
+        repo.apply(update).unwrap();
+        repo.save_pending_signed_local_directory(&key, validity)
+            .unwrap();
+        assert!(directory.path().join(PENDING_SIGNATURE_FILE).exists());
+
+        let reader = Repository::from_local_directory(directory.path(), false, &trust).unwrap();
+        assert!(reader.query(&name("foo"), &None).unwrap().is_some());
+
+        // The next save continues after the serial of the pending signature:
+        let mut repo = reader;
+        repo.save_signed_local_directory(&key, validity).unwrap();
+        assert!(!directory.path().join(PENDING_SIGNATURE_FILE).exists());
+        let metadata = RepositoryMetadata::load(&directory.path().join(SIGNATURE_FILE)).unwrap();
+        assert_eq!(metadata.serial, 3);
+        Repository::from_local_directory(directory.path(), false, &trust).unwrap();
+    }
+
+    #[test]
+    fn signed_remote_repository() {
+        use sha2::Digest;
+
+        let server = crate::remote::tests::TestServer::start();
+        let cache = tempfile::tempdir().unwrap();
+        let key = "01".repeat(32).parse::<SigningKey>().unwrap();
+        let trust = Trust::new(vec![key.public_key()], &cache.path().join("state"));
+
+        let publish = |serial: u64, data: &str| {
+            server.put("repository.json", data.as_bytes());
+            let now = crate::signature::now();
+            let mut metadata = RepositoryMetadata::new(
+                serial,
+                now,
+                now + 3600,
+                &format!("{:x}", sha2::Sha256::digest(data.as_bytes())),
+            );
+            metadata.sign(&key);
+            server.put(
+                SIGNATURE_FILE,
+                serde_json::to_string(&metadata).unwrap().as_bytes(),
+            );
+        };
+        let old_data = serde_json::to_string(&entry("foo", None, "1.0")).unwrap() + "\n";
+        let new_data = serde_json::to_string(&entry("foo", None, "1.1")).unwrap() + "\n";
+
+        assert!(Repository::from_remote(&server.url, cache.path(), false, &trust).is_err());
+
+        publish(1, &old_data);
+        Repository::from_remote(&server.url, cache.path(), false, &trust).unwrap();
+        publish(2, &new_data);
+        let repo = Repository::from_remote(&server.url, cache.path(), false, &trust).unwrap();
+        let (packet, _) = repo.query(&name("foo"), &None).unwrap().unwrap();
+        assert_eq!(packet.version.to_string(), "1.1");
+
+        // Rollback to older data:
+        publish(1, &old_data);
+        assert!(Repository::from_remote(&server.url, cache.path(), false, &trust).is_err());
+    }
+
+    #[test]
+    fn replace_and_remove_facets() {
+        let mut repo = repository(vec![
+            entry("foo", None, "1.0"),
+            entry("foo", Some("dev"), "1.0"),
+            entry("foo", Some("doc"), "1.0"),
+        ]);
+
+        let mut update = repo.create_transaction();
+        update.add_entry(entry("foo", None, "1.1"));
+        update.remove_facet(name("foo"), Some(name("doc")));
+        repo.apply(update).unwrap();
+
+        let (packet, _) = repo.query(&name("foo"), &None).unwrap().unwrap();
+        assert_eq!(packet.version.to_string(), "1.1");
+        assert!(repo
+            .query(&name("foo"), &Some(name("dev")))
+            .unwrap()
+            .is_some());
+        assert!(repo
+            .query(&name("foo"), &Some(name("doc")))
+            .unwrap()
+            .is_none());
+        assert_eq!(repo.entries().unwrap().count(), 3);
+    }
+
+    #[test]
+    fn verify_repository_files() {
+        use sha2::Digest;
+
+        let directory = tempfile::tempdir().unwrap();
+        let mut repo =
+            Repository::from_local_directory(directory.path(), true, &Trust::none()).unwrap();
+        std::fs::write(directory.path().join("foo-1.0.gng"), b"foo").unwrap();
+        std::fs::write(directory.path().join("bar-1.0.gng"), b"bar").unwrap();
+
+        let mut foo = entry("foo", None, "1.0");
+        foo.sha256 = Some(format!("{:x}", sha2::Sha256::digest(b"foo")));
+        let mut bar = entry("bar", None, "1.0");
+        bar.sha256 = Some(format!("{:x}", sha2::Sha256::digest(b"not bar")));
+        let baz = entry("baz", None, "1.0");
+
+        let mut update = repo.create_transaction();
+        update.to_apply = vec![foo, bar, baz];
+        repo.apply(update).unwrap();
+
+        let problems = repo
+            .verify_files()
+            .into_iter()
+            .map(|(f, _)| f)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            problems,
+            vec![
+                std::path::PathBuf::from("bar-1.0.gng"),
+                std::path::PathBuf::from("baz-1.0.gng")
+            ]
+        );
+
+        assert_eq!(
+            repo.packet_file(std::path::Path::new("foo-1.0.gng"))
+                .unwrap(),
+            directory.path().join("foo-1.0.gng")
+        );
+        assert!(repo
+            .packet_file(std::path::Path::new("bar-1.0.gng"))
+            .unwrap_err()
+            .to_string()
+            .starts_with("Hash mismatch for "));
+    }
+
+    #[test]
+    fn save_replaces_data() {
+        let directory = tempfile::tempdir().unwrap();
+        let entries_file = directory.path().join("repository.json");
+
+        // Old repository files have no header:
+        std::fs::write(
+            &entries_file,
+            serde_json::to_string(&entry("foo", None, "1.0")).unwrap() + "\n",
+        )
+        .unwrap();
+
+        let mut repo =
+            Repository::from_local_directory(directory.path(), false, &Trust::none()).unwrap();
+        assert_eq!(repo.generation, 0);
+        let mut update = repo.create_transaction();
+        update.to_apply = vec![entry("bar", None, "1.0"), entry("baz", None, "1.0")];
+        repo.apply(update).unwrap();
+        repo.save_local_directory().unwrap();
+
+        let mut repo =
+            Repository::from_local_directory(directory.path(), false, &Trust::none()).unwrap();
+        assert_eq!(repo.generation, 1);
+        assert_eq!(repo.entries().unwrap().count(), 3);
+
+        let mut update = repo.create_transaction();
+        update.clear();
+        repo.apply(update).unwrap();
+        repo.save_local_directory().unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&entries_file).unwrap(),
+            "{\"generation\":2}\n"
+        );
+        let repo =
+            Repository::from_local_directory(directory.path(), false, &Trust::none()).unwrap();
+        assert_eq!(repo.entries().unwrap().count(), 0);
+        assert_eq!(
+            std::fs::read_dir(directory.path()).unwrap().count(),
+            1,
+            "No temporary files are left behind"
+        );
+    }
+
+    #[test]
+    fn read_entries_file_replaces_versions() {
+        let directory = tempfile::tempdir().unwrap();
+        let entries_file = directory.path().join("repository.json");
+        let mut changed = entry("foo", None, "1.0");
+        changed.sha256 = Some("0123".to_string());
+        std::fs::write(
+            &entries_file,
+            [
+                entry("foo", None, "1.0"),
+                entry("foo", None, "1.1"),
+                entry("foo", Some("dev"), "1.0"),
+                changed,
+            ]
+            .iter()
+            .map(|e| serde_json::to_string(e).unwrap() + "\n")
+            .collect::<String>(),
+        )
+        .unwrap();
+
+        let mut repo = repository(vec![entry("foo", None, "1.0"), entry("bar", None, "1.0")]);
+        let mut update = repo.create_transaction();
+        update.read_entries_file(&entries_file).unwrap();
+        update.read_entries_file(&entries_file).unwrap();
+        repo.apply(update).unwrap();
+
+        assert_eq!(repo.entries().unwrap().count(), 4);
+        assert_eq!(
+            repo.packets()
+                .unwrap()
+                .entries
+                .iter()
+                .find(|e| e.packet_data.name == name("foo")
+                    && e.packet_data.facet_name().is_none()
+                    && e.packet_data.version == Version::try_from("1.0").unwrap())
+                .unwrap()
+                .sha256,
+            Some("0123".to_string())
+        );
+    }
+
+    #[test]
+    fn save_detects_concurrent_modification() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut first =
+            Repository::from_local_directory(directory.path(), true, &Trust::none()).unwrap();
+        let mut second =
+            Repository::from_local_directory(directory.path(), false, &Trust::none()).unwrap();
+
+        let mut update = first.create_transaction();
+        update.to_apply = vec![entry("foo", None, "1.0")];
+        first.apply(update).unwrap();
+        first.save_local_directory().unwrap();
+
+        let mut update = second.create_transaction();
+        update.to_apply = vec![entry("bar", None, "1.0")];
+        second.apply(update).unwrap();
+        assert!(second.save_local_directory().is_err());
+
+        let repo =
+            Repository::from_local_directory(directory.path(), false, &Trust::none()).unwrap();
+        assert!(repo.query(&name("foo"), &None).unwrap().is_some());
+        assert!(repo.query(&name("bar"), &None).unwrap().is_none());
+
+        // Saving again works after the first save:
+        first.save_local_directory().unwrap();
+    }
+
+    #[test]
+    fn lock_local_directory() {
+        use fs2::FileExt;
+
+        let directory = tempfile::tempdir().unwrap();
+        let lock = Repository::lock_local_directory(directory.path()).unwrap();
+
+        let other = std::fs::File::open(directory.path().join(LOCK_FILE)).unwrap();
+        assert!(other.try_lock_exclusive().is_err());
+
+        std::mem::drop(lock);
+        other.try_lock_exclusive().unwrap();
+    }
+
+    fn versions(repo: &Repository, packet: &str, requirement: &str) -> Vec<String> {
+        repo.query_versions(&name(packet), &None, &requirement.parse().unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|(p, _)| p.version.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn multiple_versions() {
+        let mut repo = repository(vec![entry("foo", None, "1.0"), entry("foo", None, "1.1")]);
+
+        let mut update = repo.create_transaction();
+        update.add_entry(entry("foo", None, "2.0"));
+        update.add_entry(entry("foo", None, "1.1"));
+        repo.apply(update).unwrap();
+        assert_eq!(versions(&repo, "foo", "*"), vec!["2.0", "1.1", "1.0"]);
+        assert_eq!(versions(&repo, "foo", "<2.0"), vec!["1.1", "1.0"]);
+
+        let mut update = repo.create_transaction();
+        update.remove_versions(name("foo"), None, &"<1.1".parse().unwrap());
+        repo.apply(update).unwrap();
+        assert_eq!(versions(&repo, "foo", "*"), vec!["2.0", "1.1"]);
+    }
+
+    #[test]
+    fn retention_and_pins() {
+        let mut repo = repository(vec![
+            entry("foo", None, "1.0"),
+            entry("foo", None, "1.1"),
+            entry("foo", None, "1.2"),
+            entry("foo", Some("dev"), "1.0"),
+            entry("foo", Some("dev"), "1.1"),
+        ]);
+        assert_eq!(repo.superseded().unwrap(), vec![]);
+
+        let mut update = repo.create_transaction();
+        update.set_retention(Retention::Newest(1));
+        update.pin(name("foo"), None, Version::try_from("1.0").unwrap());
+        repo.apply(update).unwrap();
+
+        assert!(repo
+            .is_pinned(&name("foo"), &None, &Version::try_from("1.0").unwrap())
+            .unwrap());
+        let superseded = repo
+            .superseded()
+            .unwrap()
+            .into_iter()
+            .map(|(_, f)| f.to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(superseded, vec!["foo-1.1.gng", "foo-1.0.gng"]);
+
+        // Pins survive re-adding the same version:
+        let mut update = repo.create_transaction();
+        update.add_entry(entry("foo", None, "1.0"));
+        repo.apply(update).unwrap();
+        assert!(repo
+            .is_pinned(&name("foo"), &None, &Version::try_from("1.0").unwrap())
+            .unwrap());
+
+        let mut update = repo.create_transaction();
+        update.unpin(name("foo"), None, Version::try_from("1.0").unwrap());
+        repo.apply(update).unwrap();
+        assert_eq!(repo.superseded().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn retention_is_saved() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut repo =
+            Repository::from_local_directory(directory.path(), true, &Trust::none()).unwrap();
+        let mut update = repo.create_transaction();
+        update.set_retention(Retention::Newest(3));
+        update.to_apply = vec![entry("foo", None, "1.0")];
+        update.pin(name("foo"), None, Version::try_from("1.0").unwrap());
+        repo.apply(update).unwrap();
+        repo.save_local_directory().unwrap();
+
+        let repo =
+            Repository::from_local_directory(directory.path(), false, &Trust::none()).unwrap();
+        assert_eq!(repo.retention(), Retention::Newest(3));
+        assert!(repo
+            .is_pinned(&name("foo"), &None, &Version::try_from("1.0").unwrap())
+            .unwrap());
+
+        assert_eq!("all".parse::<Retention>().unwrap(), Retention::All);
+        assert_eq!("2".parse::<Retention>().unwrap(), Retention::Newest(2));
+        assert!("0".parse::<Retention>().is_err());
+        assert!("some".parse::<Retention>().is_err());
+    }
+
+    #[test]
+    fn garbage_collection() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut repo =
+            Repository::from_local_directory(directory.path(), true, &Trust::none()).unwrap();
+        for file in ["foo-1.0.gng", "foo-1.1.gng", "foo-1.2.gng", "stray-1.0.gng"] {
+            std::fs::write(directory.path().join(file), b"data").unwrap();
+        }
+        std::fs::create_dir(directory.path().join("sub")).unwrap();
+        std::fs::write(directory.path().join("sub/other-1.0.gng"), b"data").unwrap();
+        std::fs::write(directory.path().join("notes.txt"), b"data").unwrap();
+
+        let mut update = repo.create_transaction();
+        update.set_retention(Retention::Newest(1));
+        update.to_apply = vec![
+            entry("foo", None, "1.0"),
+            entry("foo", None, "1.1"),
+            entry("foo", None, "1.2"),
+            entry("foo", None, "1.3"),
+            entry("bar", None, "1.0"),
+        ];
+        update.pin(name("foo"), None, Version::try_from("1.0").unwrap());
+        repo.apply(update).unwrap();
+        repo.save_local_directory().unwrap();
+
+        let garbage = repo.find_garbage().unwrap();
+        assert_eq!(
+            garbage.unreferenced,
+            vec![
+                std::path::PathBuf::from("stray-1.0.gng"),
+                std::path::PathBuf::from("sub/other-1.0.gng")
+            ]
+        );
+        assert_eq!(
+            garbage
+                .missing
+                .iter()
+                .map(|(_, f)| f.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                std::path::PathBuf::from("bar-1.0.gng"),
+                std::path::PathBuf::from("foo-1.3.gng")
+            ]
+        );
+        assert_eq!(
+            garbage
+                .superseded
+                .iter()
+                .map(|(_, f)| f.clone())
+                .collect::<Vec<_>>(),
+            vec![std::path::PathBuf::from("foo-1.1.gng")]
+        );
+
+        // Referenced files are never deleted:
+        assert_eq!(
+            repo.delete_garbage_files(&garbage).unwrap().deleted,
+            vec![
+                std::path::PathBuf::from("stray-1.0.gng"),
+                std::path::PathBuf::from("sub/other-1.0.gng")
+            ]
+        );
+        assert!(directory.path().join("foo-1.1.gng").is_file());
+
+        let mut update = repo.create_transaction();
+        update.remove_garbage(&garbage);
+        repo.apply(update).unwrap();
+        repo.save_local_directory().unwrap();
+        assert_eq!(
+            repo.delete_garbage_files(&garbage).unwrap(),
+            DeletedGarbage {
+                deleted: vec![std::path::PathBuf::from("foo-1.1.gng")],
+                failed: Vec::new(),
+            }
+        );
+
+        assert!(repo.find_garbage().unwrap().is_empty());
+        assert!(directory.path().join("foo-1.0.gng").is_file());
+        assert!(directory.path().join("foo-1.2.gng").is_file());
+        assert!(directory.path().join("notes.txt").is_file());
+    }
+
+    fn file_names(
+        packets: &[(gng_packet_io::BinaryPacketDefinition, std::path::PathBuf)],
+    ) -> Vec<std::path::PathBuf> {
+        packets.iter().map(|(_, f)| f.clone()).collect()
+    }
+
+    #[test]
+    fn snapshots() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut repo =
+            Repository::from_local_directory(directory.path(), true, &Trust::none()).unwrap();
+        assert_eq!(repo.snapshots().unwrap(), Vec::<String>::new());
+        assert_eq!(repo.current_snapshot().unwrap(), None);
+
+        let mut update = repo.create_transaction();
+        update.to_apply = vec![entry("foo", None, "1.0")];
+        repo.apply(update).unwrap();
+        repo.save_local_directory().unwrap();
+        assert_eq!(repo.create_snapshot(Some("one"), None).unwrap(), "one");
+        assert!(repo.create_snapshot(Some("one"), None).is_err());
+        assert!(repo.create_snapshot(Some("current"), None).is_err());
+        assert!(repo.create_snapshot(Some("../one"), None).is_err());
+
+        let mut update = repo.create_transaction();
+        update.to_apply = vec![entry("foo", None, "1.1"), entry("bar", None, "1.0")];
+        repo.apply(update).unwrap();
+        repo.save_local_directory().unwrap();
+        let today = repo.create_snapshot(None, None).unwrap();
+        assert_eq!(
+            repo.create_snapshot(None, None).unwrap(),
+            format!("{}.2", today)
+        );
+        assert_eq!(
+            repo.snapshots().unwrap(),
+            vec![today.clone(), format!("{}.2", today), "one".to_string()]
+        );
+        assert_eq!(
+            repo.current_snapshot().unwrap(),
+            Some(format!("{}.2", today))
+        );
+
+        let mut one =
+            Repository::from_local_snapshot(directory.path(), "one", &Trust::none()).unwrap();
+        assert_eq!(one.snapshot(), Some("one"));
+        assert_eq!(one.entries().unwrap().count(), 1);
+        assert!(one.save_local_directory().is_err());
+
+        let url = snapshot_url(
+            &url::Url::from_directory_path(directory.path()).unwrap(),
+            CURRENT_SNAPSHOT,
+        );
+        let current = Repository::from_url(&url, directory.path(), false, &Trust::none()).unwrap();
+        assert_eq!(current.snapshot(), Some(format!("{}.2", today).as_str()));
+        assert_eq!(current.entries().unwrap().count(), 3);
+
+        let difference = one.diff(&current).unwrap();
+        assert_eq!(
+            file_names(&difference.added),
+            vec![
+                std::path::PathBuf::from("bar-1.0.gng"),
+                std::path::PathBuf::from("foo-1.1.gng")
+            ]
+        );
+        assert_eq!(difference.removed, vec![]);
+        assert_eq!(
+            file_names(&current.diff(&one).unwrap().removed),
+            file_names(&difference.added)
+        );
+        assert!(one.diff(&one).unwrap().is_empty());
+
+        repo.rollback_to_snapshot("one").unwrap();
+        repo.save_local_directory().unwrap();
+        repo.set_current_snapshot("one", None).unwrap();
+        let repo =
+            Repository::from_local_directory(directory.path(), false, &Trust::none()).unwrap();
+        assert!(repo.diff(&one).unwrap().is_empty());
+        assert_eq!(repo.current_snapshot().unwrap(), Some("one".to_string()));
+        assert!(repo.set_current_snapshot("two", None).is_err());
+        assert!(Repository::from_local_snapshot(directory.path(), "two", &Trust::none()).is_err());
+    }
+
+    #[test]
+    fn garbage_collection_keeps_snapshot_files() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut repo =
+            Repository::from_local_directory(directory.path(), true, &Trust::none()).unwrap();
+        for file in ["foo-1.0.gng", "foo-1.1.gng"] {
+            std::fs::write(directory.path().join(file), b"data").unwrap();
+        }
+
+        let mut update = repo.create_transaction();
+        update.to_apply = vec![entry("foo", None, "1.0")];
+        repo.apply(update).unwrap();
+        repo.save_local_directory().unwrap();
+        repo.create_snapshot(Some("old"), None).unwrap();
+
+        let mut update = repo.create_transaction();
+        update.remove(name("foo"));
+        update.to_apply = vec![entry("foo", None, "1.1")];
+        repo.apply(update).unwrap();
+        repo.save_local_directory().unwrap();
+
+        assert!(repo.find_garbage().unwrap().is_empty());
+
+        let garbage = Garbage {
+            unreferenced: vec![std::path::PathBuf::from("foo-1.0.gng")],
+            ..Garbage::default()
+        };
+        assert_eq!(
+            repo.delete_garbage_files(&garbage).unwrap(),
+            DeletedGarbage::default()
+        );
+        assert!(directory.path().join("foo-1.0.gng").is_file());
+    }
+
+    #[test]
+    fn remote_snapshot() {
+        let server = crate::remote::tests::TestServer::start();
+        let cache = tempfile::tempdir().unwrap();
+        let key = "01".repeat(32).parse::<SigningKey>().unwrap();
+        let signing = Some((&key, std::time::Duration::from_secs(3600)));
+        let trust = Trust::new(vec![key.public_key()], &cache.path().join("state"));
+
+        // Build the snapshots locally, then serve them:
+        let directory = tempfile::tempdir().unwrap();
+        let mut repo =
+            Repository::from_local_directory(directory.path(), true, &Trust::none()).unwrap();
+        let mut update = repo.create_transaction();
+        update.to_apply = vec![entry("foo", None, "1.0")];
+        repo.apply(update).unwrap();
+        repo.save_signed_local_directory(&key, std::time::Duration::from_secs(3600))
+            .unwrap();
+        repo.create_snapshot(Some("old"), signing).unwrap();
+        assert_eq!(
+            RepositoryMetadata::load(&directory.path().join("snapshots/old.sig"))
+                .unwrap()
+                .expires,
+            u64::MAX
+        );
+        let serve = |files: &[&str]| {
+            for file in files {
+                server.put(file, &std::fs::read(directory.path().join(file)).unwrap());
+            }
+        };
+        let pointer = ["snapshots/current", "snapshots/current.sig"];
+        serve(&[
+            "repository.json",
+            "repository.sig",
+            "snapshots/old.json",
+            "snapshots/old.sig",
+        ]);
+        serve(&pointer);
+        let old_pointer = pointer
+            .iter()
+            .map(|f| std::fs::read(directory.path().join(f)).unwrap())
+            .collect::<Vec<_>>();
+
+        let mut url = server.url.clone();
+        url.set_fragment(Some("snapshot=current"));
+        let snapshot = Repository::from_url(&url, cache.path(), false, &trust).unwrap();
+        assert_eq!(snapshot.snapshot(), Some("old"));
+        assert_eq!(
+            snapshot.url().as_str(),
+            server.url.join("repository.json").unwrap().as_str()
+        );
+        assert_eq!(snapshot.entries().unwrap().count(), 1);
+
+        // A newer snapshot:
+        let mut update = repo.create_transaction();
+        update.to_apply = vec![entry("foo", None, "1.1")];
+        repo.apply(update).unwrap();
+        repo.save_signed_local_directory(&key, std::time::Duration::from_secs(3600))
+            .unwrap();
+        repo.create_snapshot(Some("new"), signing).unwrap();
+        assert_eq!(
+            RepositoryMetadata::load(&directory.path().join("snapshots/current.sig"))
+                .unwrap()
+                .serial,
+            2
+        );
+        serve(&["snapshots/new.json", "snapshots/new.sig"]);
+        serve(&pointer);
+        let snapshot = Repository::from_url(&url, cache.path(), false, &trust).unwrap();
+        assert_eq!(snapshot.snapshot(), Some("new"));
+
+        // A mirror pointing back to the old snapshot:
+        server.put("snapshots/current", b"old\n");
+        assert!(Repository::from_url(&url, cache.path(), false, &trust).is_err());
+        for (file, data) in pointer.iter().zip(&old_pointer) {
+            server.put(file, data);
+        }
+        assert!(Repository::from_url(&url, cache.path(), false, &trust).is_err());
+        assert!(Repository::from_url(
+            &url,
+            cache.path(),
+            false,
+            &Trust::without_rollback_protection(vec![key.public_key()])
+        )
+        .is_ok());
+
+        // Snapshot signatures are checked:
+        server.put("snapshots/old.json", b"");
+        url.set_fragment(Some("snapshot=old"));
+        assert!(Repository::from_url(&url, cache.path(), false, &trust).is_err());
+        assert!(Repository::from_url(&url, cache.path(), false, &Trust::none()).is_ok());
+
+        url.set_fragment(Some("other"));
+        assert!(Repository::from_url(&url, cache.path(), false, &Trust::none()).is_err());
+    }
+
+    #[test]
+    fn local_snapshot_pointer_is_verified() {
+        let directory = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+        let key = "01".repeat(32).parse::<SigningKey>().unwrap();
+        let signing = Some((&key, std::time::Duration::from_secs(3600)));
+        let trust = Trust::new(vec![key.public_key()], state.path());
+
+        let repo =
+            Repository::from_local_directory(directory.path(), true, &Trust::none()).unwrap();
+        repo.create_snapshot(Some("one"), signing).unwrap();
+        repo.create_snapshot(Some("two"), signing).unwrap();
+        assert_eq!(
+            Repository::from_local_snapshot(directory.path(), CURRENT_SNAPSHOT, &trust)
+                .unwrap()
+                .snapshot(),
+            Some("two")
+        );
+
+        // Unsigned pointers are refused:
+        repo.set_current_snapshot("one", None).unwrap();
+        assert!(
+            Repository::from_local_snapshot(directory.path(), CURRENT_SNAPSHOT, &trust).is_err()
+        );
+        repo.set_current_snapshot("one", signing).unwrap();
+        assert_eq!(
+            Repository::from_local_snapshot(directory.path(), CURRENT_SNAPSHOT, &trust)
+                .unwrap()
+                .snapshot(),
+            Some("one")
+        );
+
+        // Crash after the new pointer was written, before its signature was
+        // moved into place:
+        let snapshots = directory.path().join(SNAPSHOT_DIRECTORY);
+        let signature_file = snapshots.join(CURRENT_SNAPSHOT_SIGNATURE);
+        let old_signature = std::fs::read(&signature_file).unwrap();
+        repo.set_current_snapshot("two", signing).unwrap();
+        let pending_file = snapshots.join(PENDING_CURRENT_SNAPSHOT_SIGNATURE);
+        std::fs::rename(&signature_file, &pending_file).unwrap();
+        std::fs::write(&signature_file, old_signature).unwrap();
+        assert_eq!(
+            Repository::from_local_snapshot(directory.path(), CURRENT_SNAPSHOT, &trust)
+                .unwrap()
+                .snapshot(),
+            Some("two")
+        );
+
+        // The next change continues after the serial of the pending
+        // signature:
+        repo.set_current_snapshot("one", signing).unwrap();
+        assert!(!pending_file.exists());
+        assert_eq!(RepositoryMetadata::load(&signature_file).unwrap().serial, 5);
+        assert_eq!(
+            Repository::from_local_snapshot(directory.path(), CURRENT_SNAPSHOT, &trust)
+                .unwrap()
+                .snapshot(),
+            Some("one")
+        );
+    }
+
+    #[test]
+    fn backfill_files() {
+        let directory = tempfile::tempdir().unwrap();
+        let old = entry("foo", None, "1.0");
+        let mut writer = gng_packet_io::PacketWriter::new(
+            directory.path(),
+            &old.packet_data,
+            gng_packet_io::PacketPolicy::MustHaveContents,
+            gng_packet_io::ExistingPacketPolicy::Overwrite,
+        )
+        .unwrap();
+        writer
+            .add_directory(std::path::Path::new("bin"), 0o755, 0, 0)
+            .unwrap();
+        writer
+            .add_buffer(std::path::Path::new("bin/foo"), b"foo", 0o755, 0, 0)
+            .unwrap();
+        let packet_file = writer.finish().unwrap().unwrap();
+        assert_eq!(packet_file, directory.path().join(&old.file_path));
+
+        // Entries written before file lists were recorded have none:
+        std::fs::write(
+            directory.path().join("repository.json"),
+            serde_json::to_string(&old).unwrap() + "\n",
+        )
+        .unwrap();
+        let mut repo =
+            Repository::from_local_directory(directory.path(), false, &Trust::none()).unwrap();
+        let version = Version::try_from("1.0").unwrap();
+        assert_eq!(repo.files(&name("foo"), &None, &version).unwrap(), None);
+        let missing = repo
+            .without_files()
+            .unwrap()
+            .map(|(p, f)| (p.clone(), f.to_path_buf()))
+            .collect::<Vec<_>>();
+        assert_eq!(missing.len(), 1);
+
+        let mut update = repo.create_transaction();
+        for (p, f) in &missing {
+            update.set_files(
+                p.name.clone(),
+                p.facet_name(),
+                p.version.clone(),
+                repo.read_installed_files(f).unwrap(),
+            );
+        }
+        repo.apply(update).unwrap();
+
+        assert_eq!(
+            repo.files(&name("foo"), &None, &version).unwrap(),
+            Some(&[std::path::PathBuf::from("/usr/bin/foo")][..])
+        );
+        assert_eq!(repo.without_files().unwrap().count(), 0);
+    }
+
+    #[test]
+    fn find_files() {
+        let with_files = |mut entry: Entry, files: &[&str]| {
+            entry.files = Some(files.iter().map(std::path::PathBuf::from).collect());
+            entry
+        };
+        let repo = repository(vec![
+            with_files(
+                entry("foo", None, "1.0"),
+                &["/usr/bin/foo", "/usr/share/foo/data"],
+            ),
+            with_files(
+                entry("foo", Some("dev"), "1.0"),
+                &["/usr/include/foo.h", "/usr/lib/libfoo.so"],
+            ),
+            with_files(entry("ssl", None, "3.0"), &["/usr/lib/libssl.so.3"]),
+        ]);
+        let found = |pattern: &str| {
+            repo.find_file(pattern)
+                .unwrap()
+                .iter()
+                .map(|(p, _, f)| {
+                    format!(
+                        "{}:{}",
+                        p.name.combine(&p.facet_name()),
+                        f.to_string_lossy()
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(found("/usr/bin/foo"), vec!["foo:/usr/bin/foo"]);
+        assert_eq!(found("libssl.so.3"), vec!["ssl:/usr/lib/libssl.so.3"]);
+        assert_eq!(
+            found("*.so*"),
+            vec!["foo-dev:/usr/lib/libfoo.so", "ssl:/usr/lib/libssl.so.3"]
+        );
+        assert_eq!(
+            found("/usr/*/foo*"),
+            vec!["foo:/usr/bin/foo", "foo-dev:/usr/include/foo.h"]
+        );
+        assert_eq!(found("/usr/*"), Vec::<String>::new());
+        assert_eq!(found("/usr/bin/bar"), Vec::<String>::new());
+        assert!(repo.find_file("[").is_err());
+
+        assert_eq!(
+            repo.files(&name("ssl"), &None, &Version::try_from("3.0").unwrap())
+                .unwrap(),
+            Some(&[std::path::PathBuf::from("/usr/lib/libssl.so.3")][..])
+        );
+        assert_eq!(
+            repo.files(&name("ssl"), &None, &Version::try_from("1.0").unwrap())
+                .unwrap(),
+            None
+        );
+
+        let data = repo.to_json_lines().unwrap();
+        assert!(String::from_utf8(data)
+            .unwrap()
+            .contains("\"/usr/lib/libssl.so.3\""));
+    }
+
+    #[test]
+    fn binary_format() {
+        let directory = tempfile::tempdir().unwrap();
+        let entries_file = directory.path().join("repository.json");
+        let mut repo =
+            Repository::from_local_directory(directory.path(), true, &Trust::none()).unwrap();
+        assert_eq!(repo.format(), Format::JsonLines);
+
+        let mut update = repo.create_transaction();
+        update.set_retention(Retention::Newest(2));
+        update.to_apply = vec![
+            entry("foo", None, "1.0"),
+            entry("foo", Some("dev"), "1.0"),
+            entry("bar", None, "2.0"),
+        ];
+        update.pin(name("bar"), None, Version::try_from("2.0").unwrap());
+        update.set_files(
+            name("foo"),
+            None,
+            Version::try_from("1.0").unwrap(),
+            vec![std::path::PathBuf::from("/usr/bin/foo")],
+        );
+        repo.apply(update).unwrap();
+        let json = repo.to_json_lines().unwrap();
+
+        let mut update = repo.create_transaction();
+        update.set_format(Format::Binary);
+        repo.apply(update).unwrap();
+        repo.save_local_directory().unwrap();
+        assert!(crate::index::is_index(
+            &std::fs::read(&entries_file).unwrap()
+        ));
+
+        let mut repo =
+            Repository::from_local_directory(directory.path(), false, &Trust::none()).unwrap();
+        assert_eq!(repo.format(), Format::Binary);
+        assert_eq!(repo.retention(), Retention::Newest(2));
+
+        // Queries are served from the index without decoding all packets:
+        assert!(repo
+            .is_pinned(&name("bar"), &None, &Version::try_from("2.0").unwrap())
+            .unwrap());
+        assert_eq!(
+            queried_version(&repo, "foo", Some("dev"), ""),
+            Some("1.0".into())
+        );
+        assert_eq!(queried_version(&repo, "baz", None, ""), None);
+        assert_eq!(
+            repo.find_file("/usr/bin/*")
+                .unwrap()
+                .into_iter()
+                .map(|(p, f, _)| (p.name, f))
+                .collect::<Vec<_>>(),
+            vec![(name("foo"), std::path::PathBuf::from("foo-1.0.gng"))]
+        );
+        assert!(repo
+            .packet_file(std::path::Path::new("baz-1.0.gng"))
+            .is_err());
+        assert!(repo.packets.get().is_none());
+
+        // The export is independent of the format:
+        assert_eq!(
+            String::from_utf8(repo.to_json_lines().unwrap()).unwrap(),
+            String::from_utf8(json)
+                .unwrap()
+                .replacen("\"generation\":0", "\"generation\":1", 1)
+        );
+
+        // Changes keep the format:
+        let mut update = repo.create_transaction();
+        update.remove(name("foo"));
+        repo.apply(update).unwrap();
+        repo.save_local_directory().unwrap();
+        let mut repo =
+            Repository::from_local_directory(directory.path(), false, &Trust::none()).unwrap();
+        assert_eq!(repo.format(), Format::Binary);
+        assert_eq!(repo.entries().unwrap().count(), 1);
+
+        let mut update = repo.create_transaction();
+        update.set_format(Format::JsonLines);
+        repo.apply(update).unwrap();
+        repo.save_local_directory().unwrap();
+        assert!(std::fs::read_to_string(&entries_file)
+            .unwrap()
+            .starts_with("{\"generation\":3,\"retention\":\"2\"}\n"));
+    }
+
+    #[test]
+    fn binary_format_with_broken_packet() {
+        let directory = tempfile::tempdir().unwrap();
+        let entries_file = directory.path().join("repository.json");
+        let mut repo =
+            Repository::from_local_directory(directory.path(), true, &Trust::none()).unwrap();
+        let mut update = repo.create_transaction();
+        update.set_format(Format::Binary);
+        update.to_apply = vec![entry("bar", None, "2.0"), entry("foo", None, "1.0")];
+        repo.apply(update).unwrap();
+        repo.save_local_directory().unwrap();
+
+        // Break the last packet ("foo") by overwriting its zstd magic bytes:
+        let mut data = std::fs::read(&entries_file).unwrap();
+        let start = data
+            .windows(4)
+            .rposition(|w| w == [0x28, 0xb5, 0x2f, 0xfd])
+            .unwrap();
+        data[start..start + 4].copy_from_slice(b"junk");
+        std::fs::write(&entries_file, &data).unwrap();
+
+        let mut repo =
+            Repository::from_local_directory(directory.path(), false, &Trust::none()).unwrap();
+        assert_eq!(queried_version(&repo, "bar", None, ""), Some("2.0".into()));
+
+        // The broken packet is an error, not a missing packet:
+        assert!(repo.query(&name("foo"), &None).is_err());
+        assert!(repo.entries().is_err());
+        assert!(repo.newest().is_err());
+        assert!(repo.diff(&repo).is_err());
+
+        // Nothing gets lost when changing the repository:
+        assert!(repo.to_json_lines().is_err());
+        assert_eq!(repo.verify_files().len(), 1);
+        let update = repo.create_transaction();
+        assert!(repo.apply(update).is_err());
+        assert!(repo.save_local_directory().is_err());
+        assert_eq!(std::fs::read(&entries_file).unwrap(), data);
+
+        std::fs::write(&entries_file, b"GNGIDX01garbage").unwrap();
+        assert!(Repository::from_local_directory(directory.path(), false, &Trust::none()).is_err());
     }
 }
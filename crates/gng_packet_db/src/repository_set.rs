@@ -0,0 +1,360 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Combine several repositories with priorities and pinning rules
+
+use crate::{Repository, VersionRequirement};
+
+use gng_core::Name;
+use gng_packet_io::BinaryPacketDefinition;
+
+use eyre::eyre;
+
+// ----------------------------------------------------------------------
+// - Rule:
+// ----------------------------------------------------------------------
+
+/// A rule overriding the priorities of a `RepositorySet` for one packet
+///
+/// Rules apply to a packet and all its facets.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Rule {
+    /// Take the `packet` from the `repository` whenever it has a matching
+    /// version
+    Prefer {
+        /// The name of the packet
+        packet: Name,
+        /// The name of the repository
+        repository: String,
+    },
+    /// Never take the `packet` from the `repository`
+    Exclude {
+        /// The name of the packet
+        packet: Name,
+        /// The name of the repository
+        repository: String,
+    },
+}
+
+impl Rule {
+    const fn packet(&self) -> &Name {
+        match self {
+            Self::Prefer { packet, .. } | Self::Exclude { packet, .. } => packet,
+        }
+    }
+
+    fn repository(&self) -> &str {
+        match self {
+            Self::Prefer { repository, .. } | Self::Exclude { repository, .. } => repository,
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Member:
+// ----------------------------------------------------------------------
+
+/// A `Repository` in a `RepositorySet`
+pub struct Member {
+    name: String,
+    priority: i32,
+    repository: Repository,
+}
+
+impl Member {
+    /// The name of the repository in the `RepositorySet`
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The priority of the repository: Higher priorities win
+    #[must_use]
+    pub const fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    /// The repository itself
+    #[must_use]
+    pub const fn repository(&self) -> &Repository {
+        &self.repository
+    }
+}
+
+// ----------------------------------------------------------------------
+// - RepositorySet:
+// ----------------------------------------------------------------------
+
+/// Several named repositories with priorities and pinning rules
+///
+/// Packets are taken from the repository with the highest priority that has
+/// a version matching the requirements, even if a repository with a lower
+/// priority has a newer version. Repositories with the same priority are
+/// searched in the order they were added. `Rule`s override this for single
+/// packets.
+#[derive(Default)]
+pub struct RepositorySet {
+    members: Vec<Member>,
+    rules: Vec<Rule>,
+}
+
+impl RepositorySet {
+    /// Constructor
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a `repository` called `name` with a `priority`
+    ///
+    /// # Errors
+    ///
+    /// Fail if there already is a repository called `name`.
+    pub fn add(&mut self, name: &str, priority: i32, repository: Repository) -> eyre::Result<()> {
+        if self.get(name).is_some() {
+            return Err(eyre!("Repository \"{}\" was already added.", name));
+        }
+
+        // Keep members sorted by priority, in the order they were added:
+        let position = self
+            .members
+            .iter()
+            .position(|m| m.priority < priority)
+            .unwrap_or(self.members.len());
+        self.members.insert(
+            position,
+            Member {
+                name: name.to_string(),
+                priority,
+                repository,
+            },
+        );
+        Ok(())
+    }
+
+    /// Add a `rule`
+    ///
+    /// # Errors
+    ///
+    /// Fail if the repository of the `rule` is not part of the set.
+    pub fn add_rule(&mut self, rule: Rule) -> eyre::Result<()> {
+        if self.get(rule.repository()).is_none() {
+            return Err(eyre!(
+                "Rule for packet \"{}\" refers to unknown repository \"{}\".",
+                rule.packet(),
+                rule.repository()
+            ));
+        }
+        self.rules.push(rule);
+        Ok(())
+    }
+
+    /// The repository called `name`
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Member> {
+        self.members.iter().find(|m| m.name == name)
+    }
+
+    /// All repositories, in the order they are searched when no rules apply
+    pub fn members(&self) -> impl Iterator<Item = &Member> {
+        self.members.iter()
+    }
+
+    /// The repositories to take `packet` from, in the order to search them
+    fn candidates(&self, packet: &Name) -> Vec<&Member> {
+        let has_rule = |m: &Member, prefer: bool| {
+            self.rules.iter().any(|r| {
+                r.packet() == packet
+                    && r.repository() == m.name
+                    && matches!(r, Rule::Prefer { .. }) == prefer
+            })
+        };
+
+        let preferred = self.members.iter().filter(|m| has_rule(m, true));
+        let others = self
+            .members
+            .iter()
+            .filter(|m| !has_rule(m, true) && !has_rule(m, false));
+        preferred.chain(others).collect()
+    }
+
+    /// Query the best version of a packet/facet name combination that
+    /// fulfills `requirement`
+    ///
+    /// Returns the packet definition, the packet file path relative to the
+    /// repository and the repository the packet was found in.
+    ///
+    /// # Errors
+    ///
+    /// Fail if a packet of a binary index can not be decoded.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn query_version(
+        &self,
+        packet: &Name,
+        facet: &Option<Name>,
+        requirement: &VersionRequirement,
+    ) -> eyre::Result<Option<(BinaryPacketDefinition, std::path::PathBuf, &Member)>> {
+        for m in self.candidates(packet) {
+            if let Some((d, f)) = m.repository.query_version(packet, facet, requirement)? {
+                return Ok(Some((d, f, m)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Query the best version of a packet/facet name combination
+    ///
+    /// Returns the packet definition, the packet file path relative to the
+    /// repository and the repository the packet was found in.
+    ///
+    /// # Errors
+    ///
+    /// Fail if a packet of a binary index can not be decoded.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn query(
+        &self,
+        packet: &Name,
+        facet: &Option<Name>,
+    ) -> eyre::Result<Option<(BinaryPacketDefinition, std::path::PathBuf, &Member)>> {
+        self.query_version(packet, facet, &VersionRequirement::any())
+    }
+
+    /// Query all versions of a packet/facet name combination that fulfill
+    /// `requirement`, best first
+    ///
+    /// Returns the packet definitions, the packet file paths relative to
+    /// their repository and the repositories the packets were found in.
+    ///
+    /// # Errors
+    ///
+    /// Fail if a packet of a binary index can not be decoded.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn query_versions(
+        &self,
+        packet: &Name,
+        facet: &Option<Name>,
+        requirement: &VersionRequirement,
+    ) -> eyre::Result<Vec<(BinaryPacketDefinition, std::path::PathBuf, &Member)>> {
+        let mut result = Vec::new();
+        for m in self.candidates(packet) {
+            result.extend(
+                m.repository
+                    .query_versions(packet, facet, requirement)?
+                    .into_iter()
+                    .map(|(d, f)| (d, f, m)),
+            );
+        }
+        Ok(result)
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_util::{name, packet};
+
+    use gng_packet_io::BinaryPacketDefinition;
+
+    fn repository(name: &str, packets: Vec<BinaryPacketDefinition>) -> Repository {
+        Repository::from_definitions(
+            &url::Url::parse(&format!("file:///{}/repository.json", name)).unwrap(),
+            packets,
+        )
+    }
+
+    fn found(set: &RepositorySet, request: &str) -> Option<String> {
+        let request = request.parse::<crate::resolver::Request>().unwrap();
+        set.query_version(&request.packet, &request.facet, &request.requirement)
+            .unwrap()
+            .map(|(d, _, m)| format!("{}-{}@{}", d.name, d.version, m.name()))
+    }
+
+    fn set() -> RepositorySet {
+        let mut set = RepositorySet::new();
+        set.add(
+            "base",
+            0,
+            repository(
+                "base",
+                vec![packet("foo", "1.0", &[]), packet("bar", "1.0", &[])],
+            ),
+        )
+        .unwrap();
+        set.add(
+            "testing",
+            10,
+            repository(
+                "testing",
+                vec![packet("foo", "2.0", &[]), packet("bar", "2.0", &[])],
+            ),
+        )
+        .unwrap();
+        set.add(
+            "local",
+            0,
+            repository(
+                "local",
+                vec![packet("foo", "0.9", &[]), packet("baz", "1.0", &[])],
+            ),
+        )
+        .unwrap();
+        set
+    }
+
+    #[test]
+    fn priorities() {
+        let set = set();
+        assert_eq!(
+            set.members().map(Member::name).collect::<Vec<_>>(),
+            vec!["testing", "base", "local"]
+        );
+
+        assert_eq!(found(&set, "foo"), Some("foo-2.0@testing".to_string()));
+        assert_eq!(found(&set, "foo<2.0"), Some("foo-1.0@base".to_string()));
+        assert_eq!(found(&set, "foo<1.0"), Some("foo-0.9@local".to_string()));
+        assert_eq!(found(&set, "baz"), Some("baz-1.0@local".to_string()));
+        assert_eq!(found(&set, "missing"), None);
+
+        assert_eq!(
+            set.query_versions(&name("foo"), &None, &VersionRequirement::any())
+                .unwrap()
+                .iter()
+                .map(|(d, _, m)| format!("{}@{}", d.version, m.name()))
+                .collect::<Vec<_>>(),
+            vec!["2.0@testing", "1.0@base", "0.9@local"]
+        );
+    }
+
+    #[test]
+    fn rules() {
+        let mut set = set();
+        set.add_rule(Rule::Prefer {
+            packet: name("foo"),
+            repository: "local".to_string(),
+        })
+        .unwrap();
+        set.add_rule(Rule::Exclude {
+            packet: name("bar"),
+            repository: "testing".to_string(),
+        })
+        .unwrap();
+
+        assert_eq!(found(&set, "foo"), Some("foo-0.9@local".to_string()));
+        assert_eq!(found(&set, "foo>=1.0"), Some("foo-2.0@testing".to_string()));
+        assert_eq!(found(&set, "bar"), Some("bar-1.0@base".to_string()));
+        assert_eq!(found(&set, "bar>=2.0"), None);
+
+        assert!(set
+            .add_rule(Rule::Exclude {
+                packet: name("bar"),
+                repository: "unknown".to_string(),
+            })
+            .is_err());
+        assert!(set.add("local", 5, repository("other", vec![])).is_err());
+    }
+}
@@ -0,0 +1,193 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Requirements on `Version`s
+
+use gng_core::Version;
+
+use eyre::{eyre, WrapErr};
+
+// ----------------------------------------------------------------------
+// - Comparison:
+// ----------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Comparison {
+    Equal,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+impl Comparison {
+    const fn symbol(self) -> &'static str {
+        match self {
+            Self::Equal => "=",
+            Self::Greater => ">",
+            Self::GreaterOrEqual => ">=",
+            Self::Less => "<",
+            Self::LessOrEqual => "<=",
+        }
+    }
+
+    fn matches(self, version: &Version, other: &Version) -> bool {
+        let ordering = version.cmp(other);
+        match self {
+            Self::Equal => ordering.is_eq(),
+            Self::Greater => ordering.is_gt(),
+            Self::GreaterOrEqual => ordering.is_ge(),
+            Self::Less => ordering.is_lt(),
+            Self::LessOrEqual => ordering.is_le(),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// - VersionRequirement:
+// ----------------------------------------------------------------------
+
+/// A requirement a `Version` must fulfill, e.g. ">=1.0, <2.0"
+///
+/// An empty requirement (or "*") matches any `Version`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct VersionRequirement {
+    comparisons: Vec<(Comparison, Version)>,
+}
+
+impl VersionRequirement {
+    /// A requirement matching any `Version`
+    #[must_use]
+    pub fn any() -> Self {
+        Self::default()
+    }
+
+    /// A requirement matching exactly `version`
+    #[must_use]
+    pub fn exact(version: &Version) -> Self {
+        Self {
+            comparisons: vec![(Comparison::Equal, version.clone())],
+        }
+    }
+
+    /// A requirement matching all `Version`s that fulfill both this
+    /// requirement and `other`
+    #[must_use]
+    pub fn and(&self, other: &Self) -> Self {
+        let mut comparisons = self.comparisons.clone();
+        for c in &other.comparisons {
+            if !comparisons.contains(c) {
+                comparisons.push(c.clone());
+            }
+        }
+        Self { comparisons }
+    }
+
+    /// Does `version` fulfill this requirement?
+    #[must_use]
+    pub fn matches(&self, version: &Version) -> bool {
+        self.comparisons.iter().all(|(c, v)| c.matches(version, v))
+    }
+}
+
+impl std::str::FromStr for VersionRequirement {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim() == "*" {
+            return Ok(Self::any());
+        }
+
+        let comparisons = s
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                let (comparison, version) = [
+                    (">=", Comparison::GreaterOrEqual),
+                    ("<=", Comparison::LessOrEqual),
+                    (">", Comparison::Greater),
+                    ("<", Comparison::Less),
+                    ("=", Comparison::Equal),
+                ]
+                .iter()
+                .find_map(|(prefix, c)| p.strip_prefix(prefix).map(|v| (*c, v)))
+                .unwrap_or((Comparison::Equal, p));
+
+                let version = Version::try_from(version.trim())
+                    .wrap_err(eyre!("Invalid version in requirement \"{}\".", p))?;
+                Ok((comparison, version))
+            })
+            .collect::<eyre::Result<Vec<_>>>()?;
+
+        Ok(Self { comparisons })
+    }
+}
+
+impl std::fmt::Display for VersionRequirement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.comparisons.is_empty() {
+            return write!(f, "*");
+        }
+        let parts = self
+            .comparisons
+            .iter()
+            .map(|(c, v)| format!("{}{}", c.symbol(), v))
+            .collect::<Vec<_>>();
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(input: &str) -> Version {
+        Version::try_from(input).unwrap()
+    }
+
+    #[test]
+    fn requirement_matches() {
+        let requirement = ">=1.0, <1.5".parse::<VersionRequirement>().unwrap();
+        assert!(requirement.matches(&version("1.0")));
+        assert!(requirement.matches(&version("1.2-3")));
+        assert!(!requirement.matches(&version("1.5")));
+        assert!(!requirement.matches(&version("0.9")));
+
+        let requirement = "1.2".parse::<VersionRequirement>().unwrap();
+        assert!(requirement.matches(&version("1.2")));
+        assert!(!requirement.matches(&version("1.2-1")));
+        assert_eq!(requirement, VersionRequirement::exact(&version("1.2")));
+
+        let requirement = ">=1.0".parse::<VersionRequirement>().unwrap();
+        let combined = requirement.and(&"<1.5, >=1.0".parse().unwrap());
+        assert_eq!(combined.to_string(), ">=1.0, <1.5");
+        assert!(combined.matches(&version("1.2")));
+        assert!(!combined.matches(&version("1.5")));
+        assert_eq!(VersionRequirement::any().and(&requirement), requirement);
+
+        assert!(VersionRequirement::any().matches(&version("1:0.1")));
+        assert_eq!(
+            "*".parse::<VersionRequirement>().unwrap(),
+            VersionRequirement::any()
+        );
+    }
+
+    #[test]
+    fn requirement_parse() {
+        assert_eq!(
+            ">=1.0,<2.0"
+                .parse::<VersionRequirement>()
+                .unwrap()
+                .to_string(),
+            ">=1.0, <2.0"
+        );
+        assert_eq!(VersionRequirement::any().to_string(), "*");
+        assert!(">=".parse::<VersionRequirement>().is_err());
+        assert!("~1.0".parse::<VersionRequirement>().is_err());
+    }
+}
@@ -0,0 +1,890 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Resolve the dependencies of a set of packets
+
+use crate::{RepositorySet, VersionRequirement};
+
+use gng_core::Name;
+use gng_packet_io::{BinaryFacet, BinaryPacketDefinition};
+
+use eyre::{eyre, WrapErr};
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// The packet and facet name identifying a packet to install
+type Key = (Name, Option<Name>);
+
+fn key_name(key: &Key) -> String {
+    key.1
+        .as_ref()
+        .map_or_else(|| key.0.to_string(), |f| format!("{}:{}", key.0, f))
+}
+
+// ----------------------------------------------------------------------
+// - Request:
+// ----------------------------------------------------------------------
+
+/// A request to install a packet, e.g. "foo", "foo:dev" or "foo>=1.0, <2.0"
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Request {
+    /// The name of the `packet`
+    pub packet: Name,
+    /// The name of the `facet` (if any)
+    pub facet: Option<Name>,
+    /// The version `requirement`
+    pub requirement: VersionRequirement,
+}
+
+impl Request {
+    /// Request the newest version of `packet` and `facet`
+    #[must_use]
+    pub fn new(packet: Name, facet: Option<Name>) -> Self {
+        Self {
+            packet,
+            facet,
+            requirement: VersionRequirement::any(),
+        }
+    }
+}
+
+impl std::str::FromStr for Request {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (names, requirement) = s
+            .find(|c| c == '<' || c == '>' || c == '=')
+            .map_or((s, ""), |i| s.split_at(i));
+        let (packet, facet) = names
+            .split_once(':')
+            .map_or((names, None), |(p, f)| (p, Some(f)));
+
+        Ok(Self {
+            packet: Name::try_from(packet.trim())
+                .wrap_err(eyre!("Invalid packet name in \"{}\".", s))?,
+            facet: facet
+                .map(|f| Name::try_from(f.trim()))
+                .transpose()
+                .wrap_err(eyre!("Invalid facet name in \"{}\".", s))?,
+            requirement: requirement.parse()?,
+        })
+    }
+}
+
+impl std::fmt::Display for Request {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = key_name(&(self.packet.clone(), self.facet.clone()));
+        if self.requirement == VersionRequirement::any() {
+            write!(f, "{}", name)
+        } else {
+            write!(f, "{}{}", name, self.requirement)
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Reason:
+// ----------------------------------------------------------------------
+
+/// Why a packet is part of a `Resolution`
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Reason {
+    /// The packet was requested explicitly
+    Requested,
+    /// The packet is a dependency of another packet
+    Dependency {
+        /// The packet depending on this one
+        of: String,
+    },
+    /// The packet is the un-faceted packet of a facet packet
+    FacetBase {
+        /// The facet packet
+        of: String,
+    },
+    /// The packet defines the facet a facet packet belongs to
+    FacetDefinition {
+        /// The facet packet
+        of: String,
+    },
+    /// The packet defines a facet that another facet definition extends
+    FacetExtension {
+        /// The packet defining the extending facet
+        of: String,
+    },
+    /// The packet provides content for a facet that gets installed
+    ImpliedFacet {
+        /// The facet that gets installed
+        facet: Name,
+    },
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Requested => write!(f, "requested"),
+            Self::Dependency { of } => write!(f, "dependency of \"{}\"", of),
+            Self::FacetBase { of } => write!(f, "base packet of \"{}\"", of),
+            Self::FacetDefinition { of } => write!(f, "defines the facet of \"{}\"", of),
+            Self::FacetExtension { of } => write!(f, "facet extended by \"{}\"", of),
+            Self::ImpliedFacet { facet } => write!(f, "facet \"{}\" is installed", facet),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Resolution:
+// ----------------------------------------------------------------------
+
+/// A packet selected by the resolver
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ResolvedPacket {
+    /// The packet `definition`
+    pub definition: BinaryPacketDefinition,
+    /// The packet file, relative to the `repository`
+    pub file: std::path::PathBuf,
+    /// The URL of the repository the packet was taken from
+    pub repository: url::Url,
+    /// The name of the repository in the `RepositorySet`
+    pub repository_name: String,
+    /// The reasons why the packet got selected
+    pub reasons: Vec<Reason>,
+}
+
+impl ResolvedPacket {
+    /// The packet name including the facet name (if any), e.g. "foo:dev"
+    #[must_use]
+    pub fn full_name(&self) -> String {
+        key_name(&(self.definition.name.clone(), self.definition.facet_name()))
+    }
+}
+
+/// The result of resolving dependencies
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Resolution {
+    /// The packets to install, dependencies before their dependents
+    pub packets: Vec<ResolvedPacket>,
+}
+
+impl std::fmt::Display for Resolution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for p in &self.packets {
+            let reasons = p
+                .reasons
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            writeln!(
+                f,
+                "{} {} ({})",
+                p.full_name(),
+                p.definition.version,
+                reasons.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Resolver:
+// ----------------------------------------------------------------------
+
+struct Node {
+    packet: ResolvedPacket,
+    dependencies: std::collections::BTreeSet<Key>,
+}
+
+struct Resolver<'a> {
+    repositories: &'a RepositorySet,
+    nodes: std::collections::BTreeMap<Key, Node>,
+    queue: std::collections::VecDeque<(Key, Reason, VersionRequirement)>,
+    /// The combined requirements of all requests for each un-faceted packet
+    requested: std::collections::BTreeMap<Name, VersionRequirement>,
+    /// The packets that could not be resolved
+    failed: std::collections::BTreeSet<Key>,
+    errors: Vec<String>,
+}
+
+impl Resolver<'_> {
+    fn query(
+        &self,
+        key: &Key,
+        requirement: &VersionRequirement,
+    ) -> eyre::Result<Option<(BinaryPacketDefinition, std::path::PathBuf, url::Url, String)>> {
+        Ok(self
+            .repositories
+            .query_version(&key.0, &key.1, requirement)?
+            .map(|(d, f, m)| (d, f, m.repository().url().clone(), m.name().to_string())))
+    }
+
+    fn is_known(&self, key: &Key) -> bool {
+        matches!(self.repositories.query(&key.0, &key.1), Ok(Some(_)))
+    }
+
+    /// Find the packet for `key`, or describe why there is none
+    fn find(
+        &self,
+        key: &Key,
+        reason: &Reason,
+        requirement: &VersionRequirement,
+    ) -> Result<(BinaryPacketDefinition, std::path::PathBuf, url::Url, String), String> {
+        match self.query(key, requirement) {
+            Ok(Some(found)) => Ok(found),
+            Ok(None) if self.is_known(key) => Err(format!(
+                "Version conflict: \"{}\" ({}) needs version \"{}\", which is not available.",
+                key_name(key),
+                reason,
+                requirement
+            )),
+            Ok(None) => Err(format!(
+                "\"{}\" ({}) is not in any repository.",
+                key_name(key),
+                reason
+            )),
+            Err(e) => Err(format!(
+                "\"{}\" ({}) can not be looked up: {:#}",
+                key_name(key),
+                reason,
+                e
+            )),
+        }
+    }
+
+    fn enqueue(&mut self, key: Key, reason: Reason) {
+        self.queue
+            .push_back((key, reason, VersionRequirement::any()));
+    }
+
+    fn process(&mut self, key: Key, reason: Reason, requirement: VersionRequirement) {
+        if self.failed.contains(&key) {
+            // The problem was reported already
+            return;
+        }
+        if let Some(node) = self.nodes.get_mut(&key) {
+            if !requirement.matches(&node.packet.definition.version) {
+                self.errors.push(format!(
+                    "Version conflict: \"{}\" ({}) needs version \"{}\", but \"{}\" was selected.",
+                    key_name(&key),
+                    reason,
+                    requirement,
+                    node.packet.definition.version
+                ));
+            }
+            if !node.packet.reasons.contains(&reason) {
+                node.packet.reasons.push(reason);
+            }
+            return;
+        }
+
+        let base_key = (key.0.clone(), None);
+        let requirement = match (&key.1, self.nodes.get(&base_key)) {
+            // Facet packets must match the version of their base packet:
+            (Some(_), Some(base)) => VersionRequirement::exact(&base.packet.definition.version),
+            (Some(_), None) if self.failed.contains(&base_key) => {
+                self.errors.push(format!(
+                    "\"{}\" ({}) can not be installed: Its base packet \"{}\" is not available.",
+                    key_name(&key),
+                    reason,
+                    key_name(&base_key)
+                ));
+                self.failed.insert(key);
+                return;
+            }
+            (Some(_), None) => {
+                // Resolve the base packet first:
+                self.queue.push_front((key.clone(), reason, requirement));
+                self.queue.push_front((
+                    base_key,
+                    Reason::FacetBase { of: key_name(&key) },
+                    VersionRequirement::any(),
+                ));
+                return;
+            }
+            // Honor all requests for a packet, no matter which comes first:
+            (None, _) => match self.requested.get(&key.0) {
+                Some(requested) => requested.and(&requirement),
+                None => requirement,
+            },
+        };
+
+        let (definition, file, repository, repository_name) =
+            match self.find(&key, &reason, &requirement) {
+                Ok(found) => found,
+                Err(message) => {
+                    self.errors.push(message);
+                    self.failed.insert(key);
+                    return;
+                }
+            };
+
+        let name = key_name(&key);
+        let mut dependencies = std::collections::BTreeSet::new();
+        for d in &definition.dependencies {
+            dependencies.insert((d.clone(), None));
+            self.enqueue((d.clone(), None), Reason::Dependency { of: name.clone() });
+        }
+        if let Some(facet) = &key.1 {
+            dependencies.insert((key.0.clone(), None));
+            dependencies.insert((facet.clone(), None));
+            self.enqueue(
+                (facet.clone(), None),
+                Reason::FacetDefinition { of: name.clone() },
+            );
+        }
+        if let BinaryFacet::Definition(facet_definition) = &definition.facet {
+            if let Some(extends) = &facet_definition.extends {
+                dependencies.insert((extends.clone(), None));
+                self.enqueue((extends.clone(), None), Reason::FacetExtension { of: name });
+            }
+        }
+
+        self.nodes.insert(
+            key,
+            Node {
+                packet: ResolvedPacket {
+                    definition,
+                    file,
+                    repository,
+                    repository_name,
+                    reasons: vec![reason],
+                },
+                dependencies,
+            },
+        );
+    }
+
+    /// Queue the facet packets of all base packets for all facets that get installed
+    fn enqueue_implied_facets(&mut self) {
+        let facets = self
+            .nodes
+            .iter()
+            .filter(|(_, n)| matches!(n.packet.definition.facet, BinaryFacet::Definition(_)))
+            .map(|(k, _)| k.0.clone())
+            .collect::<Vec<_>>();
+        let bases = self
+            .nodes
+            .keys()
+            .filter(|k| k.1.is_none())
+            .map(|k| k.0.clone())
+            .collect::<Vec<_>>();
+
+        for b in &bases {
+            for f in &facets {
+                let key = (b.clone(), Some(f.clone()));
+                if !self.nodes.contains_key(&key) && self.is_known(&key) {
+                    self.enqueue(key, Reason::ImpliedFacet { facet: f.clone() });
+                }
+            }
+        }
+    }
+
+    fn run(&mut self) {
+        loop {
+            while let Some((key, reason, requirement)) = self.queue.pop_front() {
+                self.process(key, reason, requirement);
+            }
+            self.enqueue_implied_facets();
+            if self.queue.is_empty() {
+                break;
+            }
+        }
+    }
+
+    /// Find a dependency cycle among `remaining` nodes
+    fn find_cycle(remaining: &std::collections::BTreeMap<&Key, Vec<&Key>>) -> String {
+        let mut path: Vec<&Key> = Vec::new();
+        let mut current = *remaining.keys().next().expect("There are remaining nodes");
+        loop {
+            if let Some(position) = path.iter().position(|k| *k == current) {
+                let mut cycle = path[position..]
+                    .iter()
+                    .map(|k| key_name(k))
+                    .collect::<Vec<_>>();
+                cycle.push(key_name(current));
+                return cycle.join(" -> ");
+            }
+            path.push(current);
+            current = remaining[current][0];
+        }
+    }
+
+    /// Sort the nodes so that dependencies come before their dependents
+    ///
+    /// Of all packets ready to be installed, the one with the smallest key
+    /// comes first.
+    fn sort(mut self) -> eyre::Result<Resolution> {
+        let dependencies = self
+            .nodes
+            .iter()
+            .map(|(k, n)| {
+                (
+                    k,
+                    n.dependencies
+                        .iter()
+                        .filter(|d| *d != k && self.nodes.contains_key(*d))
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<std::collections::BTreeMap<_, _>>();
+
+        let mut missing = std::collections::BTreeMap::new();
+        let mut dependents = std::collections::BTreeMap::<&Key, Vec<&Key>>::new();
+        let mut ready = std::collections::BTreeSet::new();
+        for (k, ds) in &dependencies {
+            missing.insert(*k, ds.len());
+            if ds.is_empty() {
+                ready.insert(*k);
+            }
+            for d in ds {
+                dependents.entry(*d).or_default().push(*k);
+            }
+        }
+
+        let mut order = Vec::new();
+        while let Some(k) = ready.iter().next().copied() {
+            ready.remove(k);
+            order.push(k.clone());
+            for d in dependents.get(k).into_iter().flatten() {
+                let count = missing.get_mut(*d).expect("All nodes are known");
+                *count -= 1;
+                if *count == 0 {
+                    ready.insert(*d);
+                }
+            }
+        }
+
+        if order.len() < dependencies.len() {
+            let remaining = dependencies
+                .iter()
+                .filter(|(k, _)| missing[**k] > 0)
+                .map(|(k, ds)| {
+                    (
+                        *k,
+                        ds.iter()
+                            .filter(|d| missing[**d] > 0)
+                            .copied()
+                            .collect::<Vec<_>>(),
+                    )
+                })
+                .collect();
+            return Err(eyre!(
+                "Dependency resolution failed:\n    Dependency cycle: {}",
+                Self::find_cycle(&remaining)
+            ));
+        }
+
+        Ok(Resolution {
+            packets: order
+                .iter()
+                .map(|k| {
+                    let mut packet = self.nodes.remove(k).expect("Key is valid").packet;
+                    packet.reasons.sort();
+                    packet
+                })
+                .collect(),
+        })
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Functions:
+// ----------------------------------------------------------------------
+
+/// Resolve the `requests` and all their dependencies using `repositories`
+///
+/// Packets are taken from the repositories as described in `RepositorySet`.
+/// Facet packets of all packets are added for all facets
+/// that get installed, always in the same version as their base packet.
+///
+/// The resulting packets are sorted so that dependencies come before their
+/// dependents, and so that the same input always produces the same output.
+///
+/// # Errors
+/// Returns an error listing all missing packets and version conflicts, or
+/// a dependency cycle.
+pub fn resolve(requests: &[Request], repositories: &RepositorySet) -> eyre::Result<Resolution> {
+    let mut resolver = Resolver {
+        repositories,
+        nodes: std::collections::BTreeMap::new(),
+        queue: requests
+            .iter()
+            .map(|r| {
+                (
+                    (r.packet.clone(), r.facet.clone()),
+                    Reason::Requested,
+                    r.requirement.clone(),
+                )
+            })
+            .collect(),
+        requested: requests.iter().filter(|r| r.facet.is_none()).fold(
+            std::collections::BTreeMap::new(),
+            |mut requested, r| {
+                let requirement = requested
+                    .entry(r.packet.clone())
+                    .or_insert_with(VersionRequirement::any);
+                *requirement = requirement.and(&r.requirement);
+                requested
+            },
+        ),
+        failed: std::collections::BTreeSet::new(),
+        errors: Vec::new(),
+    };
+    resolver.run();
+
+    if !resolver.errors.is_empty() {
+        resolver.errors.sort();
+        resolver.errors.dedup();
+        return Err(eyre!(
+            "Dependency resolution failed:\n    {}",
+            resolver.errors.join("\n    ")
+        ));
+    }
+
+    resolver.sort()
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_util::{facet, name, packet};
+
+    fn repository(url: &str, packets: Vec<BinaryPacketDefinition>) -> RepositorySet {
+        let mut set = RepositorySet::new();
+        set.add(
+            "base",
+            0,
+            crate::Repository::from_definitions(&url::Url::parse(url).unwrap(), packets),
+        )
+        .unwrap();
+        set
+    }
+
+    fn requests(input: &[&str]) -> Vec<Request> {
+        input.iter().map(|r| r.parse().unwrap()).collect()
+    }
+
+    fn resolved(resolution: &Resolution) -> Vec<String> {
+        resolution
+            .packets
+            .iter()
+            .map(|p| format!("{}-{}", p.full_name(), p.definition.version))
+            .collect()
+    }
+
+    fn error(requests: &[Request], repositories: &RepositorySet) -> String {
+        resolve(requests, repositories).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn request_parse() {
+        let request = "foo:dev>=1.0".parse::<Request>().unwrap();
+        assert_eq!(request.packet, name("foo"));
+        assert_eq!(request.facet, Some(name("dev")));
+        assert_eq!(request.requirement, ">=1.0".parse().unwrap());
+        assert_eq!(request.to_string(), "foo:dev>=1.0");
+
+        assert_eq!(
+            "foo".parse::<Request>().unwrap(),
+            Request::new(name("foo"), None)
+        );
+        assert!("Foo".parse::<Request>().is_err());
+        assert!("foo:".parse::<Request>().is_err());
+        assert!("foo>=".parse::<Request>().is_err());
+    }
+
+    #[test]
+    fn resolve_transitive() {
+        let repo = repository(
+            "file:///base/repository.json",
+            vec![
+                packet("app", "1.0", &["libfoo", "libbar"]),
+                packet("libfoo", "1.0", &["libc"]),
+                packet("libfoo", "2.0", &["libc"]),
+                packet("libbar", "1.0", &["libc"]),
+                packet("libc", "1.0", &[]),
+                packet("unrelated", "1.0", &[]),
+            ],
+        );
+
+        let resolution = resolve(&requests(&["app"]), &repo).unwrap();
+        assert_eq!(
+            resolved(&resolution),
+            vec!["libc-1.0", "libbar-1.0", "libfoo-2.0", "app-1.0"]
+        );
+        assert_eq!(
+            resolution.packets[0].reasons,
+            vec![
+                Reason::Dependency {
+                    of: "libbar".into()
+                },
+                Reason::Dependency {
+                    of: "libfoo".into()
+                }
+            ]
+        );
+        assert_eq!(
+            resolution.to_string(),
+            "libc 1.0 (dependency of \"libbar\", dependency of \"libfoo\")\n\
+             libbar 1.0 (dependency of \"app\")\n\
+             libfoo 2.0 (dependency of \"app\")\n\
+             app 1.0 (requested)\n"
+        );
+
+        // Deterministic, independent of request order:
+        assert_eq!(
+            resolve(&requests(&["libbar", "app"]), &repo)
+                .unwrap()
+                .packets
+                .iter()
+                .map(ResolvedPacket::full_name)
+                .collect::<Vec<_>>(),
+            vec!["libc", "libbar", "libfoo", "app"]
+        );
+        assert_eq!(
+            resolved(&resolve(&requests(&["app", "libfoo<2.0"]), &repo).unwrap()),
+            vec!["libc-1.0", "libbar-1.0", "libfoo-1.0", "app-1.0"]
+        );
+        assert_eq!(
+            error(&requests(&["libfoo<2.0", "libfoo>=2.0"]), &repo),
+            "Dependency resolution failed:\n    \
+             Version conflict: \"libfoo\" (requested) needs version \"<2.0, >=2.0\", which is not \
+             available."
+        );
+    }
+
+    #[test]
+    fn resolve_facets() {
+        let repo = repository(
+            "file:///base/repository.json",
+            vec![
+                facet("dev", None),
+                facet("dev_doc", Some("dev")),
+                packet("libfoo", "1.0", &[]),
+                packet("libfoo:dev", "1.0", &[]),
+                packet("libfoo:dev_doc", "1.0", &[]),
+                packet("libbar", "1.0", &["libfoo"]),
+                packet("libbar:dev", "1.0", &["libfoo"]),
+            ],
+        );
+
+        let resolution = resolve(&requests(&["libbar:dev"]), &repo).unwrap();
+        assert_eq!(
+            resolved(&resolution),
+            vec![
+                "dev-1.0",
+                "libfoo-1.0",
+                "libbar-1.0",
+                "libbar:dev-1.0",
+                "libfoo:dev-1.0"
+            ]
+        );
+        assert_eq!(
+            resolution.packets[4].reasons,
+            vec![Reason::ImpliedFacet { facet: name("dev") }]
+        );
+
+        let resolution = resolve(&requests(&["libfoo", "dev_doc"]), &repo).unwrap();
+        assert_eq!(
+            resolved(&resolution),
+            vec![
+                "dev-1.0",
+                "dev_doc-1.0",
+                "libfoo-1.0",
+                "libfoo:dev-1.0",
+                "libfoo:dev_doc-1.0"
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_facets_independent_of_request_order() {
+        let repo = repository(
+            "file:///base/repository.json",
+            vec![
+                facet("dev", None),
+                packet("libfoo", "1.0", &[]),
+                packet("libfoo:dev", "1.0", &[]),
+                packet("libfoo", "2.0", &[]),
+                packet("libfoo:dev", "2.0", &[]),
+            ],
+        );
+
+        let expected = vec!["dev-1.0", "libfoo-1.0", "libfoo:dev-1.0"];
+        assert_eq!(
+            resolved(&resolve(&requests(&["libfoo<2.0", "libfoo:dev"]), &repo).unwrap()),
+            expected
+        );
+        assert_eq!(
+            resolved(&resolve(&requests(&["libfoo:dev", "libfoo<2.0"]), &repo).unwrap()),
+            expected
+        );
+        assert_eq!(
+            resolved(
+                &resolve(
+                    &requests(&["libfoo:dev", "libfoo>=1.0", "libfoo<2.0"]),
+                    &repo
+                )
+                .unwrap()
+            ),
+            expected
+        );
+    }
+
+    #[test]
+    fn resolve_independent_of_request_order() {
+        let repo = repository(
+            "file:///base/repository.json",
+            vec![
+                packet("app", "1.0", &["libfoo"]),
+                packet("libfoo", "1.0", &[]),
+                packet("libfoo", "2.0", &[]),
+                packet("libfoo", "3.0", &[]),
+            ],
+        );
+
+        for input in [
+            ["libfoo>=1.0", "libfoo<2.0", "app"],
+            ["libfoo<2.0", "libfoo>=1.0", "app"],
+            ["app", "libfoo>=1.0", "libfoo<2.0"],
+            ["app", "libfoo<2.0", "libfoo>=1.0"],
+        ] {
+            assert_eq!(
+                resolved(&resolve(&requests(&input), &repo).unwrap()),
+                vec!["libfoo-1.0", "app-1.0"],
+                "for requests {:?}",
+                input
+            );
+        }
+
+        assert!(
+            error(&requests(&["libfoo>=2.0", "libfoo<2.0"]), &repo).contains(
+                "\"libfoo\" (requested) needs version \">=2.0, <2.0\", which is not available."
+            )
+        );
+    }
+
+    #[test]
+    fn resolve_facet_without_base() {
+        let repo = repository(
+            "file:///base/repository.json",
+            vec![
+                facet("dev", None),
+                packet("libfoo", "1.0", &[]),
+                packet("libfoo:dev", "1.0", &[]),
+                packet("orphan:dev", "1.0", &[]),
+            ],
+        );
+
+        assert_eq!(
+            error(&requests(&["nothere:dev"]), &repo),
+            "Dependency resolution failed:\n    \
+             \"nothere\" (base packet of \"nothere:dev\") is not in any repository.\n    \
+             \"nothere:dev\" (requested) can not be installed: Its base packet \"nothere\" is \
+             not available."
+        );
+        assert!(error(&requests(&["orphan:dev"]), &repo)
+            .contains("\"orphan:dev\" (requested) can not be installed"));
+
+        // The base packet has a version conflict:
+        let message = error(&requests(&["libfoo:dev", "libfoo>=2.0"]), &repo);
+        assert!(
+            message.contains("\"libfoo\" (base packet of \"libfoo:dev\") needs version \">=2.0\"")
+        );
+        assert!(message.contains("\"libfoo:dev\" (requested) can not be installed"));
+    }
+
+    #[test]
+    fn resolve_repository_priority() {
+        let mut repositories = repository(
+            "file:///base/repository.json",
+            vec![
+                packet("app", "1.0", &["libfoo", "libbar"]),
+                packet("libfoo", "2.0", &[]),
+                packet("libbar", "1.0", &[]),
+            ],
+        );
+        repositories
+            .add(
+                "local",
+                10,
+                crate::Repository::from_definitions(
+                    &url::Url::parse("file:///local/repository.json").unwrap(),
+                    vec![packet("libfoo", "1.0", &[]), packet("libbar", "2.0", &[])],
+                ),
+            )
+            .unwrap();
+
+        let resolution = resolve(&requests(&["app"]), &repositories).unwrap();
+        assert_eq!(
+            resolved(&resolution),
+            vec!["libbar-2.0", "libfoo-1.0", "app-1.0"]
+        );
+        assert_eq!(
+            resolution.packets[1].repository.as_str(),
+            "file:///local/repository.json"
+        );
+        assert_eq!(resolution.packets[1].repository_name, "local");
+        assert_eq!(
+            resolution.packets[2].repository.as_str(),
+            "file:///base/repository.json"
+        );
+        assert_eq!(resolution.packets[2].repository_name, "base");
+
+        let resolution = resolve(&requests(&["app", "libfoo>=2.0"]), &repositories).unwrap();
+        assert_eq!(
+            resolved(&resolution),
+            vec!["libbar-2.0", "libfoo-2.0", "app-1.0"]
+        );
+
+        repositories
+            .add_rule(crate::repository_set::Rule::Exclude {
+                packet: name("libbar"),
+                repository: "local".to_string(),
+            })
+            .unwrap();
+        let resolution = resolve(&requests(&["app"]), &repositories).unwrap();
+        assert_eq!(
+            resolved(&resolution),
+            vec!["libbar-1.0", "libfoo-1.0", "app-1.0"]
+        );
+        assert_eq!(resolution.packets[0].repository_name, "base");
+    }
+
+    #[test]
+    fn resolve_errors() {
+        let repo = repository(
+            "file:///base/repository.json",
+            vec![
+                packet("app", "1.0", &["libfoo", "missing"]),
+                packet("libfoo", "1.0", &[]),
+                packet("libfoo:dev", "0.9", &[]),
+                packet("cycle_a", "1.0", &["cycle_b"]),
+                packet("cycle_b", "1.0", &["cycle_a"]),
+            ],
+        );
+
+        assert_eq!(
+            error(&requests(&["app", "libfoo>=2.0"]), &repo),
+            "Dependency resolution failed:\n    \
+             \"missing\" (dependency of \"app\") is not in any repository.\n    \
+             Version conflict: \"libfoo\" (requested) needs version \">=2.0\", which is not available."
+        );
+        assert_eq!(
+            error(&requests(&["libfoo:dev"]), &repo),
+            "Dependency resolution failed:\n    \
+             Version conflict: \"libfoo:dev\" (requested) needs version \"=1.0\", which is not available."
+        );
+        assert_eq!(
+            error(&requests(&["cycle_b"]), &repo),
+            "Dependency resolution failed:\n    \
+             Dependency cycle: cycle_a -> cycle_b -> cycle_a"
+        );
+    }
+}
@@ -0,0 +1,391 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Find the packets depending on other packets, e.g. to decide what to
+//! rebuild after a library was updated
+
+use crate::Repository;
+
+use gng_core::Name;
+
+use eyre::eyre;
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+type Graph = std::collections::BTreeMap<Name, std::collections::BTreeSet<Name>>;
+
+// ----------------------------------------------------------------------
+// - ReverseDependencies:
+// ----------------------------------------------------------------------
+
+/// The reverse dependencies of all packets in a `Repository`
+///
+/// Only the newest version of each packet/facet name combination is taken
+/// into account. Facets are built together with their packet, so the
+/// dependencies of all facets of a packet count as dependencies of the
+/// packet itself. The same holds for the libraries facets are linked against.
+pub struct ReverseDependencies {
+    dependencies: Graph,
+    dependents: Graph,
+    linked: std::collections::BTreeMap<String, std::collections::BTreeSet<Name>>,
+}
+
+impl ReverseDependencies {
+    /// Collect the reverse dependencies of all packets in `repository`
+    ///
+    /// # Errors
+    ///
+    /// Fail if the packets of a binary index can not be decoded.
+    #[tracing::instrument(level = "debug", skip(repository))]
+    pub fn new(repository: &Repository) -> eyre::Result<Self> {
+        let mut dependencies = Graph::new();
+        let mut dependents = Graph::new();
+        let mut linked = std::collections::BTreeMap::<_, std::collections::BTreeSet<_>>::new();
+        for (p, _) in repository.newest()? {
+            for l in &p.needed_libraries {
+                linked.entry(l.clone()).or_default().insert(p.name.clone());
+            }
+            let packet_dependencies = dependencies.entry(p.name.clone()).or_default();
+            for d in p.dependencies.into_iter().filter(|d| **d != p.name) {
+                packet_dependencies.insert(d.clone());
+                dependents
+                    .entry(d.clone())
+                    .or_default()
+                    .insert(p.name.clone());
+            }
+        }
+
+        Ok(Self {
+            dependencies,
+            dependents,
+            linked,
+        })
+    }
+
+    /// Is `packet` part of the repository?
+    #[must_use]
+    pub fn contains(&self, packet: &Name) -> bool {
+        self.dependencies.contains_key(packet)
+    }
+
+    /// The packets depending directly on `packet`, sorted by name
+    #[must_use]
+    pub fn direct(&self, packet: &Name) -> Vec<Name> {
+        self.dependents
+            .get(packet)
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// The packets linked against the library `soname`, sorted by name
+    ///
+    /// These are the packets with an ELF file naming `soname` in its
+    /// `DT_NEEDED` entries, as recorded when the packet was built.
+    #[must_use]
+    pub fn linked_against(&self, soname: &str) -> Vec<Name> {
+        self.linked
+            .get(soname)
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// All packets depending directly or indirectly on any of `packets`
+    ///
+    /// The result is in rebuild order: Each packet comes after all the other
+    /// packets in the result it depends on. Packets that could be rebuilt at
+    /// the same time are sorted by name. `packets` themselves are not part of
+    /// the result.
+    ///
+    /// # Errors
+    ///
+    /// Fail if the dependents form a dependency cycle.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn transitive(&self, packets: &[Name]) -> eyre::Result<Vec<Name>> {
+        self.rebuild_order(&self.dependents_of(packets))
+    }
+
+    /// `packets` and all packets depending directly or indirectly on any of
+    /// them
+    ///
+    /// The result is in rebuild order like in `transitive`. Packets not in
+    /// the repository are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Fail if the packets form a dependency cycle.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn including_transitive(&self, packets: &[Name]) -> eyre::Result<Vec<Name>> {
+        let mut affected = self.dependents_of(packets);
+        affected.extend(packets.iter().filter(|p| self.contains(p)));
+        self.rebuild_order(&affected)
+    }
+
+    /// All packets depending directly or indirectly on any of `packets`,
+    /// without `packets` themselves
+    fn dependents_of<'a>(&'a self, packets: &'a [Name]) -> std::collections::BTreeSet<&'a Name> {
+        let mut affected = std::collections::BTreeSet::new();
+        let mut queue = packets.iter().collect::<std::collections::VecDeque<_>>();
+        while let Some(p) = queue.pop_front() {
+            for d in self.dependents.get(p).into_iter().flatten() {
+                if !packets.contains(d) && affected.insert(d) {
+                    queue.push_back(d);
+                }
+            }
+        }
+        affected
+    }
+
+    /// Sort `affected` so that each packet comes after all the other packets
+    /// in `affected` it depends on
+    fn rebuild_order(
+        &self,
+        affected: &std::collections::BTreeSet<&Name>,
+    ) -> eyre::Result<Vec<Name>> {
+        let mut missing = affected
+            .iter()
+            .map(|p| {
+                let count = self
+                    .dependencies
+                    .get(*p)
+                    .into_iter()
+                    .flatten()
+                    .filter(|d| affected.contains(d))
+                    .count();
+                (*p, count)
+            })
+            .collect::<std::collections::BTreeMap<_, _>>();
+        let mut ready = missing
+            .iter()
+            .filter(|(_, c)| **c == 0)
+            .map(|(p, _)| *p)
+            .collect::<std::collections::BTreeSet<_>>();
+
+        let mut order = Vec::new();
+        while let Some(p) = ready.iter().next().copied() {
+            ready.remove(p);
+            order.push(p.clone());
+            for d in self.dependents.get(p).into_iter().flatten() {
+                if let Some(count) = missing.get_mut(d) {
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.insert(d);
+                    }
+                }
+            }
+        }
+
+        if order.len() < affected.len() {
+            let cycle = missing
+                .iter()
+                .filter(|(_, c)| **c > 0)
+                .map(|(p, _)| p.to_string())
+                .collect::<Vec<_>>();
+            return Err(eyre!(
+                "Can not find a rebuild order: Dependency cycle between {}.",
+                cycle.join(", ")
+            ));
+        }
+        Ok(order)
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Functions:
+// ----------------------------------------------------------------------
+
+/// The packets providing files matching `pattern`, sorted by name
+///
+/// `pattern` is matched like in `Repository::find_file`, e.g. a soname like
+/// `libssl.so.3` or a path like `/usr/bin/perl`. The dependents of these
+/// packets are all packets depending on them, not only those using the file.
+/// `ReverseDependencies::linked_against` finds the packets actually linked
+/// against a library.
+///
+/// # Errors
+///
+/// Fail if `pattern` is not a valid file pattern.
+pub fn file_providers(repository: &Repository, pattern: &str) -> eyre::Result<Vec<Name>> {
+    let providers = repository
+        .find_file(pattern)?
+        .into_iter()
+        .map(|(p, _, _)| p.name)
+        .collect::<std::collections::BTreeSet<_>>();
+    Ok(providers.into_iter().collect())
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_util::{name, packet};
+
+    use gng_core::Version;
+
+    fn names(input: &[&str]) -> Vec<Name> {
+        input.iter().map(|n| name(n)).collect()
+    }
+
+    fn repository() -> Repository {
+        Repository::from_definitions(
+            &url::Url::parse("file:///repo/repository.json").unwrap(),
+            vec![
+                packet("ssl", "3.0", &["glibc"]),
+                packet("glibc", "2.35", &[]),
+                packet("curl", "8.0", &["ssl", "zlib"]),
+                packet("curl:dev", "8.0", &["ssl"]),
+                packet("zlib", "1.2", &["glibc"]),
+                // Only the newest version counts:
+                packet("git", "2.30", &["zlib"]),
+                packet("git", "2.40", &["curl", "ssl"]),
+                packet("tools:doc", "1.0", &["git"]),
+            ],
+        )
+    }
+
+    #[test]
+    fn direct_and_transitive() {
+        let rdeps = ReverseDependencies::new(&repository()).unwrap();
+
+        assert!(rdeps.contains(&name("tools")));
+        assert!(!rdeps.contains(&name("unknown")));
+
+        assert_eq!(rdeps.direct(&name("ssl")), names(&["curl", "git"]));
+        assert_eq!(rdeps.direct(&name("zlib")), names(&["curl"]));
+        assert_eq!(rdeps.direct(&name("tools")), Vec::<Name>::new());
+
+        assert_eq!(
+            rdeps.transitive(&names(&["ssl"])).unwrap(),
+            names(&["curl", "git", "tools"])
+        );
+        assert_eq!(
+            rdeps.transitive(&names(&["glibc"])).unwrap(),
+            names(&["ssl", "zlib", "curl", "git", "tools"])
+        );
+        assert_eq!(
+            rdeps.transitive(&names(&["ssl", "curl"])).unwrap(),
+            names(&["git", "tools"])
+        );
+        assert_eq!(
+            rdeps.transitive(&names(&["unknown"])).unwrap(),
+            Vec::<Name>::new()
+        );
+    }
+
+    #[test]
+    fn dependency_cycle() {
+        let repo = Repository::from_definitions(
+            &url::Url::parse("file:///repo/repository.json").unwrap(),
+            vec![
+                packet("base", "1.0", &[]),
+                packet("a", "1.0", &["base", "b"]),
+                packet("b", "1.0", &["a"]),
+            ],
+        );
+        let rdeps = ReverseDependencies::new(&repo).unwrap();
+        assert!(rdeps.transitive(&names(&["base"])).is_err());
+        assert_eq!(rdeps.direct(&name("a")), names(&["b"]));
+    }
+
+    #[test]
+    fn linked() {
+        let linked = |full_name: &str, version: &str, dependencies: &[&str], libraries: &[&str]| {
+            let mut result = packet(full_name, version, dependencies);
+            result.needed_libraries = libraries.iter().map(|l| (*l).to_string()).collect();
+            result
+        };
+        let repo = Repository::from_definitions(
+            &url::Url::parse("file:///repo/repository.json").unwrap(),
+            vec![
+                linked("ssl", "3.0", &[], &["libc.so.6"]),
+                linked("curl", "8.0", &["ssl"], &["libc.so.6", "libssl.so.3"]),
+                linked("curl:dev", "8.0", &["ssl"], &["libz.so.1"]),
+                linked("git", "2.40", &["curl"], &["libc.so.6"]),
+                // Only the newest version counts:
+                linked("wget", "1.0", &["ssl"], &["libssl.so.3"]),
+                linked("wget", "2.0", &["ssl"], &[]),
+                // Linked without declaring a dependency:
+                linked("tool", "1.0", &[], &["libssl.so.3"]),
+                linked("tool:doc", "1.0", &["tool"], &[]),
+            ],
+        );
+        let rdeps = ReverseDependencies::new(&repo).unwrap();
+
+        assert_eq!(
+            rdeps.linked_against("libssl.so.3"),
+            names(&["curl", "tool"])
+        );
+        assert_eq!(rdeps.linked_against("libz.so.1"), names(&["curl"]));
+        assert_eq!(
+            rdeps.linked_against("libc.so.6"),
+            names(&["curl", "git", "ssl"])
+        );
+        assert_eq!(rdeps.linked_against("libfoo.so"), Vec::<Name>::new());
+
+        assert_eq!(
+            rdeps
+                .including_transitive(&names(&["tool", "curl"]))
+                .unwrap(),
+            names(&["curl", "git", "tool"])
+        );
+        assert_eq!(
+            rdeps
+                .including_transitive(&names(&["ssl", "unknown"]))
+                .unwrap(),
+            names(&["ssl", "curl", "git", "wget"])
+        );
+    }
+
+    #[test]
+    fn providers() {
+        let mut repo = Repository::from_definitions(
+            &url::Url::parse("file:///repo/repository.json").unwrap(),
+            vec![
+                packet("ssl", "3.0", &[]),
+                packet("curl", "8.0", &["ssl"]),
+                packet("git", "2.40", &["curl"]),
+            ],
+        );
+        let mut update = repo.create_transaction();
+        for (packet, version, file) in [
+            ("ssl", "3.0", "/usr/lib/libssl.so.3"),
+            ("curl", "8.0", "/usr/bin/curl"),
+            ("git", "2.40", "/usr/bin/git"),
+        ] {
+            update.set_files(
+                name(packet),
+                None,
+                Version::try_from(version).unwrap(),
+                vec![std::path::PathBuf::from(file)],
+            );
+        }
+        repo.apply(update).unwrap();
+
+        let providers = file_providers(&repo, "libssl.so.3").unwrap();
+        assert_eq!(providers, names(&["ssl"]));
+        let rdeps = ReverseDependencies::new(&repo).unwrap();
+        assert_eq!(rdeps.direct(&providers[0]), names(&["curl"]));
+        assert_eq!(
+            file_providers(&repo, "/usr/lib/libssl.so.3").unwrap(),
+            names(&["ssl"])
+        );
+        assert_eq!(
+            file_providers(&repo, "/usr/bin/*").unwrap(),
+            names(&["curl", "git"])
+        );
+        assert_eq!(
+            file_providers(&repo, "libz.so.1").unwrap(),
+            Vec::<Name>::new()
+        );
+        assert!(file_providers(&repo, "[").is_err());
+    }
+}
@@ -0,0 +1,516 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Signatures for repository data
+//!
+//! The `repository.json` of a repository is described by a `repository.sig`
+//! file next to it. That contains the hash of the repository data, a serial
+//! number that increases with every update and an expiry time. All of this is
+//! signed with one or more ed25519 keys.
+
+use eyre::{eyre, WrapErr};
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// The name of the signature file next to `repository.json`
+pub const SIGNATURE_FILE: &str = "repository.sig";
+
+fn to_hex(data: &[u8]) -> String {
+    use std::fmt::Write;
+
+    data.iter().fold(String::new(), |mut result, b| {
+        let _ = write!(result, "{:02x}", b);
+        result
+    })
+}
+
+fn from_hex<const N: usize>(input: &str) -> eyre::Result<[u8; N]> {
+    let input = input.trim();
+    if input.len() != N * 2 || !input.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(eyre!("Expected {} hex digits, got \"{}\".", N * 2, input));
+    }
+
+    let mut result = [0_u8; N];
+    for (i, b) in result.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&input[i * 2..i * 2 + 2], 16).expect("Hex digits were checked");
+    }
+    Ok(result)
+}
+
+/// The current time in seconds since the epoch
+pub(crate) fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Turn a URL into a file name
+pub(crate) fn url_key(url: &url::Url) -> String {
+    url.as_str()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+// ----------------------------------------------------------------------
+// - PublicKey:
+// ----------------------------------------------------------------------
+
+/// A ed25519 public key used to verify repository data
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct PublicKey([u8; 32]);
+
+impl PublicKey {
+    /// A short identifier for this key
+    #[must_use]
+    pub fn key_id(&self) -> String {
+        use sha2::Digest;
+        to_hex(&sha2::Sha256::digest(self.0)[..8])
+    }
+
+    fn verify(&self, message: &[u8], signature: &str) -> bool {
+        from_hex::<64>(signature).map_or(false, |signature| {
+            ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, &self.0)
+                .verify(message, &signature)
+                .is_ok()
+        })
+    }
+}
+
+impl std::str::FromStr for PublicKey {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(from_hex(s).wrap_err("Invalid public key.")?))
+    }
+}
+
+impl std::fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", to_hex(&self.0))
+    }
+}
+
+// ----------------------------------------------------------------------
+// - SigningKey:
+// ----------------------------------------------------------------------
+
+/// A ed25519 secret key used to sign repository data
+pub struct SigningKey {
+    seed: [u8; 32],
+    key_pair: ring::signature::Ed25519KeyPair,
+}
+
+impl SigningKey {
+    /// Generate a new random key
+    ///
+    /// # Errors
+    ///
+    /// Fail if no random data is available.
+    pub fn generate() -> eyre::Result<Self> {
+        use ring::rand::SecureRandom;
+
+        let mut seed = [0_u8; 32];
+        ring::rand::SystemRandom::new()
+            .fill(&mut seed)
+            .map_err(|_| eyre!("Failed to generate random data."))?;
+        Self::from_seed(seed)
+    }
+
+    fn from_seed(seed: [u8; 32]) -> eyre::Result<Self> {
+        let key_pair = ring::signature::Ed25519KeyPair::from_seed_unchecked(&seed)
+            .map_err(|_| eyre!("Invalid signing key."))?;
+        Ok(Self { seed, key_pair })
+    }
+
+    /// Read a key from a file containing its hex representation
+    ///
+    /// # Errors
+    ///
+    /// Fail if the file can not be read or contains no valid key.
+    pub fn from_file(path: &std::path::Path) -> eyre::Result<Self> {
+        std::fs::read_to_string(path)
+            .wrap_err(eyre!("Failed to read \"{}\".", path.to_string_lossy()))?
+            .parse()
+            .wrap_err(eyre!(
+                "Failed to parse signing key in \"{}\".",
+                path.to_string_lossy()
+            ))
+    }
+
+    /// The `PublicKey` matching this key
+    #[must_use]
+    pub fn public_key(&self) -> PublicKey {
+        use ring::signature::KeyPair;
+
+        let mut key = [0_u8; 32];
+        key.copy_from_slice(self.key_pair.public_key().as_ref());
+        PublicKey(key)
+    }
+
+    /// The hex representation of this key
+    ///
+    /// This is secret!
+    #[must_use]
+    pub fn to_hex(&self) -> String {
+        to_hex(&self.seed)
+    }
+
+    fn sign(&self, message: &[u8]) -> String {
+        to_hex(self.key_pair.sign(message).as_ref())
+    }
+}
+
+impl std::str::FromStr for SigningKey {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_seed(from_hex(s).wrap_err("Invalid signing key.")?)
+    }
+}
+
+impl std::fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SigningKey({})", self.public_key().key_id())
+    }
+}
+
+// ----------------------------------------------------------------------
+// - RepositoryMetadata:
+// ----------------------------------------------------------------------
+
+/// A signature in `RepositoryMetadata`
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Signature {
+    /// The `key_id` of the key used to sign
+    pub key_id: String,
+    /// The signature itself
+    pub signature: String,
+}
+
+/// Signed information about the repository data
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct RepositoryMetadata {
+    /// Increases with every update of the repository
+    pub serial: u64,
+    /// Time of signing (in seconds since the epoch)
+    pub timestamp: u64,
+    /// The data must not be used after this time (in seconds since the epoch)
+    pub expires: u64,
+    /// The SHA256 hash of the `repository.json` file
+    pub sha256: String,
+    /// The signatures
+    pub signatures: Vec<Signature>,
+}
+
+impl RepositoryMetadata {
+    /// Create unsigned metadata
+    #[must_use]
+    pub fn new(serial: u64, timestamp: u64, expires: u64, sha256: &str) -> Self {
+        Self {
+            serial,
+            timestamp,
+            expires,
+            sha256: sha256.to_string(),
+            signatures: Vec::new(),
+        }
+    }
+
+    fn payload(&self) -> Vec<u8> {
+        format!(
+            "gng-repository-v1\n{}\n{}\n{}\n{}\n",
+            self.serial, self.timestamp, self.expires, self.sha256
+        )
+        .into_bytes()
+    }
+
+    /// Sign the metadata with `key`
+    pub fn sign(&mut self, key: &SigningKey) {
+        let key_id = key.public_key().key_id();
+        let signature = key.sign(&self.payload());
+        self.signatures.retain(|s| s.key_id != key_id);
+        self.signatures.push(Signature { key_id, signature });
+    }
+
+    /// Verify the metadata for repository data with hash `sha256` at time `now`
+    ///
+    /// # Errors
+    ///
+    /// Fail if no signature of a key in `keys` is valid, if the data has
+    /// expired or if the hash does not match.
+    pub fn verify(&self, sha256: &str, keys: &[PublicKey], now: u64) -> eyre::Result<()> {
+        let payload = self.payload();
+        let is_signed = keys.iter().any(|k| {
+            let key_id = k.key_id();
+            self.signatures
+                .iter()
+                .any(|s| s.key_id == key_id && k.verify(&payload, &s.signature))
+        });
+        if !is_signed {
+            return Err(eyre!("Repository data is not signed by a trusted key."));
+        }
+        if self.sha256 != sha256 {
+            return Err(eyre!("Repository data does not match its signature."));
+        }
+        if self.expires <= now {
+            return Err(eyre!("Repository data has expired."));
+        }
+        Ok(())
+    }
+
+    /// Load metadata from `path`
+    ///
+    /// # Errors
+    ///
+    /// Fail if the file can not be read or parsed.
+    pub fn load(path: &std::path::Path) -> eyre::Result<Self> {
+        let data = std::fs::read(path)
+            .wrap_err(eyre!("Failed to read \"{}\".", path.to_string_lossy()))?;
+        serde_json::from_slice(&data)
+            .wrap_err(eyre!("Failed to parse \"{}\".", path.to_string_lossy()))
+    }
+
+    /// Save metadata to `path`
+    ///
+    /// # Errors
+    ///
+    /// Fail if the file can not be written.
+    pub fn save(&self, path: &std::path::Path) -> eyre::Result<()> {
+        let data = serde_json::to_vec_pretty(self).wrap_err("Failed to serialize metadata.")?;
+        crate::files::write_atomically(path, &data)
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Trust:
+// ----------------------------------------------------------------------
+
+/// The keys trusted to sign repositories
+///
+/// Without keys, repository data is not verified at all. Otherwise the serial
+/// of the last verified repository data is recorded per repository in a state
+/// directory and older data is refused, unless that protection was disabled
+/// explicitly.
+#[derive(Clone, Debug, Default)]
+pub struct Trust {
+    keys: Vec<PublicKey>,
+    state_directory: Option<std::path::PathBuf>,
+}
+
+/// Read the serial and hash of the last verified repository data from
+/// `state_file`
+///
+/// A missing `state_file` means no data was verified before. Anything else
+/// that can not be read is an error: Treating it as "nothing seen" would
+/// accept any older data.
+fn read_state(state_file: &std::path::Path) -> eyre::Result<Option<(u64, String)>> {
+    let state = match std::fs::read_to_string(state_file) {
+        Ok(state) => state,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(e).wrap_err(eyre!(
+                "Failed to read state file \"{}\".",
+                state_file.to_string_lossy()
+            ))
+        }
+    };
+
+    state
+        .trim()
+        .split_once(' ')
+        .and_then(|(serial, hash)| Some((serial.parse::<u64>().ok()?, hash.to_string())))
+        .filter(|(_, hash)| !hash.is_empty())
+        .map(Some)
+        .ok_or_else(|| {
+            eyre!(
+                "State file \"{}\" is corrupt, refusing to trust any repository data.",
+                state_file.to_string_lossy()
+            )
+        })
+}
+
+impl Trust {
+    /// Do not verify repository data
+    #[must_use]
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// Trust `keys`, refusing data older than what was seen before
+    ///
+    /// The serial of the last verified data of each repository is recorded in
+    /// `state_directory`.
+    #[must_use]
+    pub fn new(keys: Vec<PublicKey>, state_directory: &std::path::Path) -> Self {
+        Self {
+            keys,
+            state_directory: Some(state_directory.to_path_buf()),
+        }
+    }
+
+    /// Trust `keys`, accepting any validly signed data that has not expired
+    ///
+    /// This does not protect against a mirror serving older repository data.
+    #[must_use]
+    pub const fn without_rollback_protection(keys: Vec<PublicKey>) -> Self {
+        Self {
+            keys,
+            state_directory: None,
+        }
+    }
+
+    /// Is repository data verified?
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    /// Verify the `data` of the repository at `url` with its `metadata_file`
+    ///
+    /// Callers must go on using exactly this `data`: Reading the file again
+    /// might return something else than what was verified.
+    pub(crate) fn check(
+        &self,
+        url: &url::Url,
+        data: &[u8],
+        metadata_file: &std::path::Path,
+    ) -> eyre::Result<()> {
+        self.check_at(url, data, metadata_file, now())
+    }
+
+    fn check_at(
+        &self,
+        url: &url::Url,
+        data: &[u8],
+        metadata_file: &std::path::Path,
+        now: u64,
+    ) -> eyre::Result<()> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+
+        let result = || -> eyre::Result<()> {
+            let metadata = RepositoryMetadata::load(metadata_file)?;
+            let sha256 = crate::files::data_hash(data);
+            metadata.verify(&sha256, &self.keys, now)?;
+
+            if let Some(state_directory) = &self.state_directory {
+                let state_file = state_directory.join(format!("{}.serial", url_key(url)));
+                if let Some((serial, hash)) = read_state(&state_file)? {
+                    if metadata.serial < serial || (metadata.serial == serial && hash != sha256) {
+                        return Err(eyre!(
+                            "Repository data has serial {}, but serial {} was seen before.",
+                            metadata.serial,
+                            serial
+                        ));
+                    }
+                }
+                crate::files::write_atomically(
+                    &state_file,
+                    format!("{} {}\n", metadata.serial, sha256).as_bytes(),
+                )?;
+            }
+            Ok(())
+        };
+        result().wrap_err(eyre!("Failed to verify repository \"{}\".", url.as_str()))
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Tests:
+// ----------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(seed: u8) -> SigningKey {
+        SigningKey::from_seed([seed; 32]).unwrap()
+    }
+
+    #[test]
+    fn keys_round_trip() {
+        let key = key(1);
+        let parsed = key.to_hex().parse::<SigningKey>().unwrap();
+        assert_eq!(parsed.public_key(), key.public_key());
+        assert_eq!(
+            key.public_key().to_string().parse::<PublicKey>().unwrap(),
+            key.public_key()
+        );
+        assert!("abc".parse::<PublicKey>().is_err());
+        assert_eq!(key.public_key().key_id().len(), 16);
+        assert_ne!(
+            SigningKey::generate().unwrap().public_key(),
+            SigningKey::generate().unwrap().public_key()
+        );
+    }
+
+    #[test]
+    fn metadata_verify() {
+        let mut metadata = RepositoryMetadata::new(1, 100, 200, "abcd");
+        let trusted = [key(1).public_key()];
+
+        assert!(metadata.verify("abcd", &trusted, 150).is_err());
+        metadata.sign(&key(2));
+        assert!(metadata.verify("abcd", &trusted, 150).is_err());
+
+        metadata.sign(&key(1));
+        metadata.verify("abcd", &trusted, 150).unwrap();
+        assert!(metadata.verify("abce", &trusted, 150).is_err());
+        assert!(metadata.verify("abcd", &trusted, 200).is_err());
+
+        let mut tampered = metadata.clone();
+        tampered.expires = 1000;
+        assert!(tampered.verify("abcd", &trusted, 150).is_err());
+    }
+
+    #[test]
+    fn trust_refuses_rollback() {
+        let directory = tempfile::tempdir().unwrap();
+        let url = url::Url::parse("https://example.com/repository.json").unwrap();
+        let metadata_file = directory.path().join(SIGNATURE_FILE);
+        let trust = Trust::new(vec![key(1).public_key()], &directory.path().join("state"));
+
+        let publish = |serial: u64, data: &[u8]| {
+            let sha256 = crate::files::data_hash(data);
+            let mut metadata = RepositoryMetadata::new(serial, 100, 200, &sha256);
+            metadata.sign(&key(1));
+            metadata.save(&metadata_file).unwrap();
+        };
+
+        publish(1, b"one");
+        trust.check_at(&url, b"one", &metadata_file, 150).unwrap();
+        publish(2, b"two");
+        trust.check_at(&url, b"two", &metadata_file, 150).unwrap();
+        trust.check_at(&url, b"two", &metadata_file, 150).unwrap();
+
+        publish(1, b"one");
+        assert!(trust.check_at(&url, b"one", &metadata_file, 150).is_err());
+        publish(2, b"other");
+        assert!(trust.check_at(&url, b"other", &metadata_file, 150).is_err());
+
+        // A corrupt state file must not reset the serial:
+        let state_file = std::fs::read_dir(directory.path().join("state"))
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        for corrupt in [&b""[..], b"2", b"garbage abcd", b"\xff\xfe"] {
+            std::fs::write(&state_file, corrupt).unwrap();
+            publish(1, b"one");
+            assert!(trust.check_at(&url, b"one", &metadata_file, 150).is_err());
+            assert_eq!(std::fs::read(&state_file).unwrap(), corrupt);
+        }
+
+        // Unprotected and unverified:
+        Trust::without_rollback_protection(vec![key(1).public_key()])
+            .check_at(&url, b"one", &metadata_file, 150)
+            .unwrap();
+        Trust::none()
+            .check_at(&url, b"one", &metadata_file, 150)
+            .unwrap();
+    }
+}
@@ -0,0 +1,51 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Copyright (C) 2021 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Helpers shared by the tests of this crate
+
+use gng_core::{Name, Names, Version};
+use gng_packet_io::{BinaryFacet, BinaryFacetDefinition, BinaryFacetUsage, BinaryPacketDefinition};
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// The `Name` in `input`
+pub fn name(input: &str) -> Name {
+    Name::try_from(input).unwrap()
+}
+
+/// A packet definition for `full_name` ("foo" or "foo:dev") in `version`
+/// depending on `dependencies`
+pub fn packet(full_name: &str, version: &str, dependencies: &[&str]) -> BinaryPacketDefinition {
+    let (packet, facet) = full_name
+        .split_once(':')
+        .map_or((full_name, None), |(p, f)| (p, Some(f)));
+    BinaryPacketDefinition {
+        name: name(packet),
+        version: Version::try_from(version).unwrap(),
+        description: String::new(),
+        url: String::new(),
+        bug_url: String::new(),
+        dependencies: dependencies.iter().fold(Names::default(), |mut n, d| {
+            n.insert(name(d));
+            n
+        }),
+        needed_libraries: Vec::new(),
+        facet: facet.map_or(BinaryFacet::Main, |f| {
+            BinaryFacet::Usage(BinaryFacetUsage { name: name(f) })
+        }),
+    }
+}
+
+/// A packet defining the facet `packet_name`, which `extends` another facet
+pub fn facet(packet_name: &str, extends: Option<&str>) -> BinaryPacketDefinition {
+    let mut result = packet(packet_name, "1.0", &[]);
+    result.facet = BinaryFacet::Definition(BinaryFacetDefinition {
+        mime_types: Vec::new(),
+        files: Vec::new(),
+        extends: extends.map(name),
+        is_forbidden: false,
+    });
+    result
+}
@@ -31,6 +31,33 @@ pub enum PacketPolicy {
     MustStayEmpty,
 }
 
+/// What to do when a packet file exists already
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExistingPacketPolicy {
+    /// Replace the existing packet file
+    Overwrite,
+    /// Keep the existing packet file
+    Skip,
+    /// Fail to write the packet
+    Fail,
+}
+
+impl std::str::FromStr for ExistingPacketPolicy {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "overwrite" => Ok(Self::Overwrite),
+            "skip" => Ok(Self::Skip),
+            "fail" => Ok(Self::Fail),
+            _ => Err(eyre::eyre!(
+                "Unknown policy \"{}\", expected \"overwrite\", \"skip\" or \"fail\".",
+                s
+            )),
+        }
+    }
+}
+
 // ----------------------------------------------------------------------
 // - BinaryFacetUsage:
 // ----------------------------------------------------------------------
@@ -68,8 +95,12 @@ impl PartialOrd for BinaryFacetDefinition {
 }
 
 impl Ord for BinaryFacetDefinition {
-    fn cmp(&self, _other: &Self) -> std::cmp::Ordering {
-        std::cmp::Ordering::Equal
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.extends
+            .cmp(&other.extends)
+            .then_with(|| self.is_forbidden.cmp(&other.is_forbidden))
+            .then_with(|| self.files.cmp(&other.files))
+            .then_with(|| self.mime_types.cmp(&other.mime_types))
     }
 }
 
@@ -103,11 +134,10 @@ impl PartialOrd for BinaryFacet {
 impl Ord for BinaryFacet {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         match (&self, &other) {
-            (Self::Main | Self::Definition(_), Self::Main | Self::Definition(_)) => {
-                std::cmp::Ordering::Equal
-            }
-            (Self::Main | Self::Definition(_), Self::Usage(_)) => std::cmp::Ordering::Less,
-            (Self::Usage(_), Self::Main | Self::Definition(_)) => std::cmp::Ordering::Greater,
+            (Self::Main, Self::Main) => std::cmp::Ordering::Equal,
+            (Self::Main, _) | (Self::Definition(_), Self::Usage(_)) => std::cmp::Ordering::Less,
+            (_, Self::Main) | (Self::Usage(_), Self::Definition(_)) => std::cmp::Ordering::Greater,
+            (Self::Definition(lhs), Self::Definition(rhs)) => lhs.cmp(rhs),
             (Self::Usage(lhs), &Self::Usage(rhs)) => lhs.name.cmp(&rhs.name),
         }
     }
@@ -135,10 +165,29 @@ pub struct BinaryPacketDefinition {
     #[serde(default)]
     pub dependencies: Names,
 
+    /// The sonames the ELF files of the `Packet` are linked against
+    /// (their `DT_NEEDED` entries), sorted
+    #[serde(default)]
+    pub needed_libraries: Vec<String>,
+
     /// The `Facet`
     pub facet: BinaryFacet,
 }
 
+impl BinaryPacketDefinition {
+    /// The name of the facet this `Packet` provides content for
+    ///
+    /// This is `None` for un-faceted packets and for packets defining a facet.
+    #[must_use]
+    pub fn facet_name(&self) -> Option<Name> {
+        if let BinaryFacet::Usage(BinaryFacetUsage { name }) = &self.facet {
+            Some(name.clone())
+        } else {
+            None
+        }
+    }
+}
+
 impl PartialEq for BinaryPacketDefinition {
     fn eq(&self, other: &Self) -> bool {
         self.name == other.name && self.version == other.version && self.facet == other.facet
@@ -234,6 +283,21 @@ mod tests {
         metadata: Vec<u8>,
         test_data: &[u8],
     ) -> std::path::PathBuf {
+        try_create_packet(
+            directory,
+            metadata,
+            test_data,
+            crate::ExistingPacketPolicy::Fail,
+        )
+        .expect("Failed to write packet")
+    }
+
+    fn try_create_packet(
+        directory: &std::path::Path,
+        metadata: Vec<u8>,
+        test_data: &[u8],
+        existing: crate::ExistingPacketPolicy,
+    ) -> eyre::Result<std::path::PathBuf> {
         let mut writer = crate::PacketWriter::raw_new(
             directory,
             &Name::new("packet").unwrap(),
@@ -241,22 +305,277 @@ mod tests {
             &Version::try_from("1.0").unwrap(),
             metadata,
             crate::PacketPolicy::MustHaveContents,
+            existing,
         );
-        writer
-            .add_directory(std::path::Path::new("foo"), 0o755, 0, 0)
-            .expect("Failed to write folder information into packet");
-        writer
-            .add_buffer(
-                std::path::Path::new("foo/test.data"),
-                test_data,
-                0o644,
-                0,
-                0,
-            )
-            .expect("Failed to write data into packet");
-        let packet_path = writer.finish().expect("Failed to write packet");
+        writer.add_directory(std::path::Path::new("foo"), 0o755, 0, 0)?;
+        writer.add_buffer(
+            std::path::Path::new("foo/test.data"),
+            test_data,
+            0o644,
+            0,
+            0,
+        )?;
+        let packet_path = writer.finish()?;
         assert!(packet_path.is_some());
-        packet_path.unwrap()
+        Ok(packet_path.unwrap())
+    }
+
+    fn directory_entries(directory: &std::path::Path) -> Vec<std::ffi::OsString> {
+        let mut result = std::fs::read_dir(directory)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect::<Vec<_>>();
+        result.sort();
+        result
+    }
+
+    fn facet_definition(extends: Option<&str>, files: &[&str]) -> crate::BinaryFacet {
+        crate::BinaryFacet::Definition(crate::BinaryFacetDefinition {
+            mime_types: Vec::new(),
+            files: files.iter().map(|f| (*f).to_string()).collect(),
+            extends: extends.map(|e| Name::try_from(e).unwrap()),
+            is_forbidden: false,
+        })
+    }
+
+    fn facet_usage(name: &str) -> crate::BinaryFacet {
+        crate::BinaryFacet::Usage(crate::BinaryFacetUsage {
+            name: Name::try_from(name).unwrap(),
+        })
+    }
+
+    #[test]
+    fn binary_facet_ordering() {
+        use crate::BinaryFacet;
+
+        assert_eq!(BinaryFacet::Main, BinaryFacet::Main);
+        assert!(BinaryFacet::Main < facet_definition(None, &[]));
+        assert!(facet_definition(None, &["lib/**"]) < facet_usage("dev"));
+        assert!(facet_usage("dev") < facet_usage("doc"));
+
+        assert_eq!(
+            facet_definition(Some("dev"), &["lib/**"]),
+            facet_definition(Some("dev"), &["lib/**"])
+        );
+        assert_ne!(
+            facet_definition(Some("dev"), &["lib/**"]),
+            facet_definition(Some("dev"), &["include/**"])
+        );
+        assert_ne!(
+            facet_definition(None, &["lib/**"]),
+            facet_definition(Some("dev"), &["lib/**"])
+        );
+    }
+
+    #[test]
+    fn integration_packet_io_existing_packets() {
+        let tmp = tempfile::Builder::new()
+            .prefix("packet-io-ex-")
+            .rand_bytes(8)
+            .tempdir()
+            .expect("Failed to create temporary directory");
+
+        let packet_path = create_packet(tmp.path(), b"first".to_vec(), b"test data\n");
+        assert_eq!(
+            directory_entries(tmp.path()),
+            vec![std::ffi::OsString::from("packet-1.0.gng")]
+        );
+
+        assert!(try_create_packet(
+            tmp.path(),
+            b"second".to_vec(),
+            b"test data\n",
+            crate::ExistingPacketPolicy::Fail
+        )
+        .is_err());
+        assert_eq!(
+            crate::PacketReader::new(&packet_path)
+                .raw_metadata()
+                .unwrap(),
+            b"first"
+        );
+
+        assert_eq!(
+            try_create_packet(
+                tmp.path(),
+                b"second".to_vec(),
+                b"test data\n",
+                crate::ExistingPacketPolicy::Skip
+            )
+            .unwrap(),
+            packet_path
+        );
+        assert_eq!(
+            crate::PacketReader::new(&packet_path)
+                .raw_metadata()
+                .unwrap(),
+            b"first"
+        );
+
+        assert_eq!(
+            try_create_packet(
+                tmp.path(),
+                b"third".to_vec(),
+                b"test data\n",
+                crate::ExistingPacketPolicy::Overwrite
+            )
+            .unwrap(),
+            packet_path
+        );
+        assert_eq!(
+            crate::PacketReader::new(&packet_path)
+                .raw_metadata()
+                .unwrap(),
+            b"third"
+        );
+
+        // No partial packets are left behind:
+        assert_eq!(
+            directory_entries(tmp.path()),
+            vec![std::ffi::OsString::from("packet-1.0.gng")]
+        );
+    }
+
+    #[test]
+    fn integration_packet_io_existing_packets_are_checked_first() {
+        let tmp = tempfile::Builder::new()
+            .prefix("packet-io-exf-")
+            .rand_bytes(8)
+            .tempdir()
+            .expect("Failed to create temporary directory");
+
+        create_packet(tmp.path(), b"first".to_vec(), b"test data\n");
+
+        let writer = |existing| {
+            crate::PacketWriter::raw_new(
+                tmp.path(),
+                &Name::new("packet").unwrap(),
+                &None,
+                &Version::try_from("1.0").unwrap(),
+                b"second".to_vec(),
+                crate::PacketPolicy::MustHaveContents,
+                existing,
+            )
+        };
+
+        // Fails before writing anything:
+        let mut failing = writer(crate::ExistingPacketPolicy::Fail);
+        assert!(failing
+            .add_directory(std::path::Path::new("foo"), 0o755, 0, 0)
+            .is_err());
+        assert_eq!(
+            directory_entries(tmp.path()),
+            vec![std::ffi::OsString::from("packet-1.0.gng")]
+        );
+
+        // Skips without writing anything:
+        let mut skipping = writer(crate::ExistingPacketPolicy::Skip);
+        skipping
+            .add_directory(std::path::Path::new("foo"), 0o755, 0, 0)
+            .unwrap();
+        assert_eq!(
+            directory_entries(tmp.path()),
+            vec![std::ffi::OsString::from("packet-1.0.gng")]
+        );
+        assert_eq!(
+            skipping.finish().unwrap(),
+            Some(tmp.path().join("packet-1.0.gng"))
+        );
+    }
+
+    #[test]
+    fn integration_packet_io_existing_packets_appearing_while_writing() {
+        let tmp = tempfile::Builder::new()
+            .prefix("packet-io-exw-")
+            .rand_bytes(8)
+            .tempdir()
+            .expect("Failed to create temporary directory");
+
+        let writer = |existing| {
+            let mut writer = crate::PacketWriter::raw_new(
+                tmp.path(),
+                &Name::new("packet").unwrap(),
+                &None,
+                &Version::try_from("1.0").unwrap(),
+                b"second".to_vec(),
+                crate::PacketPolicy::MustHaveContents,
+                existing,
+            );
+            writer
+                .add_directory(std::path::Path::new("foo"), 0o755, 0, 0)
+                .unwrap();
+            writer
+        };
+
+        // Another writer moves its packet into place while this one writes:
+        let other = tempfile::Builder::new()
+            .prefix("packet-io-exw-other-")
+            .rand_bytes(8)
+            .tempdir()
+            .expect("Failed to create temporary directory");
+        let other_path = create_packet(other.path(), b"first".to_vec(), b"test data\n");
+        let packet_path = tmp.path().join("packet-1.0.gng");
+
+        let mut failing = writer(crate::ExistingPacketPolicy::Fail);
+        std::fs::copy(&other_path, &packet_path).unwrap();
+        assert!(failing.finish().is_err());
+        assert_eq!(
+            crate::PacketReader::new(&packet_path)
+                .raw_metadata()
+                .unwrap(),
+            b"first"
+        );
+
+        std::fs::remove_file(&packet_path).unwrap();
+
+        let mut skipping = writer(crate::ExistingPacketPolicy::Skip);
+        std::fs::copy(&other_path, &packet_path).unwrap();
+        assert_eq!(skipping.finish().unwrap(), Some(packet_path.clone()));
+        assert_eq!(
+            crate::PacketReader::new(&packet_path)
+                .raw_metadata()
+                .unwrap(),
+            b"first"
+        );
+
+        // No partial packets are left behind:
+        assert_eq!(
+            directory_entries(tmp.path()),
+            vec![std::ffi::OsString::from("packet-1.0.gng")]
+        );
+    }
+
+    #[test]
+    fn integration_packet_io_unfinished_packet() {
+        let tmp = tempfile::Builder::new()
+            .prefix("packet-io-uf-")
+            .rand_bytes(8)
+            .tempdir()
+            .expect("Failed to create temporary directory");
+
+        {
+            let mut writer = crate::PacketWriter::raw_new(
+                tmp.path(),
+                &Name::new("packet").unwrap(),
+                &None,
+                &Version::try_from("1.0").unwrap(),
+                b"Metadata".to_vec(),
+                crate::PacketPolicy::MustHaveContents,
+                crate::ExistingPacketPolicy::Fail,
+            );
+            writer
+                .add_directory(std::path::Path::new("foo"), 0o755, 0, 0)
+                .expect("Failed to write folder information into packet");
+            assert_eq!(
+                directory_entries(tmp.path()),
+                vec![std::ffi::OsString::from(".packet-1.0.gng.partial")]
+            );
+        }
+
+        assert_eq!(
+            directory_entries(tmp.path()),
+            Vec::<std::ffi::OsString>::new()
+        );
     }
 
     #[test]
@@ -285,6 +604,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn integration_packet_io_extra_metadata() {
+        let tmp = tempfile::Builder::new()
+            .prefix("packet-io-emd-")
+            .rand_bytes(8)
+            .tempdir()
+            .expect("Failed to create temporary directory");
+
+        let mut writer = crate::PacketWriter::raw_new(
+            tmp.path(),
+            &Name::new("packet").unwrap(),
+            &None,
+            &Version::try_from("1.0").unwrap(),
+            b"Metadata".to_vec(),
+            crate::PacketPolicy::MustHaveContents,
+            crate::ExistingPacketPolicy::Fail,
+        );
+        writer
+            .add_metadata("spdx.json", b"{}".to_vec())
+            .expect("Failed to add extra metadata");
+        writer
+            .add_buffer(std::path::Path::new("test.data"), b"test", 0o644, 0, 0)
+            .expect("Failed to write data into packet");
+        assert!(writer.add_metadata("cdx.json", b"{}".to_vec()).is_err());
+        let packet_path = writer.finish().unwrap().unwrap();
+
+        let mut reader = crate::PacketReader::new(&packet_path);
+        let (actual_meta_data, actual_contents) =
+            reader.contents().expect("Failed to get metadata");
+        assert_eq!(&actual_meta_data, b"Metadata");
+        assert_eq!(
+            actual_contents
+                .iter()
+                .map(|c| c.path.to_string_lossy().to_string())
+                .collect::<Vec<_>>(),
+            vec![".gng/packet.meta", ".gng/packet.spdx.json", "test.data"]
+        );
+    }
+
     #[test]
     fn integration_packet_io_contents() {
         let tmp = tempfile::Builder::new()
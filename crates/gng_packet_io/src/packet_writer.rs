@@ -7,8 +7,6 @@ use gng_core::{Name, Version};
 
 use eyre::{eyre, WrapErr};
 
-use crate::BinaryFacetUsage;
-
 // ----------------------------------------------------------------------
 // - Helper:
 // ----------------------------------------------------------------------
@@ -115,15 +113,54 @@ fn add_link_raw(
         .wrap_err("Failed to package a symlink.")
 }
 
+/// The path a packet is written to before it gets renamed to `full_packet_path`
+fn partial_packet_path(full_packet_path: &std::path::Path) -> std::path::PathBuf {
+    let mut file_name = std::ffi::OsString::from(".");
+    file_name.push(full_packet_path.file_name().unwrap_or_default());
+    file_name.push(".partial");
+    full_packet_path.with_file_name(file_name)
+}
+
+/// Whether to keep the packet file at `full_packet_path` instead of writing it
+///
+/// This is checked before anything is written, so no work is wasted on a
+/// packet that would be thrown away. `commit` checks again in case a packet
+/// file appeared in the meantime.
+fn keep_existing(
+    full_packet_path: &std::path::Path,
+    existing: crate::ExistingPacketPolicy,
+) -> eyre::Result<bool> {
+    if !full_packet_path.exists() {
+        return Ok(false);
+    }
+    match existing {
+        crate::ExistingPacketPolicy::Overwrite => Ok(false),
+        crate::ExistingPacketPolicy::Skip => {
+            tracing::info!(
+                "Keeping existing packet \"{}\".",
+                full_packet_path.to_string_lossy()
+            );
+            Ok(true)
+        }
+        crate::ExistingPacketPolicy::Fail => Err(eyre!(
+            "Packet \"{}\" exists already.",
+            full_packet_path.to_string_lossy()
+        )),
+    }
+}
+
 fn persist(
     full_packet_path: &std::path::Path,
     full_packet_name: &str,
     metadata: &[u8],
+    extra_metadata: &[(String, Vec<u8>)],
 ) -> eyre::Result<TarBall> {
+    // A partial packet file can only be left over by a crashed run: Replace it.
     let tarball = std::fs::OpenOptions::new()
         .write(true)
-        .create_new(true)
-        .open(full_packet_path)?;
+        .create(true)
+        .truncate(true)
+        .open(partial_packet_path(full_packet_path))?;
     let tarball = zstd::Encoder::new(tarball, 21)?;
 
     let mut tarball = tar::Builder::new(tarball);
@@ -135,18 +172,99 @@ fn persist(
     };
     add_buffer_raw(&mut tarball, &metadata_path, metadata, 0o600, 0, 0)?;
 
+    for (extension, data) in extra_metadata {
+        let extra_path =
+            std::path::PathBuf::from(".gng").join(format!("{full_packet_name}.{extension}"));
+        add_buffer_raw(&mut tarball, &extra_path, data, 0o600, 0, 0)?;
+    }
+
     Ok(tarball)
 }
 
+fn sync_directory(directory: &std::path::Path) -> eyre::Result<()> {
+    std::fs::File::open(directory)
+        .and_then(|d| d.sync_all())
+        .wrap_err(eyre!(
+            "Failed to sync directory \"{}\".",
+            directory.to_string_lossy()
+        ))
+}
+
+/// Move the packet at `partial_path` to `full_packet_path`
+///
+/// Unless existing packets are overwritten, the packet is hard linked into
+/// place: That fails if `full_packet_path` exists, even if it only appeared
+/// after the last check.
+fn commit(
+    partial_path: &std::path::Path,
+    full_packet_path: &std::path::Path,
+    existing: crate::ExistingPacketPolicy,
+) -> eyre::Result<Option<std::path::PathBuf>> {
+    let moved = if existing == crate::ExistingPacketPolicy::Overwrite {
+        if full_packet_path.exists() {
+            tracing::debug!(
+                "Overwriting existing packet \"{}\".",
+                full_packet_path.to_string_lossy()
+            );
+        }
+        std::fs::rename(partial_path, full_packet_path)
+    } else {
+        std::fs::hard_link(partial_path, full_packet_path)
+            .and_then(|()| std::fs::remove_file(partial_path))
+    };
+
+    match moved {
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            if existing == crate::ExistingPacketPolicy::Skip {
+                tracing::info!(
+                    "Keeping existing packet \"{}\".",
+                    full_packet_path.to_string_lossy()
+                );
+                std::fs::remove_file(partial_path)?;
+                return Ok(Some(full_packet_path.to_path_buf()));
+            }
+            return Err(eyre!(
+                "Packet \"{}\" exists already.",
+                full_packet_path.to_string_lossy()
+            ));
+        }
+        moved => moved.wrap_err(eyre!(
+            "Failed to move packet into place at \"{}\".",
+            full_packet_path.to_string_lossy()
+        ))?,
+    }
+    sync_directory(
+        full_packet_path
+            .parent()
+            .unwrap_or_else(|| std::path::Path::new(".")),
+    )?;
+
+    Ok(Some(full_packet_path.to_path_buf()))
+}
+
 fn close(
     tarball: TarBall,
     full_packet_path: &std::path::Path,
+    existing: crate::ExistingPacketPolicy,
 ) -> eyre::Result<Option<std::path::PathBuf>> {
-    let inner = tarball.into_inner()?;
-    inner
-        .finish()
-        .map(|_| Some(full_packet_path.to_path_buf()))
-        .wrap_err("Failed to finish ZSTD compression")
+    let partial_path = partial_packet_path(full_packet_path);
+
+    let result = tarball
+        .into_inner()
+        .wrap_err("Failed to finish tar archive")
+        .and_then(|inner| inner.finish().wrap_err("Failed to finish ZSTD compression"))
+        .and_then(|file| {
+            file.sync_all().wrap_err(eyre!(
+                "Failed to sync \"{}\" to disk.",
+                partial_path.to_string_lossy()
+            ))
+        })
+        .and_then(|()| commit(&partial_path, full_packet_path, existing));
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial_path);
+    }
+    result
 }
 
 // ----------------------------------------------------------------------
@@ -157,8 +275,11 @@ enum PacketWriterState {
     Empty {
         full_packet_name: String,
         metadata: Vec<u8>,
+        extra_metadata: Vec<(String, Vec<u8>)>,
     },
     Writing(TarBall),
+    /// Contents were added, but an existing packet file is kept
+    Skipped,
     Done,
 }
 
@@ -170,12 +291,19 @@ enum PacketWriterState {
 pub struct PacketWriter {
     full_packet_path: std::path::PathBuf,
     policy: crate::PacketPolicy,
+    existing: crate::ExistingPacketPolicy,
     state: PacketWriterState,
 }
 
 impl PacketWriter {
     /// Constructor
     ///
+    /// The packet is written into a partial file in `packet_path` first and
+    /// only renamed to its final name once it is complete. `existing` decides
+    /// what happens if a packet file of that name exists already. This is
+    /// checked before writing starts and again when moving the packet into
+    /// place.
+    ///
     /// # Errors
     ///
     /// Errors out when serialization of `packet` to JSON fails.
@@ -183,24 +311,19 @@ impl PacketWriter {
         packet_path: &std::path::Path,
         packet: &crate::BinaryPacketDefinition,
         policy: crate::PacketPolicy,
+        existing: crate::ExistingPacketPolicy,
     ) -> eyre::Result<Self> {
         let meta_data = serde_json::to_vec(packet)
             .wrap_err("Failed to serialize binary packet definition to JSON")?;
 
-        let facet_name = if let crate::BinaryFacet::Usage(BinaryFacetUsage { name }) = &packet.facet
-        {
-            Some(name.clone())
-        } else {
-            None
-        };
-
         Ok(Self::raw_new(
             packet_path,
             &packet.name,
-            &facet_name,
+            &packet.facet_name(),
             &packet.version,
             meta_data,
             policy,
+            existing,
         ))
     }
 
@@ -213,19 +336,22 @@ impl PacketWriter {
         version: &Version,
         metadata: Vec<u8>,
         policy: crate::PacketPolicy,
+        existing: crate::ExistingPacketPolicy,
     ) -> Self {
         // TODO: Make this configurable to support e.g. different compression formats?
         let file_name = versioned_full_packet_name(packet_name, facet_name, version);
 
-        let mut full_packet_path = packet_path.join(file_name);
-        full_packet_path.set_extension("gng");
+        // Do not use `set_extension`: It would replace the last part of the version!
+        let full_packet_path = packet_path.join(format!("{file_name}.gng"));
 
         Self {
             full_packet_path,
             policy,
+            existing,
             state: PacketWriterState::Empty {
                 full_packet_name: packet_name.combine(facet_name),
                 metadata,
+                extra_metadata: Vec::new(),
             },
         }
     }
@@ -238,19 +364,47 @@ impl PacketWriter {
             PacketWriterState::Empty {
                 full_packet_name,
                 metadata,
+                extra_metadata,
             } => {
+                if keep_existing(&self.full_packet_path, self.existing)? {
+                    self.state = PacketWriterState::Skipped;
+                    return Ok(());
+                }
                 self.state = PacketWriterState::Writing(persist(
                     &self.full_packet_path,
                     full_packet_name,
                     metadata,
+                    extra_metadata,
                 )?);
                 self.open_packet_file(func)
             }
             PacketWriterState::Writing(tarball) => func(tarball),
+            PacketWriterState::Skipped => Ok(()),
             PacketWriterState::Done => Err(eyre::eyre!("Packet file already closed.")),
         }
     }
 
+    /// Add additional metadata into the `.gng` directory of the packet
+    ///
+    /// The data is stored as `.gng/<packet>.<extension>`, right after the
+    /// packet metadata.
+    ///
+    /// # Errors
+    ///
+    /// Errors out when contents were added to the packet already.
+    pub fn add_metadata(&mut self, extension: &str, data: Vec<u8>) -> eyre::Result<()> {
+        match &mut self.state {
+            PacketWriterState::Empty { extra_metadata, .. } => {
+                extra_metadata.push((extension.to_string(), data));
+                Ok(())
+            }
+            _ => Err(eyre!(
+                "Can not add metadata to \"{}\": Contents were added already.",
+                self.full_packet_path.to_string_lossy()
+            )),
+        }
+    }
+
     /// Add a directory into the packet.
     #[tracing::instrument(level = "trace", skip(self))]
     pub fn add_directory(
@@ -348,6 +502,7 @@ impl PacketWriter {
             PacketWriterState::Empty {
                 full_packet_name: fpn,
                 metadata: md,
+                extra_metadata: emd,
             } => {
                 if matches!(&self.policy, crate::PacketPolicy::MustStayEmpty) {
                     tracing::debug!(
@@ -355,11 +510,14 @@ impl PacketWriter {
                         &self.full_packet_path.to_string_lossy(),
                     );
 
-                    let tb = persist(&self.full_packet_path, &fpn, &md).wrap_err(eyre!(
+                    if keep_existing(&self.full_packet_path, self.existing)? {
+                        return Ok(Some(self.full_packet_path.clone()));
+                    }
+                    let tb = persist(&self.full_packet_path, &fpn, &md, &emd).wrap_err(eyre!(
                         "Failed to persist \"{}\".",
                         self.full_packet_path.to_string_lossy(),
                     ))?;
-                    close(tb, &self.full_packet_path)
+                    close(tb, &self.full_packet_path, self.existing)
                 } else {
                     tracing::debug!(
                         "Packet \"{}\" stayed empty! SKIPPING",
@@ -383,15 +541,37 @@ impl PacketWriter {
                 );
 
                 if matches!(&self.policy, crate::PacketPolicy::MustStayEmpty) {
+                    drop(tarball);
+                    let _ = std::fs::remove_file(partial_packet_path(&self.full_packet_path));
                     Err(eyre!(
                         "Packet \"{}\" has contents, but should have stayed empty.",
                         &self.full_packet_path.to_string_lossy(),
                     ))
                 } else {
-                    close(tarball, &self.full_packet_path)
+                    close(tarball, &self.full_packet_path, self.existing)
+                }
+            }
+            PacketWriterState::Skipped => {
+                if matches!(&self.policy, crate::PacketPolicy::MustStayEmpty) {
+                    Err(eyre!(
+                        "Packet \"{}\" has contents, but should have stayed empty.",
+                        &self.full_packet_path.to_string_lossy(),
+                    ))
+                } else {
+                    Ok(Some(self.full_packet_path.clone()))
                 }
             }
             PacketWriterState::Done => Err(eyre::eyre!("Packet has already been closed.")),
         }
     }
 }
+
+impl Drop for PacketWriter {
+    fn drop(&mut self) {
+        if let PacketWriterState::Writing(_) = &self.state {
+            // Packet was not finished: Do not leave a partial file behind.
+            self.state = PacketWriterState::Done;
+            let _ = std::fs::remove_file(partial_packet_path(&self.full_packet_path));
+        }
+    }
+}